@@ -0,0 +1,38 @@
+/* The C interface to biblionotes, built with `cargo build --release
+ * --features ffi` as target/release/libbiblionotes.so (or .dylib, or .dll).
+ * Strings are UTF-8. Strings returned are freed with biblionotes_string_free,
+ * and a function that returns NULL has failed, as biblionotes_last_error
+ * says. */
+
+#ifndef BIBLIONOTES_H
+#define BIBLIONOTES_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Bibliography Bibliography;
+
+/* the bibliography in the BibTeX `source`, freed with biblionotes_free */
+Bibliography *biblionotes_parse(const char *source);
+void biblionotes_free(Bibliography *bib);
+
+/* the page on `key` with the markdown `note` as its note, through the
+ * Handlebars `template`, or just the page's content if `template` is NULL */
+char *biblionotes_render_entry(const Bibliography *bib, const char *key,
+                               const char *note, const char *template_);
+
+/* the index's entries, as a JSON array */
+char *biblionotes_index_json(const Bibliography *bib);
+
+void biblionotes_string_free(char *s);
+
+/* why the last function to fail on this thread did, or NULL; it stays valid
+ * until the next one fails */
+const char *biblionotes_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+use bib::Bibliography;
+use dates;
+use ics::{self, Event};
+
+// A calendar of the days the papers with notes came out, like "7 years since
+// Attention Is All You Need". It covers this year and the next, so that a
+// calendar subscribed to it always has the coming ones as long as the site
+// is rebuilt now and then. Entries without a month aren't in it.
+
+// the same day in another year, with 29 February falling on the 28th in
+// other years
+fn in_year(year: i64, month: u32, day: u32) -> (i64, u32, u32) {
+    let last = dates::civil_from_days(dates::days_from_civil(year, 3, 1) - 1).2;
+    if month == 2 && day > last {
+        (year, 2, last)
+    } else {
+        (year, month, day)
+    }
+}
+
+pub fn calendar(
+    bib: &Bibliography,
+    pages: &HashMap<String, String>,
+    base: Option<&str>,
+    now: SystemTime,
+) -> String {
+    let (this_year, _, _) = dates::civil(now);
+    let mut events = Vec::new();
+    for entry in bib.entries.iter().filter(|e| pages.contains_key(&e.key)) {
+        let (year, month, day) = match entry.published() {
+            Some(date) => date,
+            None => continue,
+        };
+        for when in this_year..this_year + 2 {
+            let years = when - year;
+            if years < 1 {
+                continue;
+            }
+            let unit = if years == 1 { "year" } else { "years" };
+            events.push(Event {
+                uid: format!("{}-{}@biblionotes", entry.key, when),
+                summary: format!("{} {} since {}", years, unit, entry.title),
+                description: Some(entry.short_citation()),
+                location: None,
+                url: base.map(|base| format!("{}{}", base, pages[&entry.key])),
+                date: in_year(when, month, day),
+                time: None,
+            });
+        }
+    }
+    events.sort_by_key(|e| e.date);
+    ics::calendar("Anniversaries", &events, now)
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use markdown;
+use output;
+
+// Images, PDFs and other files that notes link to. A note can link to files
+// by a path relative to it, like `![](smith2019/figure1.png)`, and every file
+// in the folder named after the entry's key next to the note is that entry's
+// attachment, linked or not. The build copies them into the output directory,
+// at the same place relative to it as they have relative to the notes, and
+// points the links at the copies.
+
+// a link in a note to a file next to it
+pub struct Reference {
+    // where its copy goes, relative to the output directory
+    pub path: PathBuf,
+    // byte offset and length of its destination in the note
+    pub offset: usize,
+    pub len: usize,
+    pub found: bool,
+}
+
+pub struct Attachments {
+    // what's linked to, as it's written in the note
+    pub references: BTreeMap<String, Reference>,
+    // every file to copy, from where it is in the output directory to where
+    // it is now
+    pub files: BTreeMap<PathBuf, PathBuf>,
+    // the files in the entry's folder, relative to the output directory
+    pub folder: Vec<PathBuf>,
+}
+
+// the destinations of the links and images in a piece of markdown: `](...)`
+// and the `src` and `href` of inline HTML, as their offsets and lengths
+fn destinations(text: &str) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    for opener in &["](", "src=\"", "href=\""] {
+        let mut i = 0;
+        while let Some(start) = text[i..].find(opener).map(|s| i + s + opener.len()) {
+            let rest = &text[start..];
+            let (start, len) = if opener.starts_with(']') {
+                match rest.strip_prefix('<') {
+                    Some(inner) => (start + 1, inner.find('>').unwrap_or(inner.len())),
+                    None => (start, rest.find(|c: char| c.is_whitespace() || c == ')')
+                        .unwrap_or(rest.len())),
+                }
+            } else {
+                (start, rest.find('"').unwrap_or(rest.len()))
+            };
+            found.push((start, len));
+            i = start + len;
+        }
+    }
+    found.sort();
+    found
+}
+
+// the file a link points at, if it points at one next to the note: not a
+// URL, a part of the page, or a page of the site
+fn local_file(destination: &str) -> Option<String> {
+    let path = destination.split(['#', '?']).next().unwrap_or("");
+    let scheme = path.find(':').is_some_and(|i| !path[..i].contains('/'));
+    if path.is_empty() || scheme || path.starts_with('/') || path.ends_with('/') {
+        return None;
+    }
+    if path.ends_with(".html") || path.ends_with(".htm") {
+        return None;
+    }
+    Some(path.replace("%20", " "))
+}
+
+// `path` with its `.` and `..` worked out, or `None` if it leaves the
+// directory it's relative to
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(c) => out.push(c),
+            Component::CurDir => {},
+            Component::ParentDir if out.pop() => {},
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+// the files a note links to and the ones in its entry's folder. `dir` is
+// where the note is, and `markdown_dir` where the notes are.
+pub fn find(note: &str, key: &str, dir: &Path, markdown_dir: &Path) -> Attachments {
+    let mut attachments = Attachments {
+        references: BTreeMap::new(),
+        files: BTreeMap::new(),
+        folder: Vec::new(),
+    };
+    // drafts are a level down, but their files go where the published
+    // notes' would
+    let relative = dir.strip_prefix(markdown_dir).map(|d| d.to_owned()).unwrap_or_default();
+    let target = |path: &Path| {
+        let inside = normalize(&relative.join(path))?;
+        Some(inside.strip_prefix(&relative).map(|p| p.to_owned()).unwrap_or(inside))
+    };
+
+    let mut folder = Vec::new();
+    output::files_under(&dir.join(key), &mut folder);
+    folder.sort();
+    for file in folder {
+        let name = file.strip_prefix(dir).unwrap().to_owned();
+        if let Some(to) = target(&name) {
+            attachments.folder.push(to.clone());
+            attachments.files.insert(to, file);
+        }
+    }
+
+    markdown::map_text(note, |offset, text| {
+        for (start, len) in destinations(text) {
+            let destination = &text[start..start + len];
+            let file = match local_file(destination) {
+                Some(file) => file,
+                None => continue,
+            };
+            let source = dir.join(&file);
+            // outside the notes, it's put with the entry's attachments
+            let path = target(Path::new(&file)).unwrap_or_else(|| {
+                let name = Path::new(&file).file_name().unwrap_or_default();
+                Path::new(key).join(name)
+            });
+            let found = source.is_file();
+            if found {
+                attachments.files.insert(path.clone(), source);
+            }
+            attachments.references.entry(destination.to_owned()).or_insert(Reference {
+                path,
+                offset: offset + start,
+                len,
+                found,
+            });
+        }
+        String::new()
+    });
+    attachments
+}
+
+// points the links to attachments at their copies, relative to `root`
+pub fn rewrite(note: &str, attachments: &Attachments, root: &str) -> String {
+    let links: HashMap<&str, String> = attachments.references.iter()
+        .filter(|&(_, r)| r.found)
+        .map(|(destination, r)| {
+            let fragment = destination.find(['#', '?']).map_or("", |i| &destination[i..]);
+            let path = r.path.to_string_lossy().replace(' ', "%20");
+            (&destination[..], format!("{}{}{}", root, path, fragment))
+        })
+        .collect();
+    if links.is_empty() {
+        return note.to_owned();
+    }
+    markdown::map_text(note, |_, text| {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, len) in destinations(text) {
+            if let Some(link) = links.get(&text[start..start + len]) {
+                out.push_str(&text[last..start]);
+                out.push_str(link);
+                last = start + len;
+            }
+        }
+        out.push_str(&text[last..]);
+        out
+    })
+}
@@ -0,0 +1,855 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
+use bib_parser;
+use csl;
+use dates;
+use diagnostics::Diagnostic;
+use latex;
+use names::Name;
+
+// a bibliography entry. Title, author and year come from bib_parser; every
+// field is also kept in `fields` as written (with @string macros expanded and
+// whitespace collapsed), for the things bib_parser doesn't expose.
+pub struct Entry {
+    pub key: String,
+    // the (1-based) line the entry starts on, or 0 if it isn't known
+    pub line: usize,
+    // the entry type, like `article`, lowercased
+    pub kind: String,
+    pub title: String,
+    pub author: String,
+    pub year: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+// a link to the paper itself, e.g. through its DOI
+pub struct Link {
+    pub label: &'static str,
+    pub url: String,
+}
+
+// the entry types of things online rather than in print, which BibLaTeX
+// has, and which often have no author or year
+const ONLINE_KINDS: &[&str] = &["online", "electronic", "www", "software", "dataset"];
+
+// the fields BibTeX and BibLaTeX define, and those biblionotes itself reads or
+// fills in; any others, like `code` or `slides`, are the bibliography's own
+const KNOWN_FIELDS: &[&str] = &[
+    "abstract", "added", "addendum", "address", "annote", "archiveprefix", "arxiv",
+    "audience", "author", "booktitle", "chapter", "checked", "citations", "cites",
+    "crossref", "date", "day", "doi", "edition", "editor", "entrysubtype", "eprint",
+    "eprintclass", "eprinttype", "eventdate", "eventtitle", "file", "howpublished",
+    "institution", "isbn", "issn", "issue", "journal", "journaltitle", "key", "keywords",
+    "language", "location", "maintitle", "month", "note", "note_author", "number",
+    "organization", "pages", "primaryclass", "publisher", "pubstate", "rating", "school",
+    "series", "shorttitle", "source", "status", "subtitle", "title", "translator", "type",
+    "url", "urldate", "venue", "version", "volume", "year",
+];
+
+// "Author (Year)", leaving out what an entry doesn't have, rather than
+// leaving empty brackets
+pub fn author_year(author: &str, year: &str) -> String {
+    match (author.trim(), year.trim()) {
+        (author, "") => author.to_owned(),
+        ("", year) => format!("({})", year),
+        (author, year) => format!("{} ({})", author, year),
+    }
+}
+
+// strips any of the given (case-insensitive) prefixes from `s`
+fn strip_prefixes<'a>(s: &'a str, prefixes: &[&str]) -> &'a str {
+    for prefix in prefixes {
+        if s.len() >= prefix.len() && s.is_char_boundary(prefix.len())
+            && s[..prefix.len()].eq_ignore_ascii_case(prefix)
+        {
+            return s[prefix.len()..].trim();
+        }
+    }
+    s
+}
+
+// percent-encodes the characters that would break a URL path
+pub fn encode_path(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ' ' | '"' | '#' | '%' | '<' | '>' | '?' | '[' | ']' | '^' | '`' | '{' | '|' | '}' => {
+                out.push_str(&format!("%{:02X}", c as u32));
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+impl Entry {
+    // points the diagnostic at the line the entry starts on, if it's known
+    pub fn label(&self, d: Diagnostic, label: &str) -> Diagnostic {
+        match self.line {
+            0 => d,
+            line => d.label(line, 1, 0, label),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|s| &s[..])
+    }
+
+    // a field as plain text, with LaTeX turned into Unicode, if it's set and
+    // not empty
+    pub fn text_field(&self, name: &str) -> Option<String> {
+        self.field(name)
+            .map(|v| latex::to_unicode(v).trim().to_owned())
+            .filter(|v| !v.is_empty())
+    }
+
+    // a field with just BibTeX's braces removed, for identifiers like DOIs
+    // that `text_field` could mangle
+    fn plain_field(&self, name: &str) -> Option<String> {
+        self.field(name)
+            .map(|v| v.replace(['{', '}'], "").trim().to_owned())
+            .filter(|v| !v.is_empty())
+    }
+
+    // the bare DOI, accepting DOIs written as URLs or with a `doi:` prefix.
+    // DOIs are case-insensitive, so it's lowercased.
+    pub fn doi(&self) -> Option<String> {
+        let doi = self.plain_field("doi")?;
+        let doi = strip_prefixes(&doi, &[
+            "https://doi.org/", "http://doi.org/",
+            "https://dx.doi.org/", "http://dx.doi.org/", "doi:",
+        ]);
+        Some(doi.to_lowercase())
+    }
+
+    // how many times the paper has been cited, from a `citations` field,
+    // which `enrich` fills in
+    pub fn citations(&self) -> Option<u64> {
+        self.plain_field("citations")?.parse().ok()
+    }
+
+    pub fn doi_url(&self) -> Option<String> {
+        self.doi().map(|doi| format!("https://doi.org/{}", encode_path(&doi)))
+    }
+
+    // the journal or proceedings the entry appeared in, under its BibTeX or
+    // BibLaTeX name, or for things online, who put it there
+    pub fn venue(&self) -> Option<String> {
+        let mut names = vec!["journal", "journaltitle", "booktitle"];
+        if ONLINE_KINDS.contains(&&self.kind[..]) {
+            names.extend(["organization", "publisher"]);
+        }
+        names.iter().find_map(|name| self.text_field(name))
+    }
+
+    // the fields biblionotes doesn't know, by name, as text. Those that are
+    // links are only stripped of their braces, so LaTeX doesn't mangle them.
+    pub fn extra_fields(&self) -> Vec<(String, String)> {
+        let is_link = |v: &str| v.starts_with("http://") || v.starts_with("https://");
+        self.fields.keys()
+            .filter(|name| !KNOWN_FIELDS.contains(&&name[..]))
+            .filter_map(|name| {
+                let value = match self.plain_field(name) {
+                    Some(url) if is_link(&url) => url,
+                    _ => self.text_field(name)?,
+                };
+                Some((name.clone(), value))
+            })
+            .collect()
+    }
+
+    // the arXiv abstract page, from an `arxiv` field or an `eprint` field
+    // (unless `archiveprefix` or `eprinttype` say it's from elsewhere)
+    pub fn arxiv_url(&self) -> Option<String> {
+        let id = self.plain_field("arxiv").or_else(|| {
+            let archive = self.plain_field("archiveprefix")
+                .or_else(|| self.plain_field("eprinttype"));
+            match archive {
+                Some(ref a) if !a.eq_ignore_ascii_case("arxiv") => None,
+                _ => self.plain_field("eprint"),
+            }
+        })?;
+        let id = strip_prefixes(&id, &[
+            "https://arxiv.org/abs/", "http://arxiv.org/abs/", "arxiv:",
+        ]);
+        Some(format!("https://arxiv.org/abs/{}", encode_path(id)))
+    }
+
+    pub fn url(&self) -> Option<String> {
+        // `\url{...}` sometimes ends up in the field itself
+        self.plain_field("url").map(|u| strip_prefixes(&u, &["\\url"]).to_owned())
+    }
+
+    // the tags in the `keywords` field, which can be separated by commas or
+    // semicolons
+    pub fn tags(&self) -> Vec<String> {
+        let keywords = match self.text_field("keywords") {
+            Some(keywords) => keywords,
+            None => return Vec::new(),
+        };
+        let mut tags: Vec<String> = Vec::new();
+        for tag in keywords.split([',', ';']).map(str::trim) {
+            if !tag.is_empty() && !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_owned());
+            }
+        }
+        tags
+    }
+
+    // the keys in the `cites` field, for bibliographies that record what
+    // each paper cites
+    pub fn cites(&self) -> Vec<String> {
+        self.plain_field("cites")
+            .map(|cites| {
+                cites.split(',').map(str::trim).filter(|k| !k.is_empty())
+                    .map(|k| k.to_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // every link to the paper, in order of preference
+    pub fn links(&self) -> Vec<Link> {
+        let mut links = Vec::new();
+        if let Some(url) = self.doi_url() {
+            links.push(Link { label: "DOI", url });
+        }
+        if let Some(url) = self.arxiv_url() {
+            links.push(Link { label: "arXiv", url });
+        }
+        if let Some(url) = self.url() {
+            // often just the DOI again
+            if !links.iter().any(|l| l.url == url) {
+                links.push(Link { label: "URL", url });
+            }
+        }
+        links
+    }
+
+    // the individual names in the author field
+    pub fn authors(&self) -> Vec<Name> {
+        self.field("author")
+            .map(|a| split_names(a).iter().map(|n| Name::parse(n)).collect())
+            .unwrap_or_default()
+    }
+
+    // what the entry sorts by in an author index: the authors' last names,
+    // then first names, ignoring particles like "van"
+    pub fn author_sort_key(&self) -> String {
+        let names: Vec<String> = self.authors().iter().map(Name::sort_key).collect();
+        if names.is_empty() {
+            self.author.clone()
+        } else {
+            names.join(", ")
+        }
+    }
+
+    // "Smith", "Smith and Jones" or "Smith et al.", followed by the year
+    pub fn short_citation(&self) -> String {
+        let surnames: Vec<String> = self.authors().iter().map(Name::surname).collect();
+        let who = match surnames.len() {
+            0 => self.title.clone(),
+            1 => surnames[0].clone(),
+            2 => format!("{} and {}", surnames[0], surnames[1]),
+            _ => format!("{} et al.", surnames[0]),
+        };
+        author_year(&who, &self.year)
+    }
+
+    // the day the entry came out, from a BibLaTeX `date` like `2017-06-12`
+    // or from the `year`, `month` and `day` fields. Without a day it's the
+    // first of the month, and without a month there's no telling.
+    pub fn published(&self) -> Option<(i64, u32, u32)> {
+        let (year, month, day): (i64, u32, u32) = match self.plain_field("date") {
+            // the start of ranges like `2017-06/2017-07`
+            Some(date) => {
+                let start = date.split('/').next().unwrap_or("");
+                let parts: Vec<&str> = start.split('-').collect();
+                let day = parts.get(2).and_then(|d| d.parse().ok()).unwrap_or(1);
+                (parts[0].parse().ok()?, parts.get(1)?.parse().ok()?, day)
+            },
+            None => {
+                let month = self.plain_field("month")?;
+                let month = month.parse().ok().or_else(|| {
+                    let name = month.to_lowercase();
+                    MONTHS.iter()
+                        .position(|m| name.len() >= 3 && m.to_lowercase().starts_with(&name))
+                        .map(|i| i as u32 + 1)
+                })?;
+                let day = self.plain_field("day").and_then(|d| d.parse().ok()).unwrap_or(1);
+                (self.year.trim().parse().ok()?, month, day)
+            },
+        };
+        dates::parse_civil(&format!("{:04}-{:02}-{:02}", year, month, day)).ok()
+    }
+}
+
+// splits a BibTeX name list on the `and`s that aren't inside braces
+fn split_names(names: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    for word in names.split_whitespace() {
+        if depth == 0 && word.eq_ignore_ascii_case("and") {
+            out.push(current.trim().to_owned());
+            current.clear();
+            continue;
+        }
+        for c in word.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                _ => {},
+            }
+        }
+        current.push_str(word);
+        current.push(' ');
+    }
+    if !current.trim().is_empty() {
+        out.push(current.trim().to_owned());
+    }
+    out
+}
+
+// a parsed bibliography, along with what's needed to point diagnostics back
+// at its source
+pub struct Bibliography {
+    pub path: String,
+    pub source: String,
+    pub entries: Vec<Entry>,
+    // the keys and lines of the entries that were left out for being
+    // malformed
+    pub skipped: Vec<(String, usize)>,
+}
+
+impl Bibliography {
+    pub fn get(&self, key: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.key == key)
+    }
+
+    // where an entry is in the source, from its `@` to the brace closing it,
+    // or the braces of its object in CSL-JSON
+    pub fn span(&self, key: &str) -> Option<Range<usize>> {
+        let entry = self.get(key)?;
+        let line_start = self.source.split_inclusive('\n')
+            .take(entry.line.saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>();
+        let at = if csl::is_csl(&self.path) { '{' } else { '@' };
+        let start = line_start + self.source[line_start..].find(at)?;
+        let text = &self.source[start..];
+        let open = text.find(['{', '('])?;
+        let (opener, closer) = match &text[open..open + 1] {
+            "{" => ('{', '}'),
+            _ => ('(', ')'),
+        };
+        let mut depth = 0;
+        for (i, c) in text[open..].char_indices() {
+            if c == opener {
+                depth += 1;
+            } else if c == closer {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(start..start + open + i + 1);
+                }
+            }
+        }
+        None
+    }
+
+    // the text of an entry as written
+    pub fn text(&self, key: &str) -> Option<&str> {
+        self.span(key).map(|span| &self.source[span])
+    }
+
+    // a diagnostic pointing at the start of the given entry, if it can be
+    // found in the source, or at the file as a whole otherwise
+    pub fn diagnostic_at(&self, d: Diagnostic, key: &str, label: &str) -> Diagnostic {
+        let d = d.in_file(&self.path, &self.source);
+        match self.get(key) {
+            Some(entry) => entry.label(d, label),
+            None => d,
+        }
+    }
+}
+
+// a slice of the source file holding a single `@type{...}` block, starting
+// at the given (1-based) line
+struct Chunk<'a> {
+    line: usize,
+    text: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    fn kind(&self) -> String {
+        let text = String::from_utf8_lossy(self.text);
+        text[1..].chars()
+            .take_while(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase()
+    }
+
+    fn key(&self) -> Option<String> {
+        let text = String::from_utf8_lossy(self.text);
+        let start = match text.find(&['{', '('][..]) {
+            Some(i) => i + 1,
+            None => return None,
+        };
+        let key: String = text[start..].chars()
+            .take_while(|&c| c != ',' && c != '}' && c != '\n')
+            .collect();
+        let key = key.trim();
+        if key.is_empty() { None } else { Some(key.to_owned()) }
+    }
+}
+
+fn split_chunks<'a>(bs: &'a [u8]) -> Vec<Chunk<'a>> {
+    // every line whose first non-blank character is `@` starts a new chunk;
+    // anything before the first one is a comment as far as BibTeX cares
+    let mut starts = Vec::new();
+    let mut line = 1;
+    let mut at_line_start = true;
+    for (i, &b) in bs.iter().enumerate() {
+        if b == b'\n' {
+            line += 1;
+            at_line_start = true;
+        } else if b == b'@' && at_line_start {
+            starts.push((i, line));
+            at_line_start = false;
+        } else if b != b' ' && b != b'\t' && b != b'\r' {
+            at_line_start = false;
+        }
+    }
+    let mut chunks = Vec::new();
+    for (n, &(start, line)) in starts.iter().enumerate() {
+        let end = starts.get(n + 1).map(|&(s, _)| s).unwrap_or(bs.len());
+        chunks.push(Chunk { line, text: &bs[start..end] });
+    }
+    chunks
+}
+
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// reads the `name = value` pairs of an entry, starting just after its key
+// (or just after the opening brace, for @string). Values can be braced,
+// quoted, bare numbers or macro names, concatenated with `#`.
+fn parse_fields(text: &str, macros: &HashMap<String, String>) -> BTreeMap<String, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut fields = BTreeMap::new();
+    let mut i = 0;
+    loop {
+        while i < chars.len() && (chars[i].is_whitespace() || chars[i] == ',') {
+            i += 1;
+        }
+        let name_start = i;
+        while i < chars.len() && chars[i] != '=' && chars[i] != '}' && chars[i] != ')' {
+            i += 1;
+        }
+        if i >= chars.len() || chars[i] != '=' {
+            return fields;
+        }
+        let name: String = chars[name_start..i].iter().collect();
+        let name = name.trim().to_lowercase();
+        i += 1;
+
+        let mut value = String::new();
+        loop {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if i >= chars.len() {
+                break;
+            }
+            match chars[i] {
+                '{' | '"' => {
+                    let close = if chars[i] == '{' { '}' } else { '"' };
+                    let mut depth = 0;
+                    i += 1;
+                    while i < chars.len() {
+                        let c = chars[i];
+                        if c == close && depth == 0 {
+                            break;
+                        }
+                        match c {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {},
+                        }
+                        value.push(c);
+                        i += 1;
+                    }
+                    i += 1;
+                },
+                _ => {
+                    let start = i;
+                    while i < chars.len() && (chars[i].is_alphanumeric()
+                        || "_-:.+".contains(chars[i]))
+                    {
+                        i += 1;
+                    }
+                    let word: String = chars[start..i].iter().collect();
+                    let word_lower = word.to_lowercase();
+                    match macros.get(&word_lower) {
+                        Some(expansion) => value.push_str(expansion),
+                        None => value.push_str(&word),
+                    }
+                    if start == i {
+                        // not something we understand; give up on this entry
+                        return fields;
+                    }
+                },
+            }
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if i < chars.len() && chars[i] == '#' {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        fields.insert(name, collapse_whitespace(&value));
+    }
+}
+
+// the text of an entry after its opening brace and, unless it's a @string,
+// its key
+fn body_of(chunk: &Chunk) -> String {
+    let text = String::from_utf8_lossy(chunk.text);
+    let open = match text.find(&['{', '('][..]) {
+        Some(i) => i + 1,
+        None => return String::new(),
+    };
+    if chunk.kind() == "string" {
+        return text[open..].to_owned();
+    }
+    match text[open..].find(',') {
+        Some(i) => text[open + i + 1..].to_owned(),
+        None => String::new(),
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July",
+    "August", "September", "October", "November", "December",
+];
+
+fn month_macros() -> HashMap<String, String> {
+    MONTHS.iter()
+        .map(|m| (m[..3].to_lowercase(), m.to_string()))
+        .collect()
+}
+
+// the line and column of the first brace that never gets closed, if any
+fn unclosed_brace(chunk: &Chunk) -> Option<(usize, usize)> {
+    let mut open = Vec::new();
+    let mut line = chunk.line;
+    let mut col = 0;
+    for c in String::from_utf8_lossy(chunk.text).chars() {
+        col += 1;
+        match c {
+            '\n' => {
+                line += 1;
+                col = 0;
+            },
+            '{' => open.push((line, col)),
+            '}' => { open.pop(); },
+            _ => {},
+        }
+    }
+    open.first().cloned()
+}
+
+fn warn_chunk<E: ::std::fmt::Debug>(bib_path: &str, source: &str, chunk: &Chunk, err: E) {
+    let key = chunk.key().unwrap_or_else(|| "<no key>".to_owned());
+    let mut d = Diagnostic::warning(format!("skipping malformed entry `{}`", key))
+        .in_file(bib_path, source);
+    if let Some((line, col)) = unclosed_brace(chunk) {
+        d = d.label(line, col, 1, "this brace is never closed");
+    }
+    d.label(chunk.line, 1, 0, "entry starts here")
+        .note(format!("parser error: {:?}", err))
+        .emit();
+}
+
+pub fn read_bib(bib_path: &str) -> Bibliography {
+    let mut file = File::open(bib_path).expect("Could not open bibliography");
+    let mut bs = Vec::new();
+    file.read_to_end(&mut bs).unwrap();
+    if csl::is_csl(bib_path) {
+        return csl::parse(bib_path, &bs);
+    }
+    parse(bib_path, &bs)
+}
+
+// parses BibTeX from somewhere other than a file; `bib_path` says where, for
+// diagnostics
+pub fn parse(bib_path: &str, bs: &[u8]) -> Bibliography {
+    let source = String::from_utf8_lossy(bs).into_owned();
+    let chunks = split_chunks(bs);
+    let (parsed, skipped) = parse_valid(bib_path, &source, bs, &chunks);
+
+    // expand macros in order, since later ones can refer to earlier ones
+    let mut macros = month_macros();
+    for chunk in chunks.iter().filter(|c| c.kind() == "string") {
+        let fields = parse_fields(&body_of(chunk), &macros);
+        macros.extend(fields);
+    }
+    let mut by_key: HashMap<String, Vec<&Chunk>> = HashMap::new();
+    for chunk in &chunks {
+        if let Some(key) = chunk.key() {
+            by_key.entry(key).or_default().push(chunk);
+        }
+    }
+
+    // a second entry with a key would have its page where the first's is
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let entries = parsed.into_iter()
+        .filter_map(|(key, entry)| entry.map(|e| (key, e)))
+        .filter(|(key, _)| {
+            let count = seen.entry(key.clone()).or_insert(0);
+            *count += 1;
+            if *count == 1 {
+                return true;
+            }
+            let d = Diagnostic::warning(format!("skipping a second entry `{}`", key))
+                .in_file(bib_path, &source);
+            match by_key.get(key).and_then(|c| c.get(*count - 1)) {
+                Some(chunk) => d.label(chunk.line, 1, 0, "entry starts here"),
+                None => d,
+            }.emit();
+            false
+        })
+        .map(|(key, entry)| {
+            let (line, kind, fields) = match by_key.get(&key).and_then(|c| c.first()) {
+                Some(chunk) => {
+                    (chunk.line, chunk.kind(), parse_fields(&body_of(chunk), &macros))
+                },
+                None => (0, String::new(), BTreeMap::new()),
+            };
+            let mut author = latex::to_unicode(&entry.author().to_string());
+            let mut year = latex::to_unicode(&entry.year().to_string());
+            // BibLaTeX has the year in the `date`, and things online are by
+            // whoever put them there
+            if year.trim().is_empty() {
+                let date = fields.get("date").map_or("", |d| d.trim_start_matches('{'));
+                year = date.chars().take_while(|c| c.is_ascii_digit()).collect();
+            }
+            if author.trim().is_empty() {
+                let by = fields.get("organization").or_else(|| fields.get("editor"));
+                author = by.map(|by| latex::to_unicode(by)).unwrap_or_default();
+            }
+            Entry {
+                title: latex::to_unicode(entry.title()),
+                author,
+                year,
+                key,
+                line,
+                kind,
+                fields,
+            }
+        })
+        .collect();
+    Bibliography {
+        path: bib_path.to_owned(),
+        source,
+        entries,
+        skipped,
+    }
+}
+
+type Parsed = Vec<(String, Option<bib_parser::Entry>)>;
+
+// parses the bibliography with bib_parser, leaving out any entries it
+// can't make sense of, along with the keys and lines of those
+fn parse_valid(
+    bib_path: &str,
+    source: &str,
+    bs: &[u8],
+    chunks: &[Chunk],
+) -> (Parsed, Vec<(String, usize)>) {
+    let err = match bib_parser::parse_bib(bs) {
+        Ok(x) => return (x, Vec::new()),
+        Err(e) => e,
+    };
+
+    // the file as a whole doesn't parse: check each entry on its own, and
+    // keep only the ones that do. @string macros are prepended to every
+    // entry so that entries using them don't get flagged.
+    if chunks.is_empty() {
+        Diagnostic::error("could not parse the bibliography, which has no entries")
+            .in_file(bib_path, source)
+            .note(format!("parser error: {:?}", err))
+            .emit();
+        return (Vec::new(), Vec::new());
+    }
+    let mut macros = Vec::new();
+    for chunk in chunks.iter().filter(|c| c.kind() == "string") {
+        match bib_parser::parse_bib(chunk.text) {
+            Ok(_) => macros.extend_from_slice(chunk.text),
+            Err(e) => warn_chunk(bib_path, source, chunk, e),
+        }
+    }
+    let mut good = macros.clone();
+    let mut skipped = Vec::new();
+    for chunk in chunks.iter().filter(|c| c.kind() != "string") {
+        let mut candidate = macros.clone();
+        candidate.extend_from_slice(chunk.text);
+        match bib_parser::parse_bib(&candidate) {
+            Ok(_) => good.extend_from_slice(chunk.text),
+            Err(e) => {
+                warn_chunk(bib_path, source, chunk, e);
+                let key = chunk.key().unwrap_or_else(|| "<no key>".to_owned());
+                skipped.push((key, chunk.line));
+            },
+        }
+    }
+    match bib_parser::parse_bib(&good) {
+        Ok(x) => (x, skipped),
+        // what's between the entries, then
+        Err(e) => {
+            Diagnostic::error("could not parse the bibliography, even without its bad entries")
+                .in_file(bib_path, source)
+                .note(format!("parser error: {:?}", e))
+                .emit();
+            (Vec::new(), skipped)
+        },
+    }
+}
+
+// the text of an entry with its key replaced
+pub fn with_key(text: &str, key: &str) -> String {
+    let start = match text.find(['{', '(']) {
+        Some(open) => open + 1,
+        None => return text.to_owned(),
+    };
+    let end = start + text[start..].find([',', '\n']).unwrap_or(text.len() - start);
+    format!("{}{}{}", &text[..start], key, &text[end..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(text: &str) -> BTreeMap<String, String> {
+        parse_fields(text, &month_macros())
+    }
+
+    fn entry(fields: &[(&str, &str)]) -> Entry {
+        Entry {
+            key: "smith2019".to_owned(),
+            line: 1,
+            kind: "article".to_owned(),
+            title: String::new(),
+            author: String::new(),
+            year: String::new(),
+            fields: fields.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect(),
+        }
+    }
+
+    #[test]
+    fn splits_at_each_entry() {
+        let source = b"% a comment\n@article{a,\n  title = {A}\n}\n  @book{b, note = {B @ C}}";
+        let chunks = split_chunks(source);
+        let read: Vec<(usize, String, Option<String>)> = chunks.iter()
+            .map(|c| (c.line, c.kind(), c.key()))
+            .collect();
+        assert_eq!(read, vec![
+            (2, "article".to_owned(), Some("a".to_owned())),
+            (5, "book".to_owned(), Some("b".to_owned())),
+        ]);
+        // an `@` inside a field doesn't start an entry
+        assert!(String::from_utf8_lossy(chunks[1].text).contains("B @ C"));
+    }
+
+    #[test]
+    fn splits_nothing_without_entries() {
+        assert!(split_chunks(b"").is_empty());
+        assert!(split_chunks(b"just a comment, me@example.org\n").is_empty());
+    }
+
+    #[test]
+    fn reads_braced_quoted_and_bare_values() {
+        let f = fields(" title = {The {RNN} Paper},\n note = \"quoted\",\n  year = 2019,\n");
+        assert_eq!(f["title"], "The {RNN} Paper");
+        assert_eq!(f["note"], "quoted");
+        assert_eq!(f["year"], "2019");
+    }
+
+    #[test]
+    fn lowercases_names_and_collapses_whitespace() {
+        let f = fields("Title = {Spread\n     over   lines}}");
+        assert_eq!(f["title"], "Spread over lines");
+    }
+
+    #[test]
+    fn expands_macros_and_concatenations() {
+        let mut macros = month_macros();
+        let icml = "International Conference on Machine Learning";
+        macros.insert("icml".to_owned(), icml.to_owned());
+        let f = parse_fields("booktitle = \"Proc. \" # ICML, month = jan}", &macros);
+        assert_eq!(f["booktitle"], "Proc. International Conference on Machine Learning");
+        assert_eq!(f["month"], "January");
+    }
+
+    #[test]
+    fn stops_at_what_it_cant_read() {
+        let f = fields("title = {A}, year = @@, author = {B}");
+        assert_eq!(f.len(), 1);
+        assert_eq!(f["title"], "A");
+    }
+
+    #[test]
+    fn finds_the_body_after_the_key() {
+        let chunks = split_chunks(b"@string{icml = \"ICML\"}\n@article{a, title = {A}}\n");
+        assert_eq!(body_of(&chunks[0]), "icml = \"ICML\"}\n");
+        assert_eq!(body_of(&chunks[1]), " title = {A}}\n");
+    }
+
+    #[test]
+    fn finds_the_brace_left_open() {
+        let chunks = split_chunks(b"@article{a,\n  title = {A,\n  year = 2019\n}\n");
+        assert_eq!(unclosed_brace(&chunks[0]), Some((1, 9)));
+        let chunks = split_chunks(b"@article{a, title = {A}}\n");
+        assert_eq!(unclosed_brace(&chunks[0]), None);
+    }
+
+    #[test]
+    fn replaces_the_key() {
+        let renamed = with_key("@article{old,\n  title = {A}}", "new");
+        assert_eq!(renamed, "@article{new,\n  title = {A}}");
+    }
+
+    #[test]
+    fn normalises_dois() {
+        let e = entry(&[("doi", "https://doi.org/10.1000/ABC")]);
+        assert_eq!(e.doi(), Some("10.1000/abc".to_owned()));
+        assert_eq!(e.doi_url(), Some("https://doi.org/10.1000/abc".to_owned()));
+        assert_eq!(entry(&[("doi", "doi:{10.1/x}")]).doi(), Some("10.1/x".to_owned()));
+    }
+
+    #[test]
+    fn keeps_fields_of_the_bibliographys_own() {
+        let e = entry(&[
+            ("title", "Known"),
+            ("code", "https://github.com/o/r_x~y"),
+            ("slides", "{Slides}"),
+            ("video", "  "),
+        ]);
+        assert_eq!(e.extra_fields(), vec![
+            ("code".to_owned(), "https://github.com/o/r_x~y".to_owned()),
+            ("slides".to_owned(), "Slides".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn puts_authors_and_years_together() {
+        assert_eq!(author_year("Smith", "2019"), "Smith (2019)");
+        assert_eq!(author_year("Smith", ""), "Smith");
+        assert_eq!(author_year(" ", "2019"), "(2019)");
+    }
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write;
+use bib;
+use html;
+use text::Text;
+use urls;
+
+// Every note on one page, for reading offline or printing: `all.html` has
+// the notes' pages one after another, in the index's order, after a table of
+// contents. Each note is an `<article>` with its key as its id, and so that
+// the notes get on together on one page, their headings go down a level to
+// fit under the page's own title, their ids (of footnotes and headings
+// alike) get the note's key in front, and links from one note to another go
+// to it further down the page instead. With `--all-pdf` the page is also
+// turned into `all.pdf` by pandoc.
+
+// a note, as the index has it, and its page's content
+pub struct Chapter<'a> {
+    pub key: &'a str,
+    pub link: &'a str,
+    pub title: &'a str,
+    pub author: &'a str,
+    pub year: &'a str,
+    pub content: &'a str,
+}
+
+// a note's content, at the top of the site instead of at its page
+fn at_top(content: &str, link: &str) -> String {
+    let root = urls::root_for(link);
+    if root.is_empty() {
+        return content.to_owned();
+    }
+    content.replace(&format!(" href=\"{}", root), " href=\"")
+        .replace(&format!(" src=\"{}", root), " src=\"")
+}
+
+pub fn page(chapters: &[Chapter], text: &Text) -> String {
+    let mut out = format!(
+        "<header>\n  <h1>{}</h1>\n</header>\n<nav class=\"contents\">\n  <h2>{}</h2>\n  <ol>\n",
+        html::escape(text.get("index_title")),
+        html::escape(text.get("contents"))
+    );
+    for c in chapters {
+        writeln!(
+            out,
+            "    <li><a href=\"#{}\">{}</a> {}</li>",
+            html::escape(&urls::slugify(c.key)),
+            html::escape(c.title),
+            html::escape(&bib::author_year(c.author, c.year))
+        ).unwrap();
+    }
+    out.push_str("  </ol>\n</nav>\n");
+    for c in chapters {
+        let id = urls::slugify(c.key);
+        let content = html::prefix_ids(&at_top(c.content, c.link), &id);
+        let mut content = html::demote_headings(&content, 1);
+        // the other notes are on this page too
+        for other in chapters {
+            let to = urls::slugify(other.key);
+            content = content
+                .replace(&format!(" href=\"{}#", other.link), &format!(" href=\"#{}-", to))
+                .replace(&format!(" href=\"{}\"", other.link), &format!(" href=\"#{}\"", to));
+        }
+        writeln!(out, "<article id=\"{}\">\n{}\n</article>", html::escape(&id), content)
+            .unwrap();
+    }
+    out
+}
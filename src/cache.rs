@@ -0,0 +1,120 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use config::Config;
+use diagnostics::Diagnostic;
+use error::Error;
+use render;
+
+// What pandoc made of each note, kept under `.biblionotes/cache/` next to the
+// bibliography so that a note that hasn't changed never goes through pandoc
+// again, even when its file has been touched, as switching branches does.
+// Each is a file named after a hash of the note and of everything pandoc is
+// run with (its path, its input format and its arguments), so a change to
+// any of them misses the cache rather than finding something stale. The hash
+// is 128-bit FNV-1a, whose output is fixed, unlike that of the standard
+// library's hasher, which can change with Rust, and the cache's `VERSION`
+// goes into it first, for changing it on purpose. What
+// pandoc's own files, like filters, have in them isn't part of it: after
+// changing those, or upgrading pandoc, clear the cache with `cache clear`.
+
+// how many notes were found in the cache, and how many went through pandoc,
+// for the build's report
+static HITS: AtomicUsize = AtomicUsize::new(0);
+static MISSES: AtomicUsize = AtomicUsize::new(0);
+
+pub fn hits() -> usize {
+    HITS.load(Ordering::Relaxed)
+}
+
+pub fn misses() -> usize {
+    MISSES.load(Ordering::Relaxed)
+}
+
+pub fn dir(config: &Config) -> PathBuf {
+    config.pandoc.cache_dir.clone().unwrap_or_else(|| {
+        let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+        dir.join(".biblionotes").join("cache")
+    })
+}
+
+// what's cached, and how it's keyed: a new version misses every old file
+const VERSION: &str = "1";
+
+// 128-bit FNV-1a
+struct Fnv(u128);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0x6c62_272e_07bb_0142_62b8_2175_6295_c58d)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u128;
+            self.0 = self.0.wrapping_mul(0x0000_0000_0100_0000_0000_0000_0000_013b);
+        }
+    }
+
+    // a string, after its length, so that ("ab", "c") and ("a", "bc") differ
+    fn text(&mut self, s: &str) {
+        self.bytes(&(s.len() as u64).to_le_bytes());
+        self.bytes(s.as_bytes());
+    }
+}
+
+fn path(config: &Config, note: &str) -> PathBuf {
+    let pandoc = &config.pandoc;
+    let extra = render::pandoc_extra(config);
+    let mut hash = Fnv::new();
+    for s in [VERSION, &pandoc.path, &pandoc.from, note] {
+        hash.text(s);
+    }
+    for list in [pandoc.args.iter().map(|a| &a[..]).collect(), extra] {
+        hash.bytes(&(list.len() as u64).to_le_bytes());
+        for arg in list {
+            hash.text(arg);
+        }
+    }
+    dir(config).join(format!("{:032x}.html", hash.0))
+}
+
+// the note as HTML, from the cache if pandoc has made it before
+pub fn to_html(config: &Config, note: &str) -> Result<String, Error> {
+    let path = path(config, note);
+    if let Ok(html) = fs::read_to_string(&path) {
+        trace!("found {} in the cache", path.display());
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(html);
+    }
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    let html = render::to_html(config, note)?;
+    // a build that can't keep what it made only has to make it again
+    let written = fs::create_dir_all(dir(config)).and_then(|_| fs::write(&path, &html));
+    if let Err(e) = written {
+        Diagnostic::warning(format!("could not cache the HTML in {}", path.display()))
+            .note(e.to_string())
+            .emit();
+    }
+    Ok(html)
+}
+
+// empties the cache, returning how many notes' HTML it had
+pub fn clear(config: &Config) -> io::Result<usize> {
+    let dir = dir(config);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut cleared = 0;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "html") {
+            fs::remove_file(&path)?;
+            cleared += 1;
+        }
+    }
+    Ok(cleared)
+}
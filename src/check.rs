@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::{Duration, SystemTime};
+use bib::Bibliography;
+use config::Config;
+use diagnostics::Diagnostic;
+use markdown;
+use site;
+
+// Checks over the notes as a whole, rather than over single pages as the
+// build does. Each check reports what it finds as warnings and returns how
+// many problems it found.
+
+// the number of words in a shingle
+const SHINGLE: usize = 3;
+
+// shingles found in more notes than this are boilerplate (like a shared
+// template), and say nothing about two notes being copies of each other
+const COMMON: usize = 50;
+
+// the set of overlapping word sequences of a note, hashed
+fn shingles(note: &str) -> HashSet<u64> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let prose = markdown::prose(note).to_lowercase();
+    let words: Vec<&str> = prose.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    words.windows(SHINGLE.min(words.len().max(1)))
+        .map(|w| {
+            let mut hasher = DefaultHasher::new();
+            w.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+// pairs of notes whose text mostly overlaps, as their keys in bibliography
+// order and their Jaccard similarity
+pub fn similar_notes(
+    bib: &Bibliography,
+    notes: &HashMap<String, String>,
+    threshold: f64,
+) -> Vec<(String, String, f64)> {
+    let keys: Vec<&str> = bib.entries.iter()
+        .map(|e| &e.key[..])
+        .filter(|k| notes.contains_key(*k))
+        .collect();
+    let sets: Vec<HashSet<u64>> = keys.iter().map(|k| shingles(&notes[*k])).collect();
+
+    // only compare notes that share a shingle, by counting shared shingles
+    // through an inverted index
+    let mut postings: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, set) in sets.iter().enumerate() {
+        for &s in set {
+            postings.entry(s).or_default().push(i);
+        }
+    }
+    let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+    for notes in postings.values().filter(|n| n.len() <= COMMON) {
+        for (n, &a) in notes.iter().enumerate() {
+            for &b in &notes[n + 1..] {
+                *shared.entry((a, b)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize, f64)> = shared.into_iter()
+        .map(|((a, b), both)| {
+            let union = sets[a].len() + sets[b].len() - both;
+            (a, b, both as f64 / union as f64)
+        })
+        .filter(|&(_, _, similarity)| similarity >= threshold)
+        .collect();
+    pairs.sort_by_key(|&(a, b, _)| (a, b));
+    pairs.into_iter()
+        .map(|(a, b, similarity)| (keys[a].to_owned(), keys[b].to_owned(), similarity))
+        .collect()
+}
+
+pub fn similar(config: &Config, bib: &Bibliography, notes: &HashMap<String, String>) -> usize {
+    let pairs = similar_notes(bib, notes, config.check.similarity);
+    for &(ref a, ref b, similarity) in &pairs {
+        let d = Diagnostic::warning(format!(
+            "the notes on `{}` and `{}` are {:.0}% the same",
+            a,
+            b,
+            similarity * 100.0
+        ));
+        let d = bib.diagnostic_at(d, a, "this entry's note");
+        let d = match bib.get(b) {
+            Some(entry) => entry.label(d, "is very similar to this one's"),
+            None => d,
+        };
+        d.note("one of them may have been copied under the wrong key").emit();
+    }
+    pairs.len()
+}
+
+// titles shorter than this, in words, are too likely to be shared by
+// different papers, like "Introduction"
+const MIN_TITLE_WORDS: usize = 3;
+
+// a title's letters and digits, lowercased, with single spaces between words
+fn normalized_title(title: &str) -> String {
+    title.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// the overlapping three-letter sequences of a normalized title, for telling
+// titles a typo or a word apart
+fn trigrams(title: &str) -> HashSet<String> {
+    let chars: Vec<char> = title.chars().collect();
+    chars.windows(3).map(|w| w.iter().collect()).collect()
+}
+
+// pairs of entries that look like the same paper, as their indices in the
+// bibliography, in order, and why
+fn duplicate_pairs(bib: &Bibliography, threshold: f64) -> Vec<(usize, usize, String)> {
+    let mut pairs = Vec::new();
+    let mut by_doi: HashMap<String, usize> = HashMap::new();
+    for (i, entry) in bib.entries.iter().enumerate() {
+        if let Some(doi) = entry.doi() {
+            match by_doi.get(&doi) {
+                Some(&first) => {
+                    pairs.push((first, i, format!("they have the same DOI, {}", doi)))
+                },
+                None => {
+                    by_doi.insert(doi, i);
+                },
+            }
+        }
+    }
+
+    // only compare titles that are the same or share a trigram few others
+    // have, through an inverted index as in `similar_notes`
+    let titles: Vec<String> = bib.entries.iter().map(|e| normalized_title(&e.title)).collect();
+    let sets: Vec<HashSet<String>> = titles.iter()
+        .map(|t| {
+            if t.split(' ').count() >= MIN_TITLE_WORDS { trigrams(t) } else { HashSet::new() }
+        })
+        .collect();
+    let mut candidates: HashSet<(usize, usize)> = HashSet::new();
+    let mut by_title: HashMap<&str, usize> = HashMap::new();
+    let mut postings: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, set) in sets.iter().enumerate().filter(|(_, set)| !set.is_empty()) {
+        if let Some(&first) = by_title.get(&titles[i][..]) {
+            candidates.insert((first, i));
+        } else {
+            by_title.insert(&titles[i], i);
+        }
+        for trigram in set {
+            postings.entry(trigram).or_default().push(i);
+        }
+    }
+    for entries in postings.values().filter(|e| e.len() <= COMMON) {
+        for (n, &a) in entries.iter().enumerate() {
+            candidates.extend(entries[n + 1..].iter().map(|&b| (a, b)));
+        }
+    }
+    let same_doi: HashSet<(usize, usize)> = pairs.iter().map(|&(a, b, _)| (a, b)).collect();
+    for (a, b) in candidates.into_iter().filter(|pair| !same_doi.contains(pair)) {
+        let both = sets[a].intersection(&sets[b]).count();
+        let similarity = both as f64 / (sets[a].len() + sets[b].len() - both) as f64;
+        if similarity >= threshold {
+            let why = if titles[a] == titles[b] {
+                "they have the same title".to_owned()
+            } else {
+                format!("their titles are {:.0}% the same", similarity * 100.0)
+            };
+            pairs.push((a, b, why));
+        }
+    }
+    pairs.sort_by_key(|&(a, b, _)| (a, b));
+    pairs
+}
+
+// entries that are likely the same paper twice, by their DOIs and titles
+pub fn duplicates(config: &Config, bib: &Bibliography) -> usize {
+    let pairs = duplicate_pairs(bib, config.check.title_similarity);
+    for &(a, b, ref why) in &pairs {
+        let (a, b) = (&bib.entries[a], &bib.entries[b]);
+        let message = format!("`{}` and `{}` look like the same paper", a.key, b.key);
+        let d = Diagnostic::warning(message).in_file(&bib.path, &bib.source);
+        b.label(a.label(d, "this entry"), "looks like a copy of it")
+            .note(why.clone())
+            .note("merge them, or tell them apart if they're different papers")
+            .emit();
+    }
+    pairs.len()
+}
+
+// markers of unfinished notes
+const TODO_MARKERS: &[&str] = &["TODO", "FIXME", "XXX", "TK"];
+
+// the (1-based) lines of a note holding a TODO marker, ignoring code blocks
+pub fn todo_lines(note: &str) -> Vec<usize> {
+    markdown::prose_lines(note).into_iter()
+        .filter(|&(_, line)| {
+            line.split(|c: char| !c.is_alphanumeric()).any(|w| TODO_MARKERS.contains(&w))
+        })
+        .map(|(n, _)| n)
+        .collect()
+}
+
+// notes that could do with some work: thin ones, ones without headings,
+// ones with TODO markers and ones that haven't been touched in a long time.
+// They're reported with the ones with the most problems first.
+pub fn quality(config: &Config, bib: &Bibliography, notes: &HashMap<String, String>) -> usize {
+    let stale = Duration::from_secs(config.check.stale_days * 24 * 60 * 60);
+    let mut reports = Vec::new();
+    for entry in &bib.entries {
+        let note = match notes.get(&entry.key) {
+            Some(note) => note,
+            None => continue,
+        };
+        let path = site::note_path(config, &entry.key);
+        let mut issues = Vec::new();
+        let words = markdown::prose(note).split_whitespace().count();
+        if words < config.check.min_words {
+            issues.push(format!("only {} words long", words));
+        }
+        let todos = todo_lines(note);
+        if markdown::headings(note).is_empty() {
+            issues.push("has no headings".to_owned());
+        }
+        if !todos.is_empty() {
+            issues.push(format!("has {} TODO markers", todos.len()));
+        }
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let age = modified.and_then(|m| SystemTime::now().duration_since(m).ok());
+        if let Some(age) = age.filter(|&age| age > stale) {
+            let days = age.as_secs() / (24 * 60 * 60);
+            issues.push(format!("last changed {} days ago", days));
+        }
+        if !issues.is_empty() {
+            reports.push((entry, path, note, issues, todos));
+        }
+    }
+
+    // a stable sort, so ties stay in bibliography order
+    reports.sort_by_key(|r| ::std::cmp::Reverse(r.3.len()));
+    for &(entry, ref path, note, ref issues, ref todos) in &reports {
+        let mut d = Diagnostic::warning(format!("the notes on `{}` need work", entry.key))
+            .in_file(&path.display().to_string(), note);
+        for &line in todos {
+            d = d.label(line, 1, 0, "unfinished");
+        }
+        for issue in issues {
+            d = d.note(format!("this note is {}", issue));
+        }
+        d.emit();
+    }
+    reports.len()
+}
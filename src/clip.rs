@@ -0,0 +1,142 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use bib_parser;
+use bib;
+use config::Config;
+use diagnostics::Diagnostic;
+use provenance::{self, Source};
+use site;
+
+// Watches the clipboard for BibTeX copied from somewhere like Google
+// Scholar, adds each new entry to the bibliography and starts a note for it,
+// marked as to be read.
+
+// how often the clipboard is looked at
+const POLL: Duration = Duration::from_secs(1);
+
+// what new notes start as
+const SCAFFOLD: &str = "---\nstatus: to-read\n---\n\n";
+
+// the command that prints the clipboard: the configured one, or the usual
+// one for the platform
+fn paste_command(config: &Config) -> Vec<String> {
+    if let Some(ref command) = config.clip.command {
+        return command.clone();
+    }
+    let command: &[&str] = if cfg!(target_os = "macos") {
+        &["pbpaste"]
+    } else if cfg!(windows) {
+        &["powershell", "-NoProfile", "-Command", "Get-Clipboard"]
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        &["wl-paste", "--no-newline"]
+    } else {
+        &["xclip", "-out", "-selection", "clipboard"]
+    };
+    command.iter().map(|s| s.to_string()).collect()
+}
+
+fn paste(command: &[String]) -> io::Result<String> {
+    let output = Command::new(&command[0]).args(&command[1..]).output()?;
+    if !output.status.success() {
+        // an empty clipboard makes some of these fail
+        return Ok(String::new());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// validates a snippet and adds its entries, unless any of them is already
+// in the bibliography
+fn add(config: &Config, snippet: &str) {
+    let entries = match bib_parser::parse_bib(snippet.as_bytes()) {
+        Ok(entries) => entries,
+        Err(e) => {
+            Diagnostic::warning("ignoring BibTeX on the clipboard that doesn't parse")
+                .note(format!("parser error: {:?}", e))
+                .emit();
+            return;
+        },
+    };
+    let keys: Vec<String> = entries.into_iter()
+        .filter(|(_, entry)| entry.is_some())
+        .map(|(key, _)| key)
+        .collect();
+    if keys.is_empty() {
+        return;
+    }
+    if Path::new(&config.bib_path).exists() {
+        let bib = bib::read_bib(&config.bib_path);
+        if let Some(key) = keys.iter().find(|k| bib.get(k).is_some()) {
+            Diagnostic::warning(format!("`{}` is already in the bibliography", key))
+                .note("nothing was added")
+                .emit();
+            return;
+        }
+    }
+
+    let appended = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.bib_path)
+        .and_then(|mut f| write!(f, "\n{}\n", snippet.trim()));
+    if let Err(e) = appended {
+        Diagnostic::error(format!("could not add to {}", config.bib_path))
+            .note(e.to_string())
+            .emit();
+        ::std::process::exit(1);
+    }
+    if let Err(e) = provenance::record(config, &keys, Source::Clipboard, None) {
+        Diagnostic::warning("could not note where the new entries came from")
+            .note(e.to_string())
+            .emit();
+    }
+    for key in &keys {
+        let path = site::note_path(config, key);
+        if path.exists() {
+            println!("added `{}`, which already has a note", key);
+            continue;
+        }
+        let created = fs::create_dir_all(&config.markdown_dir)
+            .and_then(|_| fs::write(&path, SCAFFOLD));
+        match created {
+            Ok(()) => println!("added `{}` and started {}", key, path.display()),
+            Err(e) => {
+                Diagnostic::warning(format!("added `{}` but could not start its note", key))
+                    .note(e.to_string())
+                    .emit();
+            },
+        }
+    }
+}
+
+// runs until interrupted. Whatever is on the clipboard when it starts is
+// left alone; only what's copied afterwards is added.
+pub fn watch(config: &Config) {
+    let command = paste_command(config);
+    let mut last = paste(&command).unwrap_or_else(|e| {
+        Diagnostic::error(format!("could not read the clipboard with `{}`", command[0]))
+            .note(e.to_string())
+            .note("set `command` under `[clip]` to the command that prints the clipboard")
+            .emit();
+        ::std::process::exit(1);
+    });
+    println!("watching the clipboard for BibTeX; press Ctrl-C to stop");
+    loop {
+        thread::sleep(POLL);
+        let text = match paste(&command) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        if text == last {
+            continue;
+        }
+        if text.trim_start().starts_with('@') {
+            add(config, &text);
+        }
+        last = text;
+    }
+}
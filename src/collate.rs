@@ -0,0 +1,161 @@
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
+
+// A lightweight take on the Unicode Collation Algorithm, good enough for
+// sorting names and titles: strings are compared by their base letters first
+// (so "Émile" sorts with the Es), then by accents, then by case. Locales can
+// tailor letters that their alphabets treat as separate, like Swedish "å",
+// which sorts after "z" rather than with "a".
+
+// letters sorted right after another letter (or after `z`), per locale
+fn tailoring(locale: &str) -> &'static [(char, char)] {
+    let language = locale.split(&['-', '_'][..]).next().unwrap_or("");
+    match language {
+        "sv" | "fi" => &[('å', 'z'), ('ä', 'z'), ('æ', 'z'), ('ö', 'z'), ('ø', 'z')],
+        "da" | "nb" | "nn" | "no" => &[('æ', 'z'), ('ø', 'z'), ('å', 'z')],
+        "es" => &[('ñ', 'n')],
+        "pl" => &[
+            ('ą', 'a'), ('ć', 'c'), ('ę', 'e'), ('ł', 'l'),
+            ('ń', 'n'), ('ó', 'o'), ('ś', 's'), ('ź', 'z'), ('ż', 'z'),
+        ],
+        "cs" | "sk" => &[('č', 'c'), ('ř', 'r'), ('š', 's'), ('ž', 'z')],
+        "tr" => &[('ç', 'c'), ('ğ', 'g'), ('ı', 'h'), ('ö', 'o'), ('ş', 's'), ('ü', 'u')],
+        _ => &[],
+    }
+}
+
+// leading articles ignored when sorting titles: English ones, plus those of
+// the locale's language. Articles ending in an apostrophe are elided, as in
+// French "l'", and attach to the next word.
+pub fn articles(locale: &str) -> Vec<String> {
+    let language = locale.split(&['-', '_'][..]).next().unwrap_or("");
+    let local: &[&str] = match language {
+        "de" => &["der", "die", "das", "ein", "eine"],
+        "fr" => &["le", "la", "les", "l'", "l’", "un", "une"],
+        "es" => &["el", "la", "los", "las", "un", "una"],
+        "it" => &["il", "lo", "la", "i", "gli", "le", "l'", "l’", "un", "una", "uno"],
+        "pt" => &["o", "a", "os", "as", "um", "uma"],
+        "nl" => &["de", "het", "een"],
+        "sv" | "da" | "nb" | "nn" | "no" => &["en", "ett", "et"],
+        _ => &[],
+    };
+    ["a", "an", "the"].iter().chain(local).map(|a| a.to_string()).collect()
+}
+
+// `title` without its leading article, if it has one
+pub fn strip_article<'a>(title: &'a str, articles: &[String]) -> &'a str {
+    // BibTeX titles often start with a brace, as in "{The} {Art} of..."
+    let start = title.len() - title.trim_start_matches(['{', '"', ' ']).len();
+    let rest = &title[start..];
+    for article in articles {
+        let len = article.len();
+        if rest.len() <= len || !rest.is_char_boundary(len)
+            || rest[..len].to_lowercase() != article.to_lowercase()
+        {
+            continue;
+        }
+        let after = &rest[len..];
+        let elided = article.ends_with(['\'', '’']);
+        if elided || after.starts_with([' ', '}', '~']) {
+            let next = after.trim_start_matches([' ', '}', '~']);
+            if !next.is_empty() {
+                return next;
+            }
+        }
+    }
+    title
+}
+
+// letters with no decomposition that should still sort as plain Latin
+fn expansion(c: char) -> Option<&'static str> {
+    match c {
+        'ß' => Some("ss"),
+        'æ' => Some("ae"),
+        'œ' => Some("oe"),
+        'ø' => Some("o"),
+        'ł' => Some("l"),
+        'đ' | 'ð' => Some("d"),
+        'þ' => Some("th"),
+        'ı' => Some("i"),
+        _ => None,
+    }
+}
+
+// `s` in plain ASCII as far as it can be: accents are dropped and letters
+// like "ß" spelled out, and anything else non-ASCII is left out
+pub fn to_ascii(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        if let Some(expanded) = expansion(lower) {
+            if c == lower {
+                out.push_str(expanded);
+            } else {
+                out.push_str(&expanded.to_uppercase());
+            }
+            continue;
+        }
+        decompose_canonical(c, |d| {
+            if d.is_ascii() {
+                out.push(d);
+            }
+        });
+    }
+    out
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub struct SortKey {
+    primary: Vec<u32>,
+    secondary: Vec<u32>,
+    tertiary: Vec<bool>,
+    original: String,
+}
+
+fn base_weight(c: char) -> u32 {
+    // leave room for up to three tailored letters after each base letter
+    (c as u32) * 4
+}
+
+pub fn sort_key(s: &str, locale: &str) -> SortKey {
+    let tailored = tailoring(locale);
+    let mut key = SortKey {
+        primary: Vec::new(),
+        secondary: Vec::new(),
+        tertiary: Vec::new(),
+        original: s.to_owned(),
+    };
+    for c in s.nfc() {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        key.tertiary.push(c != lower);
+        if let Some(n) = tailored.iter().position(|&(t, _)| t == lower) {
+            let after = tailored[n].1;
+            // letters tailored after the same base keep their listed order
+            let rank = tailored[..n].iter().filter(|&&(_, a)| a == after).count();
+            key.primary.push(base_weight(after) + 1 + rank as u32);
+            key.secondary.push(0);
+            continue;
+        }
+        if let Some(expanded) = expansion(lower) {
+            for e in expanded.chars() {
+                key.primary.push(base_weight(e));
+            }
+            key.secondary.push(lower as u32);
+            continue;
+        }
+        let mut accent = 0;
+        let primary = &mut key.primary;
+        decompose_canonical(lower, |d| {
+            if is_combining_mark(d) {
+                accent = accent * 0x400 + d as u32;
+            } else if d.is_alphanumeric() {
+                primary.push(base_weight(d));
+            } else if d.is_whitespace() {
+                primary.push(1);
+            }
+            // other punctuation is ignored, as in "shifted" UCA weighting
+        });
+        key.secondary.push(accent);
+    }
+    key
+}
@@ -0,0 +1,1269 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml;
+use diagnostics::Diagnostic;
+use filter::Filter;
+use text::Text;
+
+// which markdown converter to use
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Renderer {
+    #[default]
+    Pandoc,
+    Builtin,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Renderer, String> {
+        match s {
+            "pandoc" => Ok(Renderer::Pandoc),
+            "builtin" => Ok(Renderer::Builtin),
+            _ => Err(format!("unknown renderer `{}` (expected pandoc or builtin)", s)),
+        }
+    }
+}
+
+// the order of entries in the index
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum IndexSort {
+    // the order of the bibliography file
+    #[default]
+    Bib,
+    Author,
+    Title,
+    Year,
+    // most cited first
+    Citations,
+}
+
+impl FromStr for IndexSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<IndexSort, String> {
+        match s {
+            "bib" => Ok(IndexSort::Bib),
+            "author" => Ok(IndexSort::Author),
+            "title" => Ok(IndexSort::Title),
+            "year" => Ok(IndexSort::Year),
+            "citations" => Ok(IndexSort::Citations),
+            _ => Err(format!(
+                "unknown sort order `{}` (expected bib, author, title, year or citations)",
+                s
+            )),
+        }
+    }
+}
+
+// how TeX math in notes is displayed
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum MathEngine {
+    #[default]
+    MathJax,
+    Katex,
+    None,
+}
+
+impl FromStr for MathEngine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MathEngine, String> {
+        match s {
+            "mathjax" => Ok(MathEngine::MathJax),
+            "katex" => Ok(MathEngine::Katex),
+            "none" => Ok(MathEngine::None),
+            _ => Err(format!(
+                "unknown math engine `{}` (expected mathjax, katex or none)",
+                s
+            )),
+        }
+    }
+}
+
+// how the pages of entries are named
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum UrlStyle {
+    // `<key>.html`
+    #[default]
+    Key,
+    // `<author>-<year>-<title>.html`
+    Slug,
+    // `<key>/index.html`, so the URLs have no extension
+    Pretty,
+}
+
+impl FromStr for UrlStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<UrlStyle, String> {
+        match s {
+            "key" => Ok(UrlStyle::Key),
+            "slug" => Ok(UrlStyle::Slug),
+            "pretty" => Ok(UrlStyle::Pretty),
+            _ => Err(format!("unknown URL style `{}` (expected key, slug or pretty)", s)),
+        }
+    }
+}
+
+// which site to build from the notes
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Profile {
+    // without the notes marked `audience: internal`
+    Public,
+    // everything
+    #[default]
+    Internal,
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Profile, String> {
+        match s {
+            "public" => Ok(Profile::Public),
+            "internal" => Ok(Profile::Internal),
+            _ => Err(format!("unknown profile `{}` (expected public or internal)", s)),
+        }
+    }
+}
+
+// what to write about how much of the bibliography has notes
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum CoverageOutput {
+    #[default]
+    None,
+    // coverage.json
+    Json,
+    // coverage.json and a heatmap in coverage.html
+    Page,
+}
+
+impl FromStr for CoverageOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CoverageOutput, String> {
+        match s {
+            "none" => Ok(CoverageOutput::None),
+            "json" => Ok(CoverageOutput::Json),
+            "page" => Ok(CoverageOutput::Page),
+            _ => Err(format!(
+                "unknown coverage output `{}` (expected none, json or page)",
+                s
+            )),
+        }
+    }
+}
+
+pub struct MathConfig {
+    pub engine: MathEngine,
+    // render math to HTML at build time with the KaTeX command-line tool,
+    // so pages need no client-side JavaScript
+    pub prerender: bool,
+    pub katex_path: String,
+}
+
+impl Default for MathConfig {
+    fn default() -> MathConfig {
+        MathConfig {
+            engine: MathEngine::default(),
+            prerender: false,
+            katex_path: "katex".to_owned(),
+        }
+    }
+}
+
+pub struct PandocConfig {
+    // the pandoc binary to run
+    pub path: String,
+    // input format, including any extensions (e.g. `markdown+smart`)
+    pub from: String,
+    // extra arguments, passed verbatim after the ones we need
+    pub args: Vec<String>,
+    // the pandoc input format of notes in other formats than markdown, by
+    // their extension
+    pub formats: BTreeMap<String, String>,
+    // what makes all.pdf, like `xelatex` or `weasyprint`, if not pandoc's
+    // default
+    pub pdf_engine: Option<String>,
+    // keep what pandoc makes of each note, to not run it again on the same
+    pub cache: bool,
+    // where; by default `.biblionotes/cache/` next to the bibliography
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for PandocConfig {
+    fn default() -> PandocConfig {
+        PandocConfig {
+            path: "pandoc".to_owned(),
+            from: "markdown".to_owned(),
+            args: Vec::new(),
+            formats: [("org", "org"), ("rst", "rst")].iter()
+                .map(|&(ext, format)| (ext.to_owned(), format.to_owned()))
+                .collect(),
+            pdf_engine: None,
+            cache: true,
+            cache_dir: None,
+        }
+    }
+}
+
+// where and how `enrich` looks up metadata
+pub struct EnrichConfig {
+    // the cache file; by default `.biblionotes/enrich.json` next to the
+    // bibliography
+    pub cache: Option<PathBuf>,
+    pub curl: String,
+    // sent to Crossref so they can get in touch about misbehaving clients,
+    // which gets better service
+    pub mailto: Option<String>,
+    // look up every entry's citation count, and again after this many days
+    pub citations: bool,
+    pub refresh_days: u64,
+}
+
+impl Default for EnrichConfig {
+    fn default() -> EnrichConfig {
+        EnrichConfig {
+            cache: None,
+            curl: "curl".to_owned(),
+            mailto: None,
+            citations: false,
+            refresh_days: 30,
+        }
+    }
+}
+
+// settings for the `check` command
+pub struct CheckConfig {
+    // how much of their text two notes must share to be reported by
+    // `--similar`, between 0 and 1
+    pub similarity: f64,
+    // notes shorter than this are reported by `--quality` as thin
+    pub min_words: usize,
+    // and ones not changed in this many days as stale
+    pub stale_days: u64,
+    // how alike two entries' titles must be for `--duplicates` to take them
+    // for the same paper, between 0 and 1
+    pub title_similarity: f64,
+}
+
+impl Default for CheckConfig {
+    fn default() -> CheckConfig {
+        CheckConfig {
+            similarity: 0.8,
+            min_words: 150,
+            stale_days: 365,
+            title_similarity: 0.8,
+        }
+    }
+}
+
+// what a field of a note's front matter must hold, for `lint`
+pub enum FieldKind {
+    Text,
+    Number,
+    Boolean,
+    // `YYYY-MM-DD`
+    Date,
+    OneOf(Vec<String>),
+}
+
+impl FromStr for FieldKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FieldKind, String> {
+        match s {
+            "text" => Ok(FieldKind::Text),
+            "number" => Ok(FieldKind::Number),
+            "boolean" => Ok(FieldKind::Boolean),
+            "date" => Ok(FieldKind::Date),
+            _ => Err(format!(
+                "unknown field type `{}` (expected text, number, boolean, date or a list)",
+                s
+            )),
+        }
+    }
+}
+
+// the rules the `lint` command holds every note to
+#[derive(Default)]
+pub struct LintConfig {
+    // headings every note must have, like `Summary`
+    pub sections: Vec<String>,
+    pub min_words: usize,
+    pub allow_todo: bool,
+    // front matter fields every note must have
+    pub required_fields: Vec<String>,
+    // what front matter fields must hold, when they're there
+    pub fields: BTreeMap<String, FieldKind>,
+}
+
+// settings for the `check-links` command
+pub struct LinksConfig {
+    // how many external links are checked at once
+    pub jobs: usize,
+    // how long to wait for each, in seconds
+    pub timeout: u64,
+}
+
+impl Default for LinksConfig {
+    fn default() -> LinksConfig {
+        LinksConfig {
+            jobs: 8,
+            timeout: 10,
+        }
+    }
+}
+
+// how links to other sites are marked on every page
+pub struct ExternalLinksConfig {
+    // open them in a new tab
+    pub new_tab: bool,
+    // the class they get, for an icon; none if empty
+    pub class: String,
+    // domains whose links, and their subdomains', are left alone
+    pub exceptions: Vec<String>,
+}
+
+impl Default for ExternalLinksConfig {
+    fn default() -> ExternalLinksConfig {
+        ExternalLinksConfig {
+            new_tab: false,
+            class: "external".to_owned(),
+            exceptions: Vec::new(),
+        }
+    }
+}
+
+// which side wins when an entry changed both locally and in Zotero
+#[derive(Clone, Copy, PartialEq)]
+pub enum Prefer {
+    Local,
+    Zotero,
+}
+
+impl FromStr for Prefer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Prefer, String> {
+        match s {
+            "local" => Ok(Prefer::Local),
+            "zotero" => Ok(Prefer::Zotero),
+            _ => Err(format!("unknown side `{}` (expected local or zotero)", s)),
+        }
+    }
+}
+
+// settings for the `sync` command
+#[derive(Default)]
+pub struct ZoteroConfig {
+    // the ID of the group library
+    pub group: Option<String>,
+    // needed for private groups; `ZOTERO_API_KEY` works too
+    pub api_key: Option<String>,
+    // how to settle conflicts; they're only reported if unset
+    pub prefer: Option<Prefer>,
+}
+
+// how encrypted notes are decrypted
+pub struct EncryptionConfig {
+    // the age (or rage) binary to run
+    pub age: String,
+    // the identity file to decrypt with
+    pub identity: Option<PathBuf>,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> EncryptionConfig {
+        EncryptionConfig {
+            age: "age".to_owned(),
+            identity: None,
+        }
+    }
+}
+
+// where issues are opened
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tracker {
+    GitHub,
+    GitLab,
+}
+
+impl Tracker {
+    // the environment variable the token can be in
+    pub fn token_var(self) -> &'static str {
+        match self {
+            Tracker::GitHub => "GITHUB_TOKEN",
+            Tracker::GitLab => "GITLAB_TOKEN",
+        }
+    }
+}
+
+// a repository written like `github:owner/name` or `gitlab:group/project`
+pub fn parse_repo(s: &str) -> Result<(Tracker, String), String> {
+    let (tracker, path) = match s.split_once(':') {
+        Some(("github", path)) => (Tracker::GitHub, path),
+        Some(("gitlab", path)) => (Tracker::GitLab, path),
+        _ => return Err(format!(
+            "unknown repository `{}` (expected github:owner/name or gitlab:group/project)",
+            s
+        )),
+    };
+    if !path.contains('/') {
+        return Err(format!("repository `{}` needs an owner and a name", s));
+    }
+    Ok((tracker, path.to_owned()))
+}
+
+// settings for the `issues` command
+#[derive(Default)]
+pub struct IssuesConfig {
+    pub repo: Option<(Tracker, String)>,
+    // the API to use, for self-hosted GitLab or GitHub Enterprise
+    pub api: Option<String>,
+    // `GITHUB_TOKEN` or `GITLAB_TOKEN` work too
+    pub token: Option<String>,
+    pub labels: Vec<String>,
+}
+
+impl IssuesConfig {
+    pub fn api(&self) -> String {
+        match (&self.api, &self.repo) {
+            (Some(api), _) => api.trim_end_matches('/').to_owned(),
+            (None, Some((Tracker::GitLab, _))) => "https://gitlab.com/api/v4".to_owned(),
+            (None, _) => "https://api.github.com".to_owned(),
+        }
+    }
+}
+
+// settings for the `clip` command
+#[derive(Default)]
+pub struct ClipConfig {
+    // the command that prints the clipboard, with its arguments; a
+    // platform default if unset
+    pub command: Option<Vec<String>>,
+}
+
+// commands each note goes through, with their arguments
+#[derive(Default)]
+pub struct HooksConfig {
+    // on the Markdown, before it's converted
+    pub before: Option<Vec<String>>,
+    // on the HTML it's converted to
+    pub after: Option<Vec<String>>,
+}
+
+// who hears about each build, and when
+#[derive(Default)]
+pub struct NotifyConfig {
+    // a desktop notification
+    pub desktop: bool,
+    // a URL to post a message to, like a Slack or Matrix incoming webhook
+    pub webhook: Option<String>,
+    // only for builds that went wrong
+    pub failures_only: bool,
+}
+
+// the templates kinds of pages have of their own, each of which can extend
+// the base layout; pages without one use the template given on the command
+// line
+#[derive(Default)]
+pub struct TemplatesConfig {
+    // registered as the partial `base`, with blocks the others fill in
+    pub base: Option<PathBuf>,
+    // note pages
+    pub page: Option<PathBuf>,
+    // the index and the latest notes
+    pub index: Option<PathBuf>,
+    // the contributors and each contributor's page
+    pub author: Option<PathBuf>,
+}
+
+// the policy citation keys are held to
+pub struct KeysConfig {
+    // like `{author}{year}{title}`; keys can have any shape if unset
+    pub pattern: Option<String>,
+    pub lowercase: bool,
+}
+
+impl Default for KeysConfig {
+    fn default() -> KeysConfig {
+        KeysConfig {
+            pattern: None,
+            lowercase: true,
+        }
+    }
+}
+
+pub struct Config {
+    // the four positional arguments
+    pub bib_path: String,
+    pub template_path: PathBuf,
+    pub markdown_dir: PathBuf,
+    pub output_dir: PathBuf,
+    // whether failures should make the whole build fail
+    pub strict: bool,
+    // make a variable the template uses but a page doesn't have an error,
+    // instead of leaving it empty
+    pub strict_templates: bool,
+    // report what would be written instead of writing it
+    pub dry_run: bool,
+    // remove the files earlier builds wrote that this one doesn't
+    pub prune: bool,
+    pub renderer: Renderer,
+    pub pandoc: PandocConfig,
+    pub math: MathConfig,
+    // syntect theme for code blocks; no highlighting is done if unset
+    pub highlight_theme: Option<String>,
+    pub sort: IndexSort,
+    // locale used to sort names and titles, e.g. `sv` or `de-DE`
+    pub collation: String,
+    // leading articles to ignore when sorting titles, if not the defaults
+    // for the collation locale
+    pub articles: Option<Vec<String>>,
+    // show each entry's abstract in the index, collapsed
+    pub index_abstracts: bool,
+    // split the index into pages of this many entries: index.html,
+    // index2.html and so on
+    pub index_page_size: Option<usize>,
+    // how many of the notes that changed last the index template gets
+    pub recent: usize,
+    // and how many of the most cited
+    pub influential: usize,
+    // write latest.html, the notes newest first by when they were first
+    // committed, in pages of this many: latest.html, latest2.html and so on
+    pub latest: Option<usize>,
+    // the longest a page's description gets, in characters
+    pub description_length: usize,
+    // and its excerpt in the index
+    pub excerpt_length: usize,
+    // build drafts too, as if they were finished
+    pub drafts: bool,
+    // the entries to build pages for, when not all of them
+    pub filter: Filter,
+    // and list only those in the index and the other pages, too
+    pub filter_index: bool,
+    // make the same files from the same inputs, with no times taken from the
+    // clock or the file system
+    pub reproducible: bool,
+    // write the content of each page without the template, for other
+    // sites to include
+    pub fragments: bool,
+    // make pages smaller, and put this stylesheet in each of them
+    pub minify: bool,
+    pub critical_css: Option<PathBuf>,
+    // write todo.html, gathering the TODO and FIXME markers in notes
+    pub todo_page: bool,
+    // write anniversaries.ics, with the days the papers came out
+    pub anniversaries: bool,
+    // write graph.json and graph.html, with which papers cite which
+    pub graph: bool,
+    // write all.html, every note on one page, and all.pdf from it
+    pub all: bool,
+    pub all_pdf: bool,
+    pub profile: Profile,
+    // find out who wrote each note, and write contributors.html
+    pub contributors: bool,
+    // write a page for each venue, under venue/
+    pub venue_pages: bool,
+    // how many related entries each page gets
+    pub related: usize,
+    pub coverage: CoverageOutput,
+    // write stats.json and stats.html, with what the bibliography has most of
+    pub stats: bool,
+    pub url_style: UrlStyle,
+    // where the site is published, like `https://example.org/notes/`;
+    // sitemap.xml and robots.txt are only written if it's set
+    pub base_url: Option<String>,
+    // where to write everything the build knows, as JSON
+    pub emit_json: Option<PathBuf>,
+    // where to write how the build went, as JSON
+    pub report: Option<PathBuf>,
+    // the reading group's schedule, if not `schedule.toml` next to the
+    // bibliography
+    pub schedule: Option<PathBuf>,
+    // a folder of the papers as `<key>.pdf`, to put on the site
+    pub pdf_dir: Option<PathBuf>,
+    pub enrich: EnrichConfig,
+    pub check: CheckConfig,
+    pub lint: LintConfig,
+    pub links: LinksConfig,
+    pub external_links: ExternalLinksConfig,
+    pub keys: KeysConfig,
+    pub clip: ClipConfig,
+    pub zotero: ZoteroConfig,
+    pub issues: IssuesConfig,
+    pub encryption: EncryptionConfig,
+    pub notify: NotifyConfig,
+    pub hooks: HooksConfig,
+    pub templates: TemplatesConfig,
+    // a tier or label for each venue, like its CORE rank, by its name
+    pub venue_ranks: BTreeMap<String, String>,
+    // the name to use for each spelling of a venue
+    pub venue_aliases: BTreeMap<String, String>,
+    // the site's language, for templates' `lang` attributes
+    pub lang: Option<String>,
+    pub text: Text,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bib_path: String::new(),
+            template_path: PathBuf::new(),
+            markdown_dir: PathBuf::new(),
+            output_dir: PathBuf::new(),
+            strict: false,
+            strict_templates: false,
+            dry_run: false,
+            prune: false,
+            renderer: Renderer::default(),
+            pandoc: PandocConfig::default(),
+            math: MathConfig::default(),
+            highlight_theme: None,
+            sort: IndexSort::default(),
+            collation: "root".to_owned(),
+            articles: None,
+            index_abstracts: false,
+            index_page_size: None,
+            recent: 10,
+            influential: 10,
+            latest: None,
+            description_length: 200,
+            excerpt_length: 400,
+            reproducible: false,
+            drafts: false,
+            filter: Filter::default(),
+            filter_index: false,
+            fragments: false,
+            minify: false,
+            critical_css: None,
+            todo_page: false,
+            anniversaries: false,
+            graph: false,
+            all: false,
+            all_pdf: false,
+            related: 5,
+            contributors: false,
+            venue_pages: false,
+            profile: Profile::default(),
+            coverage: CoverageOutput::default(),
+            stats: false,
+            url_style: UrlStyle::default(),
+            base_url: None,
+            emit_json: None,
+            report: None,
+            schedule: None,
+            pdf_dir: None,
+            enrich: EnrichConfig::default(),
+            check: CheckConfig::default(),
+            lint: LintConfig::default(),
+            links: LinksConfig::default(),
+            external_links: ExternalLinksConfig::default(),
+            keys: KeysConfig::default(),
+            clip: ClipConfig::default(),
+            zotero: ZoteroConfig::default(),
+            issues: IssuesConfig::default(),
+            encryption: EncryptionConfig::default(),
+            notify: NotifyConfig::default(),
+            hooks: HooksConfig::default(),
+            templates: TemplatesConfig::default(),
+            venue_ranks: BTreeMap::new(),
+            venue_aliases: BTreeMap::new(),
+            lang: None,
+            text: Text::default(),
+        }
+    }
+}
+
+// the dotted name of a setting, for error messages
+fn setting(table: &str, key: &str) -> String {
+    if table.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", table, key)
+    }
+}
+
+fn get_str(table: &toml::Table, name: &str, key: &str) -> Option<String> {
+    table.get(key).map(|v| match v.as_str() {
+        Some(s) => s.to_owned(),
+        None => panic!("Config: `{}` must be a string", setting(name, key)),
+    })
+}
+
+fn get_strs(table: &toml::Table, name: &str, key: &str) -> Option<Vec<String>> {
+    table.get(key).map(|v| {
+        let strs = v.as_slice().and_then(|vs| {
+            vs.iter().map(|v| v.as_str().map(|s| s.to_owned())).collect()
+        });
+        match strs {
+            Some(strs) => strs,
+            None => panic!("Config: `{}` must be a list of strings", setting(name, key)),
+        }
+    })
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Config {
+        let mut file = File::open(path).expect("Could not open config file");
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .expect("Could not read config file");
+        let mut parser = toml::Parser::new(&contents);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let name = path.display().to_string();
+                for e in &parser.errors {
+                    let (line, col) = parser.to_linecol(e.lo);
+                    let len = (e.hi - e.lo).max(1);
+                    Diagnostic::error("could not parse config file")
+                        .in_file(&name, &contents)
+                        .label(line + 1, col + 1, len, e.desc.clone())
+                        .emit();
+                }
+                ::std::process::exit(1);
+            },
+        };
+
+        let mut config = Config::default();
+        if let Some(renderer) = get_str(&table, "", "renderer") {
+            config.renderer = renderer.parse()
+                .unwrap_or_else(|e| panic!("Config: {}", e));
+        }
+        if let Some(pandoc) = table.get("pandoc") {
+            let pandoc = pandoc.as_table()
+                .expect("Config: `pandoc` must be a table");
+            if let Some(path) = get_str(pandoc, "pandoc", "path") {
+                config.pandoc.path = path;
+            }
+            if let Some(from) = get_str(pandoc, "pandoc", "from") {
+                config.pandoc.from = from;
+            }
+            if let Some(args) = get_strs(pandoc, "pandoc", "args") {
+                config.pandoc.args = args;
+            }
+            if let Some(formats) = pandoc.get("formats") {
+                let formats = formats.as_table()
+                    .expect("Config: `pandoc.formats` must be a table");
+                for (ext, format) in formats {
+                    let format = format.as_str()
+                        .expect("Config: `pandoc.formats` must map extensions to formats");
+                    let ext = ext.trim_start_matches('.').to_owned();
+                    config.pandoc.formats.insert(ext, format.to_owned());
+                }
+            }
+            if let Some(engine) = get_str(pandoc, "pandoc", "pdf_engine") {
+                config.pandoc.pdf_engine = Some(engine);
+            }
+            if let Some(cache) = pandoc.get("cache") {
+                config.pandoc.cache = cache.as_bool()
+                    .expect("Config: `pandoc.cache` must be a boolean");
+            }
+            config.pandoc.cache_dir = get_str(pandoc, "pandoc", "cache_dir").map(PathBuf::from);
+        }
+        if let Some(collation) = get_str(&table, "", "collation") {
+            config.collation = collation;
+        }
+        if let Some(style) = get_str(&table, "", "url_style") {
+            config.url_style = style.parse().unwrap_or_else(|e| panic!("Config: {}", e));
+        }
+        if let Some(base_url) = get_str(&table, "", "base_url") {
+            config.base_url = Some(base_url);
+        }
+        if let Some(schedule) = get_str(&table, "", "schedule") {
+            config.schedule = Some(PathBuf::from(schedule));
+        }
+        if let Some(dir) = get_str(&table, "", "pdf_dir") {
+            config.pdf_dir = Some(PathBuf::from(dir));
+        }
+        if let Some(profile) = get_str(&table, "", "profile") {
+            config.profile = profile.parse().unwrap_or_else(|e| panic!("Config: {}", e));
+        }
+        if let Some(coverage) = get_str(&table, "", "coverage") {
+            config.coverage = coverage.parse().unwrap_or_else(|e| panic!("Config: {}", e));
+        }
+        if let Some(length) = table.get("description_length") {
+            config.description_length = length.as_integer()
+                .filter(|&n| n > 0)
+                .expect("Config: `description_length` must be a positive integer")
+                as usize;
+        }
+        if let Some(reproducible) = table.get("reproducible") {
+            config.reproducible = reproducible.as_bool()
+                .expect("Config: `reproducible` must be a boolean");
+        }
+        if let Some(stats) = table.get("stats") {
+            config.stats = stats.as_bool().expect("Config: `stats` must be a boolean");
+        }
+        if let Some(strict) = table.get("strict_templates") {
+            config.strict_templates = strict.as_bool()
+                .expect("Config: `strict_templates` must be a boolean");
+        }
+        if let Some(fragments) = table.get("fragments") {
+            config.fragments = fragments.as_bool()
+                .expect("Config: `fragments` must be a boolean");
+        }
+        if let Some(todo) = table.get("todo") {
+            config.todo_page = todo.as_bool().expect("Config: `todo` must be a boolean");
+        }
+        if let Some(anniversaries) = table.get("anniversaries") {
+            config.anniversaries = anniversaries.as_bool()
+                .expect("Config: `anniversaries` must be a boolean");
+        }
+        if let Some(graph) = table.get("graph") {
+            config.graph = graph.as_bool().expect("Config: `graph` must be a boolean");
+        }
+        if let Some(all) = table.get("all") {
+            config.all = all.as_bool().expect("Config: `all` must be a boolean");
+        }
+        if let Some(pdf) = table.get("all_pdf") {
+            config.all_pdf = pdf.as_bool().expect("Config: `all_pdf` must be a boolean");
+        }
+        if let Some(minify) = table.get("minify") {
+            config.minify = minify.as_bool().expect("Config: `minify` must be a boolean");
+        }
+        if let Some(path) = get_str(&table, "", "critical_css") {
+            config.critical_css = Some(PathBuf::from(path));
+        }
+        if let Some(contributors) = table.get("contributors") {
+            config.contributors = contributors.as_bool()
+                .expect("Config: `contributors` must be a boolean");
+        }
+        if let Some(venues) = table.get("venues") {
+            config.venue_pages = venues.as_bool().expect("Config: `venues` must be a boolean");
+        }
+        if let Some(related) = table.get("related") {
+            config.related = related.as_integer()
+                .filter(|&n| n >= 0)
+                .expect("Config: `related` must be a positive integer")
+                as usize;
+        }
+        if let Some(latest) = table.get("latest") {
+            let size = latest.as_integer()
+                .filter(|&n| n > 0)
+                .expect("Config: `latest` must be a positive integer");
+            config.latest = Some(size as usize);
+        }
+        if let Some(index) = table.get("index") {
+            let index = index.as_table().expect("Config: `index` must be a table");
+            if let Some(sort) = get_str(index, "index", "sort") {
+                config.sort = sort.parse().unwrap_or_else(|e| panic!("Config: {}", e));
+            }
+            config.articles = get_strs(index, "index", "articles");
+            if let Some(abstracts) = index.get("abstracts") {
+                config.index_abstracts = abstracts.as_bool()
+                    .expect("Config: `index.abstracts` must be a boolean");
+            }
+            if let Some(size) = index.get("page_size") {
+                let size = size.as_integer()
+                    .filter(|&n| n > 0)
+                    .expect("Config: `index.page_size` must be a positive integer");
+                config.index_page_size = Some(size as usize);
+            }
+            if let Some(recent) = index.get("recent") {
+                config.recent = recent.as_integer()
+                    .filter(|&n| n >= 0)
+                    .expect("Config: `index.recent` must be a positive integer")
+                    as usize;
+            }
+            if let Some(influential) = index.get("influential") {
+                config.influential = influential.as_integer()
+                    .filter(|&n| n >= 0)
+                    .expect("Config: `index.influential` must be a positive integer")
+                    as usize;
+            }
+            if let Some(length) = index.get("excerpt_length") {
+                config.excerpt_length = length.as_integer()
+                    .filter(|&n| n > 0)
+                    .expect("Config: `index.excerpt_length` must be a positive integer")
+                    as usize;
+            }
+        }
+        if let Some(math) = table.get("math") {
+            let math = math.as_table().expect("Config: `math` must be a table");
+            if let Some(engine) = get_str(math, "math", "engine") {
+                config.math.engine = engine.parse()
+                    .unwrap_or_else(|e| panic!("Config: {}", e));
+            }
+            if let Some(prerender) = math.get("prerender") {
+                config.math.prerender = prerender.as_bool()
+                    .expect("Config: `math.prerender` must be a boolean");
+            }
+            if let Some(path) = get_str(math, "math", "katex") {
+                config.math.katex_path = path;
+            }
+        }
+        if let Some(enrich) = table.get("enrich") {
+            let enrich = enrich.as_table().expect("Config: `enrich` must be a table");
+            config.enrich.cache = get_str(enrich, "enrich", "cache").map(PathBuf::from);
+            if let Some(curl) = get_str(enrich, "enrich", "curl") {
+                config.enrich.curl = curl;
+            }
+            config.enrich.mailto = get_str(enrich, "enrich", "mailto");
+            if let Some(citations) = enrich.get("citations") {
+                config.enrich.citations = citations.as_bool()
+                    .expect("Config: `enrich.citations` must be a boolean");
+            }
+            if let Some(days) = enrich.get("refresh_days") {
+                config.enrich.refresh_days = days.as_integer()
+                    .filter(|&n| n >= 0)
+                    .expect("Config: `enrich.refresh_days` must be a positive integer")
+                    as u64;
+            }
+        }
+        if let Some(check) = table.get("check") {
+            let check = check.as_table().expect("Config: `check` must be a table");
+            if let Some(similarity) = check.get("similarity") {
+                config.check.similarity = similarity.as_float()
+                    .filter(|s| (0.0..=1.0).contains(s))
+                    .expect("Config: `check.similarity` must be a number between 0 and 1");
+            }
+            if let Some(similarity) = check.get("title_similarity") {
+                let similarity = similarity.as_float().filter(|s| (0.0..=1.0).contains(s));
+                let message = "Config: `check.title_similarity` must be a number between 0 and 1";
+                config.check.title_similarity = similarity.expect(message);
+            }
+            if let Some(min_words) = check.get("min_words") {
+                config.check.min_words = min_words.as_integer()
+                    .filter(|&n| n >= 0)
+                    .expect("Config: `check.min_words` must be a positive integer")
+                    as usize;
+            }
+            if let Some(stale_days) = check.get("stale_days") {
+                config.check.stale_days = stale_days.as_integer()
+                    .filter(|&n| n >= 0)
+                    .expect("Config: `check.stale_days` must be a positive integer")
+                    as u64;
+            }
+        }
+        if let Some(lint) = table.get("lint") {
+            let lint = lint.as_table().expect("Config: `lint` must be a table");
+            if let Some(sections) = get_strs(lint, "lint", "sections") {
+                config.lint.sections = sections;
+            }
+            if let Some(min_words) = lint.get("min_words") {
+                config.lint.min_words = min_words.as_integer()
+                    .filter(|&n| n >= 0)
+                    .expect("Config: `lint.min_words` must be a positive integer")
+                    as usize;
+            }
+            if let Some(allow_todo) = lint.get("allow_todo") {
+                config.lint.allow_todo = allow_todo.as_bool()
+                    .expect("Config: `lint.allow_todo` must be a boolean");
+            }
+            if let Some(required) = get_strs(lint, "lint", "required_fields") {
+                config.lint.required_fields = required;
+            }
+            if let Some(fields) = lint.get("fields") {
+                let fields = fields.as_table().expect("Config: `lint.fields` must be a table");
+                for (name, kind) in fields {
+                    let kind = match kind.as_str() {
+                        Some(kind) => kind.parse().unwrap_or_else(|e: String| {
+                            panic!("Config: `lint.fields.{}`: {}", name, e)
+                        }),
+                        // a list of the values it can have
+                        None => {
+                            FieldKind::OneOf(get_strs(fields, "lint.fields", name).unwrap())
+                        },
+                    };
+                    config.lint.fields.insert(name.to_lowercase(), kind);
+                }
+            }
+        }
+        if let Some(links) = table.get("links") {
+            let links = links.as_table().expect("Config: `links` must be a table");
+            if let Some(jobs) = links.get("jobs") {
+                config.links.jobs = jobs.as_integer()
+                    .filter(|&n| n > 0)
+                    .expect("Config: `links.jobs` must be a positive integer")
+                    as usize;
+            }
+            if let Some(timeout) = links.get("timeout") {
+                config.links.timeout = timeout.as_integer()
+                    .filter(|&n| n > 0)
+                    .expect("Config: `links.timeout` must be a positive integer")
+                    as u64;
+            }
+        }
+        if let Some(external) = table.get("external_links") {
+            let external = external.as_table()
+                .expect("Config: `external_links` must be a table");
+            if let Some(new_tab) = external.get("new_tab") {
+                config.external_links.new_tab = new_tab.as_bool()
+                    .expect("Config: `external_links.new_tab` must be a boolean");
+            }
+            if let Some(class) = get_str(external, "external_links", "class") {
+                config.external_links.class = class;
+            }
+            if let Some(exceptions) = get_strs(external, "external_links", "exceptions") {
+                config.external_links.exceptions = exceptions;
+            }
+        }
+        if let Some(keys) = table.get("keys") {
+            let keys = keys.as_table().expect("Config: `keys` must be a table");
+            config.keys.pattern = get_str(keys, "keys", "pattern");
+            if let Some(lowercase) = keys.get("lowercase") {
+                config.keys.lowercase = lowercase.as_bool()
+                    .expect("Config: `keys.lowercase` must be a boolean");
+            }
+        }
+        if let Some(clip) = table.get("clip") {
+            let clip = clip.as_table().expect("Config: `clip` must be a table");
+            config.clip.command = get_strs(clip, "clip", "command")
+                .filter(|c| !c.is_empty());
+        }
+        if let Some(zotero) = table.get("zotero") {
+            let zotero = zotero.as_table().expect("Config: `zotero` must be a table");
+            // IDs are numbers, but can be written as strings too
+            config.zotero.group = zotero.get("group").map(|group| {
+                group.as_integer().map(|id| id.to_string())
+                    .or_else(|| group.as_str().map(|id| id.to_owned()))
+                    .expect("Config: `zotero.group` must be a group ID")
+            });
+            config.zotero.api_key = get_str(zotero, "zotero", "api_key");
+        }
+        if let Some(issues) = table.get("issues") {
+            let issues = issues.as_table().expect("Config: `issues` must be a table");
+            if let Some(repo) = get_str(issues, "issues", "repo") {
+                config.issues.repo = Some(parse_repo(&repo)
+                    .unwrap_or_else(|e| panic!("Config: {}", e)));
+            }
+            config.issues.api = get_str(issues, "issues", "api");
+            config.issues.token = get_str(issues, "issues", "token");
+            if let Some(labels) = get_strs(issues, "issues", "labels") {
+                config.issues.labels = labels;
+            }
+        }
+        if let Some(encryption) = table.get("encryption") {
+            let encryption = encryption.as_table()
+                .expect("Config: `encryption` must be a table");
+            if let Some(age) = get_str(encryption, "encryption", "age") {
+                config.encryption.age = age;
+            }
+            config.encryption.identity = get_str(encryption, "encryption", "identity")
+                .map(PathBuf::from);
+        }
+        if let Some(notify) = table.get("notify") {
+            let notify = notify.as_table().expect("Config: `notify` must be a table");
+            if let Some(desktop) = notify.get("desktop") {
+                config.notify.desktop = desktop.as_bool()
+                    .expect("Config: `notify.desktop` must be a boolean");
+            }
+            config.notify.webhook = get_str(notify, "notify", "webhook");
+            if let Some(failures_only) = notify.get("failures_only") {
+                config.notify.failures_only = failures_only.as_bool()
+                    .expect("Config: `notify.failures_only` must be a boolean");
+            }
+        }
+        if let Some(hooks) = table.get("hooks") {
+            let hooks = hooks.as_table().expect("Config: `hooks` must be a table");
+            config.hooks.before = get_strs(hooks, "hooks", "before").filter(|c| !c.is_empty());
+            config.hooks.after = get_strs(hooks, "hooks", "after").filter(|c| !c.is_empty());
+        }
+        if let Some(templates) = table.get("templates") {
+            let templates = templates.as_table()
+                .expect("Config: `templates` must be a table");
+            let path = |key| get_str(templates, "templates", key).map(PathBuf::from);
+            config.templates.base = path("base");
+            config.templates.page = path("page");
+            config.templates.index = path("index");
+            config.templates.author = path("author");
+        }
+        if let Some(ranks) = table.get("venue_ranks") {
+            let ranks = ranks.as_table().expect("Config: `venue_ranks` must be a table");
+            for (venue, label) in ranks {
+                let label = label.as_str()
+                    .expect("Config: `venue_ranks` must map venues to labels");
+                config.venue_ranks.insert(venue.clone(), label.to_owned());
+            }
+        }
+        if let Some(aliases) = table.get("venue_aliases") {
+            let aliases = aliases.as_table()
+                .expect("Config: `venue_aliases` must be a table");
+            for (spelling, venue) in aliases {
+                let venue = venue.as_str()
+                    .expect("Config: `venue_aliases` must map spellings to venues");
+                config.venue_aliases.insert(spelling.clone(), venue.to_owned());
+            }
+        }
+        config.lang = get_str(&table, "", "lang");
+        if let Some(text) = table.get("text") {
+            let text = text.as_table().expect("Config: `text` must be a table");
+            for id in text.keys() {
+                match &id[..] {
+                    "months" => {
+                        let months = get_strs(text, "text", "months").unwrap();
+                        if months.len() != 12 {
+                            panic!("Config: `text.months` must name all 12 months");
+                        }
+                        config.text.months = months;
+                    },
+                    "date_format" => {
+                        config.text.date_format = get_str(text, "text", id).unwrap();
+                    },
+                    _ if Text::is_known(id) => {
+                        config.text.set(id, get_str(text, "text", id).unwrap());
+                    },
+                    _ => panic!("Config: unknown text `{}` under [text]", id),
+                }
+            }
+        }
+        if let Some(highlight) = table.get("highlight") {
+            let highlight = highlight.as_table()
+                .expect("Config: `highlight` must be a table");
+            config.highlight_theme = get_str(highlight, "highlight", "theme");
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use toml;
+    use super::*;
+
+    // a config file, removed again even when loading it panics
+    struct ConfigFile(PathBuf);
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    // loads a config file with these contents, named after the test
+    fn load(name: &str, contents: &str) -> Config {
+        let file = ConfigFile(env::temp_dir()
+            .join(format!("biblionotes-config-{}-{}.toml", name, ::std::process::id())));
+        fs::write(&file.0, contents).expect("Could not write config file");
+        Config::load(&file.0)
+    }
+
+    fn table(contents: &str) -> toml::Table {
+        toml::Parser::new(contents).parse().expect("Could not parse table")
+    }
+
+    #[test]
+    fn reads_strings_and_lists_of_strings() {
+        let table = table("from = \"markdown\"\nargs = [\"--toc\", \"-s\"]\n");
+        assert_eq!(get_str(&table, "pandoc", "from").unwrap(), "markdown");
+        assert_eq!(get_strs(&table, "pandoc", "args").unwrap(), ["--toc", "-s"]);
+        assert!(get_str(&table, "pandoc", "path").is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "`pandoc.args` must be a list of strings")]
+    fn refuses_lists_of_other_things() {
+        get_strs(&table("args = [1, 2]\n"), "pandoc", "args");
+    }
+
+    #[test]
+    fn loads_the_pandoc_settings() {
+        let config = load("pandoc", concat!(
+            "[pandoc]\n",
+            "path = \"/opt/pandoc/bin/pandoc\"\n",
+            "from = \"markdown+smart\"\n",
+            "args = [\"--lua-filter=callouts.lua\", \"--number-sections\"]\n",
+        ));
+        assert_eq!(config.pandoc.path, "/opt/pandoc/bin/pandoc");
+        assert_eq!(config.pandoc.from, "markdown+smart");
+        assert_eq!(config.pandoc.args, ["--lua-filter=callouts.lua", "--number-sections"]);
+    }
+
+    #[test]
+    fn keeps_the_default_pandoc_settings() {
+        let config = load("pandoc-defaults", "");
+        assert_eq!(config.pandoc.path, PandocConfig::default().path);
+        assert_eq!(config.pandoc.from, PandocConfig::default().from);
+        assert!(config.pandoc.args.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Config: `pandoc.path` must be a string")]
+    fn refuses_settings_of_the_wrong_type() {
+        load("wrong-type", "[pandoc]\npath = 1\n");
+    }
+
+    #[test]
+    fn parses_url_styles() {
+        assert!("key".parse::<UrlStyle>() == Ok(UrlStyle::Key));
+        assert!("slug".parse::<UrlStyle>() == Ok(UrlStyle::Slug));
+        assert_eq!(
+            "short".parse::<UrlStyle>().err().unwrap(),
+            "unknown URL style `short` (expected key, slug or pretty)"
+        );
+    }
+
+    #[test]
+    fn loads_the_url_style() {
+        assert!(load("url-style", "url_style = \"pretty\"\n").url_style == UrlStyle::Pretty);
+        assert!(load("url-style-default", "").url_style == UrlStyle::Key);
+    }
+
+    #[test]
+    #[should_panic(expected = "Config: unknown URL style `short`")]
+    fn refuses_unknown_url_styles() {
+        load("url-style-unknown", "url_style = \"short\"\n");
+    }
+
+    #[test]
+    fn parses_repositories() {
+        let (tracker, path) = parse_repo("gitlab:group/sub/project").unwrap();
+        assert!(tracker == Tracker::GitLab);
+        assert_eq!(path, "group/sub/project");
+        assert!(parse_repo("github:owner/name").unwrap().0 == Tracker::GitHub);
+        assert_eq!(
+            parse_repo("github:owner").err().unwrap(),
+            "repository `github:owner` needs an owner and a name"
+        );
+        assert!(parse_repo("owner/name").is_err());
+    }
+
+    #[test]
+    fn loads_the_issues_settings() {
+        let config = load("issues", concat!(
+            "[issues]\n",
+            "repo = \"github:jeanm/biblionotes\"\n",
+            "labels = [\"reading\", \"notes\"]\n",
+        ));
+        let (tracker, path) = config.issues.repo.unwrap();
+        assert!(tracker == Tracker::GitHub);
+        assert_eq!(path, "jeanm/biblionotes");
+        assert_eq!(config.issues.labels, ["reading", "notes"]);
+    }
+
+    #[test]
+    fn loads_texts() {
+        let config = load("text", concat!(
+            "[text]\n",
+            "index_title = \"Lectures\"\n",
+            "date_format = \"%e %B %Y\"\n",
+            "months = [\"janvier\", \"février\", \"mars\", \"avril\", \"mai\", \"juin\", ",
+            "\"juillet\", \"août\", \"septembre\", \"octobre\", \"novembre\", \"décembre\"]\n",
+        ));
+        assert_eq!(config.text.get("index_title"), "Lectures");
+        assert_eq!(config.text.get("and"), Text::default().get("and"));
+        assert_eq!(config.text.date((2024, 8, 1)), "1 août 2024");
+    }
+
+    #[test]
+    #[should_panic(expected = "Config: `text.months` must name all 12 months")]
+    fn refuses_too_few_months() {
+        load("text-months", "[text]\nmonths = [\"janvier\"]\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Config: unknown text `index_heading` under [text]")]
+    fn refuses_unknown_texts() {
+        load("text-unknown", "[text]\nindex_heading = \"Lectures\"\n");
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+use bib::Bibliography;
+use frontmatter;
+use html;
+use text::Text;
+use urls;
+
+// Who wrote each note, for bibliographies annotated by several people. It's
+// whoever the `note_author` field of the note's front matter names (or
+// `author`, which older notes use), or else the people git blames for its
+// lines, most lines first. Notes outside a git repository, or not committed
+// yet, have no known contributors. Everyone gets a page of their own, under
+// `contributors/`, listing the notes they wrote.
+
+// the names in an `author` field, separated by commas or "and"
+fn split(field: &str) -> Vec<String> {
+    field.split(',')
+        .flat_map(|part| part.split(" and "))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_owned())
+        .collect()
+}
+
+// the authors of the committed lines of a file, most lines first
+fn blame(path: &Path) -> Vec<String> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let name = match path.file_name() {
+        Some(name) => name,
+        None => return Vec::new(),
+    };
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["blame", "--line-porcelain", "--"])
+        .arg(name)
+        .output();
+    let output = match output {
+        Ok(ref output) if output.status.success() => String::from_utf8_lossy(&output.stdout),
+        _ => return Vec::new(),
+    };
+    let mut lines: HashMap<String, usize> = HashMap::new();
+    for author in output.lines().filter_map(|l| l.strip_prefix("author ")) {
+        // what git calls lines changed since the last commit
+        if author != "Not Committed Yet" {
+            *lines.entry(author.to_owned()).or_insert(0) += 1;
+        }
+    }
+    let mut authors: Vec<(String, usize)> = lines.into_iter().collect();
+    authors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    authors.into_iter().map(|(author, _)| author).collect()
+}
+
+// who wrote the note at `path`
+pub fn of(path: &Path, note: &str) -> Vec<String> {
+    let fields = frontmatter::fields(note);
+    match fields.get("note_author").or_else(|| fields.get("author")) {
+        Some(field) => split(field),
+        None => blame(path),
+    }
+}
+
+// the URL of someone's page
+pub fn url(name: &str) -> String {
+    match urls::slugify(name) {
+        ref slug if slug.is_empty() => "contributors/anonymous.html".to_owned(),
+        slug => format!("contributors/{}.html", slug),
+    }
+}
+
+// everyone with the keys of the notes they contributed to, in bibliography
+// order, with the most notes first
+pub fn people<'a>(
+    bib: &'a Bibliography,
+    contributors: &'a HashMap<String, Vec<String>>,
+    pages: &HashMap<String, String>,
+) -> Vec<(&'a str, Vec<&'a str>)> {
+    let mut notes: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for entry in bib.entries.iter().filter(|e| pages.contains_key(&e.key)) {
+        for name in contributors.get(&entry.key).into_iter().flatten() {
+            notes.entry(name).or_default().push(&entry.key);
+        }
+    }
+    let mut people: Vec<(&str, Vec<&str>)> = notes.into_iter().collect();
+    people.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(b.0)));
+    people
+}
+
+pub fn count(keys: &[&str], text: &Text) -> String {
+    match keys.len() {
+        1 => text.get("one_note").to_owned(),
+        n => text.get("notes_count").replace("{n}", &n.to_string()),
+    }
+}
+
+// a list of notes, linked relative to `root`
+pub fn notes_list(
+    content: &mut String,
+    bib: &Bibliography,
+    keys: &[&str],
+    pages: &HashMap<String, String>,
+    root: &str,
+) {
+    content.push_str("  <ul>\n");
+    for &key in keys {
+        let entry = bib.get(key).unwrap();
+        writeln!(
+            content,
+            "    <li><a href=\"{}{}\">{}</a> {}</li>",
+            root,
+            html::escape(&pages[key]),
+            html::escape(&entry.title),
+            html::escape(&entry.short_citation())
+        ).unwrap();
+    }
+    content.push_str("  </ul>\n");
+}
+
+// the content of contributors.html: everyone, with the most notes first, and
+// the notes each contributed to
+pub fn page(
+    bib: &Bibliography,
+    contributors: &HashMap<String, Vec<String>>,
+    pages: &HashMap<String, String>,
+    text: &Text,
+) -> String {
+    let mut content = format!(
+        "<header>\n  <h1>{}</h1>\n</header>\n",
+        html::escape(text.get("contributors"))
+    );
+    for (name, keys) in people(bib, contributors, pages) {
+        writeln!(
+            content,
+            "<section class=\"contributor\">\n  \
+             <h2><a href=\"{}\">{}</a> <small>{}</small></h2>",
+            html::escape(&url(name)),
+            html::escape(name),
+            html::escape(&count(&keys, text))
+        ).unwrap();
+        notes_list(&mut content, bib, &keys, pages, "");
+        content.push_str("</section>\n");
+    }
+    content
+}
+
+// the content of someone's own page, which is a level down
+pub fn person_page(
+    bib: &Bibliography,
+    name: &str,
+    keys: &[&str],
+    pages: &HashMap<String, String>,
+    text: &Text,
+) -> String {
+    let mut content = format!(
+        "<header>\n  <h1>{}</h1>\n  <p>{}</p>\n</header>\n",
+        html::escape(name),
+        html::escape(&count(keys, text))
+    );
+    notes_list(&mut content, bib, keys, pages, "../");
+    content
+}
@@ -0,0 +1,132 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use serde_json::{self, Map, Value};
+use bib::Bibliography;
+use html;
+use text::Text;
+
+// How well the bibliography is covered by notes: how many entries of each
+// year and tag have been annotated, out of how many there are.
+
+#[derive(Clone, Copy, Default)]
+pub struct Counts {
+    pub annotated: usize,
+    pub total: usize,
+}
+
+impl Counts {
+    pub fn add(&mut self, annotated: bool) {
+        self.total += 1;
+        if annotated {
+            self.annotated += 1;
+        }
+    }
+
+    pub fn to_json(self) -> Value {
+        let mut counts = Map::new();
+        counts.insert("annotated".to_owned(), Value::U64(self.annotated as u64));
+        counts.insert("total".to_owned(), Value::U64(self.total as u64));
+        Value::Object(counts)
+    }
+}
+
+// entries without a year or tags are counted under these
+const NO_YEAR: &str = "unknown";
+const NO_TAG: &str = "(untagged)";
+
+pub struct Coverage {
+    pub years: BTreeMap<String, Counts>,
+    pub tags: BTreeMap<String, Counts>,
+    // tag, then year
+    pub grid: BTreeMap<String, BTreeMap<String, Counts>>,
+}
+
+// `annotated` holds the keys of the entries with a page
+pub fn compute(bib: &Bibliography, annotated: &HashMap<String, String>) -> Coverage {
+    let mut coverage = Coverage {
+        years: BTreeMap::new(),
+        tags: BTreeMap::new(),
+        grid: BTreeMap::new(),
+    };
+    for entry in &bib.entries {
+        let has_note = annotated.contains_key(&entry.key);
+        let year = if entry.year.trim().is_empty() { NO_YEAR } else { entry.year.trim() };
+        coverage.years.entry(year.to_owned()).or_default().add(has_note);
+        let mut tags: Vec<String> = entry.tags().iter().map(|t| t.to_lowercase()).collect();
+        if tags.is_empty() {
+            tags.push(NO_TAG.to_owned());
+        }
+        for tag in tags {
+            coverage.tags.entry(tag.clone()).or_default().add(has_note);
+            coverage.grid.entry(tag).or_default()
+                .entry(year.to_owned()).or_default()
+                .add(has_note);
+        }
+    }
+    coverage
+}
+
+fn counts_json(counts: &BTreeMap<String, Counts>) -> Value {
+    Value::Object(counts.iter().map(|(k, c)| (k.clone(), c.to_json())).collect())
+}
+
+pub fn json(coverage: &Coverage) -> String {
+    let mut root = Map::new();
+    root.insert("years".to_owned(), counts_json(&coverage.years));
+    root.insert("tags".to_owned(), counts_json(&coverage.tags));
+    let grid = coverage.grid.iter().map(|(t, years)| (t.clone(), counts_json(years)));
+    root.insert("grid".to_owned(), Value::Object(grid.collect()));
+    serde_json::to_string_pretty(&root).expect("Could not serialise coverage")
+}
+
+// a table cell shaded from white (nothing annotated) to green (everything)
+fn cell(counts: Option<&Counts>) -> String {
+    match counts {
+        Some(c) if c.total > 0 => {
+            let ratio = c.annotated as f64 / c.total as f64;
+            format!(
+                "<td style=\"background: hsl(120, 50%, {:.0}%)\" \
+                 title=\"{} of {} annotated\">{}/{}</td>",
+                100.0 - 45.0 * ratio,
+                c.annotated,
+                c.total,
+                c.annotated,
+                c.total
+            )
+        },
+        _ => "<td></td>".to_owned(),
+    }
+}
+
+// a heatmap of tags against years
+pub fn page(coverage: &Coverage, text: &Text) -> String {
+    let mut content = format!(
+        "<header>\n  <h1>{}</h1>\n  <p>{}</p>\n</header>\n<table class=\"coverage\">\n",
+        html::escape(text.get("coverage")),
+        text.get("coverage_intro")
+    );
+    content.push_str("  <tr><th></th>");
+    for year in coverage.years.keys() {
+        write!(content, "<th>{}</th>", html::escape(year)).unwrap();
+    }
+    writeln!(content, "<th>{}</th></tr>", html::escape(text.get("all"))).unwrap();
+    for (tag, years) in &coverage.grid {
+        write!(content, "  <tr><th>{}</th>", html::escape(tag)).unwrap();
+        for year in coverage.years.keys() {
+            content.push_str(&cell(years.get(year)));
+        }
+        content.push_str(&cell(coverage.tags.get(tag)));
+        content.push_str("</tr>\n");
+    }
+    content.push_str("  <tr><th>All</th>");
+    for counts in coverage.years.values() {
+        content.push_str(&cell(Some(counts)));
+    }
+    let all = coverage.years.values().fold(Counts::default(), |a, c| Counts {
+        annotated: a.annotated + c.annotated,
+        total: a.total + c.total,
+    });
+    content.push_str(&cell(Some(&all)));
+    content.push_str("</tr>\n</table>");
+    content
+}
@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, HashSet};
+use serde_json::{self, Value};
+use bib::{Bibliography, Entry};
+use diagnostics::Diagnostic;
+
+// Bibliographies in CSL-JSON, as Zotero (or Better BibTeX, which keeps its
+// citation keys) exports them, read into the same entries as BibTeX, so that
+// a bibliography kept in Zotero needn't be kept as a .bib file too. Any path
+// ending in `.json` is taken for one. Each item becomes an entry keyed by its
+// `citation-key`, or its `id` without one, with its type and fields given
+// their BibTeX names: `container-title` is the `journal` (or the `booktitle`,
+// for papers in proceedings and chapters), names are written as BibTeX would
+// have them, and `issued` becomes `year`, `month` and `day`. Such
+// bibliographies are exported from somewhere, so nothing writes to them.
+
+pub fn is_csl(path: &str) -> bool {
+    path.to_ascii_lowercase().ends_with(".json")
+}
+
+// the BibTeX entry type closest to a CSL item type
+fn kind(csl: &str) -> &'static str {
+    match csl {
+        "article-journal" | "article-magazine" | "article-newspaper" => "article",
+        "paper-conference" => "inproceedings",
+        "book" => "book",
+        "chapter" => "incollection",
+        "thesis" => "phdthesis",
+        "report" => "techreport",
+        "manuscript" => "unpublished",
+        "webpage" | "post" | "post-weblog" => "online",
+        "software" => "software",
+        "dataset" => "dataset",
+        _ => "misc",
+    }
+}
+
+// the CSL variables kept as they are, under their BibTeX names
+const FIELDS: &[(&str, &str)] = &[
+    ("title", "title"),
+    ("page", "pages"),
+    ("volume", "volume"),
+    ("issue", "number"),
+    ("edition", "edition"),
+    ("publisher", "publisher"),
+    ("publisher-place", "address"),
+    ("collection-title", "series"),
+    ("DOI", "doi"),
+    ("URL", "url"),
+    ("ISBN", "isbn"),
+    ("ISSN", "issn"),
+    ("abstract", "abstract"),
+    ("keyword", "keywords"),
+    ("note", "note"),
+    ("language", "language"),
+];
+
+// a variable as text: CSL has numbers as either numbers or strings
+fn text(value: &Value) -> Option<String> {
+    let text = match *value {
+        Value::String(ref s) => s.trim().to_owned(),
+        Value::U64(n) => n.to_string(),
+        Value::I64(n) => n.to_string(),
+        _ => return None,
+    };
+    Some(text).filter(|t| !t.is_empty())
+}
+
+// a list of names, like BibTeX's `Family, Given and Family, Given`
+fn names(value: &Value) -> Option<String> {
+    let names: Vec<String> = value.as_array()?.iter()
+        .filter_map(|name| {
+            if let Some(literal) = name.find("literal").and_then(text) {
+                // kept together, as an institution
+                return Some(format!("{{{}}}", literal));
+            }
+            let mut family = name.find("family").and_then(text)?;
+            if let Some(particle) = name.find("non-dropping-particle").and_then(text) {
+                family = format!("{} {}", particle, family);
+            }
+            let mut parts = vec![family];
+            parts.extend(name.find("suffix").and_then(text));
+            parts.extend(name.find("given").and_then(text));
+            Some(parts.join(", "))
+        })
+        .collect();
+    Some(names.join(" and ")).filter(|n| !n.is_empty())
+}
+
+// the year, month and day of a date, as far as it goes
+fn date(value: &Value) -> Vec<String> {
+    let parts = value.find("date-parts")
+        .and_then(|p| p.as_array())
+        .and_then(|p| p.first())
+        .and_then(|p| p.as_array());
+    match parts {
+        Some(parts) => parts.iter().take(3).map_while(text).collect(),
+        // `raw` and `literal` dates, as far as a year at the start of them
+        None => {
+            let raw = value.find("raw").or_else(|| value.find("literal")).and_then(text);
+            let year: String = raw.unwrap_or_default().chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            Some(year).filter(|y| !y.is_empty()).into_iter().collect()
+        },
+    }
+}
+
+fn fields(item: &Value, kind: &str) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    for &(from, to) in FIELDS {
+        if let Some(value) = item.find(from).and_then(text) {
+            fields.insert(to.to_owned(), value);
+        }
+    }
+    if let Some(container) = item.find("container-title").and_then(text) {
+        let field = match kind {
+            "inproceedings" | "incollection" => "booktitle",
+            _ => "journal",
+        };
+        fields.insert(field.to_owned(), container);
+    }
+    for role in ["author", "editor"] {
+        if let Some(names) = item.find(role).and_then(names) {
+            fields.insert(role.to_owned(), names);
+        }
+    }
+    let issued = item.find("issued").map(date).unwrap_or_default();
+    for (name, value) in ["year", "month", "day"].iter().zip(issued) {
+        fields.insert(name.to_string(), value);
+    }
+    fields
+}
+
+// where each item of the top-level array starts in `source`
+fn item_starts(source: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in source.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => {
+                if depth == 1 && c == '{' {
+                    starts.push(i);
+                }
+                depth += 1;
+            },
+            ']' | '}' => depth -= 1,
+            _ => {},
+        }
+    }
+    starts
+}
+
+pub fn parse(bib_path: &str, bs: &[u8]) -> Bibliography {
+    let source = String::from_utf8_lossy(bs).into_owned();
+    let value: Value = serde_json::from_str(&source)
+        .unwrap_or_else(|e| panic!("Could not parse bibliography: {}", e));
+    let items = value.as_array().expect("Could not parse bibliography: not a CSL-JSON list");
+    let lines = item_starts(&source).into_iter()
+        .map(|start| source[..start].matches('\n').count() + 1);
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    for (item, line) in items.iter().zip(lines) {
+        let key = item.find("citation-key").or_else(|| item.find("id")).and_then(text);
+        let key = match key {
+            Some(key) if seen.insert(key.clone()) => key,
+            Some(key) => {
+                Diagnostic::warning(format!("skipping a second entry `{}`", key))
+                    .in_file(bib_path, &source)
+                    .label(line, 1, 0, "entry starts here")
+                    .emit();
+                continue;
+            },
+            None => {
+                Diagnostic::warning("skipping an entry without an id")
+                    .in_file(bib_path, &source)
+                    .label(line, 1, 0, "entry starts here")
+                    .emit();
+                skipped.push(("<no key>".to_owned(), line));
+                continue;
+            },
+        };
+        let kind = kind(item.find("type").and_then(|t| t.as_str()).unwrap_or(""));
+        let fields = fields(item, kind);
+        let field = |name: &str| fields.get(name).cloned().unwrap_or_default();
+        entries.push(Entry {
+            title: field("title"),
+            author: field("author"),
+            year: field("year"),
+            key,
+            line,
+            kind: kind.to_owned(),
+            fields,
+        });
+    }
+    Bibliography {
+        path: bib_path.to_owned(),
+        source,
+        entries,
+        skipped,
+    }
+}
+
+// an entry as BibTeX, for exporting what was read from CSL-JSON
+pub fn bibtex(entry: &Entry) -> String {
+    let mut out = format!("@{}{{{},\n", entry.kind, entry.key);
+    for (name, value) in &entry.fields {
+        out.push_str(&format!("  {} = {{{}}},\n", name, value));
+    }
+    out.push('}');
+    out
+}
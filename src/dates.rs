@@ -0,0 +1,74 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Calendar dates, in UTC, for the few places that need to write one down.
+
+// the year, month and day of a count of days since 1970-01-01, after
+// Howard Hinnant's `civil_from_days`
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// the number of days since 1970-01-01 of a date, the inverse of
+// `civil_from_days`
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// the year, month and day of a date written as `YYYY-MM-DD`
+pub fn parse_civil(s: &str) -> Result<(i64, u32, u32), String> {
+    let invalid = || format!("invalid date `{}` (expected YYYY-MM-DD)", s);
+    let parts: Vec<&str> = s.trim().split('-').collect();
+    if parts.len() != 3 || parts[0].len() != 4 {
+        return Err(invalid());
+    }
+    let year: i64 = parts[0].parse().map_err(|_| invalid())?;
+    let month: u32 = parts[1].parse().map_err(|_| invalid())?;
+    let day: u32 = parts[2].parse().map_err(|_| invalid())?;
+    // catches days past the end of the month
+    let days = days_from_civil(year, month, day);
+    if !(1..=12).contains(&month) || civil_from_days(days) != (year, month, day) {
+        return Err(invalid());
+    }
+    Ok((year, month, day))
+}
+
+// the start of the day written as `YYYY-MM-DD`
+pub fn parse_ymd(s: &str) -> Result<SystemTime, String> {
+    let (year, month, day) = parse_civil(s)?;
+    let secs = days_from_civil(year, month, day) * 24 * 60 * 60;
+    if secs >= 0 {
+        Ok(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        Ok(UNIX_EPOCH - Duration::from_secs(-secs as u64))
+    }
+}
+
+// the year, month and day of a time
+pub fn civil(time: SystemTime) -> (i64, u32, u32) {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    civil_from_days(secs.div_euclid(24 * 60 * 60))
+}
+
+// a time as `YYYY-MM-DD`
+pub fn ymd(time: SystemTime) -> String {
+    let (year, month, day) = civil(time);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
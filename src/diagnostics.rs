@@ -0,0 +1,273 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use logging;
+
+// how many warnings and errors have been emitted, for the summary of a build
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn warnings() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+pub fn errors() -> usize {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+// a span on a single line of the source, underlined in the output
+struct Label {
+    line: usize,
+    col: usize,
+    len: usize,
+    text: String,
+}
+
+// a warning or error, optionally pointing into a source file, printed in the
+// style of rustc/codespan:
+//
+//     warning: skipping malformed entry `smith2019`
+//       --> refs.bib:42:1
+//        |
+//     42 | @article{smith2019,
+//        | ^^^^^^^^^^^^^^^^^^^ entry starts here
+//        = parser error: ...
+pub struct Diagnostic {
+    level: Level,
+    message: String,
+    file: Option<(String, String)>,
+    labels: Vec<Label>,
+    notes: Vec<String>,
+}
+
+struct Style {
+    color: bool,
+}
+
+impl Style {
+    fn paint(&self, code: &str, s: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, s)
+        } else {
+            s.to_owned()
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(level: Level, message: S) -> Diagnostic {
+        Diagnostic {
+            level,
+            message: message.into(),
+            file: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error<S: Into<String>>(message: S) -> Diagnostic {
+        Diagnostic::new(Level::Error, message)
+    }
+
+    pub fn warning<S: Into<String>>(message: S) -> Diagnostic {
+        Diagnostic::new(Level::Warning, message)
+    }
+
+    // the file labels point into, with its full contents
+    pub fn in_file(mut self, name: &str, source: &str) -> Diagnostic {
+        self.file = Some((name.to_owned(), source.to_owned()));
+        self
+    }
+
+    // underlines `len` characters from (1-based) `line` and `col`. The first
+    // label added is the primary one, shown in the `-->` line. A `len` of 0
+    // underlines up to the end of the line. A label on line or column 0,
+    // which isn't anywhere, is left out.
+    pub fn label<S: Into<String>>(
+        mut self,
+        line: usize,
+        col: usize,
+        len: usize,
+        text: S,
+    ) -> Diagnostic {
+        self.labels.push(Label { line, col, len, text: text.into() });
+        self
+    }
+
+    pub fn note<S: Into<String>>(mut self, note: S) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    fn render(&self, style: &Style) -> String {
+        let (name, color) = match self.level {
+            Level::Error => ("error", "1;31"),
+            Level::Warning => ("warning", "1;33"),
+        };
+        let mut out = format!(
+            "{}{}\n",
+            style.paint(color, name),
+            style.paint("1", &format!(": {}", self.message))
+        );
+
+        let mut labels: Vec<&Label> = self.labels.iter()
+            .filter(|l| l.line > 0 && l.col > 0)
+            .collect();
+        let primary = labels.first().map(|l| (l.line, l.col));
+        let width = labels.iter().map(|l| l.line.to_string().len()).max()
+            .unwrap_or(0);
+        let pad = " ".repeat(width);
+        let gutter = style.paint("1;34", "|");
+        if let Some((ref file, ref source)) = self.file {
+            match labels.first() {
+                Some(l) => out.push_str(&format!(
+                    "{}{} {}:{}:{}\n",
+                    pad,
+                    style.paint("1;34", "-->"),
+                    file,
+                    l.line,
+                    l.col
+                )),
+                None => out.push_str(&format!(
+                    "{}{} {}\n", pad, style.paint("1;34", "-->"), file
+                )),
+            }
+            if !labels.is_empty() {
+                out.push_str(&format!("{} {}\n", pad, gutter));
+            }
+            let lines: Vec<&str> = source.lines().collect();
+            labels.sort_by_key(|l| l.line);
+            let mut last_line = None;
+            for label in &labels {
+                let text = lines.get(label.line.saturating_sub(1)).cloned().unwrap_or("")
+                    .replace('\t', " ");
+                if let Some(last) = last_line {
+                    if label.line > last + 1 {
+                        out.push_str(&format!("{}\n", style.paint("1;34", "...")));
+                    }
+                }
+                if last_line != Some(label.line) {
+                    out.push_str(&format!(
+                        "{} {} {}\n",
+                        style.paint("1;34", &format!("{:>1$}", label.line, width)),
+                        gutter,
+                        text
+                    ));
+                }
+                last_line = Some(label.line);
+                let len = if label.len == 0 {
+                    text.chars().count().saturating_sub(label.col.saturating_sub(1)).max(1)
+                } else {
+                    label.len
+                };
+                let (mark, mark_color) = if primary == Some((label.line, label.col)) {
+                    ("^", color)
+                } else {
+                    ("-", "1;34")
+                };
+                out.push_str(&format!(
+                    "{} {} {}{} {}\n",
+                    pad,
+                    gutter,
+                    " ".repeat(label.col.saturating_sub(1)),
+                    style.paint(mark_color, &mark.repeat(len)),
+                    style.paint(mark_color, &label.text)
+                ));
+            }
+        }
+        for note in &self.notes {
+            out.push_str(&format!("{} {} {}\n", pad, style.paint("1;34", "="), note));
+        }
+        out
+    }
+
+    // prints the diagnostic to stderr, in color if that's a terminal.
+    // Warnings aren't printed with `-q`.
+    pub fn emit(&self) {
+        if self.level == Level::Warning {
+            WARNINGS.fetch_add(1, Ordering::Relaxed);
+            if logging::quiet() {
+                return;
+            }
+        } else {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+        let style = Style {
+            color: io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        };
+        logging::print(&self.render(&style));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: Style = Style { color: false };
+    const SOURCE: &str = "@article{smith2019,\n  title = {A}\n}\n";
+
+    #[test]
+    fn renders_a_message_alone() {
+        let d = Diagnostic::warning("something's off");
+        assert_eq!(d.render(&PLAIN), "warning: something's off\n");
+    }
+
+    #[test]
+    fn points_at_the_label() {
+        let d = Diagnostic::error("bad title")
+            .in_file("refs.bib", SOURCE)
+            .label(2, 3, 5, "here")
+            .note("a note");
+        assert_eq!(
+            d.render(&PLAIN),
+            "error: bad title\n \
+             --> refs.bib:2:3\n  \
+             |\n\
+             2 |   title = {A}\n  \
+             |   ^^^^^ here\n  \
+             = a note\n"
+        );
+    }
+
+    #[test]
+    fn underlines_to_the_end_of_the_line() {
+        let d = Diagnostic::warning("w").in_file("refs.bib", SOURCE).label(1, 10, 0, "key");
+        let underlined = "1 | @article{smith2019,\n  |          ^^^^^^^^^^ key\n";
+        assert!(d.render(&PLAIN).contains(underlined));
+    }
+
+    #[test]
+    fn skips_labels_on_line_or_column_zero() {
+        let d = Diagnostic::warning("w")
+            .in_file("refs.bib", SOURCE)
+            .label(0, 1, 0, "nowhere")
+            .label(1, 0, 0, "nowhere either");
+        assert_eq!(d.render(&PLAIN), "warning: w\n--> refs.bib\n");
+    }
+
+    #[test]
+    fn survives_labels_past_the_end() {
+        let d = Diagnostic::warning("w").in_file("refs.bib", SOURCE).label(99, 50, 0, "gone");
+        let rendered = d.render(&PLAIN);
+        assert!(rendered.contains("--> refs.bib:99:50"));
+        assert!(rendered.contains("^ gone"));
+    }
+
+    #[test]
+    fn marks_only_the_first_label_as_primary() {
+        let d = Diagnostic::error("e")
+            .in_file("refs.bib", SOURCE)
+            .label(3, 1, 1, "second")
+            .label(1, 1, 1, "first");
+        let rendered = d.render(&PLAIN);
+        assert!(rendered.contains("^ second"));
+        assert!(rendered.contains("- first"));
+        assert!(rendered.contains("...\n"));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::time::SystemTime;
+use bib::{self, Bibliography, Entry};
+use config::Config;
+use dates;
+use diagnostics::Diagnostic;
+use frontmatter;
+use html;
+use markdown;
+use render;
+use site;
+use sitemap;
+use text::Text;
+use urls;
+use xref;
+
+// The notes added or changed since some date, gathered into one document to
+// send around: an HTML email with its styles inlined, since mail clients
+// ignore stylesheets, or Markdown.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Markdown,
+}
+
+// a note that changed since the date, and whether it's new
+struct Item<'a> {
+    entry: &'a Entry,
+    note: &'a str,
+    new: bool,
+}
+
+// the styles of each tag in an HTML digest
+const STYLES: &[(&str, &str)] = &[
+    ("h1", "font-size: 24px; margin: 0 0 16px;"),
+    ("h2", "font-size: 20px; margin: 32px 0 4px;"),
+    ("h3", "font-size: 17px; margin: 20px 0 8px;"),
+    ("h4", "font-size: 16px; margin: 16px 0 8px;"),
+    ("h5", "font-size: 16px; margin: 16px 0 8px;"),
+    ("h6", "font-size: 16px; margin: 16px 0 8px;"),
+    ("p", "margin: 0 0 12px;"),
+    ("a", "color: #1a5fb4;"),
+    ("blockquote", "margin: 0 0 12px; padding-left: 12px; border-left: 3px solid #ccc; \
+                    color: #555;"),
+    ("pre", "background: #f4f4f4; padding: 8px; overflow-x: auto; font-size: 13px;"),
+    ("code", "font-family: Menlo, Consolas, monospace;"),
+    ("table", "border-collapse: collapse; margin: 0 0 12px;"),
+    ("th", "border: 1px solid #ccc; padding: 4px 8px;"),
+    ("td", "border: 1px solid #ccc; padding: 4px 8px;"),
+    ("img", "max-width: 100%;"),
+    ("hr", "border: 0; border-top: 1px solid #ddd; margin: 32px 0;"),
+];
+
+// adds the digest's styles to every tag they're for
+fn inline_styles(html: &str) -> String {
+    let mut out = html.to_owned();
+    for &(tag, style) in STYLES {
+        let styled = format!("<{} style=\"{}\"", tag, style);
+        out = out.replace(&format!("<{}>", tag), &format!("{}>", styled))
+            .replace(&format!("<{} ", tag), &format!("{} ", styled));
+    }
+    out
+}
+
+fn demote_markdown(note: &str) -> String {
+    let headings: Vec<usize> = markdown::prose_lines(note).into_iter()
+        .filter(|&(_, line)| line.starts_with('#'))
+        .map(|(n, _)| n)
+        .collect();
+    let mut out = String::with_capacity(note.len());
+    for (n, line) in note.lines().enumerate() {
+        if headings.contains(&(n + 1)) {
+            out.push_str("##");
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn byline(entry: &Entry, new: bool, text: &Text) -> String {
+    let who = bib::author_year(&entry.author, &entry.year);
+    format!("{} · {}", who, text.get(if new { "digest_new" } else { "digest_updated" }))
+}
+
+fn title(since: &str, text: &Text) -> String {
+    text.get("digest_title").replace("{date}", since)
+}
+
+fn html_digest(
+    config: &Config,
+    bib: &Bibliography,
+    items: &[Item],
+    pages: &HashMap<String, String>,
+    root: &str,
+    since: &str,
+) -> String {
+    let text = &config.text;
+    let mut body = format!("<h1>{}</h1>\n", html::escape(&title(since, text)));
+    for item in items {
+        let entry = item.entry;
+        let title = html::escape(&entry.title);
+        match pages.get(&entry.key) {
+            Some(url) => writeln!(
+                body,
+                "<h2><a href=\"{}{}\">{}</a></h2>",
+                html::escape(root),
+                html::escape(url),
+                title
+            ),
+            None => writeln!(body, "<h2>{}</h2>", title),
+        }.unwrap();
+        let links: Vec<String> = entry.links().iter()
+            .map(|l| format!("<a href=\"{}\">{}</a>", html::escape(&l.url), l.label))
+            .collect();
+        let mut meta = html::escape(&byline(entry, item.new, text));
+        if !links.is_empty() {
+            meta = format!("{} · {}", meta, links.join(" · "));
+        }
+        // not a `<p>`, which would get a second style
+        writeln!(
+            body,
+            "<div style=\"margin: 0 0 12px; color: #666; font-size: 14px;\">{}</div>",
+            meta
+        ).unwrap();
+        let (note, _) = xref::rewrite(item.note, bib, pages, root);
+        match render::to_html(config, &note) {
+            // below the digest's own headings, and with footnotes of its own
+            Ok(html) => {
+                let html = html::prefix_ids(&html, &urls::slugify(&entry.key));
+                body.push_str(&html::demote_headings(&html, 2));
+            },
+            Err(e) => {
+                Diagnostic::warning(format!("leaving out the note on `{}`", entry.key))
+                    .note(e.to_string())
+                    .emit();
+                let failed = html::escape(text.get("digest_failed"));
+                write!(body, "<p><em>{}</em></p>", failed).unwrap();
+            },
+        }
+        body.push_str("\n<hr>\n");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n</head>\n\
+         <body style=\"margin: 0; padding: 24px; background: #f6f6f6;\">\n\
+         <div style=\"max-width: 640px; margin: 0 auto; padding: 24px; background: #fff; \
+         color: #222; font-family: Georgia, serif; font-size: 16px; line-height: 1.5;\">\n\
+         {}</div>\n</body>\n</html>\n",
+        html::escape(&title(since, text)),
+        inline_styles(&body)
+    )
+}
+
+fn markdown_digest(
+    text: &Text,
+    bib: &Bibliography,
+    items: &[Item],
+    pages: &HashMap<String, String>,
+    root: &str,
+    since: &str,
+) -> String {
+    let mut out = format!("# {}\n", title(since, text));
+    for item in items {
+        let entry = item.entry;
+        match pages.get(&entry.key) {
+            Some(url) => write!(out, "\n## [{}]({}{})\n\n", entry.title, root, url),
+            None => write!(out, "\n## {}\n\n", entry.title),
+        }.unwrap();
+        let mut meta = byline(entry, item.new, text);
+        for link in entry.links() {
+            write!(meta, " · [{}]({})", link.label, link.url).unwrap();
+        }
+        writeln!(out, "*{}*\n", meta).unwrap();
+        let (note, _) = xref::rewrite(item.note, bib, pages, root);
+        let note = markdown::prefix_footnotes(&note, &urls::slugify(&entry.key));
+        out.push_str(demote_markdown(&frontmatter::blank(&note)).trim());
+        out.push('\n');
+    }
+    out
+}
+
+// the digest of the notes changed since the given time, newest first
+pub fn digest(
+    config: &Config,
+    bib: &Bibliography,
+    since: SystemTime,
+    format: Format,
+) -> String {
+    let (notes, _) = site::read_notes(config, bib);
+    let published: HashMap<&String, &String> = notes.iter()
+        .filter(|&(key, note)| config.drafts || !site::is_draft(config, key, note))
+        .collect();
+    let mut changed = Vec::new();
+    for entry in &bib.entries {
+        let note = match published.get(&entry.key) {
+            Some(note) => note,
+            None => continue,
+        };
+        let meta = match fs::metadata(site::note_path(config, &entry.key)) {
+            Ok(meta) => meta,
+            Err(_) => continue,
+        };
+        let modified = match meta.modified() {
+            Ok(modified) if modified >= since => modified,
+            _ => continue,
+        };
+        // not every filesystem knows when files were created
+        let new = meta.created().is_ok_and(|created| created >= since);
+        changed.push((modified, Item { entry, note, new }));
+    }
+    changed.sort_by_key(|&(modified, _)| ::std::cmp::Reverse(modified));
+    let items: Vec<Item> = changed.into_iter().map(|(_, item)| item).collect();
+
+    // notes link to their pages on the site, if we know where it is
+    let (pages, root) = match config.base_url {
+        Some(ref base) => {
+            let keys = published.keys().map(|k| k.to_string()).collect();
+            (urls::page_urls(bib, &keys, config.url_style), sitemap::normalize_base(base))
+        },
+        None => (HashMap::new(), String::new()),
+    };
+    let since = dates::ymd(since);
+    match format {
+        Format::Html => html_digest(config, bib, &items, &pages, &root, &since),
+        Format::Markdown => markdown_digest(&config.text, bib, &items, &pages, &root, &since),
+    }
+}
@@ -0,0 +1,32 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use config::Config;
+
+// Notes too sensitive to keep in the clear, encrypted with age (or rage) as
+// `<key>.md.age` next to the others. They're decrypted when they're read,
+// with the identity set under `[encryption]` or given with --identity, and
+// only for the internal site: the public one never even opens them. Nothing
+// decrypted is written anywhere but the pages built from it.
+
+// where the encrypted note on an entry is, if it has one
+pub fn path(config: &Config, key: &str) -> PathBuf {
+    config.markdown_dir.join(format!("{}.md.age", key))
+}
+
+pub fn decrypt(config: &Config, path: &Path) -> Result<String, String> {
+    let encryption = &config.encryption;
+    let identity = encryption.identity.as_ref().ok_or_else(|| {
+        "no identity to decrypt it with (pass --identity, or set `identity` under \
+         [encryption])".to_owned()
+    })?;
+    let output = Command::new(&encryption.age)
+        .arg("--decrypt")
+        .arg("--identity").arg(identity)
+        .arg(path)
+        .output()
+        .map_err(|e| format!("could not run {}: {}", encryption.age, e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    String::from_utf8(output.stdout).map_err(|_| "it isn't UTF-8 text".to_owned())
+}
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+use serde_json::{self, Map, Value};
+use bib;
+use bib::Bibliography;
+use config::Config;
+use dates;
+use diagnostics::Diagnostic;
+use html;
+
+// Fills in the fields an entry is missing (abstract, venue, pages) from the
+// metadata Crossref or DataCite hold for its DOI. They're only looked up by the
+// `enrich` command: the answers are cached, and builds just read the cache.
+// How often each paper has been cited comes along too, as `citations`; since
+// that changes, with `citations` set under `[enrich]` every entry is looked up
+// for it, and looked up again once what's cached is older than `refresh_days`.
+
+// the fields that can be filled in
+const FIELDS: &[&str] = &[
+    "abstract", "journal", "booktitle", "pages", "volume", "publisher", "citations",
+];
+
+// the metadata found for each DOI, or `None` if neither service knows it
+pub type Cache = BTreeMap<String, Option<BTreeMap<String, String>>>;
+
+pub fn cache_path(config: &Config) -> PathBuf {
+    config.enrich.cache.clone().unwrap_or_else(|| {
+        let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+        dir.join(".biblionotes").join("enrich.json")
+    })
+}
+
+pub fn load(path: &Path) -> Cache {
+    let mut contents = String::new();
+    if File::open(path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return Cache::new();
+    }
+    let value: Value = match serde_json::from_str(&contents) {
+        Ok(value) => value,
+        Err(e) => {
+            Diagnostic::warning(format!("ignoring invalid cache {}", path.display()))
+                .note(e.to_string())
+                .emit();
+            return Cache::new();
+        },
+    };
+    let mut cache = Cache::new();
+    for (doi, fields) in value.as_object().into_iter().flat_map(|o| o.iter()) {
+        let fields = fields.as_object().map(|fields| {
+            fields.iter()
+                .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_owned())))
+                .collect()
+        });
+        cache.insert(doi.clone(), fields);
+    }
+    cache
+}
+
+fn save(path: &Path, cache: &Cache) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let value: Map<String, Value> = cache.iter()
+        .map(|(doi, fields)| {
+            let fields = match *fields {
+                Some(ref fields) => Value::Object(fields.iter()
+                    .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+                    .collect()),
+                None => Value::Null,
+            };
+            (doi.clone(), fields)
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&value).map_err(io::Error::other)?;
+    File::create(path).and_then(|mut f| writeln!(f, "{}", json))
+}
+
+// fetches a JSON document with curl, returning `None` if it isn't there
+fn fetch(config: &Config, url: &str) -> Result<Option<Value>, String> {
+    let agent = match config.enrich.mailto {
+        Some(ref mailto) => format!("biblionotes (mailto:{})", mailto),
+        None => "biblionotes".to_owned(),
+    };
+    let output = Command::new(&config.enrich.curl)
+        .args(["--silent", "--show-error", "--location", "--fail"])
+        .args(["--header", "Accept: application/json", "--user-agent", &agent[..], url])
+        .output()
+        .map_err(|e| format!("could not run {}: {}", config.enrich.curl, e))?;
+    // curl exits with 22 on HTTP errors, which here means an unknown DOI
+    if output.status.code() == Some(22) {
+        return Ok(None);
+    }
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    let body = String::from_utf8_lossy(&output.stdout);
+    serde_json::from_str(&body).map(Some).map_err(|e| e.to_string())
+}
+
+// the plain text of the JATS markup Crossref uses for abstracts, without the
+// "Abstract" heading it usually starts with
+fn strip_jats(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        let tag = &rest[start..end];
+        rest = &rest[end..];
+        if tag.starts_with("<jats:title") {
+            rest = rest.find("</jats:title>").map_or("", |i| &rest[i..]);
+        } else if tag.starts_with("</jats:p") {
+            out.push(' ');
+        }
+    }
+    out.push_str(rest);
+    html::unescape(&out).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn string_at<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.find(key).and_then(|v| v.as_str()).filter(|s| !s.trim().is_empty())
+}
+
+fn from_crossref(response: &Value) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    let message = match response.find("message") {
+        Some(message) => message,
+        None => return fields,
+    };
+    if let Some(abs) = string_at(message, "abstract") {
+        fields.insert("abstract".to_owned(), strip_jats(abs));
+    }
+    let container = message.find("container-title")
+        .and_then(|t| t.as_array())
+        .and_then(|t| t.iter().filter_map(|t| t.as_str()).next());
+    if let Some(container) = container {
+        let field = match string_at(message, "type") {
+            Some("proceedings-article") | Some("book-chapter") => "booktitle",
+            _ => "journal",
+        };
+        fields.insert(field.to_owned(), container.to_owned());
+    }
+    let renamed = [("page", "pages"), ("volume", "volume"), ("publisher", "publisher")];
+    for (from, to) in renamed {
+        if let Some(value) = string_at(message, from) {
+            fields.insert(to.to_owned(), value.to_owned());
+        }
+    }
+    if let Some(count) = message.find("is-referenced-by-count").and_then(|c| c.as_u64()) {
+        fields.insert("citations".to_owned(), count.to_string());
+    }
+    fields
+}
+
+fn from_datacite(response: &Value) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    let attributes = match response.lookup("data.attributes") {
+        Some(attributes) => attributes,
+        None => return fields,
+    };
+    let abs = attributes.find("descriptions")
+        .and_then(|d| d.as_array())
+        .and_then(|ds| {
+            ds.iter().find(|d| string_at(d, "descriptionType") == Some("Abstract"))
+        })
+        .and_then(|d| string_at(d, "description"));
+    if let Some(abs) = abs {
+        fields.insert("abstract".to_owned(), strip_jats(abs));
+    }
+    let container = attributes.find("container").and_then(|c| string_at(c, "title"));
+    if let Some(container) = container {
+        fields.insert("journal".to_owned(), container.to_owned());
+    }
+    // a plain string in older responses, an object with a name in newer ones
+    let publisher = attributes.find("publisher")
+        .and_then(|p| p.as_str().or_else(|| string_at(p, "name")));
+    if let Some(publisher) = publisher {
+        fields.insert("publisher".to_owned(), publisher.to_owned());
+    }
+    if let Some(count) = attributes.find("citationCount").and_then(|c| c.as_u64()) {
+        fields.insert("citations".to_owned(), count.to_string());
+    }
+    fields
+}
+
+fn lookup(config: &Config, doi: &str) -> Result<Option<BTreeMap<String, String>>, String> {
+    let doi = bib::encode_path(doi);
+    let crossref = fetch(config, &format!("https://api.crossref.org/works/{}", doi))?;
+    if let Some(response) = crossref {
+        return Ok(Some(from_crossref(&response)));
+    }
+    let datacite = fetch(config, &format!("https://api.datacite.org/dois/{}", doi))?;
+    Ok(datacite.map(|response| from_datacite(&response)))
+}
+
+// whether the citation count of what was found for a DOI is due to be looked
+// up again, going by the day it was looked up
+fn stale(config: &Config, fields: &BTreeMap<String, String>, now: SystemTime) -> bool {
+    let checked = match fields.get("checked").and_then(|c| dates::parse_ymd(c).ok()) {
+        Some(checked) => checked,
+        None => return true,
+    };
+    let refresh = Duration::from_secs(config.enrich.refresh_days * 24 * 60 * 60);
+    now.duration_since(checked).is_ok_and(|age| age >= refresh)
+}
+
+// looks up every entry with a DOI that's missing some of the fields, unless
+// it's been looked up before, and saves what's found to the cache. With
+// `citations`, entries are looked up for their citation counts too, and
+// again when those are stale.
+pub fn enrich(config: &Config, bib: &Bibliography) {
+    let path = cache_path(config);
+    let mut cache = load(&path);
+    let mut found = 0;
+    let now = SystemTime::now();
+    for entry in &bib.entries {
+        let doi = match entry.doi() {
+            Some(doi) => doi,
+            None => continue,
+        };
+        let has_venue = entry.venue().is_some();
+        let complete = entry.field("abstract").is_some()
+            && entry.field("pages").is_some()
+            && has_venue;
+        let counting = config.enrich.citations;
+        let due = match cache.get(&doi) {
+            Some(Some(fields)) => counting && stale(config, fields, now),
+            Some(None) => false,
+            None => counting || !complete,
+        };
+        if !due {
+            continue;
+        }
+        println!("looking up {} ({})", entry.key, doi);
+        match lookup(config, &doi) {
+            Ok(Some(mut fields)) => {
+                found += 1;
+                fields.insert("checked".to_owned(), dates::ymd(now));
+                cache.insert(doi, Some(fields));
+            },
+            Ok(None) => {
+                Diagnostic::warning(format!("no metadata found for `{}`", entry.key))
+                    .note(format!("neither Crossref nor DataCite know the DOI {}", doi))
+                    .emit();
+                cache.insert(doi, None);
+            },
+            Err(e) => {
+                // not cached, so it's tried again next time
+                Diagnostic::error(format!("could not look up `{}`", entry.key))
+                    .note(e)
+                    .emit();
+            },
+        }
+    }
+    if let Err(e) = save(&path, &cache) {
+        Diagnostic::error(format!("could not write {}", path.display()))
+            .note(e.to_string())
+            .emit();
+        ::std::process::exit(1);
+    }
+    println!("found metadata for {} entries, cached in {}", found, path.display());
+}
+
+// fills in the fields entries are missing from the cache, without touching
+// the ones the bibliography already has
+pub fn apply(bib: &mut Bibliography, cache: &Cache) {
+    for entry in &mut bib.entries {
+        let fields = match entry.doi().and_then(|doi| cache.get(&doi)) {
+            Some(Some(fields)) => fields,
+            _ => continue,
+        };
+        let has_venue = entry.venue().is_some();
+        for (name, value) in fields {
+            if !FIELDS.contains(&&name[..]) || entry.fields.contains_key(name) {
+                continue;
+            }
+            // don't add a journal to a conference paper, or vice versa
+            if has_venue && (name == "journal" || name == "booktitle") {
+                continue;
+            }
+            entry.fields.insert(name.clone(), value.clone());
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use template::Failure;
+
+// everything that can go wrong while building a single page
+#[derive(Debug)]
+pub enum Error {
+    Read(PathBuf, io::Error),
+    Decrypt(PathBuf, String),
+    Write(PathBuf, io::Error),
+    Pandoc(io::Error),
+    Math(String),
+    References(Vec<String>),
+    Attachments(Vec<String>),
+    Template(Failure),
+    // the hook, and what went wrong with it
+    Hook(String, String),
+    // the file the page would be written to, and the entry whose page is
+    // already there, or `None` if it's one of the site's own
+    Clash(PathBuf, Option<String>),
+    // an entry the bibliography has, at the line, that couldn't be read
+    Malformed(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Read(ref path, ref e) =>
+                write!(f, "could not read {}: {}", path.display(), e),
+            Error::Decrypt(ref path, ref e) =>
+                write!(f, "could not decrypt {}: {}", path.display(), e),
+            Error::Write(ref path, ref e) =>
+                write!(f, "could not write {}: {}", path.display(), e),
+            Error::Pandoc(ref e) => write!(f, "pandoc failed: {}", e),
+            Error::Math(ref e) => write!(f, "could not render math: {}", e),
+            Error::References(ref keys) =>
+                write!(f, "unknown references: {}", keys.join(", ")),
+            Error::Attachments(ref files) =>
+                write!(f, "missing attachments: {}", files.join(", ")),
+            Error::Template(ref e) => write!(f, "could not fill in the template: {}", e),
+            Error::Hook(ref hook, ref e) => write!(f, "the {} hook failed: {}", hook, e),
+            Error::Clash(ref path, Some(ref key)) => {
+                write!(f, "its page, {}, is where the page on `{}` goes", path.display(), key)
+            },
+            Error::Clash(ref path, None) => {
+                write!(f, "its page, {}, is one of the site's own files", path.display())
+            },
+            Error::Malformed(line) => {
+                write!(f, "the entry at line {} of the bibliography is malformed", line)
+            },
+        }
+    }
+}
+
+impl From<Failure> for Error {
+    fn from(e: Failure) -> Error {
+        Error::Template(e)
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use serde_json::{self, Map, Value};
+use bib::{self, Bibliography, Entry};
+use config::Config;
+use csl;
+use frontmatter;
+use reading::Reading;
+use sitemap;
+use site;
+use urls;
+
+// The index in other forms, for `biblionotes export`: the entries the site
+// has pages of, in the index's order, as a Markdown reading list, a CSV, a
+// BibTeX file with just those entries, or JSON. The reading list links the
+// titles to their pages when the site's `base_url` is set.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    Csv,
+    Bib,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "md" | "markdown" => Ok(Format::Markdown),
+            "csv" => Ok(Format::Csv),
+            "bib" | "bibtex" => Ok(Format::Bib),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown export format `{}` (expected md, csv, bib or json)", s)),
+        }
+    }
+}
+
+// the columns of the CSV, and the fields of each entry in the JSON
+const COLUMNS: &[&str] = &["key", "authors", "year", "title", "tags", "status"];
+
+fn row(entry: &Entry, note: &str) -> Vec<String> {
+    let (reading, _) = Reading::from_fields(&frontmatter::fields(note));
+    vec![
+        entry.key.clone(),
+        entry.author.clone(),
+        entry.year.clone(),
+        entry.title.clone(),
+        entry.tags().join("; "),
+        reading.status.map_or("", |s| s.name()).to_owned(),
+    ]
+}
+
+// a CSV field, quoted if it has to be
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn csv(entries: &[(&Entry, String)]) -> String {
+    let mut out = format!("{}\r\n", COLUMNS.join(","));
+    for &(entry, ref note) in entries {
+        let fields: Vec<String> = row(entry, note).iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn json(entries: &[(&Entry, String)]) -> String {
+    let items = entries.iter().map(|&(entry, ref note)| {
+        let mut item: Map<String, Value> = COLUMNS.iter()
+            .map(|c| c.to_string())
+            .zip(row(entry, note).into_iter().map(Value::String))
+            .collect();
+        let tags = entry.tags().into_iter().map(Value::String).collect();
+        item.insert("tags".to_owned(), Value::Array(tags));
+        if item["status"].as_str() == Some("") {
+            item.insert("status".to_owned(), Value::Null);
+        }
+        Value::Object(item)
+    });
+    let json = serde_json::to_string_pretty(&Value::Array(items.collect()))
+        .expect("Could not serialise the export");
+    format!("{}\n", json)
+}
+
+// escapes what Markdown would take as formatting
+fn markdown_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\`*_[]<>#".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn markdown(config: &Config, bib: &Bibliography, entries: &[(&Entry, String)]) -> String {
+    let keys: HashSet<String> = entries.iter().map(|(e, _)| e.key.clone()).collect();
+    let pages = urls::page_urls(bib, &keys, config.url_style);
+    let base = config.base_url.as_ref().map(|b| sitemap::normalize_base(b));
+    let mut out = String::new();
+    for &(entry, _) in entries {
+        let title = format!("*{}*", markdown_text(&entry.title));
+        let title = match base {
+            Some(ref base) => format!("[{}]({}{})", title, base, pages[&entry.key]),
+            None => title,
+        };
+        let who = bib::author_year(&entry.author, &entry.year);
+        let mut line = if who.is_empty() {
+            format!("- {}.", title)
+        } else {
+            format!("- {}. {}.", markdown_text(&who), title)
+        };
+        if let Some(venue) = entry.venue() {
+            line.push_str(&format!(" {}.", markdown_text(&venue)));
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+fn bibtex(bib: &Bibliography, entries: &[(&Entry, String)]) -> String {
+    // a CSL-JSON bibliography's entries are written out anew
+    let texts: Vec<String> = entries.iter()
+        .filter_map(|&(e, _)| {
+            if csl::is_csl(&bib.path) {
+                Some(csl::bibtex(e))
+            } else {
+                bib.text(&e.key).map(|t| t.trim().to_owned())
+            }
+        })
+        .collect();
+    let mut out = texts.join("\n\n");
+    out.push('\n');
+    out
+}
+
+pub fn export(config: &Config, bib: &Bibliography, format: Format) -> String {
+    let entries = site::index_notes(config, bib);
+    match format {
+        Format::Markdown => markdown(config, bib, &entries),
+        Format::Csv => csv(&entries),
+        Format::Bib => bibtex(bib, &entries),
+        Format::Json => json(&entries),
+    }
+}
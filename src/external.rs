@@ -0,0 +1,122 @@
+use config::Config;
+use html;
+
+// Links to other sites, in notes, in the links to papers from the
+// bibliography and in the template alike, get `rel="noopener"`, the class
+// set under `[external_links]` (`external` unless it's changed, for an icon)
+// and, if asked for, `target="_blank"`. Links to the site's own `base_url`,
+// and to the domains listed as exceptions, are left alone, and so is what a
+// link already says: its own `target` stays, and `rel` and `class` are added
+// to.
+
+// the host of an absolute URL, lowercased and without a port, or `None` for
+// links that aren't to another site
+fn host(url: &str) -> Option<String> {
+    let web = |scheme: &str| ["http", "https"].iter().any(|s| scheme.eq_ignore_ascii_case(s));
+    let rest = match url.split_once("://") {
+        Some((scheme, rest)) if web(scheme) => rest,
+        _ => url.strip_prefix("//")?,
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit('@').next().unwrap_or("");
+    let host = host.split(':').next().unwrap_or("").to_lowercase();
+    Some(host).filter(|h| !h.is_empty())
+}
+
+// whether `host` is `domain` or one of its subdomains
+fn within(host: &str, domain: &str) -> bool {
+    let domain = domain.trim().trim_start_matches('.').to_lowercase();
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+fn is_external(url: &str, config: &Config) -> bool {
+    let to = match host(url) {
+        Some(to) => to,
+        None => return false,
+    };
+    let own = config.base_url.as_ref().and_then(|b| host(b));
+    !own.iter().chain(&config.external_links.exceptions).any(|d| within(&to, d))
+}
+
+// where the attribute `name` is in `tag`, from its name to the end of its
+// value
+fn span(tag: &str, name: &str) -> Option<(usize, usize)> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find(name).map(|i| from + i) {
+        from = start + name.len();
+        if !lower[..start].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let value = match tag[from..].trim_start().strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        let at = tag.len() - value.len();
+        let end = match value.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => at + 2 + value[1..].find(quote)?,
+            _ => value.find(char::is_whitespace).map_or(tag.len(), |e| at + e),
+        };
+        return Some((start, end));
+    }
+    None
+}
+
+// `tag` with the attribute `name` set to `value`
+fn with_attr(tag: &str, name: &str, value: &str) -> String {
+    let attribute = format!("{}=\"{}\"", name, html::escape(value));
+    match span(tag, name) {
+        Some((start, end)) => format!("{}{}{}", &tag[..start], attribute, &tag[end..]),
+        None => format!("{} {}", tag, attribute),
+    }
+}
+
+// the words of an attribute like `rel` or `class`, with `word` added
+fn adding(tag: &str, name: &str, word: &str) -> String {
+    let mut words: Vec<String> = html::attr(tag, name).unwrap_or_default()
+        .split_whitespace()
+        .map(|w| w.to_owned())
+        .collect();
+    if !words.iter().any(|w| w.eq_ignore_ascii_case(word)) {
+        words.push(word.to_owned());
+    }
+    words.join(" ")
+}
+
+// the opening tag of a link to another site, marked as one
+fn mark(tag: &str, config: &Config) -> String {
+    let policy = &config.external_links;
+    let mut tag = with_attr(tag, "rel", &adding(tag, "rel", "noopener"));
+    if !policy.class.is_empty() {
+        tag = with_attr(&tag, "class", &adding(&tag, "class", &policy.class));
+    }
+    if policy.new_tab && html::attr(&tag, "target").is_none() {
+        tag = with_attr(&tag, "target", "_blank");
+    }
+    tag
+}
+
+pub fn decorate(page: &str, config: &Config) -> String {
+    let mut out = String::with_capacity(page.len());
+    let mut rest = page;
+    while let Some(i) = rest.find("<a") {
+        let after = &rest[i + 2..];
+        let end = match after.find('>') {
+            Some(end) if after.starts_with(char::is_whitespace) => i + 2 + end,
+            _ => {
+                out.push_str(&rest[..i + 2]);
+                rest = after;
+                continue;
+            },
+        };
+        out.push_str(&rest[..i]);
+        let tag = &rest[i..end];
+        match html::attr(tag, "href") {
+            Some(ref href) if is_external(href, config) => out.push_str(&mark(tag, config)),
+            _ => out.push_str(tag),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,110 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use bib::Bibliography;
+use preview;
+
+// A C interface to the previews, for editors and other languages to embed
+// them: a bibliography is parsed once into a handle, and then its entries'
+// pages and the index's entries can be had from it. Strings go in as UTF-8
+// and come out as UTF-8 that has to be given back to
+// `biblionotes_string_free`. When a function returns null,
+// `biblionotes_last_error` says why. The header is include/biblionotes.h.
+//
+// Every pointer given to these functions must be null or what its type says:
+// a handle from `biblionotes_parse` that hasn't been freed, or a
+// NUL-terminated string.
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail<T>(error: String) -> *mut T {
+    let error = CString::new(error.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+    ptr::null_mut()
+}
+
+unsafe fn string<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("`{}` is null", name));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| format!("`{}` isn't UTF-8", name))
+}
+
+fn output(result: Result<String, String>) -> *mut c_char {
+    match result.and_then(|s| CString::new(s).map_err(|e| e.to_string())) {
+        Ok(s) => s.into_raw(),
+        Err(e) => fail(e),
+    }
+}
+
+// the bibliography in the BibTeX `source`, to be freed with `biblionotes_free`
+#[no_mangle]
+pub unsafe extern "C" fn biblionotes_parse(source: *const c_char) -> *mut Bibliography {
+    match string(source, "source") {
+        Ok(source) => Box::into_raw(Box::new(preview::parse(source))),
+        Err(e) => fail(e),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn biblionotes_free(bib: *mut Bibliography) {
+    if !bib.is_null() {
+        drop(Box::from_raw(bib));
+    }
+}
+
+// the page on `key` with `note` as its note, through `template`, or just the
+// page's content if `template` is null
+#[no_mangle]
+pub unsafe extern "C" fn biblionotes_render_entry(
+    bib: *const Bibliography,
+    key: *const c_char,
+    note: *const c_char,
+    template: *const c_char,
+) -> *mut c_char {
+    let bib = match bib.as_ref() {
+        Some(bib) => bib,
+        None => return fail("`bib` is null".to_owned()),
+    };
+    output((|| {
+        let key = string(key, "key")?;
+        let note = string(note, "note")?;
+        if template.is_null() {
+            let data = preview::page_data(bib, key, note)?;
+            return Ok(data["content"].as_str().unwrap_or_default().to_owned());
+        }
+        let template = string(template, "template")?;
+        preview::page_data(bib, key, note).and_then(|data| preview::fill(template, data))
+    })())
+}
+
+// the index's entries, as a JSON array of objects with a `key`, `link`,
+// `title`, `author`, `year` and `links` each
+#[no_mangle]
+pub unsafe extern "C" fn biblionotes_index_json(bib: *const Bibliography) -> *mut c_char {
+    match bib.as_ref() {
+        Some(bib) => output(
+            ::serde_json::to_string(&preview::index_entries(bib)).map_err(|e| e.to_string())
+        ),
+        None => fail("`bib` is null".to_owned()),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn biblionotes_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+// why the last function to fail on this thread did, until the next one does;
+// null if none has
+#[no_mangle]
+pub extern "C" fn biblionotes_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
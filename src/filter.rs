@@ -0,0 +1,133 @@
+use std::str::FromStr;
+use bib::Entry;
+
+// Which entries a partial build makes pages for, to rebuild only the notes
+// being worked on: `--only key1,key2` names them, and each `--filter` tests
+// the entry's `key`, `tag`, `type` or `year`, like `tag=ml`, `type!=misc` or
+// `year>=2020`. `=` and `!=` take several values, separated by commas, and
+// are true of an entry with any or none of them; years can also be compared
+// with `<`, `<=`, `>` and `>=`. An entry is built when it's one of those
+// named and passes every test.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Key,
+    Tag,
+    Kind,
+    Year,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// the longer operators first, so `>=` isn't taken for `>`
+const OPS: &[(&str, Op)] = &[
+    ("!=", Op::Ne),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+#[derive(Clone)]
+pub struct Condition {
+    field: Field,
+    op: Op,
+    values: Vec<String>,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Condition, String> {
+        let at = s.find(['=', '!', '<', '>'])
+            .ok_or_else(|| format!("`{}` isn't a filter, like tag=ml or year>=2020", s))?;
+        let (&(symbol, op), field) = OPS.iter()
+            .find(|&&(symbol, _)| s[at..].starts_with(symbol))
+            .map(|found| (found, s[..at].trim()))
+            .ok_or_else(|| format!("`{}` has no operator (=, !=, <, <=, > or >=)", s))?;
+        let field = match &field.to_lowercase()[..] {
+            "key" => Field::Key,
+            "tag" => Field::Tag,
+            "type" => Field::Kind,
+            "year" => Field::Year,
+            _ => return Err(format!(
+                "unknown filter field `{}` (expected key, tag, type or year)",
+                field
+            )),
+        };
+        let values: Vec<String> = s[at + symbol.len()..].split(',')
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+            .collect();
+        if values.is_empty() {
+            return Err(format!("`{}` has nothing to compare with", s));
+        }
+        let ordered = op != Op::Eq && op != Op::Ne;
+        if ordered && field != Field::Year {
+            return Err(format!("`{}`: only years can be compared with {}", s, symbol));
+        }
+        if field == Field::Year && values.iter().any(|v| v.parse::<i64>().is_err()) {
+            return Err(format!("`{}`: years must be numbers", s));
+        }
+        if ordered && values.len() > 1 {
+            return Err(format!("`{}`: {} takes a single year", s, symbol));
+        }
+        Ok(Condition { field, op, values })
+    }
+}
+
+// the year an entry came out, as a number
+fn year(entry: &Entry) -> Option<i64> {
+    let year = entry.year.trim();
+    let digits = year.find(|c: char| !c.is_ascii_digit()).unwrap_or(year.len());
+    year[..digits].parse().ok()
+}
+
+impl Condition {
+    fn holds(&self, entry: &Entry) -> bool {
+        if self.field == Field::Year && self.op != Op::Eq && self.op != Op::Ne {
+            let bound: i64 = self.values[0].parse().expect("Could not read the year");
+            return year(entry).is_some_and(|year| match self.op {
+                Op::Lt => year < bound,
+                Op::Le => year <= bound,
+                Op::Gt => year > bound,
+                _ => year >= bound,
+            });
+        }
+        let has = |value: &String| match self.field {
+            Field::Key => entry.key == *value,
+            Field::Tag => entry.tags().iter().any(|t| t.eq_ignore_ascii_case(value)),
+            Field::Kind => entry.kind.eq_ignore_ascii_case(value),
+            Field::Year => year(entry).is_some_and(|y| value.parse::<i64>() == Ok(y)),
+        };
+        self.values.iter().any(has) == (self.op == Op::Eq)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Filter {
+    // the keys from `--only`, if any
+    pub only: Vec<String>,
+    pub conditions: Vec<Condition>,
+}
+
+impl Filter {
+    // whether every entry passes, as in a full build
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.conditions.is_empty()
+    }
+
+    pub fn matches(&self, entry: &Entry) -> bool {
+        (self.only.is_empty() || self.only.contains(&entry.key))
+            && self.conditions.iter().all(|c| c.holds(entry))
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+// The metadata block a note can start with, between `---` lines, as pandoc
+// reads it:
+//
+//     ---
+//     status: reading
+//     rating: 4
+//     ---
+//
+// Only flat `key: value` pairs are understood; pandoc takes the block as
+// YAML, and the builtin renderer drops it.
+
+// the length in bytes of the front matter at the start of a note, including
+// its closing line, or 0 if there's none
+pub fn len(note: &str) -> usize {
+    let mut lines = note.split_inclusive('\n');
+    match lines.next() {
+        Some(first) if first.trim_end() == "---" => {},
+        _ => return 0,
+    }
+    let mut end = note.split_inclusive('\n').next().unwrap().len();
+    for line in lines {
+        end += line.len();
+        let line = line.trim_end();
+        if line == "---" || line == "..." {
+            return end;
+        }
+    }
+    0
+}
+
+// the `key: value` pairs of the front matter, with keys lowercased and
+// quotes around values removed
+pub fn fields(note: &str) -> BTreeMap<String, String> {
+    let block = &note[..len(note)];
+    let mut fields = BTreeMap::new();
+    for line in block.lines().skip(1) {
+        let (key, value) = match line.split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        if key.starts_with([' ', '\t', '-', '#']) {
+            continue;
+        }
+        let value = value.trim();
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        fields.insert(key.trim().to_lowercase(), value.to_owned());
+    }
+    fields
+}
+
+// the note with its front matter replaced by blank lines, so that line
+// numbers stay the same
+pub fn blank(note: &str) -> String {
+    let len = len(note);
+    let lines = note[..len].matches('\n').count();
+    format!("{}{}", "\n".repeat(lines), &note[len..])
+}
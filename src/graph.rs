@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde_json::{self, Map, Value};
+use bib::Bibliography;
+use html;
+use references;
+use text::Text;
+use xref;
+
+// Which papers cite which: an edge for every `[@key]` reference in a note,
+// every item of its reference list that's to an entry of the bibliography,
+// and every key in an entry's `cites` field. It's written as graph.json,
+// and drawn in graph.html with a small force layout, so that clusters of
+// related papers stand out.
+
+// how an edge is known, for the JSON and the colour of the line
+const FROM_NOTE: &str = "note";
+const FROM_BIB: &str = "cites";
+const FROM_REFERENCES: &str = "references";
+
+pub struct Graph {
+    // the keys of the entries in the graph, in bibliography order: those
+    // with pages and those with an edge
+    pub nodes: Vec<String>,
+    // citing key, cited key, and what says so
+    pub edges: BTreeMap<(String, String), BTreeSet<&'static str>>,
+}
+
+pub fn compute(
+    bib: &Bibliography,
+    notes: &HashMap<String, String>,
+    pages: &HashMap<String, String>,
+) -> Graph {
+    let mut edges: BTreeMap<(String, String), BTreeSet<&'static str>> = BTreeMap::new();
+    for entry in &bib.entries {
+        let key = &entry.key;
+        let note = notes.get(key).map_or("", |n| &n[..]);
+        let listed = references::extract(note, bib).into_iter().filter_map(|r| r.key);
+        let cited = xref::cited(note).into_iter().map(|k| (k, FROM_NOTE))
+            .chain(listed.map(|k| (k, FROM_REFERENCES)))
+            .chain(entry.cites().into_iter().map(|k| (k, FROM_BIB)));
+        for (cited, from) in cited {
+            if cited != *key && bib.get(&cited).is_some() {
+                edges.entry((key.clone(), cited)).or_default().insert(from);
+            }
+        }
+    }
+    let linked: BTreeSet<&String> = edges.keys().flat_map(|(a, b)| vec![a, b]).collect();
+    let nodes = bib.entries.iter()
+        .map(|e| &e.key)
+        .filter(|k| pages.contains_key(*k) || linked.contains(k))
+        .cloned()
+        .collect();
+    Graph { nodes, edges }
+}
+
+// the graph as JSON, with what's needed to label and link each node. Page
+// URLs are relative to the root of the site.
+pub fn json(graph: &Graph, bib: &Bibliography, pages: &HashMap<String, String>) -> Value {
+    let nodes = graph.nodes.iter().filter_map(|key| bib.get(key)).map(|entry| {
+        let mut node = Map::new();
+        node.insert("key".to_owned(), Value::String(entry.key.clone()));
+        node.insert("title".to_owned(), Value::String(entry.title.clone()));
+        node.insert("citation".to_owned(), Value::String(entry.short_citation()));
+        node.insert("year".to_owned(), Value::String(entry.year.clone()));
+        let tags = entry.tags().into_iter().map(Value::String).collect();
+        node.insert("tags".to_owned(), Value::Array(tags));
+        let url = pages.get(&entry.key).map_or(Value::Null, |u| Value::String(u.clone()));
+        node.insert("url".to_owned(), url);
+        Value::Object(node)
+    }).collect();
+    let edges = graph.edges.iter().map(|((from, to), how)| {
+        let mut edge = Map::new();
+        edge.insert("source".to_owned(), Value::String(from.clone()));
+        edge.insert("target".to_owned(), Value::String(to.clone()));
+        let how = how.iter().map(|h| Value::String((*h).to_owned())).collect();
+        edge.insert("from".to_owned(), Value::Array(how));
+        Value::Object(edge)
+    }).collect();
+    let mut root = Map::new();
+    root.insert("nodes".to_owned(), Value::Array(nodes));
+    root.insert("edges".to_owned(), Value::Array(edges));
+    Value::Object(root)
+}
+
+// lays the graph out in the browser: nodes push each other apart, edges pull
+// their ends together, and everything is drawn towards the middle. Nodes
+// with pages link to them; the others are the papers only cited.
+const SCRIPT: &str = r#"(function () {
+  var svg = document.getElementById("graph");
+  var ns = "http://www.w3.org/2000/svg";
+  var width = 900, height = 600;
+  var byKey = {};
+  graph.nodes.forEach(function (n, i) {
+    n.x = width / 2 + 200 * Math.cos(i);
+    n.y = height / 2 + 200 * Math.sin(i);
+    n.vx = n.vy = 0;
+    n.degree = 0;
+    byKey[n.key] = n;
+  });
+  graph.edges.forEach(function (e) {
+    e.a = byKey[e.source];
+    e.b = byKey[e.target];
+    e.a.degree++;
+    e.b.degree++;
+    e.line = document.createElementNS(ns, "line");
+    e.line.setAttribute("class", e.from.join() == "cites" ? "cites" : "note");
+    svg.appendChild(e.line);
+  });
+  graph.nodes.forEach(function (n) {
+    var group = document.createElementNS(ns, n.url ? "a" : "g");
+    if (n.url) group.setAttribute("href", n.url);
+    var circle = document.createElementNS(ns, "circle");
+    circle.setAttribute("r", 4 + 2 * Math.sqrt(n.degree));
+    circle.setAttribute("class", n.url ? "annotated" : "cited");
+    var title = document.createElementNS(ns, "title");
+    title.textContent = n.citation + ": " + n.title;
+    circle.appendChild(title);
+    group.appendChild(circle);
+    svg.appendChild(group);
+    n.circle = circle;
+  });
+  var steps = 300;
+  function step() {
+    var nodes = graph.nodes;
+    for (var i = 0; i < nodes.length; i++) {
+      for (var j = i + 1; j < nodes.length; j++) {
+        var dx = nodes[j].x - nodes[i].x, dy = nodes[j].y - nodes[i].y;
+        var d2 = Math.max(dx * dx + dy * dy, 1), f = 800 / d2;
+        var d = Math.sqrt(d2);
+        nodes[i].vx -= f * dx / d; nodes[i].vy -= f * dy / d;
+        nodes[j].vx += f * dx / d; nodes[j].vy += f * dy / d;
+      }
+    }
+    graph.edges.forEach(function (e) {
+      var dx = e.b.x - e.a.x, dy = e.b.y - e.a.y;
+      e.a.vx += 0.02 * dx; e.a.vy += 0.02 * dy;
+      e.b.vx -= 0.02 * dx; e.b.vy -= 0.02 * dy;
+    });
+    nodes.forEach(function (n) {
+      n.vx += 0.01 * (width / 2 - n.x);
+      n.vy += 0.01 * (height / 2 - n.y);
+      n.x = Math.min(width - 10, Math.max(10, n.x + n.vx));
+      n.y = Math.min(height - 10, Math.max(10, n.y + n.vy));
+      n.vx *= 0.6; n.vy *= 0.6;
+      n.circle.setAttribute("cx", n.x);
+      n.circle.setAttribute("cy", n.y);
+    });
+    graph.edges.forEach(function (e) {
+      e.line.setAttribute("x1", e.a.x); e.line.setAttribute("y1", e.a.y);
+      e.line.setAttribute("x2", e.b.x); e.line.setAttribute("y2", e.b.y);
+    });
+    if (--steps > 0) window.requestAnimationFrame(step);
+  }
+  step();
+})();"#;
+
+const STYLE: &str = "<style>\n  \
+    #graph { width: 100%; max-width: 900px; border: 1px solid #ddd; }\n  \
+    #graph line { stroke: #999; }\n  \
+    #graph line.cites { stroke-dasharray: 4 3; }\n  \
+    #graph circle.annotated { fill: #2a7ab0; }\n  \
+    #graph circle.cited { fill: #bbb; }\n\
+    </style>\n";
+
+// the content of graph.html, with the graph embedded so that the page works
+// without a server
+pub fn page(graph: &Value, text: &Text) -> String {
+    let data = serde_json::to_string(graph).expect("Could not serialise the graph")
+        // so nothing in a title can end the script
+        .replace("</", "<\\/");
+    format!(
+        "<header>\n  <h1>{}</h1>\n  <p>{}</p>\n</header>\n{}\
+         <svg id=\"graph\" viewBox=\"0 0 900 600\"></svg>\n\
+         <script>\nvar graph = {};\n{}\n</script>",
+        html::escape(text.get("graph")),
+        text.get("graph_intro"),
+        STYLE,
+        data,
+        SCRIPT
+    )
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use html;
+
+// server-side syntax highlighting of the code blocks in rendered notes. The
+// highlighted code only carries CSS classes; the colors come from a
+// stylesheet generated from the theme, so the theme can be changed without
+// re-rendering every note.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+const STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+impl Highlighter {
+    // `theme` is either the name of one of syntect's bundled themes (e.g.
+    // `InspiredGitHub`, `base16-ocean.dark`) or a path to a .tmTheme file
+    pub fn new(theme: &str) -> Result<Highlighter, String> {
+        let theme = if theme.ends_with(".tmTheme") {
+            ThemeSet::get_theme(Path::new(theme))
+                .map_err(|e| format!("could not load theme {}: {}", theme, e))?
+        } else {
+            let mut themes = ThemeSet::load_defaults().themes;
+            match themes.remove(theme) {
+                Some(t) => t,
+                None => {
+                    let names: Vec<_> = themes.keys().cloned().collect();
+                    return Err(format!(
+                        "unknown theme `{}` (available: {})",
+                        theme,
+                        names.join(", ")
+                    ));
+                },
+            }
+        };
+        Ok(Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        })
+    }
+
+    // the stylesheet templates should link to for highlighted code
+    pub fn css(&self) -> String {
+        css_for_theme_with_class_style(&self.theme, STYLE)
+            .expect("Could not generate highlighting stylesheet")
+    }
+
+    fn highlight_code(&self, lang: Option<&str>, code: &str) -> String {
+        let syntax = lang
+            .and_then(|l| self.syntaxes.find_syntax_by_token(l))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, STYLE);
+        for line in LinesWithEndings::from(code) {
+            if generator.parse_html_for_line_which_includes_newline(line).is_err() {
+                return html::escape(code);
+            }
+        }
+        generator.finalize()
+    }
+
+    // highlights every `<pre><code>` block in an HTML fragment. Both the
+    // pulldown-cmark form (`<code class="language-x">`) and pandoc's
+    // `--no-highlight` form (`<pre class="x">`) are understood.
+    pub fn highlight_html(&self, body: &str) -> String {
+        let mut out = String::with_capacity(body.len());
+        let mut rest = body;
+        while let Some(start) = rest.find("<pre") {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let block = parse_block(rest);
+            let (pre_tag, code_tag, code, len) = match block {
+                Some(b) => b,
+                None => {
+                    out.push_str("<pre");
+                    rest = &rest[4..];
+                    continue;
+                },
+            };
+            let lang = html::attr(code_tag, "class")
+                .and_then(|c| {
+                    c.split_whitespace()
+                        .find(|c| c.starts_with("language-"))
+                        .map(|c| c["language-".len()..].to_owned())
+                })
+                .or_else(|| {
+                    html::attr(pre_tag, "class")
+                        .and_then(|c| c.split_whitespace().next().map(|c| c.to_owned()))
+                });
+            let lang = lang.as_ref().map(|l| &l[..]);
+            let highlighted = self.highlight_code(lang, &html::unescape(code));
+            let class = lang
+                .map(|l| format!(" class=\"language-{}\"", html::escape(l)))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "<pre class=\"hl-code\"><code{}>{}</code></pre>",
+                class,
+                highlighted
+            ));
+            rest = &rest[len..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+// splits `<pre ...><code ...>...</code></pre>` at the start of `s` into the
+// two opening tags and the escaped code, plus the length of the whole block
+fn parse_block(s: &str) -> Option<(&str, &str, &str, usize)> {
+    let pre_end = s.find('>')? + 1;
+    let after_pre = &s[pre_end..];
+    if !after_pre.starts_with("<code") {
+        return None;
+    }
+    let code_end = after_pre.find('>')? + 1;
+    let code_start = pre_end + code_end;
+    let close = s[code_start..].find("</code></pre>")?;
+    Some((
+        &s[..pre_end],
+        &after_pre[..code_end],
+        &s[code_start..code_start + close],
+        code_start + close + "</code></pre>".len(),
+    ))
+}
@@ -0,0 +1,75 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use serde_json::{self, Map, Value};
+use bib::Entry;
+use config::Config;
+use error::Error;
+
+// Commands of your own that each note goes through on its way to becoming a
+// page, for syntax biblionotes doesn't know, like admonitions or shortcodes:
+// the `before` hook gets the note's Markdown just before it's converted (with
+// its cross-references already links), and the `after` hook the HTML it was
+// converted to, before it's put in the page. Each gets the text on stdin and
+// writes what to use instead on stdout. The entry comes in environment
+// variables: `BIBLIONOTES_KEY`, `BIBLIONOTES_TITLE`, `BIBLIONOTES_AUTHOR`,
+// `BIBLIONOTES_YEAR` and `BIBLIONOTES_TYPE`, and every field of it, as JSON,
+// in `BIBLIONOTES_ENTRY`. A hook that fails fails the note's page.
+
+// the entry as JSON, with its key, type and fields
+fn entry_json(entry: &Entry) -> String {
+    let mut fields = Map::new();
+    for (name, value) in &entry.fields {
+        fields.insert(name.clone(), Value::String(value.clone()));
+    }
+    let mut json = Map::new();
+    json.insert("key".to_owned(), Value::String(entry.key.clone()));
+    json.insert("type".to_owned(), Value::String(entry.kind.clone()));
+    json.insert("fields".to_owned(), Value::Object(fields));
+    serde_json::to_string(&json).expect("Could not serialise the entry")
+}
+
+fn run(command: &[String], hook: &str, entry: &Entry, input: &str) -> Result<String, Error> {
+    let failed = |e: String| Error::Hook(hook.to_owned(), e);
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .env("BIBLIONOTES_HOOK", hook)
+        .env("BIBLIONOTES_KEY", &entry.key)
+        .env("BIBLIONOTES_TITLE", &entry.title)
+        .env("BIBLIONOTES_AUTHOR", &entry.author)
+        .env("BIBLIONOTES_YEAR", &entry.year)
+        .env("BIBLIONOTES_TYPE", &entry.kind)
+        .env("BIBLIONOTES_ENTRY", entry_json(entry))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| failed(format!("could not run {}: {}", command[0], e)))?;
+    // unlike pandoc, a hook can write as it reads, like `sed` does, so the
+    // input goes in from another thread
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_owned();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output().map_err(|e| failed(e.to_string()))?;
+    if !output.status.success() {
+        return Err(failed(String::from_utf8_lossy(&output.stderr).trim().to_owned()));
+    }
+    writer.join().expect("Could not write to the hook").map_err(|e| failed(e.to_string()))?;
+    String::from_utf8(output.stdout).map_err(|_| failed("its output isn't UTF-8".to_owned()))
+}
+
+// the note's Markdown, after the `before` hook if there is one
+pub fn before(config: &Config, entry: &Entry, markdown: &str) -> Result<String, Error> {
+    match config.hooks.before {
+        Some(ref command) => run(command, "before", entry, markdown),
+        None => Ok(markdown.to_owned()),
+    }
+}
+
+// the note's HTML, after the `after` hook if there is one
+pub fn after(config: &Config, entry: &Entry, html: String) -> Result<String, Error> {
+    match config.hooks.after {
+        Some(ref command) => run(command, "after", entry, &html),
+        None => Ok(html),
+    }
+}
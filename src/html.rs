@@ -0,0 +1,273 @@
+// small helpers for generating and post-processing HTML
+use std::collections::HashMap;
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// undoes `escape`, plus the numeric forms pandoc likes to use
+pub fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+// the identifier pandoc gives a heading with the given text: lowercase,
+// with runs of spaces turned into hyphens, only letters, digits, `_`, `-`
+// and `.` kept, and starting with a letter
+pub fn identifier(text: &str) -> String {
+    let kept: String = text.chars()
+        .skip_while(|c| !c.is_alphabetic())
+        .filter(|&c| c.is_alphanumeric() || c.is_whitespace() || "_-.".contains(c))
+        .flat_map(char::to_lowercase)
+        .collect();
+    let id = kept.split_whitespace().collect::<Vec<_>>().join("-");
+    if id.is_empty() {
+        "section".to_owned()
+    } else {
+        id
+    }
+}
+
+// `identifier`, with `-1`, `-2`... added to ones already used on the page
+pub fn unique_identifier(text: &str, seen: &mut HashMap<String, usize>) -> String {
+    let id = identifier(text);
+    let count = seen.entry(id.clone()).or_insert(0);
+    *count += 1;
+    if *count == 1 {
+        id
+    } else {
+        format!("{}-{}", id, *count - 1)
+    }
+}
+
+// gives every `<h1>`...`<h6>` without an id the one pandoc would have given
+// it, so both renderers' headings can be linked to the same way
+pub fn add_heading_ids(body: &str) -> String {
+    let mut seen = HashMap::new();
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(i) = rest.find("<h") {
+        let level = rest[i + 2..].chars().next().filter(|c| ('1'..='6').contains(c));
+        let tag_end = rest[i..].find('>').map(|e| i + e);
+        let (level, tag_end) = match (level, tag_end) {
+            (Some(level), Some(tag_end)) => (level, tag_end),
+            _ => {
+                out.push_str(&rest[..i + 2]);
+                rest = &rest[i + 2..];
+                continue;
+            },
+        };
+        let tag = &rest[i..tag_end];
+        let close = format!("</h{}>", level);
+        let content_end = rest[tag_end..].find(&close).map_or(rest.len(), |e| tag_end + e);
+        let content = &rest[tag_end + 1..content_end];
+        out.push_str(&rest[..i]);
+        match attr(tag, "id") {
+            Some(id) => {
+                seen.insert(id, 1);
+                out.push_str(tag);
+            },
+            None => {
+                let id = unique_identifier(&unescape(&strip_tags(content)), &mut seen);
+                out.push_str(&format!("{} id=\"{}\"", tag, escape(&id)));
+            },
+        }
+        rest = &rest[tag_end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// a heading of a page, for its table of contents
+pub struct Heading {
+    pub level: usize,
+    pub id: String,
+    pub text: String,
+}
+
+// the headings of `body` that have ids, as `add_heading_ids` leaves them, in
+// order
+pub fn headings(body: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut rest = body;
+    while let Some(i) = rest.find("<h") {
+        rest = &rest[i + 2..];
+        let level = match rest.chars().next().and_then(|c| c.to_digit(10)) {
+            Some(level) if (1..=6).contains(&level) => level as usize,
+            _ => continue,
+        };
+        let tag_end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let close = format!("</h{}>", level);
+        let content_end = rest[tag_end..].find(&close).map_or(rest.len(), |e| tag_end + e);
+        if let Some(id) = attr(&rest[..tag_end], "id") {
+            let text = unescape(&strip_tags(&rest[tag_end + 1..content_end]));
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            headings.push(Heading { level, id, text });
+        }
+        rest = &rest[content_end..];
+    }
+    headings
+}
+
+// the text of an HTML fragment, without its tags
+pub fn strip_tags(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {},
+        }
+    }
+    out
+}
+
+// the name of a tag like `<p class="x">` or `</p>`, lowercased, and whether
+// it closes an element
+fn tag_name(tag: &str) -> (String, bool) {
+    let inner = tag.trim_start_matches('<');
+    let closing = inner.starts_with('/');
+    let name = inner.trim_start_matches('/')
+        .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    (name, closing)
+}
+
+// `html` without the elements `drop` picks by their name and opening tag,
+// along with what's in them
+fn without<F: Fn(&str, &str) -> bool>(html: &str, drop: F) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    // the element being left out, and how many of its kind are open in it
+    let mut dropping: Option<(String, usize)> = None;
+    while let Some(i) = rest.find('<') {
+        let end = match rest[i..].find('>') {
+            Some(end) => i + end + 1,
+            None => break,
+        };
+        let tag = &rest[i..end];
+        let (name, closing) = tag_name(tag);
+        if let Some((ref dropped, ref mut depth)) = dropping {
+            if name == *dropped && closing {
+                *depth -= 1;
+            } else if name == *dropped && !tag.ends_with("/>") {
+                *depth += 1;
+            }
+            if *depth == 0 {
+                dropping = None;
+            }
+        } else {
+            out.push_str(&rest[..i]);
+            if closing || !drop(&name, tag) {
+                out.push_str(tag);
+            } else if !tag.ends_with("/>") {
+                dropping = Some((name, 1));
+            }
+        }
+        rest = &rest[end..];
+    }
+    if dropping.is_none() {
+        out.push_str(rest);
+    }
+    out
+}
+
+// what holds paragraphs that aren't the note's own running text
+const ASIDES: &[&str] = &[
+    "aside", "blockquote", "details", "dl", "figure", "nav", "ol", "table", "ul",
+];
+
+// the first paragraph of a note's HTML as plain text, leaving out lists,
+// tables, quotes, definitions and footnotes, and in the paragraph, footnote
+// markers, math and other superscripts
+pub fn first_paragraph(html: &str) -> Option<String> {
+    let class_has = |tag: &str, word: &str| attr(tag, "class").is_some_and(|c| c.contains(word));
+    let prose = without(html, |name, tag| {
+        ASIDES.contains(&name)
+            || ((name == "section" || name == "div") && class_has(tag, "footnote"))
+    });
+    let prose = without(&prose, |name, tag| {
+        ["math", "script", "style", "sup"].contains(&name)
+            || (name == "span" && (class_has(tag, "math") || class_has(tag, "katex")))
+    });
+    let mut rest = &prose[..];
+    while let Some(i) = rest.find("<p") {
+        let after = &rest[i + 2..];
+        rest = after;
+        if !after.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            continue;
+        }
+        let start = match after.find('>') {
+            Some(start) => start + 1,
+            None => break,
+        };
+        let end = after.find("</p>").unwrap_or(after.len()).max(start);
+        let text = unescape(&strip_tags(&after[start..end]));
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !text.is_empty() {
+            return Some(text);
+        }
+    }
+    None
+}
+
+// the value of attribute `name` in an opening tag like `<pre class="rust">`
+pub fn attr(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+    while let Some(i) = rest.find(name) {
+        let before = rest[..i].chars().last();
+        let after = &rest[i + name.len()..];
+        rest = after;
+        if !before.is_some_and(|c| c.is_whitespace()) {
+            continue;
+        }
+        let after = after.trim_start();
+        if !after.starts_with('=') {
+            continue;
+        }
+        let value = after[1..].trim_start();
+        let quote = match value.chars().next() {
+            Some(q) if q == '"' || q == '\'' => q,
+            _ => {
+                let end = value.find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(value.len());
+                return Some(unescape(&value[..end]));
+            },
+        };
+        return value[1..].find(quote).map(|end| unescape(&value[1..end + 1]));
+    }
+    None
+}
+
+// moves the headings of a note `levels` levels down, to go below the
+// headings of a page bundling several notes
+pub fn demote_headings(html: &str, levels: usize) -> String {
+    let mut out = html.to_owned();
+    for level in (1..=6 - levels).rev() {
+        out = out.replace(&format!("<h{}", level), &format!("<h{}", level + levels))
+            .replace(&format!("</h{}>", level), &format!("</h{}>", level + levels));
+    }
+    out
+}
+
+// gives every id in a note's HTML, and the links within the note to them,
+// `prefix` in front, so that footnotes (which both renderers number from 1
+// in each note) and headings don't collide on a page bundling several notes
+pub fn prefix_ids(html: &str, prefix: &str) -> String {
+    html.replace(" id=\"", &format!(" id=\"{}-", prefix))
+        .replace(" href=\"#", &format!(" href=\"#{}-", prefix))
+}
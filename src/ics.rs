@@ -0,0 +1,117 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use dates;
+
+// iCalendar files (RFC 5545), for calendar apps to import or subscribe to.
+// Times are floating: an event at 16:00 is at 16:00 wherever the calendar
+// is, which is what a reading group that meets in one place wants.
+
+pub struct Event {
+    // unique and stable across builds, so that calendars update events
+    // instead of adding them again
+    pub uid: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub url: Option<String>,
+    // year, month and day
+    pub date: (i64, u32, u32),
+    // hours and minutes, and how long it lasts in minutes; all-day if unset
+    pub time: Option<(u32, u32, u32)>,
+}
+
+// text with the characters iCalendar gives a meaning escaped
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {},
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// adds a content line, folded so that no line is longer than 75 bytes
+fn push_line(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn date(days: i64) -> String {
+    let (year, month, day) = dates::civil_from_days(days);
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+// a time of day, `minutes` after the start of the day
+fn date_time(days: i64, minutes: i64) -> String {
+    let days = days + minutes.div_euclid(24 * 60);
+    let minutes = minutes.rem_euclid(24 * 60);
+    format!("{}T{:02}{:02}00", date(days), minutes / 60, minutes % 60)
+}
+
+// when the calendar was made, in UTC as iCalendar wants
+fn stamp(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let day = 24 * 60 * 60;
+    let (days, secs) = (secs.div_euclid(day), secs.rem_euclid(day));
+    format!("{}T{:02}{:02}{:02}Z", date(days), secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// a calendar of the given events. `made` is when its contents last
+// changed.
+pub fn calendar(name: &str, events: &[Event], made: SystemTime) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//biblionotes//biblionotes//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape(name)));
+    let made = stamp(made);
+    for event in events {
+        let (year, month, day) = event.date;
+        let days = dates::days_from_civil(year, month, day);
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:{}", event.uid));
+        push_line(&mut out, &format!("DTSTAMP:{}", made));
+        match event.time {
+            Some((hours, minutes, length)) => {
+                let start = (hours * 60 + minutes) as i64;
+                push_line(&mut out, &format!("DTSTART:{}", date_time(days, start)));
+                let end = date_time(days, start + length as i64);
+                push_line(&mut out, &format!("DTEND:{}", end));
+            },
+            None => {
+                push_line(&mut out, &format!("DTSTART;VALUE=DATE:{}", date(days)));
+                push_line(&mut out, &format!("DTEND;VALUE=DATE:{}", date(days + 1)));
+            },
+        }
+        push_line(&mut out, &format!("SUMMARY:{}", escape(&event.summary)));
+        if let Some(ref description) = event.description {
+            push_line(&mut out, &format!("DESCRIPTION:{}", escape(description)));
+        }
+        if let Some(ref location) = event.location {
+            push_line(&mut out, &format!("LOCATION:{}", escape(location)));
+        }
+        if let Some(ref url) = event.url {
+            push_line(&mut out, &format!("URL:{}", url));
+        }
+        push_line(&mut out, "END:VEVENT");
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
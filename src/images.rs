@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use html;
+
+// Images in notes are loaded lazily, and say how big they are, so that the
+// page doesn't jump about as they come in: every `<img>` gets
+// `loading="lazy"`, and the ones of attachments get the `width` and `height`
+// of their files, unless the note gave its own. Sizes are read from the
+// headers of PNG, GIF, JPEG and WebP files; other images only get
+// `loading`.
+
+fn be16(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from(*b.get(at)?) << 8 | u32::from(*b.get(at + 1)?))
+}
+
+fn le16(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from(*b.get(at)?) | u32::from(*b.get(at + 1)?) << 8)
+}
+
+fn le24(b: &[u8], at: usize) -> Option<u32> {
+    Some(le16(b, at)? | u32::from(*b.get(at + 2)?) << 16)
+}
+
+fn be32(b: &[u8], at: usize) -> Option<u32> {
+    Some(be16(b, at)? << 16 | be16(b, at + 2)?)
+}
+
+fn le32(b: &[u8], at: usize) -> Option<u32> {
+    Some(le16(b, at)? | le16(b, at + 2)? << 16)
+}
+
+// the size given by a JPEG's start-of-frame segment
+fn jpeg(b: &[u8]) -> Option<(u32, u32)> {
+    let mut at = 2;
+    loop {
+        if *b.get(at)? != 0xFF {
+            return None;
+        }
+        let marker = *b.get(at + 1)?;
+        match marker {
+            // padding, and markers without a length
+            0xFF => at += 1,
+            0x01 | 0xD0..=0xD7 => at += 2,
+            // frames, but not the huffman and arithmetic tables among them
+            0xC0..=0xCF if marker != 0xC4 && marker != 0xC8 && marker != 0xCC => {
+                return Some((be16(b, at + 7)?, be16(b, at + 5)?));
+            },
+            _ => at += 2 + be16(b, at + 2)? as usize,
+        }
+    }
+}
+
+fn webp(b: &[u8]) -> Option<(u32, u32)> {
+    match b.get(12..16)? {
+        b"VP8 " => Some((le16(b, 26)? & 0x3FFF, le16(b, 28)? & 0x3FFF)),
+        b"VP8L" => {
+            let bits = le32(b, 21)?;
+            Some(((bits & 0x3FFF) + 1, (bits >> 14 & 0x3FFF) + 1))
+        },
+        b"VP8X" => Some((le24(b, 24)? + 1, le24(b, 27)? + 1)),
+        _ => None,
+    }
+}
+
+// the width and height of an image file, in pixels
+pub fn dimensions(path: &Path) -> Option<(u32, u32)> {
+    let b = fs::read(path).ok()?;
+    let size = if b.starts_with(b"\x89PNG\r\n\x1a\n") {
+        (be32(&b, 16)?, be32(&b, 20)?)
+    } else if b.starts_with(b"GIF87a") || b.starts_with(b"GIF89a") {
+        (le16(&b, 6)?, le16(&b, 8)?)
+    } else if b.starts_with(&[0xFF, 0xD8]) {
+        jpeg(&b)?
+    } else if b.starts_with(b"RIFF") && b.get(8..12) == Some(&b"WEBP"[..]) {
+        webp(&b)?
+    } else {
+        return None;
+    };
+    Some(size).filter(|&(w, h)| w > 0 && h > 0)
+}
+
+// the extensions of the files whose sizes are read
+const EXTENSIONS: &[&str] = &["png", "gif", "jpg", "jpeg", "webp"];
+
+// the sizes of the images among `files`, from where each is in the output
+// directory to where it is now, by the links to them relative to `root`
+pub fn sizes(files: &BTreeMap<PathBuf, PathBuf>, root: &str) -> HashMap<String, (u32, u32)> {
+    files.iter()
+        .filter(|&(_, from)| {
+            let extension = from.extension().map(|e| e.to_string_lossy().to_lowercase());
+            extension.is_some_and(|e| EXTENSIONS.contains(&&e[..]))
+        })
+        .filter_map(|(to, from)| {
+            let link = format!("{}{}", root, to.to_string_lossy().replace(' ', "%20"));
+            Some((link, dimensions(from)?))
+        })
+        .collect()
+}
+
+// `body` with its images lazy, and sized from `sizes` by their `src`
+pub fn lazy(body: &str, sizes: &HashMap<String, (u32, u32)>) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(i) = rest.find("<img") {
+        let after = &rest[i + 4..];
+        let end = match after.find('>') {
+            Some(end) if after.starts_with(|c: char| c.is_whitespace()) => i + 4 + end,
+            _ => {
+                out.push_str(&rest[..i + 4]);
+                rest = after;
+                continue;
+            },
+        };
+        out.push_str(&rest[..i]);
+        // the attributes go before a closing `/`
+        let tag = rest[i..end].trim_end_matches('/').trim_end();
+        out.push_str(tag);
+        if html::attr(tag, "loading").is_none() {
+            out.push_str(" loading=\"lazy\"");
+        }
+        let sized = html::attr(tag, "width").is_some() || html::attr(tag, "height").is_some();
+        let src = html::attr(tag, "src").unwrap_or_default();
+        let src = src.split(['#', '?']).next().unwrap_or("");
+        if let Some(&(width, height)) = sizes.get(src).filter(|_| !sized) {
+            out.push_str(&format!(" width=\"{}\" height=\"{}\"", width, height));
+        }
+        out.push_str(&rest[i + tag.len()..end]);
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
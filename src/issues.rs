@@ -0,0 +1,212 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use serde_json::{self, Map, Value};
+use bib::{Bibliography, Entry};
+use config::{Config, Tracker};
+use diagnostics::Diagnostic;
+
+// An issue on GitHub or GitLab for every entry without a note, so a team can
+// hand out papers to annotate the way it hands out any other work. The
+// issues can be printed as JSON, for other tools to file, or opened directly
+// in the repository set under `[issues]`. The issues opened so far are kept
+// in `.biblionotes/issues.json` next to the bibliography, so each entry only
+// ever gets one.
+
+pub struct Issue {
+    pub key: String,
+    pub title: String,
+    pub body: String,
+}
+
+fn state_path(config: &Config) -> PathBuf {
+    let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+    dir.join(".biblionotes").join("issues.json")
+}
+
+// the URL of the issue opened for each key
+fn load(path: &Path) -> BTreeMap<String, String> {
+    let mut contents = String::new();
+    if File::open(path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return BTreeMap::new();
+    }
+    match serde_json::from_str::<Value>(&contents) {
+        Ok(value) => value.as_object().into_iter()
+            .flat_map(|o| o.iter())
+            .filter_map(|(key, url)| url.as_str().map(|u| (key.clone(), u.to_owned())))
+            .collect(),
+        Err(e) => {
+            Diagnostic::warning(format!("ignoring invalid issue list {}", path.display()))
+                .note(e.to_string())
+                .emit();
+            BTreeMap::new()
+        },
+    }
+}
+
+fn save(path: &Path, opened: &BTreeMap<String, String>) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let value: Map<String, Value> = opened.iter()
+        .map(|(key, url)| (key.clone(), Value::String(url.clone())))
+        .collect();
+    let json = serde_json::to_string_pretty(&value).map_err(io::Error::other)?;
+    File::create(path).and_then(|mut f| writeln!(f, "{}", json))
+}
+
+fn body(config: &Config, entry: &Entry) -> String {
+    let mut body = format!("{}: *{}*", entry.short_citation(), entry.title);
+    if let Some(venue) = entry.venue() {
+        body.push_str(&format!(", {}", venue));
+    }
+    body.push_str("\n\n");
+    for link in entry.links() {
+        body.push_str(&format!("- {}: {}\n", link.label, link.url));
+    }
+    if let Some(abs) = entry.text_field("abstract") {
+        body.push_str(&format!("\n> {}\n", abs.replace('\n', "\n> ")));
+    }
+    let note = config.markdown_dir.join(format!("{}.md", entry.key));
+    body.push_str(&format!("\nThe note goes in `{}`.\n", note.display()));
+    body
+}
+
+// the issues for the entries without notes that don't have one yet, in
+// bibliography order
+pub fn issues(
+    config: &Config,
+    bib: &Bibliography,
+    notes: &HashMap<String, String>,
+) -> Vec<Issue> {
+    let opened = load(&state_path(config));
+    bib.entries.iter()
+        .filter(|e| !notes.contains_key(&e.key) && !opened.contains_key(&e.key))
+        .map(|entry| Issue {
+            key: entry.key.clone(),
+            title: format!("Annotate {}", entry.short_citation()),
+            body: body(config, entry),
+        })
+        .collect()
+}
+
+pub fn json(config: &Config, issues: &[Issue]) -> String {
+    let labels: Vec<Value> = config.issues.labels.iter().cloned().map(Value::String).collect();
+    let issues = issues.iter().map(|issue| {
+        let mut object = Map::new();
+        object.insert("key".to_owned(), Value::String(issue.key.clone()));
+        object.insert("title".to_owned(), Value::String(issue.title.clone()));
+        object.insert("body".to_owned(), Value::String(issue.body.clone()));
+        object.insert("labels".to_owned(), Value::Array(labels.clone()));
+        Value::Object(object)
+    }).collect();
+    serde_json::to_string_pretty(&Value::Array(issues)).expect("Could not serialise issues")
+}
+
+// `%`-encodes a project path, which is how GitLab wants it in URLs
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                (b as char).to_string()
+            },
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// opens one issue, returning its URL
+fn open_one(config: &Config, issue: &Issue, token: &str) -> Result<String, String> {
+    let issues = &config.issues;
+    let (tracker, path) = issues.repo.as_ref().unwrap();
+    let mut request = Map::new();
+    request.insert("title".to_owned(), Value::String(issue.title.clone()));
+    let (url, auth, body_field, labels) = match *tracker {
+        Tracker::GitHub => (
+            format!("{}/repos/{}/issues", issues.api(), path),
+            format!("Authorization: Bearer {}", token),
+            "body",
+            Value::Array(issues.labels.iter().cloned().map(Value::String).collect()),
+        ),
+        Tracker::GitLab => (
+            format!("{}/projects/{}/issues", issues.api(), encode(path)),
+            format!("PRIVATE-TOKEN: {}", token),
+            "description",
+            Value::String(issues.labels.join(",")),
+        ),
+    };
+    request.insert(body_field.to_owned(), Value::String(issue.body.clone()));
+    request.insert("labels".to_owned(), labels);
+    let request = serde_json::to_string(&request).expect("Could not serialise issue");
+
+    let mut child = Command::new(&config.enrich.curl)
+        .args(["--silent", "--show-error", "--fail", "--request", "POST"])
+        .args(["--header", "Content-Type: application/json", "--header"])
+        .arg(auth)
+        .args(["--user-agent", "biblionotes", "--data-binary", "@-"])
+        .arg(&url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", config.enrich.curl, e))?;
+    child.stdin.take().unwrap().write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    let response = String::from_utf8_lossy(&output.stdout);
+    let response: Value = serde_json::from_str(&response).map_err(|e| e.to_string())?;
+    // GitHub and GitLab name it differently
+    response.find("html_url").or_else(|| response.find("web_url"))
+        .and_then(|u| u.as_str())
+        .map(|u| u.to_owned())
+        .ok_or_else(|| "the response has no issue URL".to_owned())
+}
+
+// opens the issues in the configured repository, stopping at the first one
+// that fails
+pub fn open(config: &Config, issues: &[Issue]) {
+    let &(tracker, ref repo) = match config.issues.repo {
+        Some(ref repo) => repo,
+        None => {
+            Diagnostic::error("no repository to open issues in")
+                .note("pass --repo, or set `repo` under [issues], like \"github:owner/name\"")
+                .emit();
+            ::std::process::exit(1);
+        },
+    };
+    let token = config.issues.token.clone()
+        .or_else(|| env::var(tracker.token_var()).ok())
+        .unwrap_or_else(|| {
+            Diagnostic::error(format!("no token to open issues in {}", repo))
+                .note(format!("set `token` under [issues], or {}", tracker.token_var()))
+                .emit();
+            ::std::process::exit(1);
+        });
+    let path = state_path(config);
+    let mut opened = load(&path);
+    let mut failed = false;
+    for issue in issues {
+        match open_one(config, issue, &token) {
+            Ok(url) => {
+                println!("opened {} for `{}`", url, issue.key);
+                opened.insert(issue.key.clone(), url);
+            },
+            Err(e) => {
+                Diagnostic::error(format!("could not open an issue for `{}`", issue.key))
+                    .note(e)
+                    .emit();
+                failed = true;
+                break;
+            },
+        }
+    }
+    save(&path, &opened).expect("Could not write the issue list");
+    if failed {
+        ::std::process::exit(1);
+    }
+}
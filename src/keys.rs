@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use serde_json::{self, Map, Value};
+use bib::{Bibliography, Entry};
+use collate;
+use config::Config;
+use diagnostics::Diagnostic;
+use encrypted;
+use provenance;
+use site;
+use xref;
+use zotero;
+
+// Keeps citation keys to a policy, since they end up in file names, URLs and
+// the `[@key]` references between notes: ASCII only, lowercase unless told
+// otherwise, and following a pattern like `{author}{year}{title}` if one is
+// set. `fmt --fix-keys` renames the keys that don't, along with their notes
+// and the references to them, and remembers the old keys so the build can
+// leave redirects behind.
+
+enum Part {
+    Literal(char),
+    // the first author's last name
+    Author,
+    // four digits
+    Year,
+    // the first significant word of the title
+    Title,
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if c != '{' {
+            parts.push(Part::Literal(c.to_ascii_lowercase()));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let end = rest.find('}')
+            .ok_or_else(|| format!("unclosed `{{` in the key pattern `{}`", pattern))?;
+        parts.push(match &rest[1..end] {
+            "author" => Part::Author,
+            "year" => Part::Year,
+            "title" => Part::Title,
+            other => return Err(format!(
+                "unknown placeholder `{{{}}}` in the key pattern `{}` \
+                 (expected author, year or title)",
+                other,
+                pattern
+            )),
+        });
+        rest = &rest[end + 1..];
+    }
+    Ok(parts)
+}
+
+// the configured pattern, exiting if it's invalid
+fn pattern(config: &Config) -> Option<Vec<Part>> {
+    config.keys.pattern.as_ref().map(|p| match parse_pattern(p) {
+        Ok(parts) => parts,
+        Err(e) => {
+            Diagnostic::error(e).emit();
+            ::std::process::exit(1);
+        },
+    })
+}
+
+// whether a lowercased key has the shape of the pattern, allowing for a
+// trailing letter to tell apart keys that would otherwise clash
+fn matches(parts: &[Part], key: &[char]) -> bool {
+    let (part, rest) = match parts.split_first() {
+        Some(split) => split,
+        None => return key.is_empty() || key.len() == 1 && key[0].is_ascii_lowercase(),
+    };
+    match *part {
+        Part::Literal(c) => key.first() == Some(&c) && matches(rest, &key[1..]),
+        Part::Year => {
+            key.len() >= 4 && key[..4].iter().all(|c| c.is_ascii_digit())
+                && matches(rest, &key[4..])
+        },
+        Part::Author | Part::Title => {
+            let word = |c: &char| match *part {
+                Part::Author => c.is_ascii_lowercase(),
+                _ => c.is_ascii_lowercase() || c.is_ascii_digit(),
+            };
+            let longest = key.iter().take_while(|c| word(c)).count();
+            (1..=longest).any(|n| matches(rest, &key[n..]))
+        },
+    }
+}
+
+// what's wrong with a key, if anything
+fn problems(config: &Config, parts: Option<&[Part]>, key: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !key.is_ascii() {
+        problems.push("it has non-ASCII characters".to_owned());
+    }
+    if config.keys.lowercase && key.chars().any(char::is_uppercase) {
+        problems.push("it has uppercase letters".to_owned());
+    }
+    if let Some(parts) = parts {
+        let lower: Vec<char> = key.to_lowercase().chars().collect();
+        if !matches(parts, &lower) {
+            let pattern = config.keys.pattern.as_ref().unwrap();
+            problems.push(format!("it doesn't follow the pattern `{}`", pattern));
+        }
+    }
+    problems
+}
+
+// title words too common to stand for the title
+const SMALL_WORDS: &[&str] = &[
+    "a", "an", "the", "on", "of", "in", "for", "and", "to", "with", "from", "is",
+];
+
+// an ASCII word made of the letters and digits of `s`
+fn ascii_word(s: &str) -> String {
+    collate::to_ascii(s).chars().filter(|c| c.is_ascii_alphanumeric()).collect()
+}
+
+// the key the policy would give an entry, if it has what the pattern needs
+fn generate(config: &Config, parts: Option<&[Part]>, entry: &Entry) -> Option<String> {
+    let key = match parts {
+        Some(parts) => {
+            let mut key = String::new();
+            for part in parts {
+                match *part {
+                    Part::Literal(c) => key.push(c),
+                    Part::Author => {
+                        let authors = entry.authors();
+                        let last = ascii_word(&authors.first()?.last);
+                        if last.is_empty() {
+                            return None;
+                        }
+                        key.push_str(&last);
+                    },
+                    Part::Year => {
+                        let digits: String = entry.year.chars()
+                            .filter(|c| c.is_ascii_digit())
+                            .collect();
+                        if digits.len() != 4 {
+                            return None;
+                        }
+                        key.push_str(&digits);
+                    },
+                    Part::Title => {
+                        let word = entry.title.split_whitespace()
+                            .map(ascii_word)
+                            .find(|w| {
+                                !w.is_empty() && !SMALL_WORDS.contains(&&w.to_lowercase()[..])
+                            })?;
+                        key.push_str(&word);
+                    },
+                }
+            }
+            key
+        },
+        None => collate::to_ascii(&entry.key),
+    };
+    if config.keys.lowercase {
+        Some(key.to_lowercase())
+    } else {
+        Some(key)
+    }
+}
+
+// reports the keys that break the policy, returning how many do
+pub fn lint(config: &Config, bib: &Bibliography) -> usize {
+    let parts = pattern(config);
+    let mut count = 0;
+    for entry in &bib.entries {
+        let problems = problems(config, parts.as_deref(), &entry.key);
+        if problems.is_empty() {
+            continue;
+        }
+        count += 1;
+        let d = Diagnostic::warning(format!("the key `{}` breaks the key policy", entry.key));
+        let mut d = bib.diagnostic_at(d, &entry.key, "this key");
+        for problem in problems {
+            d = d.note(problem);
+        }
+        d.note("`biblionotes fmt --fix-keys` can rename it").emit();
+    }
+    count
+}
+
+// the new key of every entry whose key breaks the policy, in bibliography
+// order. Keys that would clash get a letter added, as BibTeX styles do.
+fn renames(config: &Config, bib: &Bibliography) -> Vec<(String, String)> {
+    let parts = pattern(config);
+    let mut taken: HashSet<String> = bib.entries.iter().map(|e| e.key.to_lowercase()).collect();
+    let mut renames = Vec::new();
+    for entry in &bib.entries {
+        if problems(config, parts.as_deref(), &entry.key).is_empty() {
+            continue;
+        }
+        let base = match generate(config, parts.as_deref(), entry) {
+            Some(base) => base,
+            None => {
+                let d = Diagnostic::warning(format!("cannot rename `{}`", entry.key));
+                bib.diagnostic_at(d, &entry.key, "this entry")
+                    .note("it's missing the author or year the key pattern needs")
+                    .emit();
+                continue;
+            },
+        };
+        let new = ::std::iter::once(base.clone())
+            .chain(('a'..='z').map(|c| format!("{}{}", base, c)))
+            .find(|k| !taken.contains(&k.to_lowercase()));
+        match new {
+            Some(new) => {
+                taken.insert(new.to_lowercase());
+                renames.push((entry.key.clone(), new));
+            },
+            None => {
+                Diagnostic::warning(format!("cannot rename `{}`", entry.key))
+                    .note(format!("every key from `{}` to `{}z` is taken", base, base))
+                    .emit();
+            },
+        }
+    }
+    renames
+}
+
+// the byte offset of an entry's key in the bibliography source
+fn key_offset(bib: &Bibliography, entry: &Entry) -> Option<usize> {
+    let line_start = bib.source.split_inclusive('\n')
+        .take(entry.line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    let open = line_start + bib.source[line_start..].find(['{', '('])? + 1;
+    let rest = &bib.source[open..];
+    let start = open + rest.len() - rest.trim_start().len();
+    let after = bib.source[start..].strip_prefix(&entry.key[..])?;
+    if after.starts_with(|c: char| c == ',' || c.is_whitespace()) {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+// maps old keys to the ones that replaced them
+type Redirects = BTreeMap<String, String>;
+
+fn redirects_path(config: &Config) -> PathBuf {
+    let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+    dir.join(".biblionotes").join("redirects.json")
+}
+
+fn load_redirects(path: &Path) -> Redirects {
+    let mut contents = String::new();
+    if File::open(path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return Redirects::new();
+    }
+    match serde_json::from_str::<Value>(&contents) {
+        Ok(value) => value.as_object().into_iter()
+            .flat_map(|o| o.iter())
+            .filter_map(|(old, new)| new.as_str().map(|new| (old.clone(), new.to_owned())))
+            .collect(),
+        Err(e) => {
+            Diagnostic::warning(format!("ignoring invalid redirects {}", path.display()))
+                .note(e.to_string())
+                .emit();
+            Redirects::new()
+        },
+    }
+}
+
+fn save_redirects(path: &Path, redirects: &Redirects) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let value: Map<String, Value> = redirects.iter()
+        .map(|(old, new)| (old.clone(), Value::String(new.clone())))
+        .collect();
+    let json = serde_json::to_string_pretty(&value).map_err(io::Error::other)?;
+    File::create(path).and_then(|mut f| writeln!(f, "{}", json))
+}
+
+// the current key for each old one that was renamed, following keys renamed
+// more than once to the end
+pub fn redirects(config: &Config) -> HashMap<String, String> {
+    let redirects = load_redirects(&redirects_path(config));
+    let mut resolved = HashMap::new();
+    for old in redirects.keys() {
+        let mut new = &redirects[old];
+        // a cycle can only come from editing the file by hand
+        for _ in 0..redirects.len() {
+            match redirects.get(new) {
+                Some(next) => new = next,
+                None => break,
+            }
+        }
+        if new != old {
+            resolved.insert(old.clone(), new.clone());
+        }
+    }
+    resolved
+}
+
+fn fail(message: String, e: io::Error) -> ! {
+    Diagnostic::error(message).note(e.to_string()).emit();
+    ::std::process::exit(1);
+}
+
+// renames the keys that break the policy: in the bibliography, in the names
+// of their notes and in the references other notes make to them. Everything
+// is checked before anything is changed.
+pub fn fix(config: &Config, bib: &Bibliography) {
+    let renames = renames(config, bib);
+    if renames.is_empty() {
+        println!("every key follows the key policy");
+        return;
+    }
+    let new_keys: HashMap<String, String> = renames.iter().cloned().collect();
+
+    let mut offsets = Vec::new();
+    for (old, new) in &renames {
+        let entry = bib.get(old).unwrap();
+        match key_offset(bib, entry) {
+            Some(offset) => offsets.push((offset, old, new)),
+            None => {
+                let d = Diagnostic::error(format!("could not find the key `{}`", old));
+                bib.diagnostic_at(d, old, "in this entry")
+                    .note("nothing was renamed")
+                    .emit();
+                ::std::process::exit(1);
+            },
+        }
+        let from = site::note_path(config, old);
+        // drafts stay drafts, and notes keep their format
+        let ext = from.extension().unwrap_or_default().to_string_lossy();
+        let to = from.with_file_name(format!("{}.{}", new, ext));
+        if from.is_file() && to.exists() {
+            Diagnostic::error(format!("cannot rename the note on `{}`", old))
+                .note(format!("{} already exists", to.display()))
+                .note("nothing was renamed")
+                .emit();
+            ::std::process::exit(1);
+        }
+    }
+    let mut source = bib.source.clone();
+    offsets.sort_by_key(|&(offset, _, _)| ::std::cmp::Reverse(offset));
+    for (offset, old, new) in offsets {
+        source.replace_range(offset..offset + old.len(), new);
+    }
+    let (notes, failures) = site::read_notes(config, bib);
+    if !failures.is_empty() {
+        Diagnostic::error("some notes could not be read")
+            .note("nothing was renamed")
+            .emit();
+        ::std::process::exit(1);
+    }
+
+    // write the bibliography in one go, so it's never left half-renamed
+    let bib_path = Path::new(&config.bib_path);
+    let temp = bib_path.with_extension("bib.tmp");
+    File::create(&temp)
+        .and_then(|mut f| f.write_all(source.as_bytes()))
+        .and_then(|_| fs::rename(&temp, bib_path))
+        .unwrap_or_else(|e| fail(format!("could not write {}", bib_path.display()), e));
+    for (key, note) in &notes {
+        let path = site::note_path(config, key);
+        let locked = encrypted::path(config, key);
+        let key = new_keys.get(key).unwrap_or(key);
+        // encrypted notes can only be moved, since they can't be written
+        // back without the recipients' keys
+        if !path.is_file() && locked.is_file() {
+            let new_path = encrypted::path(config, key);
+            if new_path != locked {
+                fs::rename(&locked, &new_path).unwrap_or_else(|e| {
+                    fail(format!("could not rename {}", locked.display()), e)
+                });
+            }
+            if xref::rename(note, &new_keys) != *note {
+                Diagnostic::warning(format!("{} still uses the old keys", new_path.display()))
+                    .note("encrypted notes aren't rewritten; update its references by hand")
+                    .emit();
+            }
+            continue;
+        }
+        let ext = path.extension().unwrap_or_default().to_string_lossy();
+        let new_path = path.with_file_name(format!("{}.{}", key, ext));
+        if new_path != path {
+            fs::rename(&path, &new_path)
+                .unwrap_or_else(|e| fail(format!("could not rename {}", path.display()), e));
+        }
+        let renamed = xref::rename(note, &new_keys);
+        // notes in other formats were read as markdown, and can't be written
+        // back as it
+        if renamed != *note && site::note_format(config, &path).is_some() {
+            Diagnostic::warning(format!("{} still uses the old keys", new_path.display()))
+                .note("only markdown notes are rewritten; update its references by hand")
+                .emit();
+        } else if renamed != *note {
+            File::create(&new_path)
+                .and_then(|mut f| f.write_all(renamed.as_bytes()))
+                .unwrap_or_else(|e| fail(format!("could not write {}", new_path.display()), e));
+        }
+    }
+
+    let path = redirects_path(config);
+    let mut redirects = load_redirects(&path);
+    for (old, new) in &renames {
+        for target in redirects.values_mut().filter(|t| *t == old) {
+            *target = new.clone();
+        }
+        // the new key may have been used before
+        redirects.remove(new);
+        redirects.insert(old.clone(), new.clone());
+        println!("renamed `{}` to `{}`", old, new);
+    }
+    if let Err(e) = save_redirects(&path, &redirects) {
+        fail(format!("could not write {}", path.display()), e);
+    }
+    if let Err(e) = provenance::rename(config, &renames) {
+        fail("could not update where entries came from".to_owned(), e);
+    }
+    if let Err(e) = zotero::rename(config, &renames) {
+        fail("could not update what was synced with Zotero".to_owned(), e);
+    }
+}
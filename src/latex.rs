@@ -0,0 +1,205 @@
+use unicode_normalization::UnicodeNormalization;
+
+// Turns the LaTeX found in bibliography fields into plain Unicode text:
+// accents like `{\"o}` and `\v{c}` become the accented letters, commands
+// like `\ss` and `\&` become the characters they stand for, formatting
+// commands like `\emph{...}` are dropped (keeping their text), and so are
+// the braces BibTeX uses to protect capitals. Math between dollars is kept
+// as it is, for MathJax or KaTeX to deal with.
+
+// the combining character each accent command adds to its argument
+fn accent(command: &str) -> Option<char> {
+    Some(match command {
+        "'" => '\u{301}',
+        "`" => '\u{300}',
+        "^" => '\u{302}',
+        "\"" => '\u{308}',
+        "~" => '\u{303}',
+        "=" => '\u{304}',
+        "." => '\u{307}',
+        "u" => '\u{306}',
+        "v" => '\u{30c}',
+        "H" => '\u{30b}',
+        "c" => '\u{327}',
+        "k" => '\u{328}',
+        "r" => '\u{30a}',
+        "d" => '\u{323}',
+        "b" => '\u{331}',
+        _ => return None,
+    })
+}
+
+// commands that stand for a character (or a few) on their own
+fn symbol(command: &str) -> Option<&'static str> {
+    Some(match command {
+        "ss" => "ß",
+        "ae" => "æ",
+        "AE" => "Æ",
+        "oe" => "œ",
+        "OE" => "Œ",
+        "o" => "ø",
+        "O" => "Ø",
+        "aa" => "å",
+        "AA" => "Å",
+        "l" => "ł",
+        "L" => "Ł",
+        "i" => "ı",
+        "j" => "ȷ",
+        "&" => "&",
+        "%" => "%",
+        "$" => "$",
+        "#" => "#",
+        "_" => "_",
+        "{" => "{",
+        "}" => "}",
+        " " | "\\" => " ",
+        "ldots" | "dots" | "textellipsis" => "…",
+        "textendash" => "–",
+        "textemdash" => "—",
+        "textquoteleft" => "‘",
+        "textquoteright" => "’",
+        "textquotedblleft" => "“",
+        "textquotedblright" => "”",
+        "S" => "§",
+        "P" => "¶",
+        "copyright" | "textcopyright" => "©",
+        "textregistered" => "®",
+        "texttrademark" => "™",
+        "pounds" | "textsterling" => "£",
+        "euro" | "texteuro" => "€",
+        "textdegree" => "°",
+        // spacing and line-breaking hints
+        "," | ";" | ":" | "!" | "/" | "-" | "relax" | "noindent" | "newblock" => "",
+        _ => return None,
+    })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    // the name of the command after a backslash: a run of letters, or a
+    // single other character
+    fn command(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start && self.peek().is_some() {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        // spaces after a command made of letters only end the command
+        if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            while self.peek() == Some(' ') {
+                self.pos += 1;
+            }
+        }
+        name
+    }
+
+    // a command's argument: a braced group, another command, or a single
+    // character
+    fn argument(&mut self) -> String {
+        while self.peek() == Some(' ') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            Some('{') => {
+                self.pos += 1;
+                self.text(true)
+            },
+            Some('\\') => {
+                self.pos += 1;
+                self.after_backslash()
+            },
+            Some(c) => {
+                self.pos += 1;
+                c.to_string()
+            },
+            None => String::new(),
+        }
+    }
+
+    fn after_backslash(&mut self) -> String {
+        let command = self.command();
+        if let Some(mark) = accent(&command) {
+            // dotless letters are only there to make room for the accent
+            let mut arg = self.argument().replacen('ı', "i", 1).replacen('ȷ', "j", 1);
+            // the accent goes on the first letter, even in `\'{ab}`
+            let at = arg.char_indices().nth(1).map_or(arg.len(), |(i, _)| i);
+            arg.insert(at, mark);
+            return arg;
+        }
+        if let Some(s) = symbol(&command) {
+            return s.to_owned();
+        }
+        // formatting commands like `\emph{...}`, and any we don't know,
+        // are dropped, leaving their argument
+        String::new()
+    }
+
+    // the text up to the end of the input, or up to the closing brace if
+    // `group` is set
+    fn text(&mut self, group: bool) -> String {
+        let mut out = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '}' if group => return out,
+                '{' | '}' => {},
+                '\\' => out.push_str(&self.after_backslash()),
+                '~' => out.push('\u{a0}'),
+                '$' => {
+                    // keep math, dollars and all
+                    out.push('$');
+                    while let Some(m) = self.peek() {
+                        self.pos += 1;
+                        out.push(m);
+                        if m == '\\' {
+                            if let Some(escaped) = self.peek() {
+                                self.pos += 1;
+                                out.push(escaped);
+                            }
+                        } else if m == '$' {
+                            break;
+                        }
+                    }
+                },
+                '-' if self.starts_with("--") => {
+                    self.pos += 2;
+                    out.push('—');
+                },
+                '-' if self.starts_with("-") => {
+                    self.pos += 1;
+                    out.push('–');
+                },
+                '`' if self.starts_with("`") => {
+                    self.pos += 1;
+                    out.push('“');
+                },
+                '\'' if self.starts_with("'") => {
+                    self.pos += 1;
+                    out.push('”');
+                },
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+}
+
+pub fn to_unicode(s: &str) -> String {
+    let mut parser = Parser { chars: s.chars().collect(), pos: 0 };
+    parser.text(false).nfc().collect()
+}
@@ -0,0 +1,41 @@
+// The core of biblionotes: reading BibTeX, turning notes into HTML with the
+// builtin renderer, and putting them through a template. It doesn't need
+// pandoc, the file system or the command line, so it also builds for wasm32,
+// for previews in a browser or a serverless function; the `wasm` feature
+// exports them to JavaScript, the `ffi` feature to C and the `python` feature
+// to Python.
+
+extern crate bib_parser;
+extern crate handlebars;
+extern crate log;
+#[cfg(feature = "python")]
+extern crate pyo3;
+extern crate pulldown_cmark;
+extern crate serde_json;
+extern crate toml;
+extern crate unicode_normalization;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
+pub mod bib;
+pub mod config;
+pub mod csl;
+pub mod dates;
+pub mod diagnostics;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filter;
+pub mod frontmatter;
+pub mod html;
+pub mod latex;
+pub mod logging;
+pub mod markdown;
+pub mod names;
+pub mod preview;
+#[cfg(feature = "python")]
+mod python;
+pub mod render;
+pub mod template;
+pub mod text;
+pub mod xref;
@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+use config::Config;
+use diagnostics::Diagnostic;
+use html;
+use logging::Progress;
+use output;
+use sitemap;
+
+// Checks the links in a built site: that links between pages, and to
+// stylesheets, scripts and images, point at files that are there, and that
+// links to a part of a page point at an id the page has. With `--external`,
+// links to other sites are checked too, by asking for each URL once.
+
+// the attribute holding the link, for the tags that have one
+const LINKING: &[(&str, &str)] = &[
+    ("a", "href"),
+    ("area", "href"),
+    ("link", "href"),
+    ("img", "src"),
+    ("script", "src"),
+    ("iframe", "src"),
+    ("source", "src"),
+    ("audio", "src"),
+    ("video", "src"),
+    ("embed", "src"),
+];
+
+struct Link {
+    line: usize,
+    target: String,
+}
+
+// the line of a link, the link, and what's wrong with it, or for external
+// links, the URL to ask for
+type Problem<'a> = (usize, &'a str, Result<String, String>);
+
+struct Page {
+    links: Vec<Link>,
+    ids: HashSet<String>,
+}
+
+// the links on a page, with the lines they're on, and the ids links can
+// point at
+fn scan(page: &str) -> Page {
+    let mut links = Vec::new();
+    let mut ids = HashSet::new();
+    let mut i = 0;
+    while let Some(start) = page[i..].find('<').map(|s| i + s) {
+        let rest = &page[start..];
+        if rest.starts_with("<!--") {
+            i = rest.find("-->").map_or(page.len(), |e| start + e + 3);
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = &page[start..end];
+        let name = tag[1..].split(|c: char| !c.is_ascii_alphanumeric()).next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        i = end;
+        if let Some(id) = html::attr(tag, "id") {
+            ids.insert(id);
+        }
+        if name == "a" {
+            if let Some(anchor) = html::attr(tag, "name") {
+                ids.insert(anchor);
+            }
+        }
+        let linking = LINKING.iter().find(|&&(t, _)| t == name).map(|&(_, a)| a);
+        if let Some(target) = linking.and_then(|a| html::attr(tag, a)) {
+            let line = page[..start].matches('\n').count() + 1;
+            links.push(Link { line, target: target.trim().to_owned() });
+        }
+        // what's in scripts and styles only looks like tags
+        if name == "script" || name == "style" {
+            let close = format!("</{}", name);
+            i = page[end..].find(&close).map_or(page.len(), |e| end + e);
+        }
+    }
+    Page { links, ids }
+}
+
+// `%20` and the like, decoded
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(b) if bytes[i] == b'%' => {
+                out.push(b);
+                i += 3;
+            },
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+enum Target {
+    External(String),
+    // a file of the site, relative to its root, and maybe the id of a part
+    Internal(PathBuf, Option<String>),
+    // what can't be checked, like `mailto:` links
+    Other,
+}
+
+// what a link on the page at `page` (relative to the root) points at. Links
+// to the site's own base URL are internal ones, and so are paths starting
+// with `/`, which start at the path of the base URL.
+fn target(link: &str, page: &Path, base: Option<&str>) -> Result<Target, String> {
+    // like `/notes/` for `https://example.org/notes/`
+    let root = base.and_then(|b| b.splitn(4, '/').nth(3)).map_or("/".to_owned(), |p| {
+        format!("/{}", p)
+    });
+    let link = match base {
+        Some(base) if link.starts_with(base) => format!("{}{}", root, &link[base.len()..]),
+        _ => link.to_owned(),
+    };
+    let link = &link[..];
+    if link.starts_with("//") {
+        return Ok(Target::External(format!("https:{}", link)));
+    }
+    let scheme = link.split(':').next().filter(|s| {
+        s.len() < link.len()
+            && s.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+    });
+    match scheme.map(|s| s.to_ascii_lowercase()) {
+        Some(ref s) if s == "http" || s == "https" => {
+            return Ok(Target::External(link.to_owned()));
+        },
+        Some(_) => return Ok(Target::Other),
+        None => {},
+    }
+    let (path, fragment) = match link.split_once('#') {
+        Some((path, fragment)) => (path, Some(decode(fragment))),
+        None => (link, None),
+    };
+    let path = path.split('?').next().unwrap_or("");
+    let fragment = fragment.filter(|f| !f.is_empty());
+    if path.is_empty() {
+        return Ok(Target::Internal(page.to_owned(), fragment));
+    }
+    let path = decode(path);
+    let mut resolved = PathBuf::new();
+    let joined = if path.starts_with('/') {
+        match path.strip_prefix(&root[..]) {
+            Some(path) => PathBuf::from(path),
+            None => return Err(format!("is outside {}", root)),
+        }
+    } else {
+        page.parent().unwrap_or_else(|| Path::new("")).join(&path)
+    };
+    for component in joined.components() {
+        match component {
+            Component::Normal(c) => resolved.push(c),
+            Component::ParentDir if !resolved.pop() => {
+                return Err("points outside the site".to_owned());
+            },
+            _ => {},
+        }
+    }
+    if path.ends_with('/') {
+        resolved.push("index.html");
+    }
+    Ok(Target::Internal(resolved, fragment))
+}
+
+// asks for a URL, without downloading it where the server allows
+fn fetch(config: &Config, url: &str) -> Result<(), String> {
+    let get = |head: bool| {
+        let mut command = Command::new(&config.enrich.curl);
+        command.args(["--silent", "--show-error", "--location"])
+            .args(["--user-agent", "biblionotes", "--write-out", "\n%{http_code}"])
+            .arg("--max-time").arg(config.links.timeout.to_string());
+        if head {
+            command.arg("--head");
+        } else {
+            command.args(["--range", "0-0"]);
+        }
+        let output = command.arg(url)
+            .output()
+            .map_err(|e| format!("could not run {}: {}", config.enrich.curl, e))?;
+        if !output.status.success() {
+            let e = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("could not be reached ({})", e.trim()));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout.lines().last().and_then(|code| code.trim().parse().ok()).unwrap_or(0))
+    };
+    let mut status: u32 = get(true)?;
+    // some servers don't do HEAD
+    if status == 405 || status == 501 {
+        status = get(false)?;
+    }
+    if status >= 400 {
+        return Err(format!("gives HTTP status {}", status));
+    }
+    Ok(())
+}
+
+// the external URLs that are broken, and why, asking for several at once
+fn check_external(config: &Config, urls: Vec<String>) -> HashMap<String, String> {
+    if urls.is_empty() {
+        return HashMap::new();
+    }
+    let progress = Mutex::new(Progress::new(urls.len()));
+    let queue = Mutex::new(urls.into_iter());
+    let broken = Mutex::new(HashMap::new());
+    thread::scope(|scope| {
+        for _ in 0..config.links.jobs {
+            scope.spawn(|| loop {
+                let url = match queue.lock().unwrap().next() {
+                    Some(url) => url,
+                    None => break,
+                };
+                progress.lock().unwrap().start(&url);
+                debug!("checking {}", url);
+                if let Err(e) = fetch(config, &url) {
+                    broken.lock().unwrap().insert(url, e);
+                }
+                progress.lock().unwrap().finish_one();
+            });
+        }
+    });
+    progress.into_inner().unwrap().finish();
+    broken.into_inner().unwrap()
+}
+
+// checks the links of every page under `dir`, reporting the broken ones
+// page by page, and returns how many there are
+pub fn check(config: &Config, dir: &Path, external: bool) -> usize {
+    let mut files = Vec::new();
+    output::files_under(dir, &mut files);
+    let files: HashSet<PathBuf> = files.iter()
+        .filter_map(|f| f.strip_prefix(dir).ok())
+        .map(|f| f.to_owned())
+        .collect();
+    let mut pages = BTreeMap::new();
+    for file in files.iter().filter(|f| f.extension().is_some_and(|e| e == "html")) {
+        let mut contents = String::new();
+        File::open(dir.join(file))
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .expect("Could not read page");
+        pages.insert(file.clone(), scan(&contents));
+    }
+    if pages.is_empty() {
+        Diagnostic::error(format!("no pages in {}", dir.display())).emit();
+        ::std::process::exit(1);
+    }
+
+    let base = config.base_url.as_ref().map(|b| sitemap::normalize_base(b));
+    let mut problems: BTreeMap<&Path, Vec<Problem>> = BTreeMap::new();
+    let mut urls = HashSet::new();
+    for (path, page) in &pages {
+        for link in page.links.iter().filter(|l| !l.target.is_empty()) {
+            let problem = match target(&link.target, path, base.as_ref().map(|b| &b[..])) {
+                Err(e) => Some(Err(e)),
+                Ok(Target::External(url)) => {
+                    if external {
+                        urls.insert(url.clone());
+                        Some(Ok(url))
+                    } else {
+                        None
+                    }
+                },
+                Ok(Target::Other) => None,
+                Ok(Target::Internal(file, fragment)) => {
+                    // a link to a directory is one to its index.html
+                    let file = if files.contains(&file) {
+                        file
+                    } else {
+                        file.join("index.html")
+                    };
+                    if !files.contains(&file) {
+                        Some(Err("doesn't exist".to_owned()))
+                    } else {
+                        match (pages.get(&file), fragment) {
+                            (Some(page), Some(id)) if !page.ids.contains(&id) => {
+                                Some(Err(format!("has no `#{}` to go to", id)))
+                            },
+                            _ => None,
+                        }
+                    }
+                },
+            };
+            if let Some(problem) = problem {
+                problems.entry(path).or_default().push((link.line, &link.target, problem));
+            }
+        }
+    }
+
+    let mut urls: Vec<String> = urls.into_iter().collect();
+    urls.sort();
+    let broken = check_external(config, urls);
+    let mut count = 0;
+    for (path, problems) in problems {
+        let problems: Vec<_> = problems.into_iter()
+            .filter_map(|(line, link, problem)| match problem {
+                Err(e) => Some((line, link, e)),
+                Ok(url) => broken.get(&url).map(|e| (line, link, e.clone())),
+            })
+            .collect();
+        if problems.is_empty() {
+            continue;
+        }
+        count += problems.len();
+        let mut diagnostic =
+            Diagnostic::warning(format!("broken links in {}", dir.join(path).display()));
+        for (line, link, e) in problems {
+            diagnostic = diagnostic.note(format!("line {}: `{}` {}", line, link, e));
+        }
+        diagnostic.emit();
+    }
+    count
+}
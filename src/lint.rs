@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, HashMap};
+use bib::Bibliography;
+use check;
+use config::{Config, FieldKind, LintConfig};
+use dates;
+use diagnostics::Diagnostic;
+use frontmatter;
+use markdown;
+use reading::Reading;
+use site;
+
+// Rules every note is held to, for `biblionotes lint`, so that notes written
+// by several people have the same shape: sections they must have, how long
+// they must be, no TODO markers, and what their front matter must hold. The
+// rules are under `[lint]` in the config file. Each note that breaks any is
+// reported, with every rule it breaks.
+
+// a broken rule, and the (1-based) line of the note it's about, if any
+struct Violation {
+    line: Option<usize>,
+    message: String,
+}
+
+fn violation<S: Into<String>>(line: Option<usize>, message: S) -> Violation {
+    Violation { line, message: message.into() }
+}
+
+// the line of the front matter field `name`
+fn field_line(note: &str, name: &str) -> Option<usize> {
+    let is_field = |line: &str| {
+        line.split_once(':').is_some_and(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+    };
+    note[..frontmatter::len(note)].lines().position(is_field).map(|i| i + 1)
+}
+
+fn expected(kind: &FieldKind) -> String {
+    match *kind {
+        FieldKind::Text => "text".to_owned(),
+        FieldKind::Number => "a number".to_owned(),
+        FieldKind::Boolean => "true or false".to_owned(),
+        FieldKind::Date => "a date (YYYY-MM-DD)".to_owned(),
+        FieldKind::OneOf(ref values) => format!("one of {}", values.join(", ")),
+    }
+}
+
+fn holds(kind: &FieldKind, value: &str) -> bool {
+    match *kind {
+        FieldKind::Text => true,
+        FieldKind::Number => value.parse::<f64>().is_ok(),
+        FieldKind::Boolean => ["true", "false", "yes", "no"].contains(&value),
+        FieldKind::Date => dates::parse_civil(value).is_ok(),
+        FieldKind::OneOf(ref values) => values.iter().any(|v| v == value),
+    }
+}
+
+// what's wrong with the front matter: fields the build reads that don't make
+// sense, fields that must be there and aren't, and fields that don't hold
+// what they should
+fn front_matter(rules: &LintConfig, note: &str, found: &mut Vec<Violation>) {
+    let opened = note.lines().next().is_some_and(|l| l.trim_end() == "---");
+    if opened && frontmatter::len(note) == 0 {
+        found.push(violation(Some(1), "the front matter isn't closed with `---`"));
+        return;
+    }
+    let fields = frontmatter::fields(note);
+    for name in ["status", "rating"] {
+        if let Some(value) = fields.get(name) {
+            let one = BTreeMap::from([(name.to_owned(), value.clone())]);
+            for e in Reading::from_fields(&one).1 {
+                found.push(violation(field_line(note, name), e));
+            }
+        }
+    }
+    let mut kinds: Vec<(&str, &FieldKind)> = vec![("draft", &FieldKind::Boolean)];
+    kinds.extend(rules.fields.iter().map(|(name, kind)| (&name[..], kind)));
+    for (name, kind) in kinds {
+        let value = match fields.get(name) {
+            Some(value) => value,
+            None => continue,
+        };
+        if !holds(kind, value) {
+            let message = format!("`{}` is `{}`, but must be {}", name, value, expected(kind));
+            found.push(violation(field_line(note, name), message));
+        }
+    }
+    for name in &rules.required_fields {
+        if !fields.contains_key(&name.to_lowercase()) {
+            found.push(violation(None, format!("there's no `{}` in the front matter", name)));
+        }
+    }
+}
+
+fn violations(rules: &LintConfig, note: &str) -> Vec<Violation> {
+    let mut found = Vec::new();
+    front_matter(rules, note, &mut found);
+    let headings = markdown::headings(note);
+    for section in &rules.sections {
+        if !headings.iter().any(|h| h.text.eq_ignore_ascii_case(section.trim())) {
+            found.push(violation(None, format!("there's no \"{}\" section", section)));
+        }
+    }
+    let words = markdown::prose(note).split_whitespace().count();
+    if words < rules.min_words {
+        let message = format!(
+            "it's {} words long, under the {} it must be",
+            words,
+            rules.min_words
+        );
+        found.push(violation(None, message));
+    }
+    if !rules.allow_todo {
+        for line in check::todo_lines(note) {
+            found.push(violation(Some(line), "a TODO marker"));
+        }
+    }
+    found
+}
+
+// reports the notes that break the rules, and returns how many rules they
+// break altogether
+pub fn run(config: &Config, bib: &Bibliography, notes: &HashMap<String, String>) -> usize {
+    let mut broken = 0;
+    for entry in &bib.entries {
+        let note = match notes.get(&entry.key) {
+            Some(note) => note,
+            None => continue,
+        };
+        let found = violations(&config.lint, note);
+        if found.is_empty() {
+            continue;
+        }
+        broken += found.len();
+        let path = site::note_path(config, &entry.key).display().to_string();
+        let rules = if found.len() == 1 { "a rule" } else { "rules" };
+        let message = format!("the note on `{}` breaks {}", entry.key, rules);
+        let mut d = Diagnostic::warning(message).in_file(&path, note);
+        for v in &found {
+            d = match v.line {
+                Some(line) => d.label(line, 1, 0, &v.message[..]),
+                None => d.note(&v.message[..]),
+            };
+        }
+        d.emit();
+    }
+    broken
+}
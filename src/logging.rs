@@ -0,0 +1,118 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use log::{self, LevelFilter, Log, Metadata, Record};
+
+// What the build is doing, as much as asked for with `-q` and `-v`, and a
+// progress bar while entries are built. Everything goes to stderr, like
+// diagnostics, and is printed above the progress bar.
+
+// how wide the bar itself is, and the most of the current key that's shown
+const BAR_WIDTH: usize = 30;
+const KEY_WIDTH: usize = 40;
+
+// the progress bar on screen, if there is one
+static BAR: Mutex<Option<String>> = Mutex::new(None);
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // not the chatter of our dependencies
+        metadata.level() <= log::max_level() && metadata.target().starts_with("biblionotes")
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let level = record.level().as_str().to_lowercase();
+            print(&format!("{}: {}\n", level, record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+// `verbosity` is -1 for `-q`, which leaves only errors, and otherwise the
+// number of `-v`s: one adds timings, two everything
+pub fn init(verbosity: i32) {
+    let level = match verbosity {
+        v if v < 0 => LevelFilter::Error,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+// whether only errors are wanted
+pub fn quiet() -> bool {
+    log::max_level() < LevelFilter::Warn
+}
+
+// writes to stderr, moving the progress bar below the text
+pub fn print(text: &str) {
+    let bar = BAR.lock().unwrap();
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+    if bar.is_some() {
+        write!(stderr, "\r\x1b[K").unwrap();
+    }
+    write!(stderr, "{}", text).unwrap();
+    if let Some(ref bar) = *bar {
+        write!(stderr, "{}", bar).unwrap();
+    }
+}
+
+// how far through the entries a build is. It's only shown on a terminal,
+// and not with `-q`.
+pub struct Progress {
+    total: usize,
+    done: usize,
+    shown: bool,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Progress {
+        let shown = total > 0
+            && io::stderr().is_terminal()
+            && log::max_level() >= LevelFilter::Info;
+        Progress { total, done: 0, shown }
+    }
+
+    // shows that work on the entry with the given key has started
+    pub fn start(&mut self, key: &str) {
+        if !self.shown {
+            return;
+        }
+        let filled = BAR_WIDTH * self.done / self.total;
+        let head = if filled < BAR_WIDTH { ">" } else { "" };
+        let key: String = key.chars().take(KEY_WIDTH).collect();
+        let bar = format!(
+            "[{}{}{}] {}/{} {}",
+            "=".repeat(filled),
+            head,
+            " ".repeat(BAR_WIDTH - filled - head.len()),
+            self.done,
+            self.total,
+            key
+        );
+        let mut shown = BAR.lock().unwrap();
+        eprint!("\r\x1b[K{}", bar);
+        *shown = Some(bar);
+    }
+
+    pub fn finish_one(&mut self) {
+        self.done += 1;
+    }
+
+    // takes the bar off the screen
+    pub fn finish(self) {
+        let mut shown = BAR.lock().unwrap();
+        if shown.take().is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
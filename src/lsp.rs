@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::time::SystemTime;
+use serde_json::{self, Value};
+use bib::{self, Bibliography, Entry};
+use diagnostics::Diagnostic;
+use xref;
+
+// A language server for notes, for `biblionotes lsp`: editors start it and
+// talk to it over stdin and stdout. It completes keys after `@`, shows what
+// an entry is when the cursor is on its key, and marks references to keys
+// the bibliography doesn't have. The bibliography is read again whenever it
+// changes. Documents are synced whole, and positions are in UTF-16, as the
+// protocol has them by default.
+
+// what the protocol calls errors and severities
+const METHOD_NOT_FOUND: i64 = -32601;
+const SEVERITY_ERROR: u64 = 1;
+// completion items that are references
+const KIND_REFERENCE: u64 = 18;
+
+struct Server {
+    bib_path: String,
+    bib: Bibliography,
+    modified: Option<SystemTime>,
+    // the text of each open document, by URI
+    documents: HashMap<String, String>,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn object(pairs: Vec<(&str, Value)>) -> Value {
+    Value::Object(pairs.into_iter().map(|(k, v)| (k.to_owned(), v)).collect())
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_owned())
+}
+
+// the next message, or `None` at the end of the input
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "a message has no Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_str(&String::from_utf8_lossy(&body))
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+fn send(message: Value) {
+    let body = serde_json::to_string(&message).expect("Could not serialise a message");
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| stdout.flush())
+        .expect("Could not write to the editor");
+}
+
+fn respond(id: &Value, result: Value) {
+    send(object(vec![
+        ("jsonrpc", string("2.0")),
+        ("id", id.clone()),
+        ("result", result),
+    ]));
+}
+
+fn notify(method: &str, params: Value) {
+    send(object(vec![
+        ("jsonrpc", string("2.0")),
+        ("method", string(method)),
+        ("params", params),
+    ]));
+}
+
+// the line and UTF-16 column of a byte offset
+fn position(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character: usize = before[start..].chars().map(char::len_utf16).sum();
+    object(vec![
+        ("line", Value::U64(line as u64)),
+        ("character", Value::U64(character as u64)),
+    ])
+}
+
+// the byte offset of a position, clamped to its line
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position.find("line").and_then(|l| l.as_u64()).unwrap_or(0) as usize;
+    let character = position.find("character").and_then(|c| c.as_u64()).unwrap_or(0) as usize;
+    let start = match line {
+        0 => 0,
+        n => match text.match_indices('\n').nth(n - 1) {
+            Some((i, _)) => i + 1,
+            None => return text.len(),
+        },
+    };
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+// the key around `at`, if it follows an `@`, and where it starts
+fn key_at(text: &str, at: usize) -> Option<(usize, &str)> {
+    let before: usize = text[..at].chars().rev()
+        .take_while(|&c| xref::is_key_char(c))
+        .map(char::len_utf8)
+        .sum();
+    let start = at - before;
+    let end = text[at..].find(|c| !xref::is_key_char(c)).map_or(text.len(), |i| at + i);
+    if !text[..start].ends_with('@') {
+        return None;
+    }
+    Some((start, &text[start..end]))
+}
+
+// an entry, in markdown, for hovers and completions
+fn describe(entry: &Entry) -> String {
+    let who = bib::author_year(&entry.author, &entry.year);
+    let mut description = format!("**{}**\n\n{}", entry.title, who);
+    if let Some(venue) = entry.venue() {
+        description.push_str(&format!(", *{}*", venue));
+    }
+    description
+}
+
+fn markdown(value: String) -> Value {
+    object(vec![("kind", string("markdown")), ("value", Value::String(value))])
+}
+
+impl Server {
+    // reads the bibliography again if it has changed, and checks every open
+    // document against it
+    fn refresh(&mut self) {
+        let now = modified(&self.bib_path);
+        if now == self.modified {
+            return;
+        }
+        self.bib = bib::read_bib(&self.bib_path);
+        self.modified = now;
+        let uris: Vec<String> = self.documents.keys().cloned().collect();
+        for uri in uris {
+            self.check(&uri);
+        }
+    }
+
+    fn check(&self, uri: &str) {
+        let text = match self.documents.get(uri) {
+            Some(text) => text,
+            None => return,
+        };
+        let (_, unknown) = xref::rewrite(text, &self.bib, &HashMap::new(), "");
+        let diagnostics = unknown.iter().map(|u| object(vec![
+            ("range", object(vec![
+                ("start", position(text, u.offset)),
+                ("end", position(text, u.offset + u.len)),
+            ])),
+            ("severity", Value::U64(SEVERITY_ERROR)),
+            ("source", string("biblionotes")),
+            ("message", Value::String(format!("`{}` is not in the bibliography", u.key))),
+        ]));
+        notify("textDocument/publishDiagnostics", object(vec![
+            ("uri", string(uri)),
+            ("diagnostics", Value::Array(diagnostics.collect())),
+        ]));
+    }
+
+    // the document and byte offset a request is about
+    fn at<'a>(&'a self, params: &Value) -> Option<(&'a str, usize)> {
+        let uri = params.lookup("textDocument.uri").and_then(|u| u.as_str())?;
+        let text = self.documents.get(uri)?;
+        Some((text, offset(text, params.find("position")?)))
+    }
+
+    fn complete(&self, params: &Value) -> Value {
+        let (text, at) = match self.at(params) {
+            Some(at) => at,
+            None => return Value::Array(Vec::new()),
+        };
+        let prefix = match key_at(text, at) {
+            Some((start, _)) => &text[start..at],
+            None => return Value::Array(Vec::new()),
+        };
+        let items = self.bib.entries.iter()
+            .filter(|e| e.key.starts_with(prefix))
+            .map(|e| object(vec![
+                ("label", string(&e.key)),
+                ("kind", Value::U64(KIND_REFERENCE)),
+                ("detail", Value::String(e.short_citation())),
+                ("documentation", markdown(describe(e))),
+            ]));
+        Value::Array(items.collect())
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (text, at) = match self.at(params) {
+            Some(at) => at,
+            None => return Value::Null,
+        };
+        let (start, key) = match key_at(text, at) {
+            Some(found) => found,
+            None => return Value::Null,
+        };
+        match self.bib.get(key) {
+            Some(entry) => object(vec![
+                ("contents", markdown(describe(entry))),
+                ("range", object(vec![
+                    ("start", position(text, start)),
+                    ("end", position(text, start + key.len())),
+                ])),
+            ]),
+            None => Value::Null,
+        }
+    }
+
+    fn open(&mut self, params: &Value, text: Option<&Value>) {
+        let uri = params.lookup("textDocument.uri").and_then(|u| u.as_str());
+        if let (Some(uri), Some(text)) = (uri, text.and_then(|t| t.as_str())) {
+            self.documents.insert(uri.to_owned(), text.to_owned());
+            self.check(uri);
+        }
+    }
+
+    // handles a message, returning false once the editor says to exit
+    fn handle(&mut self, message: &Value) -> bool {
+        self.refresh();
+        let method = message.find("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = message.find("params").cloned().unwrap_or(Value::Null);
+        let id = message.find("id");
+        let result = match method {
+            "initialize" => object(vec![
+                ("capabilities", object(vec![
+                    ("textDocumentSync", Value::U64(1)),
+                    ("completionProvider", object(vec![
+                        ("triggerCharacters", Value::Array(vec![string("@")])),
+                    ])),
+                    ("hoverProvider", Value::Bool(true)),
+                ])),
+                ("serverInfo", object(vec![("name", string("biblionotes"))])),
+            ]),
+            "textDocument/completion" => self.complete(&params),
+            "textDocument/hover" => self.hover(&params),
+            "textDocument/didOpen" => {
+                self.open(&params, params.lookup("textDocument.text"));
+                return true;
+            },
+            "textDocument/didChange" => {
+                // whole documents, so the last change is all of it
+                let text = params.find("contentChanges")
+                    .and_then(|c| c.as_array())
+                    .and_then(|c| c.last())
+                    .and_then(|c| c.find("text"));
+                self.open(&params, text);
+                return true;
+            },
+            "textDocument/didClose" => {
+                if let Some(uri) = params.lookup("textDocument.uri").and_then(|u| u.as_str()) {
+                    self.documents.remove(uri);
+                }
+                return true;
+            },
+            "shutdown" => Value::Null,
+            "exit" => return false,
+            _ => {
+                // notifications needn't be answered
+                if let Some(id) = id {
+                    send(object(vec![
+                        ("jsonrpc", string("2.0")),
+                        ("id", id.clone()),
+                        ("error", object(vec![
+                            ("code", Value::I64(METHOD_NOT_FOUND)),
+                            ("message", Value::String(format!("unknown method `{}`", method))),
+                        ])),
+                    ]));
+                }
+                return true;
+            },
+        };
+        if let Some(id) = id {
+            respond(id, result);
+        }
+        true
+    }
+}
+
+pub fn run(bib_path: &str) {
+    let mut server = Server {
+        bib_path: bib_path.to_owned(),
+        bib: bib::read_bib(bib_path),
+        modified: modified(bib_path),
+        documents: HashMap::new(),
+    };
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(e) => {
+                Diagnostic::error("could not read a message from the editor")
+                    .note(e.to_string())
+                    .emit();
+                ::std::process::exit(1);
+            },
+        };
+        if !server.handle(&message) {
+            return;
+        }
+    }
+}
@@ -1,165 +1,864 @@
 extern crate bib_parser;
+extern crate biblionotes;
+extern crate getopts;
 extern crate handlebars;
-extern crate pandoc;
+#[macro_use]
+extern crate log;
+extern crate serde_json;
+extern crate syntect;
+extern crate toml;
+extern crate unicode_normalization;
+
+// the rendering core is in the library, so it can be built for wasm32
+use biblionotes::{bib, config, csl, dates, diagnostics, error, frontmatter, html, logging};
+use biblionotes::{markdown, render, template, text, xref};
+
+mod anniversaries;
+mod attachments;
+mod book;
+mod cache;
+mod check;
+mod clip;
+mod collate;
+mod contributors;
+mod coverage;
+mod digest;
+mod encrypted;
+mod enrich;
+mod export;
+mod external;
+mod graph;
+mod highlight;
+mod hooks;
+mod ics;
+mod images;
+mod issues;
+mod keys;
+mod links;
+mod lint;
+mod lsp;
+mod math;
+mod meta;
+mod minify;
+mod model;
+mod notify;
+mod output;
+mod pdfs;
+mod provenance;
+mod reading;
+mod references;
+mod related;
+mod remote;
+mod report;
+mod schedule;
+mod setup;
+mod site;
+mod sitemap;
+mod statistics;
+mod tags;
+mod todo;
+mod updated;
+mod urls;
+mod vars;
+mod venues;
+mod zotero;
 
-use std::collections::BTreeMap;
 use std::env;
-use std::fmt;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use bib_parser::Entry;
-use handlebars::Handlebars;
-use pandoc::OutputFormat::Html5;
-use pandoc::InputFormat::Markdown;
-use pandoc::OutputKind::Pipe as OutputPipe;
-use pandoc::InputKind::Pipe as InputPipe;
-use pandoc::PandocOutput::ToBuffer;
-use pandoc::PandocOption::MathJax;
-
-fn read_bib(bib_path: &str) -> Vec<(String, Option<Entry>)> {
-    let mut file = File::open(bib_path).expect("Could not open bibliography");
-    let mut bs = Vec::new();
-    file.read_to_end(&mut bs).unwrap();
-    let parsed = bib_parser::parse_bib(&bs);
-    match parsed {
-        Err(e) => panic!("{:?}", e),
-        Ok(x) => x,
-    }
-}
-
-fn make_index(index: Vec<(String, String, String, String)>) -> String {
-    let mut content = String::new();
-    let intro = format_args!(r#"<header>
-  <h1>Annotated bibliography</h1>
-  <p>This is an annotated bibliography of various papers I find interesting. It is automatically generated by <a href="https://github.com/jeanm/biblionotes">BiblioNotes</a> from a BibTeX file and an archive of Markdown files.</p>
-</header>
-<ul class="nonetype">
-"#);
-    fmt::write(&mut content, intro).unwrap();
-
-    for (link, author, year, title) in index {
-        fmt::write(
-            &mut content,
-            format_args!(
-                "  <li><a href=\"{}\">{}</a><br>\n  {} ({})\n",
-                link,
-                title,
-                author,
-                year,
-            )
-        ).unwrap();
-    }
-    fmt::write(&mut content, format_args!("</ul>")).unwrap();
-    content
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+use getopts::{Matches, Options};
+use config::{Config, MathEngine};
+use diagnostics::{Diagnostic, Level};
+
+fn options() -> Options {
+    let mut opts = Options::new();
+    opts.optopt("c", "config", "read settings from a TOML file", "FILE");
+    opts.optopt(
+        "",
+        "renderer",
+        "markdown converter: pandoc (default) or builtin",
+        "NAME"
+    );
+    opts.optopt("", "pandoc", "path to the pandoc binary", "PATH");
+    opts.optopt(
+        "",
+        "from",
+        "pandoc input format, with extensions (default: markdown)",
+        "FORMAT"
+    );
+    opts.optmulti(
+        "",
+        "pandoc-arg",
+        "extra argument for pandoc, e.g. --pandoc-arg=--number-sections",
+        "ARG"
+    );
+    opts.optflag("", "no-cache", "run pandoc on every note, without the cache of its HTML");
+    opts.optopt("", "math", "math engine: mathjax (default), katex or none", "ENGINE");
+    opts.optflag(
+        "",
+        "prerender-math",
+        "render math to HTML at build time with KaTeX (needs the katex CLI)"
+    );
+    opts.optopt(
+        "",
+        "highlight",
+        "highlight code blocks with a syntect theme (name or .tmTheme file)",
+        "THEME"
+    );
+    opts.optopt(
+        "",
+        "sort",
+        "index order: bib (default), author, title, year or citations",
+        "ORDER",
+    );
+    opts.optflag("", "abstracts", "show collapsible abstracts in the index");
+    opts.optopt(
+        "",
+        "index-page-size",
+        "split the index into pages of N entries",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "latest",
+        "also write latest.html, the newest notes first, in pages of N",
+        "N",
+    );
+    opts.optopt("", "collation", "locale for sorting, e.g. sv or de (default: root)", "LOCALE");
+    opts.optflag("", "similar", "check: report notes that are nearly the same");
+    opts.optflag("", "quality", "check: report thin, unfinished or stale notes");
+    opts.optflag("", "keys", "check: report keys that break the key policy");
+    opts.optflag("", "duplicates", "check: report entries that look like the same paper");
+    opts.optflag("", "external", "check-links: also check links to other sites");
+    opts.optopt("", "jobs", "check-links: how many sites to ask at once (default: 8)", "N");
+    opts.optopt(
+        "",
+        "timeout",
+        "check-links: seconds to wait for each site (default: 10)",
+        "SECS"
+    );
+    opts.optflag("", "fix-keys", "fmt: rename keys that break the key policy");
+    opts.optopt("", "url-style", "page names: key (default), slug or pretty", "STYLE");
+    opts.optopt(
+        "",
+        "base-url",
+        "where the site is published; writes sitemap.xml and robots.txt",
+        "URL"
+    );
+    opts.optopt(
+        "",
+        "coverage",
+        "write how much of the bibliography has notes: json or page (a heatmap)",
+        "OUTPUT"
+    );
+    opts.optopt(
+        "",
+        "description-length",
+        "the longest a page's description gets, in characters (default: 200)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "excerpt-length",
+        "the longest an index excerpt gets, in characters (default: 400)",
+        "N",
+    );
+    opts.optflag(
+        "",
+        "reproducible",
+        "make the same output from the same input, taking no times from the clock or files",
+    );
+    opts.optflag(
+        "",
+        "stats",
+        "also write stats.html and stats.json, with entries by year, venue, tag and type",
+    );
+    opts.optflag(
+        "",
+        "contributors",
+        "also write contributors.html, with who wrote which notes (by git blame)"
+    );
+    opts.optflag("", "venues", "also write a page for each venue, under venue/");
+    opts.optflag("", "graph", "also write graph.html, showing which papers cite which");
+    opts.optflag("", "all", "also write all.html, with every note on one page");
+    opts.optflag("", "all-pdf", "also write all.pdf from all.html, with pandoc");
+    opts.optopt(
+        "",
+        "profile",
+        "which site to build: internal (default, everything) or public",
+        "PROFILE"
+    );
+    opts.optopt(
+        "",
+        "identity",
+        "the age identity file to decrypt encrypted notes (<key>.md.age) with",
+        "FILE"
+    );
+    opts.optflag("", "minify", "make pages smaller, leaving out comments and extra whitespace");
+    opts.optopt(
+        "",
+        "critical-css",
+        "put the stylesheet in FILE in the head of every page",
+        "FILE"
+    );
+    opts.optflag("", "drafts", "also build notes marked as drafts");
+    opts.optopt("", "only", "only build the pages of these entries", "KEY,KEY");
+    opts.optmulti(
+        "",
+        "filter",
+        "only build the pages of entries that pass, e.g. tag=ml or year>=2020",
+        "TEST"
+    );
+    opts.optflag("", "filter-index", "list only the entries built in the index, too");
+    opts.optflag("", "notify", "show a desktop notification when the build is done");
+    opts.optflag(
+        "",
+        "fragments",
+        "write pages without the template, for inclusion in another site"
+    );
+    opts.optopt(
+        "",
+        "emit-json",
+        "also write the parsed entries, notes and output paths as JSON",
+        "PATH"
+    );
+    opts.optopt(
+        "",
+        "report",
+        "write how the build went (pages, warnings, failures, timing) as JSON",
+        "PATH"
+    );
+    opts.optopt(
+        "",
+        "pdf-dir",
+        "copy <key>.pdf from DIR into the output, and link to it from the pages",
+        "DIR"
+    );
+    opts.optopt(
+        "",
+        "schedule",
+        "the reading group's schedule (default: schedule.toml next to the .bib)",
+        "PATH"
+    );
+    opts.optflag("", "open", "issues: open the issues instead of printing them as JSON");
+    opts.optopt(
+        "",
+        "repo",
+        "issues: where to open them, like github:owner/name",
+        "REPO"
+    );
+    opts.optopt("", "zotero-group", "sync: the Zotero group library to pull from", "ID");
+    opts.optopt(
+        "",
+        "prefer",
+        "sync: settle conflicts in favour of local or zotero",
+        "SIDE"
+    );
+    opts.optopt(
+        "",
+        "since",
+        "digest: include the notes changed on or after this date",
+        "YYYY-MM-DD"
+    );
+    opts.optopt("", "format", "export: md, csv, bib or json", "FORMAT");
+    opts.optflag("", "todo", "also write todo.html, listing the TODOs left in notes");
+    opts.optflag(
+        "",
+        "anniversaries",
+        "also write anniversaries.ics, marking the days the papers came out"
+    );
+    opts.optflag(
+        "",
+        "dry-run",
+        "check everything and report what would be written, without writing"
+    );
+    opts.optflag("", "prune", "remove the pages of deleted notes and renamed keys");
+    opts.optflag("", "strict", "exit with an error if any entry fails to build");
+    opts.optflag(
+        "",
+        "strict-templates",
+        "fail pages that are missing a variable the template uses"
+    );
+    opts.optflagmulti("v", "verbose", "say more about what's going on; twice for everything");
+    opts.optflag("q", "quiet", "only print errors");
+    opts.optflag("h", "help", "print this help and exit");
+    opts
+}
+
+// parses an option value, exiting with the parse error if it's invalid
+fn parse_arg<T: FromStr<Err = String>>(value: &str) -> T {
+    match value.parse() {
+        Ok(v) => v,
+        Err(e) => {
+            Diagnostic::error(e).emit();
+            std::process::exit(1);
+        },
+    }
+}
+
+// a count given as an option, which can't be zero
+fn positive(name: &str, value: &str) -> u64 {
+    match value.parse() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            Diagnostic::error(format!("--{} must be a positive number", name)).emit();
+            std::process::exit(1);
+        },
+    }
+}
+
+// the settings shared by every command: the config file, overridden by
+// whatever was given on the command line
+fn load_config(matches: &Matches) -> Config {
+    let mut config = match matches.opt_str("config") {
+        Some(path) => Config::load(Path::new(&path)),
+        None => Config::default(),
+    };
+    if let Some(renderer) = matches.opt_str("renderer") {
+        config.renderer = parse_arg(&renderer);
+    }
+    if let Some(path) = matches.opt_str("pandoc") {
+        config.pandoc.path = path;
+    }
+    if let Some(from) = matches.opt_str("from") {
+        config.pandoc.from = from;
+    }
+    config.pandoc.args.extend(matches.opt_strs("pandoc-arg"));
+    if matches.opt_present("no-cache") {
+        config.pandoc.cache = false;
+    }
+    if let Some(engine) = matches.opt_str("math") {
+        config.math.engine = parse_arg(&engine);
+    }
+    if matches.opt_present("prerender-math") {
+        config.math.prerender = true;
+    }
+    if config.math.prerender && config.math.engine == MathEngine::None {
+        Diagnostic::warning("--prerender-math has no effect with --math none")
+            .emit();
+        config.math.prerender = false;
+    }
+    if let Some(theme) = matches.opt_str("highlight") {
+        config.highlight_theme = Some(theme);
+    }
+
+    if let Some(sort) = matches.opt_str("sort") {
+        config.sort = parse_arg(&sort);
+    }
+    if let Some(collation) = matches.opt_str("collation") {
+        config.collation = collation;
+    }
+    if matches.opt_present("abstracts") {
+        config.index_abstracts = true;
+    }
+    if let Some(size) = matches.opt_str("index-page-size") {
+        config.index_page_size = Some(positive("index-page-size", &size) as usize);
+    }
+    if let Some(size) = matches.opt_str("latest") {
+        config.latest = Some(positive("latest", &size) as usize);
+    }
+    if let Some(style) = matches.opt_str("url-style") {
+        config.url_style = parse_arg(&style);
+    }
+    if let Some(base_url) = matches.opt_str("base-url") {
+        config.base_url = Some(base_url);
+    }
+    if let Some(coverage) = matches.opt_str("coverage") {
+        config.coverage = parse_arg(&coverage);
+    }
+    if matches.opt_present("stats") {
+        config.stats = true;
+    }
+    if let Some(length) = matches.opt_str("description-length") {
+        config.description_length = positive("description-length", &length) as usize;
+    }
+    if let Some(length) = matches.opt_str("excerpt-length") {
+        config.excerpt_length = positive("excerpt-length", &length) as usize;
+    }
+    if matches.opt_present("reproducible") {
+        config.reproducible = true;
+    }
+    if let Some(profile) = matches.opt_str("profile") {
+        config.profile = parse_arg(&profile);
+    }
+    if let Some(identity) = matches.opt_str("identity") {
+        config.encryption.identity = Some(PathBuf::from(identity));
+    }
+    if matches.opt_present("minify") {
+        config.minify = true;
+    }
+    if let Some(path) = matches.opt_str("critical-css") {
+        config.critical_css = Some(PathBuf::from(path));
+    }
+    if matches.opt_present("drafts") {
+        config.drafts = true;
+    }
+    if let Some(keys) = matches.opt_str("only") {
+        let keys = keys.split(',').map(|k| k.trim()).filter(|k| !k.is_empty());
+        config.filter.only = keys.map(|k| k.to_owned()).collect();
+    }
+    for test in matches.opt_strs("filter") {
+        config.filter.conditions.push(parse_arg(&test));
+    }
+    config.filter_index = matches.opt_present("filter-index");
+    if matches.opt_present("notify") {
+        config.notify.desktop = true;
+    }
+    if matches.opt_present("fragments") {
+        config.fragments = true;
+    }
+    if let Some(path) = matches.opt_str("emit-json") {
+        config.emit_json = Some(PathBuf::from(path));
+    }
+    if let Some(path) = matches.opt_str("report") {
+        config.report = Some(PathBuf::from(path));
+    }
+    if let Some(jobs) = matches.opt_str("jobs") {
+        config.links.jobs = positive("jobs", &jobs) as usize;
+    }
+    if let Some(timeout) = matches.opt_str("timeout") {
+        config.links.timeout = positive("timeout", &timeout);
+    }
+    if let Some(repo) = matches.opt_str("repo") {
+        config.issues.repo = Some(config::parse_repo(&repo).unwrap_or_else(|e| {
+            Diagnostic::error(e).emit();
+            std::process::exit(1);
+        }));
+    }
+    if let Some(group) = matches.opt_str("zotero-group") {
+        config.zotero.group = Some(group);
+    }
+    if let Some(prefer) = matches.opt_str("prefer") {
+        config.zotero.prefer = Some(parse_arg(&prefer));
+    }
+    if let Some(dir) = matches.opt_str("pdf-dir") {
+        config.pdf_dir = Some(PathBuf::from(dir));
+    }
+    if let Some(path) = matches.opt_str("schedule") {
+        config.schedule = Some(PathBuf::from(path));
+    }
+    if matches.opt_present("todo") {
+        config.todo_page = true;
+    }
+    if matches.opt_present("contributors") {
+        config.contributors = true;
+    }
+    if matches.opt_present("venues") {
+        config.venue_pages = true;
+    }
+    if matches.opt_present("graph") {
+        config.graph = true;
+    }
+    if matches.opt_present("all") {
+        config.all = true;
+    }
+    if matches.opt_present("all-pdf") {
+        config.all_pdf = true;
+    }
+    if matches.opt_present("anniversaries") {
+        config.anniversaries = true;
+    }
+    config.strict = matches.opt_present("strict");
+    if matches.opt_present("strict-templates") {
+        config.strict_templates = true;
+    }
+    config.dry_run = matches.opt_present("dry-run");
+    config.prune = matches.opt_present("prune");
+    // pandoc and the build take the time from here
+    if config.reproducible && env::var_os("SOURCE_DATE_EPOCH").is_none() {
+        env::set_var("SOURCE_DATE_EPOCH", "0");
+    }
+    config
+}
+
+fn build(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.template_path = PathBuf::from(&args[1]);
+    config.markdown_dir = PathBuf::from(&args[2]);
+    config.output_dir = PathBuf::from(&args[3]);
+
+    let started = Instant::now();
+    let built = site::build(&config);
+    let duration = started.elapsed();
+    // not counting the summaries' own
+    let (warnings, errors) = (diagnostics::warnings(), diagnostics::errors());
+    let (skipped, failures): (Vec<_>, Vec<_>) = built.failures.into_iter()
+        .partition(|f| matches!(f.1, error::Error::Malformed(_)));
+    let level = if config.strict { Level::Error } else { Level::Warning };
+    if !skipped.is_empty() {
+        let keys: Vec<_> = skipped.iter().map(|f| &f.0[..]).collect();
+        Diagnostic::new(
+            level,
+            format!("skipped {} malformed entries of the bibliography", skipped.len())
+        ).note(keys.join(", ")).emit();
+    }
+    if !failures.is_empty() {
+        let keys: Vec<_> = failures.iter().map(|f| &f.0[..]).collect();
+        Diagnostic::new(
+            level,
+            format!("{} entries failed to build", failures.len())
+        ).note(keys.join(", ")).emit();
+    }
+    let summary = notify::Summary {
+        pages: built.pages,
+        warnings,
+        failed: failures.iter().map(|f| f.0.clone()).collect(),
+        skipped: skipped.iter().map(|f| f.0.clone()).collect(),
+    };
+    if let Some(ref path) = config.report {
+        let report = report::Report {
+            entries: built.entries,
+            notes: built.notes,
+            pages: built.pages,
+            files: built.files,
+            cache_hits: cache::hits(),
+            cache_misses: cache::misses(),
+            warnings,
+            errors,
+            failed: &summary.failed,
+            malformed: &summary.skipped,
+            duration,
+            dry_run: config.dry_run,
+        };
+        fs::write(path, format!("{}\n", report::json(&report)))
+            .expect("Could not write the build report");
+    }
+    for e in notify::send(&config, &summary) {
+        Diagnostic::warning(e).emit();
+    }
+    if config.strict && !(skipped.is_empty() && failures.is_empty()) {
+        std::process::exit(1);
+    }
+}
+
+fn clean(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.template_path = PathBuf::from(&args[1]);
+    config.markdown_dir = PathBuf::from(&args[2]);
+    config.output_dir = PathBuf::from(&args[3]);
+
+    let failures = site::clean(&config);
+    if !failures.is_empty() {
+        let keys: Vec<_> = failures.iter().map(|f| &f.0[..]).collect();
+        Diagnostic::error(format!("{} entries failed to build", failures.len()))
+            .note(keys.join(", "))
+            .emit();
+        std::process::exit(1);
+    }
+}
+
+fn check(mut config: Config, matches: &Matches, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let bib = bib::read_bib(&config.bib_path);
+    let (notes, failures) = site::read_notes(&config, &bib);
+    // with no checks named, run them all
+    let checks = ["similar", "quality", "keys", "duplicates"];
+    let all = !checks.iter().any(|c| matches.opt_present(c));
+    let mut problems = failures.len();
+    if all || matches.opt_present("similar") {
+        problems += check::similar(&config, &bib, &notes);
+    }
+    if all || matches.opt_present("quality") {
+        problems += check::quality(&config, &bib, &notes);
+    }
+    if all || matches.opt_present("keys") {
+        problems += keys::lint(&config, &bib);
+    }
+    if all || matches.opt_present("duplicates") {
+        problems += check::duplicates(&config, &bib);
+    }
+    if problems > 0 {
+        Diagnostic::error(format!("found {} problems", problems)).emit();
+        std::process::exit(1);
+    }
+}
+
+fn lint(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let bib = bib::read_bib(&config.bib_path);
+    let (notes, failures) = site::read_notes(&config, &bib);
+    let problems = failures.len() + lint::run(&config, &bib, &notes);
+    if problems > 0 {
+        Diagnostic::error(format!("found {} problems", problems)).emit();
+        std::process::exit(1);
+    }
+}
+
+fn check_links(config: Config, matches: &Matches, args: &[String]) {
+    let broken = links::check(&config, Path::new(&args[0]), matches.opt_present("external"));
+    if broken > 0 {
+        Diagnostic::error(format!("found {} broken links", broken)).emit();
+        std::process::exit(1);
+    }
+}
+
+fn fmt(mut config: Config, matches: &Matches, args: &[String]) {
+    remote::writable(&args[0]);
+    config.bib_path = args[0].clone();
+    config.markdown_dir = PathBuf::from(&args[1]);
+    if !matches.opt_present("fix-keys") {
+        Diagnostic::error("nothing to do")
+            .note("pass --fix-keys to rename the keys that break the key policy")
+            .emit();
+        std::process::exit(1);
+    }
+    let bib = bib::read_bib(&config.bib_path);
+    keys::fix(&config, &bib);
+}
+
+fn clip(mut config: Config, args: &[String]) {
+    remote::writable(&args[0]);
+    config.bib_path = args[0].clone();
+    config.markdown_dir = PathBuf::from(&args[1]);
+    clip::watch(&config);
+}
+
+fn digest(mut config: Config, matches: &Matches, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let since = match matches.opt_str("since") {
+        Some(since) => dates::parse_ymd(&since).unwrap_or_else(|e| {
+            Diagnostic::error(e).emit();
+            std::process::exit(1);
+        }),
+        None => {
+            Diagnostic::error("digest needs --since").emit();
+            std::process::exit(1);
+        },
+    };
+    let output = Path::new(&args[2]);
+    let markdown = output.extension().is_some_and(|e| e == "md" || e == "markdown");
+    let format = if markdown { digest::Format::Markdown } else { digest::Format::Html };
+    let bib = bib::read_bib(&config.bib_path);
+    let contents = digest::digest(&config, &bib, since, format);
+    let mut file = File::create(output).expect("Could not open digest file");
+    write!(file, "{}", contents).expect("Could not write to digest file");
+}
+
+fn export(mut config: Config, matches: &Matches, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let format = match matches.opt_str("format") {
+        Some(format) => parse_arg(&format),
+        None => {
+            Diagnostic::error("export needs --format").emit();
+            std::process::exit(1);
+        },
+    };
+    let mut bib = bib::read_bib(&config.bib_path);
+    enrich::apply(&mut bib, &enrich::load(&enrich::cache_path(&config)));
+    venues::apply(&mut bib, &config.venue_aliases);
+    print!("{}", export::export(&config, &bib, format));
+}
+
+fn enrich(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    let bib = bib::read_bib(&config.bib_path);
+    enrich::enrich(&config, &bib);
+}
+
+fn suggest_tags(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let bib = bib::read_bib(&config.bib_path);
+    let (notes, _) = site::read_notes(&config, &bib);
+    let suggestions = tags::suggest(&bib, &notes);
+    let mut report = File::create(&args[2]).expect("Could not open report file");
+    write!(report, "{}", tags::report(&suggestions))
+        .expect("Could not write to report file");
+    println!("suggested tags for {} entries in {}", suggestions.len(), args[2]);
+}
+
+fn issues(mut config: Config, matches: &Matches, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let bib = bib::read_bib(&config.bib_path);
+    let (notes, _) = site::read_notes(&config, &bib);
+    let issues = issues::issues(&config, &bib, &notes);
+    if matches.opt_present("open") {
+        issues::open(&config, &issues);
+    } else {
+        println!("{}", issues::json(&config, &issues));
+    }
+}
+
+fn sync(mut config: Config, args: &[String]) {
+    remote::writable(&args[0]);
+    config.bib_path = args[0].clone();
+    let group = match config.zotero.group {
+        Some(ref group) => group.clone(),
+        None => {
+            Diagnostic::error("no Zotero group to sync with")
+                .note("give one with --zotero-group, or `group` under [zotero] in the config")
+                .emit();
+            std::process::exit(1);
+        },
+    };
+    let bib = bib::read_bib(&config.bib_path);
+    zotero::sync(&config, &bib, &group);
+}
+
+fn stats(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    let bib = bib::read_bib(&config.bib_path);
+    println!("{} entries", bib.entries.len());
+    let provenance = provenance::load(&config);
+    for (source, n) in provenance::counts(&bib, &provenance) {
+        println!("  {:<6} {}", n, source.label().to_lowercase());
+    }
+}
+
+fn template_vars(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let (pages, index) = site::template_data(&config);
+    // the base layout is the only partial there is
+    let partials: &[&str] = if config.templates.base.is_some() { &["base"] } else { &[] };
+    print!("{}", vars::list(&pages, &index, partials));
+}
+
+fn cache_clear(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    match cache::clear(&config) {
+        Ok(n) => {
+            println!("cleared the HTML of {} notes from {}", n, cache::dir(&config).display())
+        },
+        Err(e) => {
+            Diagnostic::error("could not clear the cache").note(e.to_string()).emit();
+            std::process::exit(1);
+        },
+    }
+}
+
+fn lsp(config: Config, args: &[String]) {
+    lsp::run(&remote::local(&config, &args[0]));
+}
+
+fn fixture(mut config: Config, args: &[String]) {
+    config.bib_path = remote::local(&config, &args[0]);
+    config.markdown_dir = PathBuf::from(&args[1]);
+    let key = &args[2];
+    match site::fixture(&config, key) {
+        Ok(Some(context)) => {
+            let json = serde_json::to_string_pretty(&context)
+                .expect("Could not serialise the template data");
+            println!("{}", json);
+        },
+        Ok(None) => {
+            Diagnostic::error(format!("`{}` has no page", key))
+                .note("only entries with a note that's built get one")
+                .emit();
+            std::process::exit(1);
+        },
+        Err(e) => {
+            Diagnostic::error(format!("could not build `{}`", key)).note(e.to_string()).emit();
+            std::process::exit(1);
+        },
+    }
+}
+
+fn usage(opts: &Options) {
+    let brief = "syntax: biblionotes [options] <bibliography> <template> \
+                 <markdown_dir> <output_dir>\n        \
+                 biblionotes clean [options] <bibliography> <template> \
+                 <markdown_dir> <output_dir>\n        \
+                 biblionotes check [options] <bibliography> <markdown_dir>\n        \
+                 biblionotes lint [options] <bibliography> <markdown_dir>\n        \
+                 biblionotes check-links [--external] [options] <output_dir>\n        \
+                 biblionotes fmt --fix-keys [options] <bibliography> \
+                 <markdown_dir>\n        \
+                 biblionotes export --format <format> [options] <bibliography> \
+                 <markdown_dir>\n        \
+                 biblionotes enrich [options] <bibliography>\n        \
+                 biblionotes clip [options] <bibliography> <markdown_dir>\n        \
+                 biblionotes digest --since <date> [options] <bibliography> \
+                 <markdown_dir> <output>\n        \
+                 biblionotes suggest-tags [options] <bibliography> \
+                 <markdown_dir> <report>\n        \
+                 biblionotes issues [--open] [options] <bibliography> \
+                 <markdown_dir>\n        \
+                 biblionotes sync --zotero-group <id> [options] <bibliography>\n        \
+                 biblionotes fixture [options] <bibliography> <markdown_dir> <key>\n        \
+                 biblionotes stats [options] <bibliography>\n        \
+                 biblionotes lsp [options] <bibliography>\n        \
+                 biblionotes template vars [options] <bibliography> <markdown_dir>\n        \
+                 biblionotes cache clear [options] <bibliography>\n        \
+                 biblionotes setup";
+    writeln!(&mut std::io::stderr(), "{}", opts.usage(brief)).unwrap();
 }
 
 fn main() {
     let args: Vec<_> = env::args().collect();
-    if args.len() == 5 {
-        // read .bib file
-        let entries = read_bib(&args[1]);
-
-        // register handlebars template
-        let templ_path = PathBuf::from(&args[2]);
-        let mut hbs = Handlebars::new();
-        let mut templ_file = File::open(templ_path)
-            .expect("Could not open template file");
-        let mut templ_contents = String::new();
-        templ_file.read_to_string(&mut templ_contents)
-            .expect("Could not read template file");
-        hbs.register_template_string("t", templ_contents)
-            .expect("Could not register template");
-
-        // this vector will be used to create an index for the notes
-        let mut index = Vec::new();
-
-        // output all the individual files
-        let markdown_path = PathBuf::from(&args[3]);
-        let output_path = PathBuf::from(&args[4]);
-        for (key, entry) in entries {
-            let entry = match entry {
-                Some(entry) => entry,
-                None => continue,
-            };
-
-            // read the markdown source, or continue if it doesn't exist
-            let md_name = format!("{}.md", &key);
-            let md_path = markdown_path.join(md_name);
-            let mut md_file = match File::open(md_path) {
-                Err(_) => continue,
-                Ok(f) => f,
-            };
-            let mut md_contents = String::new();
-            md_file.read_to_string(&mut md_contents)
-                .expect("Could not read markdown file");
-            
-            // set up and run pandoc
-            let mut pandoc = pandoc::new();
-            pandoc.set_output_format(Html5)
-                  .set_output(OutputPipe)
-                  .set_input_format(Markdown)
-                  .set_input(InputPipe(md_contents))
-                  .add_option(MathJax(None));
-            let pandoc_output = pandoc.execute().expect("Could not run pandoc");
-
-            // extract the output
-            let body = match pandoc_output {
-                ToBuffer(s) => s,
-                _ => unreachable!(),
-            };
-
-            // add the header
-            let rendered = format!(
-                "<header><h1>{}</h1><cite>{} ({}) <em>{}</em></cite></header>\n{}",
-                entry.title(),
-                entry.author().to_string(),
-                entry.year(),
-                entry.title(),
-                body
-            );
-
-            // run handlebars
-            let mut data = BTreeMap::new();
-            data.insert("title", entry.title());
-            data.insert("content", &rendered);
-            let rendered_again = hbs.render("t", &data)
-                .expect("Handlebars failed to run");
-
-            // write output
-            let html_name = format!("{}.html", &key);
-            let html_path = output_path.join(html_name.clone());
-            let mut html_file = File::create(html_path)
-                .expect("Could not open output file");
-            writeln!(html_file, "{}", rendered_again)
-                .expect("Could not write to output file");
-            
-            index.push((
-                html_name,
-                entry.author().to_string(),
-                entry.year().to_string(),
-                entry.title().to_owned()
-            ));
-        }
-
-        // now build the index
-        let index_contents = make_index(index);
-        let mut data = BTreeMap::new();
-        data.insert("title", "Annotated bibliography");
-        data.insert("content", &index_contents);
-        let rendered_index = hbs.render("t", &data)
-            .expect("Handlebars failed to run");
-
-        // write output
-        let index_path = output_path.join("index.html");
-        let mut index_file = File::create(index_path)
-            .expect("Could not open index file");
-        writeln!(index_file, "{}", rendered_index)
-            .expect("Could not write to index file");
+    let opts = options();
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(e) => {
+            writeln!(&mut std::io::stderr(), "{}", e).unwrap();
+            std::process::exit(1);
+        },
+    };
+    logging::init(if matches.opt_present("quiet") {
+        -1
     } else {
-        writeln!(
-            &mut std::io::stderr(),
-            "syntax: biblionotes <bibliography> <template> <markdown_dir> <output_dir>"
-        ).unwrap();
+        matches.opt_count("verbose") as i32
+    });
+    let free = &matches.free;
+    if matches.opt_present("help") {
+        return usage(&opts);
+    }
+    match (free.first().map(|s| &s[..]), free.len()) {
+        (Some("clean"), 5) => clean(load_config(&matches), &free[1..]),
+        (Some("clean"), _) => usage(&opts),
+        (Some("check"), 3) => check(load_config(&matches), &matches, &free[1..]),
+        (Some("check"), _) => usage(&opts),
+        (Some("lint"), 3) => lint(load_config(&matches), &free[1..]),
+        (Some("lint"), _) => usage(&opts),
+        (Some("check-links"), 2) => {
+            check_links(load_config(&matches), &matches, &free[1..])
+        },
+        (Some("check-links"), _) => usage(&opts),
+        (Some("fmt"), 3) => fmt(load_config(&matches), &matches, &free[1..]),
+        (Some("fmt"), _) => usage(&opts),
+        (Some("clip"), 3) => clip(load_config(&matches), &free[1..]),
+        (Some("clip"), _) => usage(&opts),
+        (Some("digest"), 4) => digest(load_config(&matches), &matches, &free[1..]),
+        (Some("digest"), _) => usage(&opts),
+        (Some("export"), 3) => export(load_config(&matches), &matches, &free[1..]),
+        (Some("export"), _) => usage(&opts),
+        (Some("enrich"), 2) => enrich(load_config(&matches), &free[1..]),
+        (Some("enrich"), _) => usage(&opts),
+        (Some("suggest-tags"), 4) => suggest_tags(load_config(&matches), &free[1..]),
+        (Some("suggest-tags"), _) => usage(&opts),
+        (Some("issues"), 3) => issues(load_config(&matches), &matches, &free[1..]),
+        (Some("issues"), _) => usage(&opts),
+        (Some("sync"), 2) => sync(load_config(&matches), &free[1..]),
+        (Some("sync"), _) => usage(&opts),
+        (Some("fixture"), 4) => fixture(load_config(&matches), &free[1..]),
+        (Some("fixture"), _) => usage(&opts),
+        (Some("stats"), 2) => stats(load_config(&matches), &free[1..]),
+        (Some("stats"), _) => usage(&opts),
+        (Some("lsp"), 2) => lsp(load_config(&matches), &free[1..]),
+        (Some("lsp"), _) => usage(&opts),
+        (Some("template"), 4) if free[1] == "vars" => {
+            template_vars(load_config(&matches), &free[2..])
+        },
+        (Some("template"), _) => usage(&opts),
+        (Some("cache"), 3) if free[1] == "clear" => {
+            cache_clear(load_config(&matches), &free[2..])
+        },
+        (Some("cache"), _) => usage(&opts),
+        (Some("setup"), 1) => {
+            if let Some(args) = setup::run() {
+                build(Config::load(Path::new(setup::CONFIG)), &args)
+            }
+        },
+        (Some("setup"), _) => usage(&opts),
+        (_, 4) => build(load_config(&matches), free),
+        _ => usage(&opts),
     }
 }
@@ -0,0 +1,285 @@
+// helpers for rewriting markdown source before it's converted
+use std::collections::HashMap;
+use frontmatter;
+use html;
+
+// the length of the code span starting at the beginning of `s` (which must
+// start with a backtick), or `None` if it's never closed
+fn code_span_len(s: &str) -> Option<usize> {
+    let run = s.chars().take_while(|&c| c == '`').count();
+    let mut i = run;
+    while i < s.len() {
+        let rest = &s[i..];
+        let next = rest.find('`')?;
+        let close = rest[next..].chars().take_while(|&c| c == '`').count();
+        if close == run {
+            return Some(i + next + close);
+        }
+        i += next + close;
+    }
+    None
+}
+
+fn map_inline<F: FnMut(usize, &str) -> String>(
+    text: &str,
+    offset: usize,
+    f: &mut F,
+    out: &mut String,
+) {
+    let mut start = 0;
+    let mut i = 0;
+    while let Some(tick) = text[i..].find('`') {
+        let tick = i + tick;
+        // an escaped backtick doesn't start a code span
+        if tick > 0 && text[..tick].ends_with('\\') {
+            i = tick + 1;
+            continue;
+        }
+        match code_span_len(&text[tick..]) {
+            Some(len) => {
+                out.push_str(&f(offset + start, &text[start..tick]));
+                out.push_str(&text[tick..tick + len]);
+                start = tick + len;
+                i = start;
+            },
+            None => {
+                i = tick + text[tick..].chars().take_while(|&c| c == '`').count();
+            },
+        }
+    }
+    out.push_str(&f(offset + start, &text[start..]));
+}
+
+// calls `f` on every part of a markdown document that isn't code (fenced
+// code blocks or inline code spans) or front matter, along with its byte
+// offset in the document, and stitches the results back together with the
+// rest untouched
+pub fn map_text<F: FnMut(usize, &str) -> String>(markdown: &str, mut f: F) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+    let front = frontmatter::len(markdown);
+    out.push_str(&markdown[..front]);
+    let mut text_start = front;
+    let mut pos = front;
+    for line in markdown[front..].split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) => {
+                if trimmed.starts_with(marker) {
+                    fence = None;
+                }
+                out.push_str(line);
+                text_start = pos + line.len();
+            },
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                map_inline(&markdown[text_start..pos], text_start, &mut f, &mut out);
+                fence = Some(&trimmed[..3]);
+                out.push_str(line);
+                text_start = pos + line.len();
+            },
+            None => {},
+        }
+        pos += line.len();
+    }
+    map_inline(&markdown[text_start..], text_start, &mut f, &mut out);
+    out
+}
+
+// gives the labels of a note's footnotes, their `[^label]` markers and
+// definitions alike, `prefix` in front, so that notes put one after another
+// in a single document keep their own footnotes
+pub fn prefix_footnotes(markdown: &str, prefix: &str) -> String {
+    map_text(markdown, |_, text| {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(i) = rest.find("[^") {
+            let after = &rest[i + 2..];
+            let is_label = |l: &&str| {
+                !l.is_empty() && !l.contains(|c: char| c.is_whitespace() || c == '[')
+            };
+            let label = after.find(']').map(|end| &after[..end]).filter(is_label);
+            out.push_str(&rest[..i + 2]);
+            if let Some(label) = label {
+                out.push_str(prefix);
+                out.push('-');
+                out.push_str(label);
+                rest = &after[label.len()..];
+            } else {
+                rest = after;
+            }
+        }
+        out.push_str(rest);
+        out
+    })
+}
+
+// the prose of a note: its text without any code
+pub fn prose(markdown: &str) -> String {
+    let mut text = String::new();
+    map_text(markdown, |_, t| {
+        text.push_str(t);
+        text.push(' ');
+        String::new()
+    });
+    text
+}
+
+// the (1-based) line and column of a byte offset
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rfind('\n').map_or(before, |i| &before[i + 1..]).chars().count() + 1;
+    (line, col)
+}
+
+// the lines of a document outside fenced code blocks and front matter,
+// with their (1-based) line numbers
+pub fn prose_lines(markdown: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut fence: Option<&str> = None;
+    let front = markdown[..frontmatter::len(markdown)].matches('\n').count();
+    for (n, line) in markdown.lines().enumerate().skip(front) {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        lines.push((n + 1, line));
+    }
+    lines
+}
+
+pub struct Heading {
+    pub line: usize,
+    pub text: String,
+    // the identifier renderers give the heading, for linking to it
+    pub id: String,
+}
+
+// the text of a heading without its inline markup, roughly as it renders
+fn heading_text(s: &str) -> String {
+    let mut text = s.replace(['*', '`'], "");
+    // keep the text of links, drop their targets
+    while let Some(start) = text.find("](") {
+        let end = text[start..].find(')').map_or(text.len(), |e| start + e + 1);
+        text.replace_range(start..end, "");
+    }
+    text.replace(['[', ']'], "").trim().to_owned()
+}
+
+// the ATX (`## Title`) and setext (`Title` underlined) headings of a document
+pub fn headings(markdown: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut seen = HashMap::new();
+    let mut previous: Option<(usize, &str)> = None;
+    for (n, line) in prose_lines(markdown) {
+        let trimmed = line.trim();
+        let atx = trimmed.starts_with('#')
+            && trimmed.trim_start_matches('#').starts_with(' ')
+            && trimmed.chars().take_while(|&c| c == '#').count() <= 6;
+        let ruled = |c: char| trimmed.len() >= 2 && trimmed.chars().all(|u| u == c);
+        let heading = if atx {
+            let text = trimmed.trim_start_matches('#').trim_end_matches('#');
+            Some((n, text))
+        } else if ruled('=') || ruled('-') {
+            previous.filter(|&(_, p)| !p.trim().is_empty())
+        } else {
+            None
+        };
+        if let Some((line, text)) = heading {
+            let text = heading_text(text);
+            let id = html::unique_identifier(&text, &mut seen);
+            headings.push(Heading { line, text, id });
+        }
+        previous = Some((n, line));
+    }
+    headings
+}
+
+// whether a block of lines is a paragraph of prose, rather than a heading, a
+// list, a table, a quote, a picture, display math or a link definition
+fn is_prose(lines: &[&str]) -> bool {
+    let first = lines[0].trim_start();
+    let starts = ["#", "|", ">", "<", "![", "- ", "* ", "+ ", "[^", "$$", ":"];
+    let numbered = first.find(['.', ')'])
+        .is_some_and(|i| i > 0 && first[..i].chars().all(|c| c.is_ascii_digit()));
+    let ruled = |line: &&str| {
+        let line = line.trim();
+        line.len() >= 2 && (line.chars().all(|c| c == '=') || line.chars().all(|c| c == '-'))
+    };
+    let definition = first.starts_with('[') && first.contains("]:");
+    !lines[0].starts_with("    ")
+        && !starts.iter().any(|s| first.starts_with(s))
+        && !numbered
+        && !definition
+        && !lines.iter().any(ruled)
+}
+
+// the text of a piece of markdown without its markup: links keep their text,
+// and pictures, citations, footnote markers, math and HTML tags go
+fn plain(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        let tag = c == '<'
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/');
+        let skip = if c == '\\' {
+            // an escaped character stands for itself
+            let escaped = rest[1..].chars().next();
+            out.extend(escaped);
+            1 + escaped.map_or(0, char::len_utf8)
+        } else if rest.starts_with("![") || rest.starts_with("[@") || rest.starts_with("[^") {
+            let close = rest.find(']').map_or(rest.len(), |i| i + 1);
+            match rest[close..].strip_prefix('(') {
+                Some(target) => close + 1 + target.find(')').map_or(target.len(), |i| i + 1),
+                None => close,
+            }
+        } else if c == '[' {
+            // the text stays, and the target goes with the closing bracket
+            1
+        } else if rest.starts_with("](") {
+            rest.find(')').map_or(rest.len(), |i| i + 1)
+        } else if c == '$' {
+            rest[1..].find('$').map_or(1, |i| i + 2)
+        } else if tag {
+            rest.find('>').map_or(1, |i| i + 1)
+        } else if c == '*' || c == '`' || c == ']' || rest.starts_with("~~") {
+            if rest.starts_with("~~") { 2 } else { 1 }
+        } else if c == '_' {
+            // only emphasis, not the underscores in names like `snake_case`
+            let before = out.chars().last().is_none_or(|b| !b.is_alphanumeric());
+            let after = rest[1..].chars().next().is_none_or(|a| !a.is_alphanumeric());
+            if !before && !after {
+                out.push('_');
+            }
+            1
+        } else {
+            out.push(c);
+            c.len_utf8()
+        };
+        rest = &rest[skip..];
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// the first paragraph of prose in a note, as plain text, for describing it
+pub fn first_paragraph(markdown: &str) -> Option<String> {
+    let mut paragraphs: Vec<Vec<&str>> = vec![Vec::new()];
+    for (_, line) in prose_lines(markdown) {
+        if line.trim().is_empty() {
+            paragraphs.push(Vec::new());
+        } else {
+            paragraphs.last_mut().unwrap().push(line);
+        }
+    }
+    paragraphs.iter()
+        .filter(|p| !p.is_empty() && is_prose(p))
+        .map(|p| plain(&p.join("\n")))
+        .find(|text| !text.is_empty())
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use error::Error;
+use html;
+
+const INLINE: (&str, &str) = ("<span class=\"math inline\">\\(", "\\)</span>");
+const DISPLAY: (&str, &str) = ("<span class=\"math display\">\\[", "\\]</span>");
+
+fn katex(path: &str, tex: &str, display: bool) -> Result<String, Error> {
+    let mut command = Command::new(path);
+    if display {
+        command.arg("--display-mode");
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Math(format!("could not run {}: {}", path, e)))?;
+    child.stdin.take().unwrap().write_all(tex.as_bytes())
+        .map_err(|e| Error::Math(e.to_string()))?;
+    let output = child.wait_with_output()
+        .map_err(|e| Error::Math(e.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Math(format!("`{}`: {}", tex, stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+// replaces the math spans produced by either renderer with HTML rendered by
+// the KaTeX command-line tool. The pages then only need KaTeX's stylesheet.
+pub fn prerender(body: &str, katex_path: &str) -> Result<String, Error> {
+    // notes tend to repeat the same symbols, so only render each one once
+    let mut cache: HashMap<(bool, String), String> = HashMap::new();
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    loop {
+        let next = [(false, INLINE), (true, DISPLAY)].iter()
+            .filter_map(|&(display, (open, close))| {
+                rest.find(open).map(|i| (i, display, open, close))
+            })
+            .min_by_key(|&(i, _, _, _)| i);
+        let (start, display, open, close) = match next {
+            Some(n) => n,
+            None => break,
+        };
+        let tex_start = start + open.len();
+        let len = match rest[tex_start..].find(close) {
+            Some(len) => len,
+            None => break,
+        };
+        let tex = html::unescape(&rest[tex_start..tex_start + len]);
+        out.push_str(&rest[..start]);
+        let key = (display, tex);
+        if !cache.contains_key(&key) {
+            let rendered = katex(katex_path, &key.1, display)?;
+            cache.insert(key.clone(), rendered);
+        }
+        out.push_str(&cache[&key]);
+        rest = &rest[tex_start + len + close.len()..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `cat` prints the TeX it's given, as if KaTeX had rendered it
+    #[test]
+    fn prerenders_the_math_pandoc_writes() {
+        let body = "<p><span class=\"math inline\">\\(x &lt; y\\)</span> and \
+                    <span class=\"math inline\">\\(x &lt; y\\)</span></p>";
+        assert_eq!(prerender(body, "cat").unwrap(), "<p>x < y and x < y</p>");
+    }
+
+    #[test]
+    fn leaves_everything_else_alone() {
+        let body = "<p>\\(x\\) <span class=\"math inline\">\\(x</p>";
+        assert_eq!(prerender(body, "cat").unwrap(), body);
+    }
+
+    #[test]
+    fn fails_when_katex_does() {
+        // `cat` doesn't take KaTeX's `--display-mode`
+        let body = "<span class=\"math display\">\\[x\\]</span>";
+        assert!(prerender(body, "cat").is_err());
+        assert!(prerender(body, "/nonexistent/katex").is_err());
+    }
+}
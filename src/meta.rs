@@ -0,0 +1,123 @@
+use std::fmt::Write;
+use serde_json::{Map, Value};
+use bib::Entry;
+use html;
+use markdown;
+
+// What a page says about itself to whatever shows a preview of a link to
+// it: Open Graph and Twitter Card tags, and the data to write them by hand.
+
+// words that end in a full stop without ending a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "e.g", "i.e", "et al", "cf", "vs", "etc", "resp", "Fig", "Eq",
+];
+
+// the start of `text`, cut at a word boundary
+pub fn excerpt(text: &str, max: usize) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut out = String::new();
+    for word in &words {
+        if out.chars().count() + word.chars().count() + 1 > max {
+            out.push('…');
+            return out;
+        }
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+
+// the start of `text`: as many whole sentences as fit in `max` characters,
+// or if the first doesn't come close, as many words
+pub fn summary(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+    let limit = text.char_indices().nth(max).map_or(text.len(), |(i, _)| i);
+    let end = text[..limit].rmatch_indices(['.', '!', '?'])
+        .map(|(i, _)| i)
+        .find(|&i| {
+            text[i + 1..].starts_with(char::is_whitespace)
+                && !ABBREVIATIONS.iter().any(|a| text[..i].ends_with(a))
+        });
+    match end {
+        Some(end) if text[..end].chars().count() >= max / 2 => text[..=end].to_owned(),
+        _ => excerpt(text, max),
+    }
+}
+
+pub struct PageMeta {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub year: String,
+    pub description: Option<String>,
+    // the full URL of the page, if the base URL is known
+    pub url: Option<String>,
+}
+
+impl PageMeta {
+    // describes an entry with the first paragraph of its note, or failing
+    // that, its abstract, in at most `max` characters
+    pub fn new(entry: &Entry, note: &str, url: Option<String>, max: usize) -> PageMeta {
+        let text = markdown::first_paragraph(note).or_else(|| entry.text_field("abstract"));
+        let description = text.map(|t| summary(&t, max)).filter(|d| !d.is_empty());
+        PageMeta {
+            title: entry.title.clone(),
+            authors: entry.authors().iter().map(|n| n.full()).collect(),
+            year: entry.year.clone(),
+            description,
+            url,
+        }
+    }
+
+    // the `<meta>` tags, for templates to include with `{{{og_meta}}}`
+    pub fn tags(&self) -> String {
+        let mut tags = String::new();
+        // Open Graph tags are properties, Twitter's are names
+        let mut property = |name: &str, value: &str| {
+            let attr = if name.starts_with("twitter:") { "name" } else { "property" };
+            writeln!(
+                tags,
+                "<meta {}=\"{}\" content=\"{}\">",
+                attr,
+                name,
+                html::escape(value)
+            ).unwrap();
+        };
+        property("og:type", "article");
+        property("og:title", &self.title);
+        if let Some(ref description) = self.description {
+            property("og:description", description);
+        }
+        if let Some(ref url) = self.url {
+            property("og:url", url);
+        }
+        for author in &self.authors {
+            property("article:author", author);
+        }
+        property("twitter:card", "summary");
+        property("twitter:title", &self.title);
+        if let Some(ref description) = self.description {
+            property("twitter:description", description);
+        }
+        if let Some(ref url) = self.url {
+            writeln!(tags, "<link rel=\"canonical\" href=\"{}\">", html::escape(url)).unwrap();
+        }
+        tags
+    }
+
+    pub fn insert_into(&self, data: &mut Map<String, Value>) {
+        let authors = self.authors.iter().map(|a| Value::String(a.clone())).collect();
+        data.insert("authors".to_owned(), Value::Array(authors));
+        data.insert("year".to_owned(), Value::String(self.year.clone()));
+        if let Some(ref description) = self.description {
+            data.insert("description".to_owned(), Value::String(description.clone()));
+        }
+        if let Some(ref url) = self.url {
+            data.insert("canonical_url".to_owned(), Value::String(url.clone()));
+        }
+        data.insert("og_meta".to_owned(), Value::String(self.tags()));
+    }
+}
@@ -0,0 +1,110 @@
+// Makes pages smaller, for `--minify`: comments go, runs of whitespace
+// become a single space or newline, and stylesheets lose theirs too. A small
+// stylesheet can also be put in each page's head, so it doesn't have to be
+// fetched. Whitespace is never removed altogether, since between inline
+// elements it shows, and `<pre>`, `<textarea>` and `<script>` are left
+// exactly as they were.
+
+// elements whose contents aren't HTML
+const VERBATIM: &[&str] = &["pre", "textarea", "script", "style"];
+
+// the name of the element a tag at the start of `s` opens, lowercased
+fn opened(s: &str) -> Option<String> {
+    let name: String = s.strip_prefix('<')?
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect();
+    Some(name.to_ascii_lowercase()).filter(|n| !n.is_empty())
+}
+
+// where the closing tag of `name` ends in `s`, or the end of `s`
+fn closing_end(s: &str, name: &str) -> usize {
+    let lower = s.to_ascii_lowercase();
+    let tag = format!("</{}", name);
+    match lower.find(&tag) {
+        Some(start) => s[start..].find('>').map_or(s.len(), |end| start + end + 1),
+        None => s.len(),
+    }
+}
+
+// a run of whitespace, shortened to one character
+fn collapse(run: &str) -> char {
+    if run.contains('\n') { '\n' } else { ' ' }
+}
+
+pub fn html(page: &str) -> String {
+    let mut out = String::with_capacity(page.len());
+    let mut rest = page;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(rest.len(), |end| end + 3);
+            // conditional comments are markup for old browsers
+            if rest.starts_with("<!--[if") {
+                out.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+        } else if let Some(name) = opened(rest).filter(|n| VERBATIM.contains(&&n[..])) {
+            let end = closing_end(rest, &name);
+            if name == "style" {
+                let open = rest.find('>').map_or(end, |i| i + 1).min(end);
+                let close = rest[..end].rfind("</").unwrap_or(end).max(open);
+                out.push_str(&rest[..open]);
+                out.push_str(&css(&rest[open..close]));
+                out.push_str(&rest[close..end]);
+            } else {
+                out.push_str(&rest[..end]);
+            }
+            rest = &rest[end..];
+        } else if c.is_whitespace() {
+            let len = rest.find(|c: char| !c.is_whitespace()).unwrap_or(rest.len());
+            // a comment can have been between two runs
+            if !out.ends_with(char::is_whitespace) {
+                out.push(collapse(&rest[..len]));
+            }
+            rest = &rest[len..];
+        } else {
+            let len = rest[1..].find(|c: char| c == '<' || c.is_whitespace())
+                .map_or(rest.len(), |i| i + 1);
+            out.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+    }
+    out.trim().to_owned()
+}
+
+// a stylesheet without its comments, and with whitespace only where it's
+// needed
+pub fn css(sheet: &str) -> String {
+    let mut out = String::with_capacity(sheet.len());
+    let mut rest = sheet;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("/*") {
+            rest = rest.find("*/").map_or("", |end| &rest[end + 2..]);
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..].find(c).map_or(rest.len(), |i| i + 2);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if c.is_whitespace() {
+            rest = rest.trim_start();
+            // only where it means something, like in `1px solid` or `a :hover`
+            let after = out.ends_with(|c: char| "{};:,>".contains(c));
+            let before = rest.starts_with(|c: char| "{};,>".contains(c));
+            if !after && !before && !rest.is_empty() {
+                out.push(' ');
+            }
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out.trim().to_owned()
+}
+
+// puts a stylesheet at the end of the page's head
+pub fn inline_css(page: &str, sheet: &str) -> String {
+    let style = format!("<style>{}</style>", css(sheet));
+    match page.to_ascii_lowercase().find("</head>") {
+        Some(end) => format!("{}{}{}", &page[..end], style, &page[end..]),
+        None => format!("{}{}", style, page),
+    }
+}
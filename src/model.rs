@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use serde_json::{self, Map, Value};
+use bib::{Bibliography, Entry};
+use config::Config;
+use frontmatter;
+use markdown;
+use provenance::{self, Provenance};
+use reading::Reading;
+use references;
+use site;
+use urls;
+use xref;
+
+// Everything a build knows about the bibliography and the notes, as one JSON
+// document, for tools that would rather not parse BibTeX themselves.
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_owned())
+}
+
+fn strings<'a, I: IntoIterator<Item = &'a String>>(strs: I) -> Value {
+    Value::Array(strs.into_iter().map(|s| string(s)).collect())
+}
+
+fn optional(s: Option<String>) -> Value {
+    s.map_or(Value::Null, Value::String)
+}
+
+fn note_json(
+    bib: &Bibliography,
+    entry: &Entry,
+    note: &str,
+    cited_by: &[String],
+    config: &Config,
+) -> Value {
+    let mut json = Map::new();
+    let path = site::note_path(config, &entry.key);
+    json.insert("path".to_owned(), string(&path.display().to_string()));
+    let fields = frontmatter::fields(note);
+    let (reading, _) = Reading::from_fields(&fields);
+    let front = fields.iter().map(|(k, v)| (k.clone(), string(v))).collect();
+    json.insert("front_matter".to_owned(), Value::Object(front));
+    if let Some(status) = reading.status {
+        json.insert("status".to_owned(), string(status.name()));
+    }
+    if let Some(rating) = reading.rating {
+        json.insert("rating".to_owned(), Value::U64(rating as u64));
+    }
+    let words = markdown::prose(note).split_whitespace().count();
+    json.insert("words".to_owned(), Value::U64(words as u64));
+    let headings = markdown::headings(note).into_iter().map(|h| {
+        let mut heading = Map::new();
+        heading.insert("line".to_owned(), Value::U64(h.line as u64));
+        heading.insert("text".to_owned(), Value::String(h.text));
+        heading.insert("id".to_owned(), Value::String(h.id));
+        Value::Object(heading)
+    });
+    json.insert("headings".to_owned(), Value::Array(headings.collect()));
+    json.insert("cites".to_owned(), strings(&xref::cited(note)));
+    json.insert("cited_by".to_owned(), strings(cited_by));
+    let references = references::extract(note, bib).into_iter().map(|r| {
+        let mut reference = Map::new();
+        reference.insert("line".to_owned(), Value::U64(r.line as u64));
+        reference.insert("text".to_owned(), Value::String(r.text));
+        reference.insert("key".to_owned(), optional(r.key));
+        reference.insert("doi".to_owned(), optional(r.doi));
+        reference.insert("url".to_owned(), optional(r.url));
+        Value::Object(reference)
+    });
+    json.insert("references".to_owned(), Value::Array(references.collect()));
+    Value::Object(json)
+}
+
+fn entry_json(
+    bib: &Bibliography,
+    entry: &Entry,
+    notes: &HashMap<String, String>,
+    pages: &HashMap<String, String>,
+    backlinks: &HashMap<String, Vec<String>>,
+    provenance: &Provenance,
+    config: &Config,
+) -> Value {
+    let mut json = Map::new();
+    json.insert("key".to_owned(), string(&entry.key));
+    json.insert("line".to_owned(), Value::U64(entry.line as u64));
+    json.insert("title".to_owned(), string(&entry.title));
+    json.insert("author".to_owned(), string(&entry.author));
+    let authors = entry.authors().into_iter().map(|n| {
+        let mut name = Map::new();
+        let parts = [("first", n.first), ("von", n.von), ("last", n.last), ("jr", n.jr)];
+        for (part, value) in parts {
+            name.insert(part.to_owned(), Value::String(value));
+        }
+        Value::Object(name)
+    });
+    json.insert("authors".to_owned(), Value::Array(authors.collect()));
+    json.insert("year".to_owned(), string(&entry.year));
+    let fields = entry.fields.iter().map(|(k, v)| (k.clone(), string(v))).collect();
+    json.insert("fields".to_owned(), Value::Object(fields));
+    json.insert("tags".to_owned(), strings(&entry.tags()));
+    let links = entry.links().into_iter().map(|l| {
+        let mut link = Map::new();
+        link.insert("label".to_owned(), string(l.label));
+        link.insert("url".to_owned(), Value::String(l.url));
+        Value::Object(link)
+    });
+    json.insert("links".to_owned(), Value::Array(links.collect()));
+    if let Some(url) = pages.get(&entry.key) {
+        json.insert("page".to_owned(), string(url));
+        let output = config.output_dir.join(urls::file_for(url));
+        json.insert("output".to_owned(), string(&output.display().to_string()));
+    }
+    let note = match notes.get(&entry.key) {
+        Some(note) => {
+            let cited_by = backlinks.get(&entry.key).map_or(&[][..], |b| &b[..]);
+            note_json(bib, entry, note, cited_by, config)
+        },
+        None => Value::Null,
+    };
+    json.insert("note".to_owned(), note);
+    json.insert("provenance".to_owned(), provenance::of(provenance, &entry.key).to_json());
+    Value::Object(json)
+}
+
+// every entry of the bibliography, in its order, with its note if it has one
+pub fn json(
+    bib: &Bibliography,
+    notes: &HashMap<String, String>,
+    pages: &HashMap<String, String>,
+    backlinks: &HashMap<String, Vec<String>>,
+    config: &Config,
+) -> String {
+    let mut root = Map::new();
+    root.insert("bibliography".to_owned(), string(&bib.path));
+    let provenance = provenance::load(config);
+    let entries = bib.entries.iter()
+        .map(|e| entry_json(bib, e, notes, pages, backlinks, &provenance, config))
+        .collect();
+    root.insert("entries".to_owned(), Value::Array(entries));
+    serde_json::to_string_pretty(&root).expect("Could not serialise the site model")
+}
@@ -0,0 +1,181 @@
+use latex;
+
+// Parsing of personal names following BibTeX's rules, which split a name
+// into First, von, Last and Jr parts. Names can be written as "First von
+// Last", "von Last, First" or "von Last, Jr, First"; the von part is made of
+// the words starting with a lowercase letter, so "Ludwig van Beethoven" and
+// "van Beethoven, Ludwig" are the same name. Braces protect a group of words
+// from being split, as in "{Barnes and Noble}".
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Name {
+    pub first: String,
+    pub von: String,
+    pub last: String,
+    pub jr: String,
+}
+
+// splits `s` on whitespace and `~` outside braces
+fn words(s: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            _ => {},
+        }
+        if depth == 0 && (c.is_whitespace() || c == '~') {
+            if !word.is_empty() {
+                out.push(word.clone());
+                word.clear();
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        out.push(word);
+    }
+    out
+}
+
+// splits `s` on commas outside braces
+fn parts(s: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut part = String::new();
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            ',' if depth == 0 => {
+                out.push(part.trim().to_owned());
+                part.clear();
+                continue;
+            },
+            _ => {},
+        }
+        part.push(c);
+    }
+    out.push(part.trim().to_owned());
+    out
+}
+
+// "letters" that are written as commands, whose case is that of the command
+const FOREIGN: &[&str] = &[
+    "aa", "AA", "ae", "AE", "oe", "OE", "o", "O", "l", "L", "ss", "i", "j",
+];
+
+// whether a word counts as lowercase, i.e. belongs to the von part. Only
+// letters outside braces count, except for "special characters" like
+// `{\"o}`, whose case is that of the letter they apply to.
+fn is_lowercase(word: &str) -> bool {
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '{' {
+            if chars.peek() != Some(&'\\') {
+                // an ordinary braced group is caseless, so not von
+                return false;
+            }
+            chars.next();
+            let command: String = chars.clone().take_while(|c| c.is_alphabetic()).collect();
+            if FOREIGN.contains(&&command[..]) {
+                return command.starts_with(|c: char| c.is_lowercase());
+            }
+            // an accent: skip it and look at the letter it applies to
+            for _ in command.chars() {
+                chars.next();
+            }
+            return chars.find(|c| c.is_alphabetic()).is_some_and(|c| c.is_lowercase());
+        }
+        if c.is_alphabetic() {
+            return c.is_lowercase();
+        }
+    }
+    false
+}
+
+// suffixes recognised at the end of a "First Last Jr." name. BibTeX itself
+// only knows about Jr in the comma forms, but "John Smith Jr." is common
+// enough that treating "Jr." as the last name would sort it with the Js.
+fn is_suffix(word: &str) -> bool {
+    matches!(word.trim_end_matches('.'), "Jr" | "Sr" | "II" | "III" | "IV")
+}
+
+fn join(words: &[String]) -> String {
+    words.join(" ")
+}
+
+impl Name {
+    pub fn parse(name: &str) -> Name {
+        let parts = parts(name);
+        let (first, rest, jr) = match parts.len() {
+            1 => {
+                // First von Last: von starts at the first lowercase word
+                // (but the last word is always part of Last)
+                let mut ws = words(&parts[0]);
+                let mut jr = Vec::new();
+                if ws.len() > 1 && is_suffix(&ws[ws.len() - 1]) {
+                    jr = ws.split_off(ws.len() - 1);
+                }
+                if ws.is_empty() {
+                    return Name::default();
+                }
+                let upto = ws.len() - 1;
+                let von_start = ws[..upto].iter().position(|w| is_lowercase(w));
+                match von_start {
+                    Some(v) => (ws[..v].to_vec(), ws[v..].to_vec(), jr),
+                    None => (ws[..upto].to_vec(), ws[upto..].to_vec(), jr),
+                }
+            },
+            2 => (words(&parts[1]), words(&parts[0]), Vec::new()),
+            _ => (words(&parts[2..].join(", ")), words(&parts[0]), words(&parts[1])),
+        };
+        // in "von Last" the von part runs up to the last lowercase word,
+        // leaving at least one word for Last
+        let mut von_end = 0;
+        if !rest.is_empty() {
+            for (i, w) in rest[..rest.len() - 1].iter().enumerate() {
+                if is_lowercase(w) {
+                    von_end = i + 1;
+                }
+            }
+        }
+        Name {
+            first: latex::to_unicode(&join(&first)),
+            von: latex::to_unicode(&join(&rest[..von_end])),
+            last: latex::to_unicode(&join(&rest[von_end..])),
+            jr: latex::to_unicode(&join(&jr)),
+        }
+    }
+
+    // the name as it's cited: "van den Berg"
+    pub fn surname(&self) -> String {
+        if self.von.is_empty() {
+            self.last.clone()
+        } else {
+            format!("{} {}", self.von, self.last)
+        }
+    }
+
+    // the name in reading order: "Jan van den Berg Jr."
+    pub fn full(&self) -> String {
+        [&self.first, &self.von, &self.last, &self.jr].iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| &s[..])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // the key names are sorted by: last name first, ignoring the von part
+    // as biblatex does by default, so "van den Berg" sorts with the Bs
+    pub fn sort_key(&self) -> String {
+        [&self.last, &self.first, &self.von, &self.jr].iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| &s[..])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
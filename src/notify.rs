@@ -0,0 +1,112 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use serde_json::{self, Map, Value};
+use config::Config;
+
+// Word of how each build went, for builds nobody is watching, like those run
+// by a file watcher or on a schedule: a desktop notification (with
+// notify-send, or osascript on macOS) and a message posted to a webhook, as
+// `{"text": ...}`, which Slack's incoming webhooks and Matrix's (through
+// hookshot) both take. Each says how many pages were built, with how many
+// warnings, and which entries failed or were skipped.
+
+pub struct Summary {
+    pub pages: usize,
+    pub warnings: usize,
+    pub failed: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl Summary {
+    fn failed(&self) -> bool {
+        !self.failed.is_empty() || !self.skipped.is_empty()
+    }
+
+    fn title(&self) -> &'static str {
+        if self.failed() {
+            "biblionotes: the build went wrong"
+        } else {
+            "biblionotes: built the site"
+        }
+    }
+
+    fn body(&self) -> String {
+        let mut body = format!("built {} pages, with {} warnings", self.pages, self.warnings);
+        if !self.failed.is_empty() {
+            let keys = self.failed.join(", ");
+            body.push_str(&format!("; {} entries failed: {}", self.failed.len(), keys));
+        }
+        if !self.skipped.is_empty() {
+            let keys = self.skipped.join(", ");
+            body.push_str(&format!("; {} malformed entries: {}", self.skipped.len(), keys));
+        }
+        body
+    }
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let output = command.output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_owned())
+    }
+}
+
+fn desktop(title: &str, body: &str) -> Result<(), String> {
+    if cfg!(target_os = "macos") {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let script = format!(
+            "display notification {} with title {}",
+            quote(body),
+            quote(title)
+        );
+        run(Command::new("osascript").args(["-e", &script]))
+    } else {
+        run(Command::new("notify-send").args([title, body]))
+    }
+}
+
+fn webhook(config: &Config, url: &str, text: &str) -> Result<(), String> {
+    let mut message = Map::new();
+    message.insert("text".to_owned(), Value::String(text.to_owned()));
+    let message = serde_json::to_string(&message).expect("Could not serialise the message");
+    let mut child = Command::new(&config.enrich.curl)
+        .args(["--silent", "--show-error", "--fail", "--request", "POST"])
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--user-agent", "biblionotes", "--data-binary", "@-"])
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("could not run {}: {}", config.enrich.curl, e))?;
+    child.stdin.take().unwrap().write_all(message.as_bytes()).map_err(|e| e.to_string())?;
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_owned())
+    }
+}
+
+// sends the summary wherever it's asked for, returning what went wrong
+pub fn send(config: &Config, summary: &Summary) -> Vec<String> {
+    let notify = &config.notify;
+    if notify.failures_only && !summary.failed() {
+        return Vec::new();
+    }
+    let (title, body) = (summary.title(), summary.body());
+    let mut errors = Vec::new();
+    if notify.desktop {
+        if let Err(e) = desktop(title, &body) {
+            errors.push(format!("could not show a desktop notification: {}", e));
+        }
+    }
+    if let Some(ref url) = notify.webhook {
+        if let Err(e) = webhook(config, url, &format!("{}\n{}", title, body)) {
+            errors.push(format!("could not post to the webhook: {}", e));
+        }
+    }
+    errors
+}
@@ -0,0 +1,500 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+// Where a build writes its files. With `--dry-run` nothing is written;
+// instead each file is compared with what's already there, and the plan is
+// reported at the end.
+//
+// Files aren't written where they go straight away, but into a staging
+// directory, and only moved into place once the whole build is done and
+// every entry built, so a build that fails or is stopped halfway leaves the
+// site as it was rather than with some pages new and the index old. The
+// staging directory is next to the output directory rather than in it, so
+// that deploying the site never takes it along, but still on the same disk,
+// so that moving a file is renaming it. The files it replaces are moved
+// aside first, and if any file can't be moved into place, those already
+// moved are put back.
+//
+// Every build also leaves a manifest of the files it wrote in the output
+// directory. It's what marks the directory as ours, and it's how pruning
+// knows which files an earlier build made that this one no longer does,
+// without ever touching files put there by hand.
+
+const MANIFEST: &str = ".biblionotes-manifest";
+const STAGING: &str = "biblionotes-staging";
+// where the files a build replaces wait until all of it is in place
+const PREVIOUS: &str = "biblionotes-previous";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Write,
+    DryRun,
+    // only working out which files there would be, as `clean` does
+    Plan,
+    // making everything but keeping none of it, as `fixture` does
+    Discard,
+}
+
+enum Action {
+    Create,
+    // with the reason it would change
+    Update(String),
+    Unchanged,
+}
+
+pub struct Output {
+    dir: PathBuf,
+    // next to `dir`, and named after it
+    staging: PathBuf,
+    previous: PathBuf,
+    mode: Mode,
+    // every file of this build inside the output directory, relative to it
+    made: RefCell<BTreeSet<PathBuf>>,
+    // what's been written to the staging directory, relative to it
+    staged: RefCell<BTreeSet<PathBuf>>,
+    planned: RefCell<Vec<(PathBuf, Action)>>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// every file under a directory, if it exists
+pub fn files_under(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            files_under(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+// whether a path stays inside the directory it's relative to
+fn inside(path: &Path) -> bool {
+    path.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+// a hidden directory next to `dir`, like `.site.biblionotes-staging` for
+// `site`, even when `dir` is given as `.`
+fn beside(dir: &Path, what: &str) -> PathBuf {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_owned());
+    let name = dir.file_name().map_or("output".into(), |n| n.to_string_lossy());
+    dir.with_file_name(format!(".{}.{}", name, what))
+}
+
+impl Output {
+    pub fn new(dir: &Path, mode: Mode) -> Output {
+        let staging = beside(dir, STAGING);
+        let previous = beside(dir, PREVIOUS);
+        // what a build that didn't finish left
+        if mode == Mode::Write {
+            let _ = fs::remove_dir_all(&staging);
+            let _ = fs::remove_dir_all(&previous);
+        }
+        Output {
+            dir: dir.to_owned(),
+            staging,
+            previous,
+            mode,
+            made: RefCell::new(BTreeSet::new()),
+            staged: RefCell::new(BTreeSet::new()),
+            planned: RefCell::new(Vec::new()),
+        }
+    }
+
+    // the directories files of this build are in, for pandoc to find
+    // images in: the files written so far first, then those already there
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        if self.mode == Mode::Write {
+            vec![self.staging.clone(), self.dir.clone()]
+        } else {
+            vec![self.dir.clone()]
+        }
+    }
+
+    // whether files are only being counted, so there's no point making them
+    pub fn planning(&self) -> bool {
+        self.mode == Mode::Plan
+    }
+
+    // writes a file, creating its directory if needed. `path` is relative to
+    // the output directory, and `sources` are the files it's made from, to
+    // explain why it would change.
+    pub fn write(&self, path: &Path, contents: &str, sources: &[&Path]) -> io::Result<()> {
+        self.write_bytes(path, contents.as_bytes(), sources)
+    }
+
+    // copies a file into the output directory, as `write` would write it
+    pub fn copy(&self, path: &Path, from: &Path) -> io::Result<()> {
+        if self.mode == Mode::Plan || self.mode == Mode::Discard {
+            return self.write_bytes(path, &[], &[]);
+        }
+        self.write_bytes(path, &fs::read(from)?, &[from])
+    }
+
+    // `write`, for files that aren't text
+    pub fn write_bytes(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        sources: &[&Path],
+    ) -> io::Result<()> {
+        let relative = path;
+        if inside(path) {
+            self.made.borrow_mut().insert(path.to_owned());
+        }
+        let path = self.dir.join(path);
+        match self.mode {
+            Mode::Write => {
+                trace!("writing {}", path.display());
+                let staged = if inside(relative) {
+                    self.staged.borrow_mut().insert(relative.to_owned());
+                    self.staging.join(relative)
+                } else {
+                    path
+                };
+                if let Some(dir) = staged.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                return File::create(&staged).and_then(|mut f| f.write_all(contents));
+            },
+            Mode::Plan | Mode::Discard => return Ok(()),
+            Mode::DryRun => {},
+        }
+        let mut old = Vec::new();
+        let action = match File::open(&path).and_then(|mut f| f.read_to_end(&mut old)) {
+            Err(_) => Action::Create,
+            Ok(_) if old == contents => Action::Unchanged,
+            Ok(_) => {
+                let written = modified(&path);
+                let newer = sources.iter()
+                    .find(|s| modified(s).is_some_and(|m| written.is_none_or(|w| m > w)));
+                Action::Update(match newer {
+                    Some(source) => format!("{} is newer than the output", source.display()),
+                    None => "the settings or the bibliography changed".to_owned(),
+                })
+            },
+        };
+        self.planned.borrow_mut().push((path, action));
+        Ok(())
+    }
+
+    // how many files the build writes, or would write
+    pub fn files_made(&self) -> usize {
+        self.made.borrow().len()
+    }
+
+    // moves one staged file into place, moving the file it replaces aside
+    fn swap(&self, path: &Path) -> io::Result<()> {
+        let to = self.dir.join(path);
+        let old = self.previous.join(path);
+        if let Some(dir) = to.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let replacing = to.is_file();
+        if replacing {
+            if let Some(dir) = old.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::rename(&to, &old)?;
+        }
+        fs::rename(self.staging.join(path), &to).inspect_err(|_| {
+            if replacing {
+                let _ = fs::rename(&old, &to);
+            }
+        })
+    }
+
+    // undoes `swap`, as far as it can: there's nothing more to do about a
+    // file that can't be put back
+    fn unswap(&self, path: &Path) {
+        let to = self.dir.join(path);
+        let old = self.previous.join(path);
+        let _ = fs::rename(&to, self.staging.join(path));
+        if old.is_file() {
+            let _ = fs::rename(&old, &to);
+        }
+    }
+
+    // moves what the build wrote from the staging directory into place, now
+    // that it's all been written, all of it or, if some file can't be, none
+    pub fn commit(&self) -> io::Result<()> {
+        if self.mode != Mode::Write {
+            return Ok(());
+        }
+        let staged = self.staged.borrow();
+        for (i, path) in staged.iter().enumerate() {
+            if let Err(e) = self.swap(path) {
+                for path in staged.iter().take(i).rev() {
+                    self.unswap(path);
+                }
+                let _ = fs::remove_dir_all(&self.previous);
+                return Err(e);
+            }
+        }
+        for dir in [&self.staging, &self.previous] {
+            match fs::remove_dir_all(dir) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    // the files the last build wrote, or `None` if the directory doesn't
+    // look like one of ours
+    fn manifest(&self) -> Option<BTreeSet<PathBuf>> {
+        let mut contents = String::new();
+        File::open(self.dir.join(MANIFEST))
+            .and_then(|mut f| f.read_to_string(&mut contents))
+            .ok()?;
+        Some(contents.lines()
+            .map(PathBuf::from)
+            .filter(|p| !p.as_os_str().is_empty() && inside(p))
+            .collect())
+    }
+
+    // counts the files the last build wrote as this one's too, for builds of
+    // only some of the pages, which leave the others as they were
+    pub fn keep_previous(&self) {
+        let old = self.manifest().unwrap_or_default();
+        self.made.borrow_mut().extend(old);
+    }
+
+    pub fn save_manifest(&self) -> io::Result<()> {
+        let mut contents = String::new();
+        for path in self.made.borrow().iter() {
+            contents.push_str(&path.to_string_lossy());
+            contents.push('\n');
+        }
+        File::create(self.dir.join(MANIFEST))
+            .and_then(|mut f| f.write_all(contents.as_bytes()))
+    }
+
+    // removes the files the last build wrote that this one didn't, along
+    // with any directories that leaves empty, and returns them
+    pub fn prune(&self) -> Result<Vec<PathBuf>, String> {
+        let old = self.manifest().ok_or_else(|| format!(
+            "{} has no {}, so it doesn't look like biblionotes made it",
+            self.dir.display(),
+            MANIFEST
+        ))?;
+        let made = self.made.borrow();
+        let mut removed = Vec::new();
+        for path in old.difference(&made) {
+            let full = self.dir.join(path);
+            if !full.is_file() {
+                continue;
+            }
+            fs::remove_file(&full)
+                .map_err(|e| format!("could not remove {}: {}", full.display(), e))?;
+            info!("removed {}", full.display());
+            removed.push(full);
+            // `remove_dir` only removes empty directories
+            let mut dir = path.parent();
+            while let Some(d) = dir.filter(|d| !d.as_os_str().is_empty()) {
+                if fs::remove_dir(self.dir.join(d)).is_err() {
+                    break;
+                }
+                dir = d.parent();
+            }
+        }
+        Ok(removed)
+    }
+
+    // prints what a dry run would have done, including the files already in
+    // the output directory that the build no longer makes
+    pub fn report(&self) {
+        if self.mode != Mode::DryRun {
+            return;
+        }
+        let planned = self.planned.borrow();
+        let (mut created, mut updated, mut unchanged) = (0, 0, 0);
+        for (path, action) in planned.iter() {
+            match action {
+                Action::Create => {
+                    created += 1;
+                    println!("create  {}", path.display());
+                },
+                Action::Update(reason) => {
+                    updated += 1;
+                    println!("update  {} ({})", path.display(), reason);
+                },
+                Action::Unchanged => unchanged += 1,
+            }
+        }
+        let made = self.made.borrow();
+        let ours = self.manifest().unwrap_or_default();
+        let mut existing = Vec::new();
+        files_under(&self.dir, &mut existing);
+        existing.sort();
+        let mut stale = 0;
+        for path in &existing {
+            let relative = match path.strip_prefix(&self.dir) {
+                Ok(relative) if relative != Path::new(MANIFEST) => relative,
+                _ => continue,
+            };
+            if made.contains(relative) {
+                continue;
+            }
+            stale += 1;
+            if ours.contains(relative) {
+                println!("stale   {} (no longer generated; --prune removes it)", path.display());
+            } else {
+                println!("stale   {} (not made by biblionotes)", path.display());
+            }
+        }
+        println!(
+            "dry run: would create {} files and update {}, leaving {} unchanged; \
+             {} stale files",
+            created,
+            updated,
+            unchanged,
+            stale
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    // an empty directory of the test's own
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("biblionotes-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    // a build writing the given files
+    fn build(dir: &Path, files: &[(&str, &str)]) -> Output {
+        let output = Output::new(dir, Mode::Write);
+        for &(path, contents) in files {
+            output.write(Path::new(path), contents, &[]).unwrap();
+        }
+        output
+    }
+
+    #[test]
+    fn tells_paths_inside_the_directory() {
+        assert!(inside(Path::new("index.html")));
+        assert!(inside(Path::new("./pdfs/a.pdf")));
+        assert!(!inside(Path::new("../elsewhere.html")));
+        assert!(!inside(Path::new("pdfs/../../elsewhere.html")));
+        assert!(!inside(Path::new("/etc/passwd")));
+    }
+
+    #[test]
+    fn stages_next_to_the_output_directory() {
+        let dir = temp_dir("beside");
+        let output = build(&dir, &[("index.html", "new")]);
+        let name = dir.file_name().unwrap().to_string_lossy();
+        let staging = dir.with_file_name(format!(".{}.biblionotes-staging", name));
+        assert_eq!(output.staging, staging.canonicalize().unwrap());
+        assert!(output.staging.join("index.html").is_file());
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        output.commit().unwrap();
+        assert!(!output.staging.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn writes_nothing_until_committed() {
+        let dir = temp_dir("commit");
+        fs::write(dir.join("index.html"), "old").unwrap();
+        let output = build(&dir, &[("index.html", "new"), ("a/b.html", "b")]);
+        assert_eq!(read(&dir.join("index.html")), "old");
+        assert!(!dir.join("a/b.html").exists());
+        output.commit().unwrap();
+        assert_eq!(read(&dir.join("index.html")), "new");
+        assert_eq!(read(&dir.join("a/b.html")), "b");
+        assert!(!output.staging.exists());
+        assert!(!output.previous.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn puts_everything_back_when_a_file_cant_be_moved() {
+        let dir = temp_dir("rollback");
+        fs::write(dir.join("a.html"), "old").unwrap();
+        // a directory where the build wants a file
+        fs::create_dir_all(dir.join("b.html/c")).unwrap();
+        let output = build(&dir, &[("a.html", "new"), ("b.html", "b"), ("new.html", "n")]);
+        assert!(output.commit().is_err());
+        assert_eq!(read(&dir.join("a.html")), "old");
+        assert!(dir.join("b.html").is_dir());
+        assert!(!dir.join("new.html").exists());
+        assert!(!output.previous.exists());
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&output.staging).unwrap();
+    }
+
+    #[test]
+    fn clears_what_an_unfinished_build_staged() {
+        let dir = temp_dir("leftover");
+        let output = build(&dir, &[("a.html", "a")]);
+        let staging = output.staging.clone();
+        drop(output);
+        assert!(staging.join("a.html").is_file());
+        build(&dir, &[]).commit().unwrap();
+        assert!(!staging.exists());
+        assert!(!dir.join("a.html").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prunes_only_what_the_last_build_wrote() {
+        let dir = temp_dir("prune");
+        let output = build(&dir, &[("keep.html", "k"), ("old/gone.html", "g")]);
+        output.commit().unwrap();
+        output.save_manifest().unwrap();
+        fs::write(dir.join("mine.html"), "by hand").unwrap();
+
+        let output = build(&dir, &[("keep.html", "k")]);
+        output.commit().unwrap();
+        let removed = output.prune().unwrap();
+        assert_eq!(removed, vec![dir.join("old/gone.html")]);
+        assert!(!dir.join("old").exists());
+        assert!(dir.join("keep.html").is_file());
+        assert!(dir.join("mine.html").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_prune_without_a_manifest() {
+        let dir = temp_dir("unmarked");
+        fs::write(dir.join("mine.html"), "by hand").unwrap();
+        assert!(build(&dir, &[]).prune().is_err());
+        assert!(dir.join("mine.html").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_write_nothing() {
+        let dir = temp_dir("dry-run");
+        let output = Output::new(&dir, Mode::DryRun);
+        output.write(Path::new("index.html"), "new", &[]).unwrap();
+        output.commit().unwrap();
+        assert_eq!(output.files_made(), 1);
+        assert!(fs::read_dir(&dir).unwrap().next().is_none());
+        assert!(!output.staging.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
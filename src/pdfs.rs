@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use bib::Bibliography;
+use diagnostics::Diagnostic;
+
+// A folder of the papers themselves, named after their keys, like
+// `smith2019.pdf`. Each one with a page is copied into `pdfs/` in the output
+// directory, and linked to from the page and the index, so readers can open
+// the paper next to the notes on it.
+
+// the folder the PDFs go in, in the output directory
+pub const DIR: &str = "pdfs";
+
+// the PDF for each entry in `dir`, warning about the ones that aren't for any
+// entry, since they're probably misnamed
+pub fn find(dir: &Path, bib: &Bibliography) -> HashMap<String, PathBuf> {
+    let files = match fs::read_dir(dir) {
+        Ok(files) => files,
+        Err(e) => {
+            Diagnostic::warning(format!("could not read the PDFs in {}", dir.display()))
+                .note(e.to_string())
+                .emit();
+            return HashMap::new();
+        },
+    };
+    let mut pdfs = HashMap::new();
+    let mut unknown = Vec::new();
+    for path in files.filter_map(|f| f.ok()).map(|f| f.path()) {
+        let is_pdf = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+        if !is_pdf || !path.is_file() {
+            continue;
+        }
+        let key = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        if bib.get(&key).is_some() {
+            pdfs.insert(key, path);
+        } else {
+            unknown.push(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+        }
+    }
+    if !unknown.is_empty() {
+        unknown.sort();
+        let mut warning = Diagnostic::warning(format!(
+            "{} PDFs in {} match no entry in the bibliography",
+            unknown.len(),
+            dir.display()
+        ));
+        for name in unknown {
+            warning = warning.note(name);
+        }
+        warning.note("PDFs are found by key, like `smith2019.pdf`").emit();
+    }
+    pdfs
+}
+
+// where an entry's PDF goes, relative to the output directory
+pub fn path(key: &str) -> PathBuf {
+    Path::new(DIR).join(format!("{}.pdf", key))
+}
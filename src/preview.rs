@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use handlebars::Handlebars;
+use serde_json::{Map, Value};
+use bib::{self, Bibliography, Entry};
+use config::{Config, Renderer};
+use html;
+use render;
+use text::Text;
+use xref;
+
+// Pages rendered from a bibliography, a note and a template given as strings,
+// with nothing read from or written to disk, for previews of a note as it's
+// being written. They're as the builtin renderer would build them, with the
+// note's references linked as if every entry had a page, and with the
+// template getting `title`, `content`, `root`, `key`, `links` and `text`.
+// Previews of the index list every entry, in bibliography order, and their
+// templates get them as `entries`.
+
+pub fn parse(source: &str) -> Bibliography {
+    bib::parse("preview.bib", source.as_bytes())
+}
+
+// every entry's page, as the default URL style has it
+fn pages(bib: &Bibliography) -> HashMap<String, String> {
+    bib.entries.iter()
+        .map(|e| (e.key.clone(), format!("{}.html", e.key)))
+        .collect()
+}
+
+fn links_html(entry: &Entry) -> String {
+    entry.links().iter()
+        .map(|l| format!("<a href=\"{}\">{}</a>", html::escape(&l.url), l.label))
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+fn links_data(entry: &Entry) -> Value {
+    Value::Array(entry.links().into_iter().map(|l| {
+        let mut link = Map::new();
+        link.insert("label".to_owned(), Value::String(l.label.to_owned()));
+        link.insert("url".to_owned(), Value::String(l.url));
+        Value::Object(link)
+    }).collect())
+}
+
+// the page `data` is for, through `template`
+pub fn fill(template: &str, mut data: Map<String, Value>) -> Result<String, String> {
+    let mut hbs = Handlebars::new();
+    hbs.register_template_string("t", template).map_err(|e| e.to_string())?;
+    data.insert("root".to_owned(), Value::String(String::new()));
+    data.insert("text".to_owned(), Text::default().data());
+    hbs.render("t", &data).map_err(|e| e.to_string())
+}
+
+// the page on the entry `key` of the BibTeX in `bib`, with `note` (markdown)
+// as its note
+pub fn page(bib: &str, key: &str, note: &str, template: &str) -> Result<String, String> {
+    page_data(&parse(bib), key, note).and_then(|data| fill(template, data))
+}
+
+// the template data of the page on `key`, whose `content` is the page
+// without the template
+pub fn page_data(
+    bib: &Bibliography,
+    key: &str,
+    note: &str,
+) -> Result<Map<String, Value>, String> {
+    let entry = bib.get(key).ok_or_else(|| format!("no entry with key `{}`", key))?;
+    let (note, _) = xref::rewrite(note, bib, &pages(bib), "");
+    let config = Config { renderer: Renderer::Builtin, ..Config::default() };
+    let body = render::to_html(&config, &note).map_err(|e| e.to_string())?;
+    let links = links_html(entry);
+    let nav = if links.is_empty() {
+        String::new()
+    } else {
+        format!("<nav class=\"links\">{}</nav>", links)
+    };
+    let content = format!(
+        "<header><h1>{}</h1><cite>{} <em>{}</em></cite>{}</header>\n{}",
+        html::escape(&entry.title),
+        html::escape(&bib::author_year(&entry.author, &entry.year)),
+        html::escape(&entry.title),
+        nav,
+        body
+    );
+    let mut data = Map::new();
+    data.insert("title".to_owned(), Value::String(entry.title.clone()));
+    data.insert("content".to_owned(), Value::String(content));
+    data.insert("key".to_owned(), Value::String(entry.key.clone()));
+    data.insert("links".to_owned(), links_data(entry));
+    Ok(data)
+}
+
+// every entry, as the `entries` of the index's template data
+pub fn index_entries(bib: &Bibliography) -> Value {
+    let pages = pages(bib);
+    Value::Array(bib.entries.iter().map(|entry| {
+        let mut object = Map::new();
+        object.insert("key".to_owned(), Value::String(entry.key.clone()));
+        object.insert("link".to_owned(), Value::String(pages[&entry.key].clone()));
+        object.insert("title".to_owned(), Value::String(entry.title.clone()));
+        object.insert("author".to_owned(), Value::String(entry.author.clone()));
+        object.insert("year".to_owned(), Value::String(entry.year.clone()));
+        object.insert("links".to_owned(), links_data(entry));
+        Value::Object(object)
+    }).collect())
+}
+
+// the index of the BibTeX in `bib`
+pub fn index(bib: &str, template: &str) -> Result<String, String> {
+    let bib = parse(bib);
+    let text = Text::default();
+    let pages = pages(&bib);
+    let mut content = format!(
+        "<header>\n  <h1>{}</h1>\n  <p>{}</p>\n</header>\n<ul class=\"nonetype\">\n",
+        html::escape(text.get("index_title")),
+        text.get("index_intro")
+    );
+    for entry in &bib.entries {
+        let links = match links_html(entry) {
+            ref links if links.is_empty() => String::new(),
+            links => format!(" · {}", links),
+        };
+        content.push_str(&format!(
+            "  <li><a href=\"{}\">{}</a><br>\n  {}{}\n",
+            html::escape(&pages[&entry.key]),
+            html::escape(&entry.title),
+            html::escape(&bib::author_year(&entry.author, &entry.year)),
+            links
+        ));
+    }
+    content.push_str("</ul>\n");
+    let mut data = Map::new();
+    data.insert("title".to_owned(), Value::String(text.get("index_title").to_owned()));
+    data.insert("content".to_owned(), Value::String(content));
+    data.insert("entries".to_owned(), index_entries(&bib));
+    fill(template, data)
+}
+
+#[cfg(feature = "wasm")]
+mod js {
+    use wasm_bindgen::prelude::*;
+
+    #[wasm_bindgen(js_name = previewPage)]
+    pub fn page(bib: &str, key: &str, note: &str, template: &str) -> Result<String, JsValue> {
+        super::page(bib, key, note, template).map_err(|e| JsValue::from_str(&e))
+    }
+
+    #[wasm_bindgen(js_name = previewIndex)]
+    pub fn index(bib: &str, template: &str) -> Result<String, JsValue> {
+        super::index(bib, template).map_err(|e| JsValue::from_str(&e))
+    }
+}
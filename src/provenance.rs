@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+use serde_json::{self, Map, Value};
+use bib::Bibliography;
+use config::Config;
+use dates;
+use diagnostics::Diagnostic;
+
+// Where each entry came from. The commands that add entries note it in
+// `.biblionotes/provenance.json` next to the bibliography, and anything they
+// didn't add is taken to have been typed in by hand.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Source {
+    Manual,
+    Clipboard,
+    Doi,
+    Zotero,
+    Merge,
+}
+
+// in the order they're listed in
+pub const SOURCES: &[Source] = &[
+    Source::Manual,
+    Source::Clipboard,
+    Source::Doi,
+    Source::Zotero,
+    Source::Merge,
+];
+
+impl Source {
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Manual => "manual",
+            Source::Clipboard => "clipboard",
+            Source::Doi => "doi",
+            Source::Zotero => "zotero",
+            Source::Merge => "merge",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Source::Manual => "Added by hand",
+            Source::Clipboard => "Pasted from the clipboard",
+            Source::Doi => "Imported by DOI",
+            Source::Zotero => "Synced from Zotero",
+            Source::Merge => "Merged from another file",
+        }
+    }
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Source, String> {
+        SOURCES.iter().cloned().find(|source| source.name() == s)
+            .ok_or_else(|| format!("unknown source `{}`", s))
+    }
+}
+
+pub struct Origin {
+    pub source: Source,
+    // the day it was added, as `YYYY-MM-DD`
+    pub added: Option<String>,
+    // whatever identifies where exactly, like a Zotero group or a file
+    pub detail: Option<String>,
+}
+
+impl Origin {
+    pub fn to_json(&self) -> Value {
+        let mut origin = Map::new();
+        origin.insert("source".to_owned(), Value::String(self.source.name().to_owned()));
+        if let Some(ref added) = self.added {
+            origin.insert("added".to_owned(), Value::String(added.clone()));
+        }
+        if let Some(ref detail) = self.detail {
+            origin.insert("detail".to_owned(), Value::String(detail.clone()));
+        }
+        Value::Object(origin)
+    }
+
+    fn from_json(value: &Value) -> Option<Origin> {
+        let field = |name: &str| {
+            value.find(name).and_then(|v| v.as_str()).map(|s| s.to_owned())
+        };
+        Some(Origin {
+            source: field("source")?.parse().ok()?,
+            added: field("added"),
+            detail: field("detail"),
+        })
+    }
+}
+
+const MANUAL: Origin = Origin { source: Source::Manual, added: None, detail: None };
+
+pub type Provenance = BTreeMap<String, Origin>;
+
+// where an entry came from
+pub fn of<'a>(provenance: &'a Provenance, key: &str) -> &'a Origin {
+    provenance.get(key).unwrap_or(&MANUAL)
+}
+
+fn path(config: &Config) -> PathBuf {
+    let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+    dir.join(".biblionotes").join("provenance.json")
+}
+
+pub fn load(config: &Config) -> Provenance {
+    let path = path(config);
+    let mut contents = String::new();
+    if File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return Provenance::new();
+    }
+    match serde_json::from_str::<Value>(&contents) {
+        Ok(value) => value.as_object().into_iter()
+            .flat_map(|o| o.iter())
+            .filter_map(|(key, origin)| Origin::from_json(origin).map(|o| (key.clone(), o)))
+            .collect(),
+        Err(e) => {
+            Diagnostic::warning(format!("ignoring invalid provenance {}", path.display()))
+                .note(e.to_string())
+                .emit();
+            Provenance::new()
+        },
+    }
+}
+
+fn save(config: &Config, provenance: &Provenance) -> io::Result<()> {
+    let path = path(config);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let value: Map<String, Value> = provenance.iter()
+        .map(|(key, origin)| (key.clone(), origin.to_json()))
+        .collect();
+    let json = serde_json::to_string_pretty(&value).map_err(io::Error::other)?;
+    File::create(path).and_then(|mut f| writeln!(f, "{}", json))
+}
+
+// notes that the given entries were just added
+pub fn record(
+    config: &Config,
+    keys: &[String],
+    source: Source,
+    detail: Option<&str>,
+) -> io::Result<()> {
+    let mut provenance = load(config);
+    let added = dates::ymd(SystemTime::now());
+    for key in keys {
+        provenance.insert(key.clone(), Origin {
+            source,
+            added: Some(added.clone()),
+            detail: detail.map(|d| d.to_owned()),
+        });
+    }
+    save(config, &provenance)
+}
+
+// moves what's known about renamed entries to their new keys
+pub fn rename(config: &Config, renames: &[(String, String)]) -> io::Result<()> {
+    let mut provenance = load(config);
+    if provenance.is_empty() {
+        return Ok(());
+    }
+    let moved: Vec<(String, Origin)> = renames.iter()
+        .filter_map(|(old, new)| provenance.remove(old).map(|o| (new.clone(), o)))
+        .collect();
+    provenance.extend(moved);
+    save(config, &provenance)
+}
+
+// how many entries of the bibliography came from each source, leaving out
+// the sources none did
+pub fn counts(bib: &Bibliography, provenance: &Provenance) -> Vec<(Source, usize)> {
+    SOURCES.iter()
+        .map(|&source| {
+            let n = bib.entries.iter()
+                .filter(|e| of(provenance, &e.key).source == source)
+                .count();
+            (source, n)
+        })
+        .filter(|&(_, n)| n > 0)
+        .collect()
+}
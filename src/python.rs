@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use serde_json::{self, Map, Value};
+use bib::{Bibliography, Entry};
+use preview;
+
+// A Python module with the entries of a bibliography, how their tags go
+// together and the previews, for scripts to ask their own questions of the
+// same data the site is built from. Entries and the tag graph come out as
+// plain dicts and lists. Build it with `maturin develop`; pyproject.toml
+// turns on the `python` feature.
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_owned())
+}
+
+fn strings(strs: &[String]) -> Value {
+    Value::Array(strs.iter().map(|s| string(s)).collect())
+}
+
+fn entry_data(entry: &Entry) -> Value {
+    let mut data = Map::new();
+    data.insert("key".to_owned(), string(&entry.key));
+    data.insert("title".to_owned(), string(&entry.title));
+    data.insert("author".to_owned(), string(&entry.author));
+    let authors = entry.authors().into_iter().map(|n| {
+        let mut name = Map::new();
+        let parts = [("first", n.first), ("von", n.von), ("last", n.last), ("jr", n.jr)];
+        for (part, value) in parts {
+            name.insert(part.to_owned(), Value::String(value));
+        }
+        Value::Object(name)
+    });
+    data.insert("authors".to_owned(), Value::Array(authors.collect()));
+    data.insert("year".to_owned(), string(&entry.year));
+    data.insert("venue".to_owned(), entry.venue().map_or(Value::Null, Value::String));
+    let fields = entry.fields.iter().map(|(k, v)| (k.clone(), string(v))).collect();
+    data.insert("fields".to_owned(), Value::Object(fields));
+    data.insert("tags".to_owned(), strings(&entry.tags()));
+    data.insert("cites".to_owned(), strings(&entry.cites()));
+    let links = entry.links().into_iter().map(|l| {
+        let mut link = Map::new();
+        link.insert("label".to_owned(), string(l.label));
+        link.insert("url".to_owned(), Value::String(l.url));
+        Value::Object(link)
+    });
+    data.insert("links".to_owned(), Value::Array(links.collect()));
+    Value::Object(data)
+}
+
+// each tag's entries, and how many entries each pair of tags share
+fn tag_graph_data(bib: &Bibliography) -> Value {
+    let mut entries: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut shared: BTreeMap<(String, String), u64> = BTreeMap::new();
+    for entry in &bib.entries {
+        let mut tags = entry.tags();
+        tags.sort();
+        for (i, tag) in tags.iter().enumerate() {
+            entries.entry(tag.clone()).or_default().push(entry.key.clone());
+            for other in &tags[i + 1..] {
+                *shared.entry((tag.clone(), other.clone())).or_insert(0) += 1;
+            }
+        }
+    }
+    let tags = entries.into_iter().map(|(tag, keys)| (tag, strings(&keys))).collect();
+    let edges = shared.into_iter().map(|((a, b), count)| {
+        let mut edge = Map::new();
+        edge.insert("tags".to_owned(), Value::Array(vec![Value::String(a), Value::String(b)]));
+        edge.insert("entries".to_owned(), Value::U64(count));
+        Value::Object(edge)
+    });
+    let mut graph = Map::new();
+    graph.insert("tags".to_owned(), Value::Object(tags));
+    graph.insert("edges".to_owned(), Value::Array(edges.collect()));
+    Value::Object(graph)
+}
+
+// the same value as Python objects, by way of Python's own JSON parser
+fn to_python(py: Python, value: &Value) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(py.import_bound("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+// every entry of the BibTeX in `bib`, in order
+#[pyfunction]
+fn entries(py: Python, bib: &str) -> PyResult<PyObject> {
+    let bib = preview::parse(bib);
+    to_python(py, &Value::Array(bib.entries.iter().map(entry_data).collect()))
+}
+
+#[pyfunction]
+fn tag_graph(py: Python, bib: &str) -> PyResult<PyObject> {
+    to_python(py, &tag_graph_data(&preview::parse(bib)))
+}
+
+// the page on `key`, or just its content without a template
+#[pyfunction]
+#[pyo3(signature = (bib, key, note, template=None))]
+fn render_page(bib: &str, key: &str, note: &str, template: Option<&str>) -> PyResult<String> {
+    let data = preview::page_data(&preview::parse(bib), key, note)
+        .map_err(PyValueError::new_err)?;
+    match template {
+        Some(template) => preview::fill(template, data).map_err(PyValueError::new_err),
+        None => Ok(data["content"].as_str().unwrap_or_default().to_owned()),
+    }
+}
+
+#[pyfunction]
+fn render_index(bib: &str, template: &str) -> PyResult<String> {
+    preview::index(bib, template).map_err(PyValueError::new_err)
+}
+
+#[pymodule]
+fn biblionotes(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(entries, m)?)?;
+    m.add_function(wrap_pyfunction!(tag_graph, m)?)?;
+    m.add_function(wrap_pyfunction!(render_page, m)?)?;
+    m.add_function(wrap_pyfunction!(render_index, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+// Where a paper is in the reading queue, and what was thought of it, from
+// the `status` and `rating` fields of its note's front matter.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Status {
+    ToRead,
+    Reading,
+    Read,
+}
+
+pub const STATUSES: &[Status] = &[Status::ToRead, Status::Reading, Status::Read];
+
+impl Status {
+    // as written in front matter, and in class names and file names
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::ToRead => "to-read",
+            Status::Reading => "reading",
+            Status::Read => "read",
+        }
+    }
+
+    // what its label is called under `[text]`
+    pub fn text_id(self) -> &'static str {
+        match self {
+            Status::ToRead => "to_read",
+            Status::Reading => "reading",
+            Status::Read => "read",
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Status, String> {
+        match s {
+            "to-read" => Ok(Status::ToRead),
+            "reading" => Ok(Status::Reading),
+            "read" => Ok(Status::Read),
+            _ => Err(format!(
+                "unknown reading status `{}` (expected to-read, reading or read)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Reading {
+    pub status: Option<Status>,
+    // from 1 to 5
+    pub rating: Option<u8>,
+}
+
+impl Reading {
+    // reads the status and rating from front matter fields, along with
+    // complaints about the values that don't make sense
+    pub fn from_fields(fields: &BTreeMap<String, String>) -> (Reading, Vec<String>) {
+        let mut reading = Reading::default();
+        let mut errors = Vec::new();
+        if let Some(status) = fields.get("status") {
+            match status.parse() {
+                Ok(status) => reading.status = Some(status),
+                Err(e) => errors.push(e),
+            }
+        }
+        if let Some(rating) = fields.get("rating") {
+            match rating.parse() {
+                Ok(rating @ 1..=5) => reading.rating = Some(rating),
+                _ => errors.push(format!("invalid rating `{}` (expected 1 to 5)", rating)),
+            }
+        }
+        (reading, errors)
+    }
+}
+
+// a rating as five stars, filled in up to the rating
+pub fn stars(rating: u8) -> String {
+    (1..=5).map(|n| if n <= rating { '★' } else { '☆' }).collect()
+}
@@ -0,0 +1,174 @@
+use bib::{Bibliography, Entry};
+use markdown;
+use xref;
+
+// The reference lists notes end with: the items under a "References" (or
+// "Bibliography") heading, up to the next heading, read as citations. Each
+// item is a list item, or a paragraph when they aren't in a list, and is
+// matched to an entry of the bibliography when it can be: by a `[@key]` in
+// it, then by its DOI or URL, and then by having an entry's title in it.
+// They go into the site model and the citation graph, whether they're
+// matched or not, so that papers noted down by hand count like `[@key]`s.
+
+const HEADINGS: &[&str] = &["references", "bibliography"];
+
+// titles shorter than this, in words, are too likely to turn up in another
+// paper's reference by chance
+const MIN_TITLE_WORDS: usize = 3;
+
+pub struct Reference {
+    // the (1-based) line the item starts at
+    pub line: usize,
+    pub text: String,
+    // the entry it's to, if it's one of the bibliography's
+    pub key: Option<String>,
+    pub doi: Option<String>,
+    pub url: Option<String>,
+}
+
+// the line without a list marker, if it starts a list item
+fn list_item(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(rest) = line.strip_prefix(marker) {
+            return Some(rest);
+        }
+    }
+    // `1.`, `1)` and `[1]`
+    let bracketed = line.starts_with('[');
+    let digits = line[bracketed as usize..].find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 {
+        return None;
+    }
+    let at = bracketed as usize + digits;
+    let close = if bracketed { "]" } else { "." };
+    let rest = line[at..].strip_prefix(close)
+        .or_else(|| line[at..].strip_prefix(')').filter(|_| !bracketed))?;
+    Some(rest).filter(|r| r.starts_with(' ')).map(|r| r.trim_start())
+}
+
+// the items of the reference section, with the lines they start at
+fn items(note: &str) -> Vec<(usize, String)> {
+    let headings = markdown::headings(note);
+    let start = match headings.iter()
+        .find(|h| HEADINGS.iter().any(|name| h.text.trim().eq_ignore_ascii_case(name)))
+    {
+        Some(heading) => heading.line,
+        None => return Vec::new(),
+    };
+    let end = headings.iter().map(|h| h.line).find(|&l| l > start).unwrap_or(usize::MAX);
+    let mut items: Vec<(usize, String)> = Vec::new();
+    let mut open = false;
+    for (n, line) in markdown::prose_lines(note) {
+        if n <= start || n >= end {
+            continue;
+        }
+        let trimmed = line.trim();
+        // a blank line, or a setext heading's underline
+        if trimmed.is_empty() || trimmed.chars().all(|c| c == '=' || c == '-') {
+            open = false;
+            continue;
+        }
+        match list_item(line) {
+            Some(rest) => items.push((n, rest.to_owned())),
+            None if open => {
+                let item = items.last_mut().expect("Could not continue the reference");
+                item.1.push(' ');
+                item.1.push_str(trimmed);
+            },
+            None => items.push((n, trimmed.to_owned())),
+        }
+        open = true;
+    }
+    items
+}
+
+// what a link or DOI in running text runs to
+fn token_end(s: &str) -> usize {
+    s.find(|c: char| c.is_whitespace() || "()<>[]\"".contains(c)).unwrap_or(s.len())
+}
+
+fn trim_punctuation(s: &str) -> &str {
+    s.trim_end_matches(['.', ',', ';', ':'])
+}
+
+fn find_url(text: &str) -> Option<String> {
+    let start = text.find("https://").or_else(|| text.find("http://"))?;
+    let rest = &text[start..];
+    Some(trim_punctuation(&rest[..token_end(rest)]).to_owned())
+}
+
+// the DOI in the text, lowercased as `Entry::doi` has them
+fn find_doi(text: &str) -> Option<String> {
+    let mut from = 0;
+    while let Some(i) = text[from..].find("10.").map(|i| from + i) {
+        from = i + 3;
+        let after_word = text[..i].chars().last().is_some_and(char::is_alphanumeric);
+        let rest = &text[i..];
+        let doi = trim_punctuation(&rest[..token_end(rest)]);
+        let (prefix, suffix) = match doi.split_once('/') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let registrant = &prefix[3..];
+        if !after_word && !registrant.is_empty() && !suffix.is_empty()
+            && registrant.chars().all(|c| c.is_ascii_digit() || c == '.')
+        {
+            return Some(doi.to_lowercase());
+        }
+    }
+    None
+}
+
+// the words of a text, lowercased, for comparing titles
+fn words(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn same_url(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/').eq_ignore_ascii_case(b.trim_end_matches('/'))
+}
+
+// the entry a reference is to, if the bibliography has it
+fn matching<'a>(
+    bib: &'a Bibliography,
+    text: &str,
+    doi: &Option<String>,
+    url: &Option<String>,
+) -> Option<&'a Entry> {
+    if let Some(entry) = xref::cited(text).iter().find_map(|key| bib.get(key)) {
+        return Some(entry);
+    }
+    if let Some(ref doi) = *doi {
+        if let Some(entry) = bib.entries.iter().find(|e| e.doi().as_ref() == Some(doi)) {
+            return Some(entry);
+        }
+    }
+    if let Some(ref url) = *url {
+        let has = |e: &&Entry| e.field("url").is_some_and(|u| same_url(u.trim(), url));
+        if let Some(entry) = bib.entries.iter().find(has) {
+            return Some(entry);
+        }
+    }
+    // the longest title in it, in case one title is part of another
+    let text = format!(" {} ", words(text));
+    bib.entries.iter()
+        .filter_map(|e| Some((e, words(&e.text_field("title")?))))
+        .filter(|(_, title)| title.split(' ').count() >= MIN_TITLE_WORDS)
+        .filter(|(_, title)| text.contains(&format!(" {} ", title)))
+        .max_by_key(|(_, title)| title.len())
+        .map(|(e, _)| e)
+}
+
+pub fn extract(note: &str, bib: &Bibliography) -> Vec<Reference> {
+    items(note).into_iter().map(|(line, text)| {
+        let doi = find_doi(&text);
+        let url = find_url(&text);
+        let key = matching(bib, &text, &doi, &url).map(|e| e.key.clone());
+        Reference { line, text, key, doi, url }
+    }).collect()
+}
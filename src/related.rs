@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use bib::{Bibliography, Entry};
+
+// The entries most like each entry, for a "see also" section on its page.
+// Two entries are alike when they share tags, authors or a venue, and more
+// so the closer together they came out. Only entries with pages are
+// suggested, since they're the ones that can be linked to.
+
+// how much each thing in common counts
+const TAG: f64 = 2.0;
+const AUTHOR: f64 = 3.0;
+const VENUE: f64 = 1.0;
+// at most, for coming out the same year, and falling off with each year
+// apart
+const YEAR: f64 = 1.0;
+
+// what entries can have in common, each with its weight
+fn features(entry: &Entry) -> Vec<(String, f64)> {
+    let mut features = Vec::new();
+    for tag in entry.tags() {
+        features.push((format!("tag:{}", tag.to_lowercase()), TAG));
+    }
+    // last name and first initial, so "J. Smith" is "John Smith"
+    for name in entry.authors() {
+        let initial: String = name.first.chars().take(1).flat_map(char::to_lowercase).collect();
+        let author = format!("author:{} {}", name.last.to_lowercase(), initial);
+        if !features.iter().any(|(f, _)| *f == author) {
+            features.push((author, AUTHOR));
+        }
+    }
+    if let Some(venue) = entry.venue() {
+        features.push((format!("venue:{}", venue.to_lowercase()), VENUE));
+    }
+    features
+}
+
+// up to `count` related entries for each entry with a page, best first
+pub fn compute(
+    bib: &Bibliography,
+    pages: &HashMap<String, String>,
+    count: usize,
+) -> HashMap<String, Vec<String>> {
+    let entries: Vec<&Entry> = bib.entries.iter()
+        .filter(|e| pages.contains_key(&e.key))
+        .collect();
+    // only score entries with something in common, found through an
+    // inverted index
+    let mut postings: HashMap<String, Vec<(usize, f64)>> = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        for (feature, weight) in features(entry) {
+            postings.entry(feature).or_default().push((i, weight));
+        }
+    }
+    let mut scores: Vec<HashMap<usize, f64>> = vec![HashMap::new(); entries.len()];
+    for posting in postings.values() {
+        for &(i, weight) in posting {
+            for &(j, _) in posting.iter().filter(|&&(j, _)| j != i) {
+                *scores[i].entry(j).or_insert(0.0) += weight;
+            }
+        }
+    }
+
+    let mut related = HashMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let year: Option<i64> = entry.year.trim().parse().ok();
+        let mut scored: Vec<(usize, f64)> = scores[i].iter()
+            .map(|(&j, &score)| {
+                let other: Option<i64> = entries[j].year.trim().parse().ok();
+                let near = match (year, other) {
+                    (Some(a), Some(b)) => YEAR / (1.0 + (a - b).abs() as f64),
+                    _ => 0.0,
+                };
+                (j, score + near)
+            })
+            .collect();
+        // ties in bibliography order, so pages don't change between builds
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+        let keys: Vec<String> = scored.into_iter()
+            .take(count)
+            .map(|(j, _)| entries[j].key.clone())
+            .collect();
+        if !keys.is_empty() {
+            related.insert(entry.key.clone(), keys);
+        }
+    }
+    related
+}
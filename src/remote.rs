@@ -0,0 +1,143 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use config::Config;
+use csl;
+use diagnostics::Diagnostic;
+use urls;
+
+// Bibliographies kept somewhere else, like a raw GitHub file or an Overleaf
+// export link, given as an http(s) URL instead of a path. Each is fetched
+// into `.biblionotes/remote/` in the current directory, along with its ETag,
+// so that later builds only download it again once it has changed, and still
+// work offline from the copy. They're read-only: the commands that change
+// the bibliography refuse them.
+
+const DIR: &str = ".biblionotes/remote";
+
+pub fn is_remote(path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+// the copy of a remote bibliography, named after its URL, and read as CSL-JSON
+// if the URL's is
+fn cached(url: &str) -> PathBuf {
+    let name = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let (stem, extension) = if csl::is_csl(name) {
+        (&name[..name.len() - ".json".len()], "json")
+    } else {
+        (name.trim_end_matches(".bib"), "bib")
+    };
+    Path::new(DIR).join(format!("{}.{}", urls::slugify(stem), extension))
+}
+
+// fetches `url` into `path` unless `etag` says the copy is still current,
+// returning the ETag of what's there now
+fn fetch(
+    config: &Config,
+    url: &str,
+    path: &Path,
+    etag: Option<&str>,
+) -> Result<Option<String>, String> {
+    let temp = path.with_extension("bib.tmp");
+    let mut command = Command::new(&config.enrich.curl);
+    command.args(["--silent", "--show-error", "--location", "--dump-header", "-"])
+        .args(["--write-out", "\n%{http_code}", "--output"])
+        .arg(&temp);
+    if let Some(etag) = etag {
+        command.arg("--header").arg(format!("If-None-Match: {}", etag));
+    }
+    let output = command.arg(url)
+        .output()
+        .map_err(|e| format!("could not run {}: {}", config.enrich.curl, e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let status = stdout.lines().last().and_then(|s| s.trim().parse().ok()).unwrap_or(0);
+    // after redirects, only the headers of the last response count
+    let mut found = None;
+    for line in stdout.lines() {
+        if line.starts_with("HTTP/") {
+            found = None;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("etag") {
+                found = Some(value.trim().to_owned());
+            }
+        }
+    }
+    match status {
+        304 => {
+            let _ = fs::remove_file(&temp);
+            Ok(etag.map(|e| e.to_owned()))
+        },
+        200..=299 => {
+            fs::rename(&temp, path).map_err(|e| e.to_string())?;
+            Ok(found)
+        },
+        _ => {
+            let _ = fs::remove_file(&temp);
+            Err(format!("the server answered with HTTP status {}", status))
+        },
+    }
+}
+
+// a path to read the bibliography from: `path` itself, or for a URL, the
+// copy of it, brought up to date
+pub fn local(config: &Config, path: &str) -> String {
+    if !is_remote(path) {
+        return path.to_owned();
+    }
+    let copy = cached(path);
+    let etag_path = copy.with_extension("etag");
+    if let Err(e) = fs::create_dir_all(DIR) {
+        Diagnostic::error(format!("could not create {}", DIR)).note(e.to_string()).emit();
+        ::std::process::exit(1);
+    }
+    // an ETag is no use without the copy it's for
+    let etag = fs::read_to_string(&etag_path).ok()
+        .filter(|_| copy.exists())
+        .map(|e| e.trim().to_owned());
+    match fetch(config, path, &copy, etag.as_ref().map(|e| &e[..])) {
+        Ok(Some(ref new)) if etag.as_ref() == Some(new) => {
+            debug!("{} hasn't changed", path);
+        },
+        Ok(new) => {
+            info!("fetched {}", path);
+            let _ = match new {
+                Some(new) => fs::write(&etag_path, new),
+                None => fs::remove_file(&etag_path),
+            };
+        },
+        Err(e) => {
+            if !copy.exists() {
+                Diagnostic::error(format!("could not fetch {}", path)).note(e).emit();
+                ::std::process::exit(1);
+            }
+            Diagnostic::warning(format!("could not fetch {}", path))
+                .note(e)
+                .note(format!("using the copy in {}", copy.display()))
+                .emit();
+        },
+    }
+    copy.to_string_lossy().into_owned()
+}
+
+// exits if `path` is a URL, or CSL-JSON, for the commands that write to the
+// bibliography
+pub fn writable(path: &str) {
+    if is_remote(path) {
+        Diagnostic::error("can't change a bibliography fetched from a URL")
+            .note(format!("{} is read-only; edit it where it's kept", path))
+            .emit();
+        ::std::process::exit(1);
+    }
+    if csl::is_csl(path) {
+        Diagnostic::error("can't change a CSL-JSON bibliography")
+            .note(format!("{} is exported; edit the library it's exported from", path))
+            .emit();
+        ::std::process::exit(1);
+    }
+}
@@ -0,0 +1,232 @@
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use pulldown_cmark::{html as cmark_html, Parser};
+use pulldown_cmark::{OPTION_ENABLE_FOOTNOTES, OPTION_ENABLE_TABLES};
+use config::{Config, MathEngine, PandocConfig, Renderer};
+use error::Error;
+use frontmatter;
+use html;
+use markdown;
+
+// converts a markdown note to an HTML fragment by piping it through pandoc;
+// `extra` holds arguments implied by other settings
+pub fn pandoc(
+    config: &PandocConfig,
+    extra: &[&str],
+    markdown: &str,
+) -> io::Result<String> {
+    let mut args = vec!["--from", &config.from, "--to", "html5"];
+    args.extend(extra);
+    args.extend(config.args.iter().map(|a| &a[..]));
+    run(config, &args, markdown)
+}
+
+// converts a note in another format, like `org`, to markdown, so that it can
+// be read like the others. Its metadata becomes front matter.
+pub fn to_markdown(config: &PandocConfig, format: &str, note: &str) -> io::Result<String> {
+    let args = ["--from", format, "--to", "markdown", "--standalone", "--wrap=preserve"];
+    run(config, &args, note)
+}
+
+// turns a page into a PDF, finding its images under the `resources`
+// directories, in order. Which PDF engine makes it is up to pandoc, unless
+// `pdf_engine` says.
+pub fn to_pdf(
+    config: &PandocConfig,
+    title: &str,
+    page: &str,
+    resources: &[PathBuf],
+) -> io::Result<Vec<u8>> {
+    let mut args: Vec<String> = ["--from", "html", "--to", "pdf", "--output", "-"].iter()
+        .map(|a| a.to_string())
+        .collect();
+    args.push(format!("--metadata=title={}", title));
+    let resources = env::join_paths(resources).map_err(io::Error::other)?;
+    args.push(format!("--resource-path={}", resources.to_string_lossy()));
+    if let Some(ref engine) = config.pdf_engine {
+        args.push(format!("--pdf-engine={}", engine));
+    }
+    let args: Vec<&str> = args.iter().map(|a| &a[..]).collect();
+    run_bytes(config, &args, page)
+}
+
+fn run(config: &PandocConfig, args: &[&str], input: &str) -> io::Result<String> {
+    let output = run_bytes(config, args, input)?;
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+fn run_bytes(config: &PandocConfig, args: &[&str], input: &str) -> io::Result<Vec<u8>> {
+    let mut child = Command::new(&config.path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // pandoc reads all of its input before writing anything, so there is no
+    // risk of a deadlock here; dropping stdin closes the pipe
+    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(stderr.trim().to_owned()));
+    }
+    Ok(output.stdout)
+}
+
+// placeholders are plain words, so that markdown leaves them alone; the
+// trailing `X` keeps `...1X` from matching inside `...10X`
+const MATH_PLACEHOLDER: &str = "BIBLIONOTESMATH";
+
+// finds the closing `$` (or `$$`) of a math span starting at `start`, using
+// pandoc's `tex_math_dollars` rules: no space just inside the delimiters, and
+// an inline closing `$` can't be followed by a digit
+fn math_end(chars: &[char], start: usize, display: bool) -> Option<usize> {
+    let mut i = start;
+    if display {
+        while i + 1 < chars.len() {
+            if chars[i] == '\\' {
+                i += 2;
+                continue;
+            }
+            if chars[i] == '$' && chars[i + 1] == '$' {
+                return Some(i);
+            }
+            i += 1;
+        }
+        return None;
+    }
+    if i >= chars.len() || chars[i].is_whitespace() {
+        return None;
+    }
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\n' if i + 1 < chars.len() && chars[i + 1] == '\n' => return None,
+            '$' if !chars[i - 1].is_whitespace() => {
+                let digit = chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+                if !digit {
+                    return Some(i);
+                }
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+    None
+}
+
+// replaces TeX math in the markdown source with placeholders, returning the
+// new source and the extracted math (with a display flag). Code blocks and
+// code spans are left untouched.
+fn extract_math(markdown: &str) -> (String, Vec<(bool, String)>) {
+    let mut math = Vec::new();
+    let result = markdown::map_text(markdown, |_, text| {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\\' if i + 1 < chars.len() => {
+                    result.push(c);
+                    result.push(chars[i + 1]);
+                    i += 2;
+                },
+                '$' => {
+                    let display = chars.get(i + 1) == Some(&'$');
+                    let start = if display { i + 2 } else { i + 1 };
+                    match math_end(&chars, start, display) {
+                        Some(end) => {
+                            let tex: String = chars[start..end].iter().collect();
+                            result.push_str(&format!("{}{}X", MATH_PLACEHOLDER, math.len()));
+                            math.push((display, tex));
+                            i = if display { end + 2 } else { end + 1 };
+                        },
+                        None => {
+                            result.push(c);
+                            i += 1;
+                        },
+                    }
+                },
+                _ => {
+                    result.push(c);
+                    i += 1;
+                },
+            }
+        }
+        result
+    });
+    (result, math)
+}
+
+// the builtin renderer: pulldown-cmark, with math passed through in the same
+// form pandoc's `--mathjax` produces, so templates work with either. Without
+// a math engine the TeX source is shown as is.
+fn builtin(markdown: &str, engine: MathEngine) -> String {
+    let (source, math) = extract_math(markdown);
+    let parser = Parser::new_ext(&source, OPTION_ENABLE_TABLES | OPTION_ENABLE_FOOTNOTES);
+    let mut body = String::new();
+    cmark_html::push_html(&mut body, parser);
+    for (n, &(display, ref tex)) in math.iter().enumerate() {
+        let span = if engine == MathEngine::None {
+            let class = if display { "display" } else { "inline" };
+            format!("<span class=\"math {}\">{}</span>", class, html::escape(tex))
+        } else if display {
+            format!("<span class=\"math display\">\\[{}\\]</span>", html::escape(tex))
+        } else {
+            format!("<span class=\"math inline\">\\({}\\)</span>", html::escape(tex))
+        };
+        body = body.replace(&format!("{}{}X", MATH_PLACEHOLDER, n), &span);
+    }
+    body
+}
+
+// the arguments to pandoc that other settings imply
+pub fn pandoc_extra(config: &Config) -> Vec<&'static str> {
+    let mut extra = Vec::new();
+    // the same markup for KaTeX too: pandoc's `--katex` leaves out the `\(`
+    // and `\[` around the TeX, which KaTeX's auto-render, the builtin renderer
+    // and `math::prerender` all expect
+    if config.math.engine != MathEngine::None {
+        extra.push("--mathjax");
+    }
+    // leave code blocks alone if we're going to highlight them
+    if config.highlight_theme.is_some() {
+        extra.push("--no-highlight");
+    }
+    extra
+}
+
+// converts a note to an HTML fragment with the configured renderer
+pub fn to_html(config: &Config, markdown: &str) -> Result<String, Error> {
+    let body = match config.renderer {
+        Renderer::Pandoc => {
+            pandoc(&config.pandoc, &pandoc_extra(config), markdown).map_err(Error::Pandoc)
+        },
+        // pandoc reads front matter itself
+        Renderer::Builtin => Ok(builtin(&frontmatter::blank(markdown), config.math.engine)),
+    };
+    // pandoc already gives headings ids, unless told not to
+    body.map(|body| html::add_heading_ids(&body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(engine: MathEngine) -> Config {
+        let mut config = Config::default();
+        config.math.engine = engine;
+        config.math.prerender = engine == MathEngine::Katex;
+        config
+    }
+
+    #[test]
+    fn asks_pandoc_for_delimited_math_with_either_engine() {
+        assert_eq!(pandoc_extra(&config(MathEngine::MathJax)), ["--mathjax"]);
+        assert_eq!(pandoc_extra(&config(MathEngine::Katex)), ["--mathjax"]);
+        assert!(pandoc_extra(&config(MathEngine::None)).is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+use serde_json::{self, Map, Value};
+
+// `--report <path>`: how a build went, as JSON, for CI to post on a pull
+// request or to fail on, such as when coverage drops. The schema is stable:
+// fields are only ever added to it, and `version` goes up if one has to
+// change.
+
+const VERSION: u64 = 1;
+
+pub struct Report<'a> {
+    pub entries: usize,
+    // the entries with a page
+    pub notes: usize,
+    pub pages: usize,
+    pub files: usize,
+    pub cache_hits: usize,
+    pub cache_misses: usize,
+    pub warnings: usize,
+    pub errors: usize,
+    pub failed: &'a [String],
+    pub malformed: &'a [String],
+    pub duration: Duration,
+    pub dry_run: bool,
+}
+
+fn keys(keys: &[String]) -> Value {
+    Value::Array(keys.iter().map(|k| Value::String(k.clone())).collect())
+}
+
+pub fn json(report: &Report) -> String {
+    let count = |n: usize| Value::U64(n as u64);
+    // the share of entries with notes, as a percentage, to one decimal place
+    let coverage = match report.entries {
+        0 => 0.0,
+        entries => (report.notes as f64 * 1000.0 / entries as f64).round() / 10.0,
+    };
+    let mut cache = Map::new();
+    cache.insert("hits".to_owned(), count(report.cache_hits));
+    cache.insert("misses".to_owned(), count(report.cache_misses));
+    let fields = [
+        ("version", Value::U64(VERSION)),
+        ("dry_run", Value::Bool(report.dry_run)),
+        ("entries", count(report.entries)),
+        ("notes", count(report.notes)),
+        ("without_notes", count(report.entries.saturating_sub(report.notes))),
+        ("coverage", Value::F64(coverage)),
+        ("pages", count(report.pages)),
+        ("files", count(report.files)),
+        ("cache", Value::Object(cache)),
+        ("warnings", count(report.warnings)),
+        ("errors", count(report.errors)),
+        ("failed", keys(report.failed)),
+        ("malformed", keys(report.malformed)),
+        ("duration_ms", Value::U64(report.duration.as_millis() as u64)),
+    ];
+    let mut json = Map::new();
+    for (name, value) in fields {
+        json.insert(name.to_owned(), value);
+    }
+    serde_json::to_string_pretty(&json).expect("Could not serialise the report")
+}
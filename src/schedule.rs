@@ -0,0 +1,396 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use toml;
+use bib::{self, Bibliography};
+use config::{Config, UrlStyle};
+use dates;
+use diagnostics::Diagnostic;
+use html;
+use ics::{self, Event};
+use text::Text;
+use urls;
+
+// Reading-group mode. A `schedule.toml` next to the bibliography says which
+// entries are discussed when:
+//
+//     name = "Reading group"
+//     time = "16:00"
+//     minutes = 90
+//     location = "Room 4.12"
+//
+//     [[session]]
+//     date = "2024-03-05"
+//     title = "Attention"
+//     entries = ["vaswani2017attention", "bahdanau2015neural"]
+//
+// Sessions can have their own `time`, `minutes` and `location`. The build
+// then writes a schedule page, a page for each session with the notes on
+// its entries, and a calendar of the sessions.
+
+// how long sessions last, if the schedule doesn't say
+const DEFAULT_MINUTES: u32 = 60;
+
+pub struct Session {
+    pub date: (i64, u32, u32),
+    pub title: String,
+    // hours and minutes; if unset, the session takes the whole day
+    pub time: Option<(u32, u32)>,
+    pub minutes: u32,
+    pub location: Option<String>,
+    pub keys: Vec<String>,
+    // relative to the root of the site
+    pub url: String,
+}
+
+pub struct Schedule {
+    pub path: PathBuf,
+    pub name: String,
+    // by date
+    pub sessions: Vec<Session>,
+    // when the file last changed, for the calendar
+    pub modified: SystemTime,
+}
+
+pub fn path(config: &Config) -> PathBuf {
+    config.schedule.clone().unwrap_or_else(|| {
+        let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+        dir.join("schedule.toml")
+    })
+}
+
+fn get_str(table: &toml::Table, key: &str, what: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(v) => match v.as_str() {
+            Some(s) => Ok(Some(s.to_owned())),
+            None => Err(format!("`{}` of {} must be a string", key, what)),
+        },
+    }
+}
+
+fn get_minutes(table: &toml::Table, what: &str) -> Result<Option<u32>, String> {
+    match table.get("minutes") {
+        None => Ok(None),
+        Some(v) => match v.as_integer() {
+            Some(n) if n > 0 && n < 24 * 60 => Ok(Some(n as u32)),
+            _ => Err(format!("`minutes` of {} must be a number under a day's worth", what)),
+        },
+    }
+}
+
+// a time of day written as `HH:MM`
+fn parse_time(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid time `{}` (expected HH:MM)", s);
+    let (hours, minutes) = s.trim().split_once(':').ok_or_else(invalid)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+    Ok((hours, minutes))
+}
+
+fn get_time(table: &toml::Table, what: &str) -> Result<Option<(u32, u32)>, String> {
+    match get_str(table, "time", what)? {
+        Some(time) => parse_time(&time).map(Some),
+        None => Ok(None),
+    }
+}
+
+// the value of a setting, or nothing if it's wrong, noting why
+fn checked<T>(errors: &mut Vec<String>, setting: Result<Option<T>, String>) -> Option<T> {
+    setting.unwrap_or_else(|e| {
+        errors.push(e);
+        None
+    })
+}
+
+// the schedule at `path`, or a list of what's wrong with it
+fn parse(
+    path: &Path,
+    table: &toml::Table,
+    bib: &Bibliography,
+    style: UrlStyle,
+) -> Result<Schedule, Vec<String>> {
+    let mut errors = Vec::new();
+    let name = checked(&mut errors, get_str(table, "name", "the schedule"))
+        .unwrap_or_else(|| "Reading group".to_owned());
+    let time = checked(&mut errors, get_time(table, "the schedule"));
+    let minutes = checked(&mut errors, get_minutes(table, "the schedule"))
+        .unwrap_or(DEFAULT_MINUTES);
+    let location = checked(&mut errors, get_str(table, "location", "the schedule"));
+    let tables = match table.get("session").map(|s| s.as_slice()) {
+        None => Vec::new(),
+        Some(Some(sessions)) => sessions.iter().map(|s| s.as_table()).collect(),
+        Some(None) => vec![None],
+    };
+
+    let known: HashSet<&str> = bib.entries.iter().map(|e| &e.key[..]).collect();
+    let mut sessions = Vec::new();
+    for (n, session) in tables.into_iter().enumerate() {
+        let what = format!("session {}", n + 1);
+        let session = match session {
+            Some(session) => session,
+            None => {
+                errors.push("sessions must be tables, written `[[session]]`".to_owned());
+                continue;
+            },
+        };
+        let date = match checked(&mut errors, get_str(session, "date", &what)) {
+            Some(date) => match dates::parse_civil(&date) {
+                Ok(date) => date,
+                Err(e) => {
+                    errors.push(format!("{}: {}", what, e));
+                    continue;
+                },
+            },
+            None => {
+                errors.push(format!("{} has no `date`", what));
+                continue;
+            },
+        };
+        let keys = match session.get("entries") {
+            None => Vec::new(),
+            Some(entries) => {
+                let keys: Option<Vec<String>> = entries.as_slice().and_then(|vs| {
+                    vs.iter().map(|v| v.as_str().map(|s| s.to_owned())).collect()
+                });
+                match keys {
+                    Some(keys) => keys,
+                    None => {
+                        errors.push(format!("`entries` of {} must be a list of keys", what));
+                        continue;
+                    },
+                }
+            },
+        };
+        // unknown keys are most likely typos, which shouldn't stop the build
+        let (keys, unknown): (Vec<String>, Vec<String>) = keys.into_iter()
+            .partition(|k| known.contains(&k[..]));
+        for key in unknown {
+            Diagnostic::warning(format!("{} lists unknown entry `{}`", what, key))
+                .note(format!("in {}", path.display()))
+                .emit();
+        }
+        let title = checked(&mut errors, get_str(session, "title", &what));
+        let place = checked(&mut errors, get_str(session, "location", &what));
+        sessions.push(Session {
+            date,
+            title: title.unwrap_or_default(),
+            time: checked(&mut errors, get_time(session, &what)).or(time),
+            minutes: checked(&mut errors, get_minutes(session, &what)).unwrap_or(minutes),
+            location: place.or_else(|| location.clone()),
+            keys,
+            url: String::new(),
+        });
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    sessions.sort_by_key(|s| (s.date, s.time));
+    let mut taken = HashSet::new();
+    for (n, session) in sessions.iter_mut().enumerate() {
+        if session.title.is_empty() {
+            session.title = format!("Session {}", n + 1);
+        }
+        let (year, month, day) = session.date;
+        let base = format!("{:04}-{:02}-{:02}", year, month, day);
+        let mut name = base.clone();
+        let mut n = 1;
+        while !taken.insert(name.clone()) {
+            n += 1;
+            name = format!("{}-{}", base, n);
+        }
+        session.url = match style {
+            UrlStyle::Pretty => format!("sessions/{}/", name),
+            UrlStyle::Key | UrlStyle::Slug => format!("sessions/{}.html", name),
+        };
+    }
+    let modified = fs::metadata(path).and_then(|m| m.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+    Ok(Schedule { path: path.to_owned(), name, sessions, modified })
+}
+
+// reads the schedule, if there is one. A schedule that's set in the config
+// but missing, or that can't be understood, is an error.
+pub fn load(config: &Config, bib: &Bibliography) -> Option<Schedule> {
+    let path = path(config);
+    let mut contents = String::new();
+    if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)) {
+        // only a schedule set in the config has to be there
+        config.schedule.as_ref()?;
+        Diagnostic::error(format!("could not read the schedule {}", path.display()))
+            .note(e.to_string())
+            .emit();
+        ::std::process::exit(1);
+    }
+    let name = path.display().to_string();
+    let mut parser = toml::Parser::new(&contents);
+    let table = match parser.parse() {
+        Some(table) => table,
+        None => {
+            for e in &parser.errors {
+                let (line, col) = parser.to_linecol(e.lo);
+                let len = (e.hi - e.lo).max(1);
+                Diagnostic::error("could not parse the schedule")
+                    .in_file(&name, &contents)
+                    .label(line + 1, col + 1, len, e.desc.clone())
+                    .emit();
+            }
+            ::std::process::exit(1);
+        },
+    };
+    match parse(&path, &table, bib, config.url_style) {
+        Ok(schedule) => Some(schedule),
+        Err(errors) => {
+            for e in errors {
+                Diagnostic::error(format!("invalid schedule {}", name)).note(e).emit();
+            }
+            ::std::process::exit(1);
+        },
+    }
+}
+
+// when a session is, like `2024-03-05, 16:00–17:30`
+fn when(session: &Session, text: &Text) -> String {
+    let mut when = text.date(session.date);
+    if let Some((hours, minutes)) = session.time {
+        let end = hours * 60 + minutes + session.minutes;
+        write!(
+            when,
+            ", {:02}:{:02}–{:02}:{:02}",
+            hours,
+            minutes,
+            end / 60 % 24,
+            end % 60
+        ).unwrap();
+    }
+    when
+}
+
+fn details(session: &Session, text: &Text) -> String {
+    match session.location {
+        Some(ref location) => format!("{} · {}", when(session, text), location),
+        None => when(session, text),
+    }
+}
+
+// a line for an entry: its title, linked to its page if it has one,
+// followed by who and when
+fn entry_line(
+    bib: &Bibliography,
+    key: &str,
+    pages: &HashMap<String, String>,
+    root: &str,
+) -> String {
+    let entry = match bib.get(key) {
+        Some(entry) => entry,
+        None => return html::escape(key),
+    };
+    let title = html::escape(&entry.title);
+    let title = match pages.get(key) {
+        Some(url) => format!(
+            "<a href=\"{}{}\">{}</a>",
+            html::escape(root),
+            html::escape(url),
+            title
+        ),
+        None => title,
+    };
+    let who = bib::author_year(&entry.author, &entry.year);
+    format!("{} <cite>{}</cite>", title, html::escape(&who))
+}
+
+// the content of the schedule page, at the root of the site
+pub fn page(
+    schedule: &Schedule,
+    bib: &Bibliography,
+    pages: &HashMap<String, String>,
+    text: &Text,
+) -> String {
+    let name = html::escape(&schedule.name);
+    let mut out = format!("<header>\n  <h1>{}</h1>\n</header>\n", name);
+    for session in &schedule.sessions {
+        writeln!(out, "<section class=\"session\">").unwrap();
+        writeln!(
+            out,
+            "  <h2><a href=\"{}\">{}</a></h2>",
+            html::escape(&session.url),
+            html::escape(&session.title)
+        ).unwrap();
+        let details = details(session, text);
+        writeln!(out, "  <p class=\"when\">{}</p>", html::escape(&details)).unwrap();
+        if !session.keys.is_empty() {
+            writeln!(out, "  <ul>").unwrap();
+            for key in &session.keys {
+                writeln!(out, "    <li>{}</li>", entry_line(bib, key, pages, "")).unwrap();
+            }
+            writeln!(out, "  </ul>").unwrap();
+        }
+        writeln!(out, "</section>").unwrap();
+    }
+    out
+}
+
+// the content of a session's page, with the notes on its entries, already
+// rendered, one after the other
+pub fn session_page(
+    schedule: &Schedule,
+    session: &Session,
+    bib: &Bibliography,
+    pages: &HashMap<String, String>,
+    notes: &HashMap<String, String>,
+    text: &Text,
+) -> String {
+    let root = urls::root_for(&session.url);
+    let mut out = format!(
+        "<header>\n  <h1>{}</h1>\n  <p class=\"when\">{}</p>\n  \
+         <p><a href=\"{}schedule.html\">{}</a></p>\n</header>\n",
+        html::escape(&session.title),
+        html::escape(&details(session, text)),
+        html::escape(&root),
+        html::escape(&schedule.name)
+    );
+    for key in &session.keys {
+        writeln!(out, "<section class=\"session-entry\">").unwrap();
+        writeln!(out, "<h2>{}</h2>", entry_line(bib, key, pages, &root)).unwrap();
+        match notes.get(key) {
+            Some(note) => out.push_str(&html::demote_headings(note, 2)),
+            None => writeln!(out, "<p><em>{}</em></p>", html::escape(text.get("no_notes")))
+                .unwrap(),
+        }
+        writeln!(out, "</section>").unwrap();
+    }
+    out
+}
+
+// the sessions as an iCalendar file, with links to their pages if we know
+// where the site is
+pub fn calendar(schedule: &Schedule, bib: &Bibliography, base: Option<&str>) -> String {
+    // stable across builds, and different for different groups
+    let group = urls::slugify(&schedule.name);
+    let events: Vec<Event> = schedule.sessions.iter().map(|session| {
+        let papers: Vec<String> = session.keys.iter()
+            .filter_map(|key| bib.get(key))
+            .map(|e| format!("{} ({} {})", e.title, e.author, e.year))
+            .collect();
+        let name = session.url.trim_start_matches("sessions/")
+            .trim_end_matches('/')
+            .trim_end_matches(".html");
+        Event {
+            uid: format!("{}-{}@biblionotes", group, name),
+            summary: format!("{}: {}", schedule.name, session.title),
+            description: if papers.is_empty() { None } else { Some(papers.join("\n")) },
+            location: session.location.clone(),
+            url: base.map(|base| format!("{}{}", base, session.url)),
+            date: session.date,
+            time: session.time.map(|(hours, minutes)| (hours, minutes, session.minutes)),
+        }
+    }).collect();
+    ics::calendar(&schedule.name, &events, schedule.modified)
+}
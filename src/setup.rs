@@ -0,0 +1,222 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use csl;
+use diagnostics::Diagnostic;
+
+// `biblionotes setup`, for starting a site without knowing the command line:
+// it asks, one question at a time and each with an answer to take by just
+// pressing enter, for the bibliography (offering the ones in the current
+// directory), where the notes and the site go, a look for the pages and the
+// site's title. It then makes the notes directory, writes a starting
+// template and `biblionotes.toml`, and says how to build the site again,
+// before building it for the first time.
+
+pub const CONFIG: &str = "biblionotes.toml";
+const TEMPLATE: &str = "template.hbs";
+
+// a name, the page's colors and fonts, and the syntect theme that goes
+// with them for code
+const THEMES: &[(&str, &str, &str)] = &[
+    (
+        "light",
+        "body { font-family: system-ui, sans-serif; color: #222; background: #fff; }\n    \
+         a { color: #2a7ab0; }",
+        "InspiredGitHub",
+    ),
+    (
+        "dark",
+        "body { font-family: system-ui, sans-serif; color: #ddd; background: #1d1f21; }\n    \
+         a { color: #81a2be; }",
+        "base16-ocean.dark",
+    ),
+    (
+        "serif",
+        "body { font-family: Georgia, serif; color: #222; background: #fdfcf8; }\n    \
+         a { color: #8b3a3a; }",
+        "InspiredGitHub",
+    ),
+];
+
+// the starting template, with `{style}` for the theme's
+const PAGE: &str = r#"<!DOCTYPE html>
+<html lang="{{#if lang}}{{lang}}{{else}}en{{/if}}">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{{title}}</title>
+  <link rel="stylesheet" href="{{root}}highlight.css">
+  <script defer src="https://cdn.jsdelivr.net/npm/mathjax@3/es5/tex-chtml.js"></script>
+  <style>
+    {style}
+    main { max-width: 42em; margin: 2em auto; padding: 0 1em; line-height: 1.5; }
+  </style>
+</head>
+<body>
+  <main>
+    {{{content}}}
+  </main>
+  <footer><p><a href="{{root}}index.html">{{text.index_title}}</a></p></footer>
+</body>
+</html>
+"#;
+
+// where the answers come from
+struct Prompt<R> {
+    input: R,
+}
+
+impl<R: BufRead> Prompt<R> {
+    // the answer to a question, or `default` if there's none
+    fn ask(&mut self, question: &str, default: &str) -> String {
+        if default.is_empty() {
+            print!("{}: ", question);
+        } else {
+            print!("{} [{}]: ", question, default);
+        }
+        io::stdout().flush().expect("Could not write the question");
+        let mut line = String::new();
+        // the end of the input takes every default, as far as there are any
+        if self.input.read_line(&mut line).expect("Could not read the answer") == 0 {
+            println!();
+            if default.is_empty() {
+                Diagnostic::error("setup stopped without an answer").emit();
+                ::std::process::exit(1);
+            }
+        }
+        match line.trim() {
+            "" => default.to_owned(),
+            answer => answer.to_owned(),
+        }
+    }
+
+    fn yes(&mut self, question: &str, default: bool) -> bool {
+        loop {
+            let answer = self.ask(question, if default { "Y/n" } else { "y/N" });
+            match &answer.to_lowercase()[..] {
+                "y" | "yes" => return true,
+                "n" | "no" => return false,
+                // no answer
+                "y/n" => return default,
+                _ => println!("  please answer y or n"),
+            }
+        }
+    }
+
+    // whether to write the file at `path`, asking first if it's there
+    fn may_write(&mut self, path: &str) -> bool {
+        let question = format!("Replace the {} there is?", path);
+        !Path::new(path).exists() || self.yes(&question, false)
+    }
+
+    // one of `choices`, by number, the first unless another is picked
+    fn choose(&mut self, question: &str, choices: &[String]) -> usize {
+        println!("{}", question);
+        for (i, choice) in choices.iter().enumerate() {
+            println!("  {}) {}", i + 1, choice);
+        }
+        loop {
+            let answer = self.ask("  pick one", "1");
+            match answer.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= choices.len() => return n - 1,
+                _ => println!("  please answer with a number from 1 to {}", choices.len()),
+            }
+        }
+    }
+}
+
+// the bibliographies in a directory, by name
+fn bibliographies(dir: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = fs::read_dir(dir).into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            let name = p.to_string_lossy().to_lowercase();
+            name.ends_with(".bib") || csl::is_csl(&name)
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+fn toml_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn bibliography<R: BufRead>(prompt: &mut Prompt<R>) -> String {
+    let found = bibliographies(Path::new("."));
+    let mut choices: Vec<String> = found.iter()
+        .map(|p| p.strip_prefix(".").unwrap_or(p).display().to_string())
+        .collect();
+    if !choices.is_empty() {
+        choices.push("another file, or a URL".to_owned());
+        let picked = prompt.choose("Which bibliography are the notes on?", &choices);
+        if picked < found.len() {
+            return choices[picked].clone();
+        }
+    }
+    loop {
+        let path = prompt.ask("Where is the bibliography (.bib, CSL-JSON or a URL)", "");
+        let remote = path.starts_with("http://") || path.starts_with("https://");
+        if remote || Path::new(&path).is_file() {
+            return path;
+        }
+        println!("  there's no file at `{}`", path);
+    }
+}
+
+// asks what there is to ask and sets up the site, returning the arguments
+// to build it with, or `None` if there's nothing to build
+pub fn run() -> Option<[String; 4]> {
+    let stdin = io::stdin();
+    let mut prompt = Prompt { input: stdin.lock() };
+    println!("Setting up an annotated bibliography. Enter takes the answer in [brackets].");
+    let bib = bibliography(&mut prompt);
+    let notes = prompt.ask("Where do the notes go (one Markdown file per entry)", "notes");
+    let output = prompt.ask("Where does the site go", "site");
+    let names: Vec<String> = THEMES.iter().map(|t| t.0.to_owned()).collect();
+    let theme = prompt.choose("What should the pages look like?", &names);
+    let (_, style, highlight) = THEMES[theme];
+    let title = prompt.ask("What's the site called", "Annotated bibliography");
+
+    fs::create_dir_all(&notes).expect("Could not create the notes directory");
+    if prompt.may_write(TEMPLATE) {
+        fs::write(TEMPLATE, PAGE.replace("{style}", style))
+            .expect("Could not write the template");
+    }
+    let config = format!(
+        "# written by `biblionotes setup`; the README has all that can go here.\n\
+         # Build the site with:\n\
+         #   biblionotes --config {} {} {} {} {}\n\
+         \n\
+         [highlight]\n\
+         theme = {}\n\
+         \n\
+         [text]\n\
+         index_title = {}\n",
+        CONFIG,
+        bib,
+        TEMPLATE,
+        notes,
+        output,
+        toml_string(highlight),
+        toml_string(&title)
+    );
+    if prompt.may_write(CONFIG) {
+        fs::write(CONFIG, config).expect("Could not write the config file");
+    }
+    println!(
+        "\nTo build the site again, run:\n  biblionotes --config {} {} {} {} {}\n",
+        CONFIG,
+        bib,
+        TEMPLATE,
+        notes,
+        output
+    );
+    if !prompt.yes("Build it now?", true) {
+        return None;
+    }
+    Some([bib, TEMPLATE.to_owned(), notes, output])
+}
@@ -0,0 +1,1850 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use handlebars::Handlebars;
+use serde_json::{self, Map, Value};
+use anniversaries;
+use attachments::{self, Reference};
+use bib;
+use bib::{Bibliography, Entry, Link};
+use book;
+use cache;
+use collate;
+use coverage;
+use dates;
+use contributors;
+use config::{Config, CoverageOutput, IndexSort, Profile, Renderer};
+use diagnostics::{Diagnostic, Level};
+use encrypted;
+use enrich;
+use external;
+use frontmatter;
+use graph;
+use error::Error;
+use highlight::Highlighter;
+use hooks;
+use html;
+use images;
+use keys;
+use logging::Progress;
+use math;
+use meta::{self, PageMeta};
+use minify;
+use model;
+use output::{Mode, Output};
+use pdfs;
+use provenance::{self, Provenance};
+use reading;
+use related;
+use reading::Reading;
+use schedule::{self, Schedule};
+use markdown;
+use render;
+use sitemap;
+use statistics;
+use template::{self, Failure};
+use text::Text;
+use todo;
+use updated;
+use urls;
+use venues;
+use xref;
+
+// the names templates are registered under
+const DEFAULT: &str = "t";
+const BASE: &str = "base";
+
+// which of the configured templates a page goes through
+#[derive(Clone, Copy)]
+enum Layout {
+    Page,
+    Index,
+    Author,
+    Default,
+}
+
+impl Layout {
+    fn name(self) -> &'static str {
+        match self {
+            Layout::Page => "page",
+            Layout::Index => "index",
+            Layout::Author => "author",
+            Layout::Default => DEFAULT,
+        }
+    }
+}
+
+// a registered template, with its source to check pages against and to show
+// where one failed
+struct Template {
+    name: &'static str,
+    path: PathBuf,
+    source: String,
+}
+
+// reads and registers the template from the command line, the base layout
+// and each kind of page's own
+fn register_templates(config: &Config, hbs: &mut Handlebars) -> Vec<Template> {
+    let own = &config.templates;
+    let files = [
+        (DEFAULT, Some(&config.template_path)),
+        (BASE, own.base.as_ref()),
+        (Layout::Page.name(), own.page.as_ref()),
+        (Layout::Index.name(), own.index.as_ref()),
+        (Layout::Author.name(), own.author.as_ref()),
+    ];
+    let mut templates = Vec::new();
+    for (name, path) in files {
+        let path = match path {
+            Some(path) => path,
+            None => continue,
+        };
+        let mut source = String::new();
+        File::open(path)
+            .expect("Could not open template file")
+            .read_to_string(&mut source)
+            .expect("Could not read template file");
+        hbs.register_template_string(name, &source).expect("Could not register template");
+        templates.push(Template { name, path: path.clone(), source });
+    }
+    templates
+}
+
+// state shared by every page of a build
+struct Site<'a> {
+    config: &'a Config,
+    bib: &'a Bibliography,
+    hbs: Handlebars,
+    templates: Vec<Template>,
+    highlighter: Option<Highlighter>,
+    output: Output,
+    // the URL of every entry that has a note
+    pages: HashMap<String, String>,
+    // the source of every note, read up front to collect the links between
+    // them
+    notes: HashMap<String, String>,
+    // the keys of the notes that cite each entry, in bibliography order
+    backlinks: HashMap<String, Vec<String>>,
+    // the keys of the notes that are drafts
+    drafts: HashSet<String>,
+    // the entries most like each entry, best first
+    related: HashMap<String, Vec<String>>,
+    // who wrote each note, if asked for
+    contributors: HashMap<String, Vec<String>>,
+    // when each note last changed
+    updated: HashMap<String, SystemTime>,
+    // when each note was written, for the latest notes
+    created: HashMap<String, SystemTime>,
+    // the PDF of each entry, if there's a folder of them
+    pdfs: HashMap<String, PathBuf>,
+    // the stylesheet to put in every page
+    critical_css: Option<String>,
+    provenance: Provenance,
+}
+
+// where the note on an entry is: `<key>.md`, or `drafts/<key>.md` for notes
+// kept out of the published site. Notes in other formats, like `<key>.org`,
+// are looked for after markdown ones, and new notes are markdown.
+pub fn note_path(config: &Config, key: &str) -> PathBuf {
+    let extensions = Some("md").into_iter()
+        .chain(config.pandoc.formats.keys().map(|e| &e[..]).filter(|&e| e != "md"));
+    let names: Vec<String> = extensions.map(|ext| format!("{}.{}", key, ext)).collect();
+    let drafts = config.markdown_dir.join("drafts");
+    names.iter().map(|name| config.markdown_dir.join(name))
+        .chain(names.iter().map(|name| drafts.join(name)))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| config.markdown_dir.join(&names[0]))
+}
+
+// the time of the build, which reproducible builds take from
+// `SOURCE_DATE_EPOCH`, as pandoc does
+pub fn now(config: &Config) -> SystemTime {
+    if !config.reproducible {
+        return SystemTime::now();
+    }
+    let secs = env::var("SOURCE_DATE_EPOCH").ok().and_then(|s| s.trim().parse().ok());
+    UNIX_EPOCH + Duration::from_secs(secs.unwrap_or(0))
+}
+
+// where the note on an entry was read from, which is where it's encrypted if
+// there's no plain one
+fn source_path(config: &Config, key: &str) -> PathBuf {
+    match note_path(config, key) {
+        path if !path.is_file() => encrypted::path(config, key),
+        path => path,
+    }
+}
+
+// the pandoc format of a note that isn't markdown, by its extension
+pub fn note_format<'a>(config: &'a Config, path: &Path) -> Option<&'a str> {
+    let ext = path.extension()?.to_str()?;
+    if ext == "md" {
+        return None;
+    }
+    config.pandoc.formats.get(ext).map(|f| &f[..])
+}
+
+// whether a note is a draft, by being in `drafts/` or saying so in its front
+// matter
+pub fn is_draft(config: &Config, key: &str, note: &str) -> bool {
+    let in_drafts = note_path(config, key).parent()
+        .is_some_and(|dir| dir.ends_with("drafts") && dir != config.markdown_dir);
+    let marked = frontmatter::fields(note).get("draft")
+        .is_some_and(|d| d == "true" || d == "yes");
+    in_drafts || marked
+}
+
+// whether an entry is only for the internal site, by its note's front matter
+// or its `audience` field saying `internal`
+pub fn is_internal(entry: &Entry, note: &str) -> bool {
+    let fields = frontmatter::fields(note);
+    let audience = fields.get("audience").cloned().or_else(|| entry.text_field("audience"));
+    audience.is_some_and(|a| a.eq_ignore_ascii_case("internal"))
+}
+
+// everything the index needs to know about a page
+struct IndexEntry {
+    key: String,
+    link: String,
+    author: String,
+    author_sort: String,
+    year: String,
+    title: String,
+    links: Vec<Link>,
+    // where the paper's PDF is, relative to the output directory
+    pdf_url: Option<String>,
+    abstract_text: Option<String>,
+    reading: Reading,
+    draft: bool,
+    updated: Option<SystemTime>,
+    created: Option<SystemTime>,
+    description: Option<String>,
+    // the start of the note as it's rendered, as plain text
+    excerpt: Option<String>,
+    citations: Option<u64>,
+    // the venue's rank, and the venue
+    rank: Option<(String, String)>,
+    // the entry's fields biblionotes doesn't know, by name
+    extra: Vec<(String, String)>,
+    // the page's content, for all.html
+    content: Option<String>,
+}
+
+// the template data of a page, and its line in the index
+type Page = (Map<String, Value>, IndexEntry);
+
+// the fields biblionotes doesn't know as template data, as `extra.code` and
+// so on
+fn extra_data(extra: &[(String, String)]) -> Value {
+    Value::Object(extra.iter()
+        .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+        .collect())
+}
+
+// links to the paper, separated by middle dots
+fn links_html(links: &[Link]) -> String {
+    links.iter()
+        .map(|l| format!("<a href=\"{}\">{}</a>", html::escape(&l.url), l.label))
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+// the same links as template data
+fn links_data(links: &[Link]) -> Value {
+    Value::Array(links.iter().map(|l| {
+        let mut link = Map::new();
+        link.insert("label".to_owned(), Value::String(l.label.to_owned()));
+        link.insert("url".to_owned(), Value::String(l.url.clone()));
+        Value::Object(link)
+    }).collect())
+}
+
+// puts `items` in the index's order, by their author sort key, year, title
+// and citation count
+fn sort_like_index<T, F>(items: &mut [T], config: &Config, fields: F)
+where
+    F: Fn(&T) -> (&str, &str, &str, Option<u64>),
+{
+    let locale = &config.collation[..];
+    match config.sort {
+        IndexSort::Bib => {},
+        IndexSort::Author => items.sort_by_cached_key(|e| {
+            let (author_sort, year, _, _) = fields(e);
+            (collate::sort_key(author_sort, locale), year.to_owned())
+        }),
+        IndexSort::Title => {
+            let articles = config.articles.clone()
+                .unwrap_or_else(|| collate::articles(locale));
+            items.sort_by_cached_key(|e| {
+                collate::sort_key(collate::strip_article(fields(e).2, &articles), locale)
+            })
+        },
+        IndexSort::Year => items.sort_by_cached_key(|e| {
+            let (author_sort, year, _, _) = fields(e);
+            (year.to_owned(), collate::sort_key(author_sort, locale))
+        }),
+        // entries without a count go last
+        IndexSort::Citations => items.sort_by_cached_key(|e| {
+            let (author_sort, year, _, citations) = fields(e);
+            let author_sort = collate::sort_key(author_sort, locale);
+            (::std::cmp::Reverse(citations), year.to_owned(), author_sort)
+        }),
+    }
+}
+
+fn sort_index(index: &mut [IndexEntry], config: &Config) {
+    sort_like_index(index, config, |e| (&e.author_sort, &e.year, &e.title, e.citations));
+}
+
+fn index_header(text: &Text) -> String {
+    format!(
+        "<header>\n  <h1>{}</h1>\n  <p>{}</p>\n</header>\n",
+        html::escape(text.get("index_title")),
+        text.get("index_intro")
+    )
+}
+
+// the file of the `n`th page of the index, counting from 1
+fn index_file(n: usize) -> String {
+    if n == 1 { "index.html".to_owned() } else { format!("index{}.html", n) }
+}
+
+// what a build found converting each note that its line in the index needs,
+// so that a build of only some of the pages can list the others without
+// converting them again. It's kept in `.biblionotes/` next to the
+// bibliography, by output directory, rather than in the site, which would
+// publish every note in full.
+fn converted_path(config: &Config) -> PathBuf {
+    let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+    dir.join(".biblionotes").join("index.json")
+}
+
+// the output directory, as the converted notes are filed under it
+fn converted_site(config: &Config) -> String {
+    let dir = config.output_dir.canonicalize().unwrap_or_else(|_| config.output_dir.clone());
+    dir.display().to_string()
+}
+
+// each note's excerpt and, for all.html, content, by key
+type Converted = HashMap<String, (Option<String>, Option<String>)>;
+
+// what's kept for every output directory
+fn read_converted_sites(config: &Config) -> Map<String, Value> {
+    fs::read_to_string(converted_path(config)).ok()
+        .and_then(|json| serde_json::from_str::<Value>(&json).ok())
+        .and_then(|json| json.as_object().cloned())
+        .unwrap_or_default()
+}
+
+fn read_converted(config: &Config) -> Converted {
+    let sites = read_converted_sites(config);
+    let mut converted = HashMap::new();
+    let notes = sites.get(&converted_site(config)).and_then(|s| s.as_object());
+    for (key, e) in notes.into_iter().flat_map(|o| o.iter()) {
+        let field = |name| e.find(name).and_then(|v| v.as_str()).map(|v| v.to_owned());
+        converted.insert(key.clone(), (field("excerpt"), field("content")));
+    }
+    converted
+}
+
+fn save_converted(config: &Config, converted: &Converted) -> io::Result<()> {
+    let text = |s: &Option<String>| s.clone().map_or(Value::Null, Value::String);
+    let notes: Map<String, Value> = converted.iter().map(|(key, (excerpt, content))| {
+        let mut e = Map::new();
+        e.insert("excerpt".to_owned(), text(excerpt));
+        e.insert("content".to_owned(), text(content));
+        (key.clone(), Value::Object(e))
+    }).collect();
+    let mut sites = read_converted_sites(config);
+    sites.insert(converted_site(config), Value::Object(notes));
+    let path = converted_path(config);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_string(&sites).expect("Could not serialise the index's data");
+    fs::write(path, json)
+}
+
+// the files the site has besides the notes' pages, which no note's can be
+const OWN_FILES: &[&str] = &[
+    "all.html", "all.pdf", "anniversaries.ics", "contributors.html", "coverage.html",
+    "coverage.json", "graph.html", "graph.json", "highlight.css", "index.json",
+    "robots.txt", "schedule.html", "schedule.ics", "sitemap.xml", "stats.html",
+    "stats.json", "todo.html",
+];
+
+fn own_file(file: &str) -> bool {
+    // `index.html`, `index2.html`... and the same for the latest notes
+    let numbered = |prefix: &str| {
+        file.strip_prefix(prefix)
+            .and_then(|f| f.strip_suffix(".html"))
+            .is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+    };
+    OWN_FILES.contains(&file)
+        || numbered("index")
+        || numbered("latest")
+        || reading::STATUSES.iter().any(|s| file == format!("{}.html", s.name()))
+}
+
+// the notes whose pages would be written over another's, or over one of the
+// site's own files, whatever the case of their names (which doesn't matter
+// on macOS or Windows). The first in the bibliography keeps its page.
+fn clashes(bib: &Bibliography, pages: &HashMap<String, String>) -> Vec<(String, Error)> {
+    let mut taken: HashMap<String, &str> = HashMap::new();
+    let mut clashes = Vec::new();
+    for entry in bib.entries.iter().filter(|e| pages.contains_key(&e.key)) {
+        let file = urls::file_for(&pages[&entry.key]);
+        let name = file.to_string_lossy().to_lowercase();
+        let clash = if own_file(&name) {
+            Some(None)
+        } else {
+            taken.get(&name).map(|key| Some(key.to_string()))
+        };
+        match clash {
+            Some(with) => clashes.push((entry.key.clone(), Error::Clash(file, with))),
+            None => {
+                taken.insert(name, &entry.key);
+            },
+        }
+    }
+    clashes
+}
+
+// the file of the `n`th page of the latest notes, counting from 1
+fn latest_file(n: usize) -> String {
+    if n == 1 { "latest.html".to_owned() } else { format!("latest{}.html", n) }
+}
+
+// links to the pages either side of the `n`th of `count`, of the index or
+// of whatever else `file` names the pages of
+fn pagination_html(n: usize, count: usize, file: fn(usize) -> String, text: &Text) -> String {
+    let mut nav = String::from("\n<nav class=\"pagination\">");
+    if n > 1 {
+        nav.push_str(&format!(
+            "<a rel=\"prev\" href=\"{}\">{}</a> ",
+            file(n - 1),
+            html::escape(text.get("previous"))
+        ));
+    }
+    let page_count = text.get("page_count")
+        .replace("{n}", &n.to_string())
+        .replace("{total}", &count.to_string());
+    nav.push_str(&html::escape(&page_count));
+    if n < count {
+        nav.push_str(&format!(
+            " <a rel=\"next\" href=\"{}\">{}</a>",
+            file(n + 1),
+            html::escape(text.get("next"))
+        ));
+    }
+    nav.push_str("</nav>");
+    nav
+}
+
+// the same as template data, for templates with their own navigation
+fn pagination_data(n: usize, count: usize, file: fn(usize) -> String) -> Value {
+    let mut pagination = Map::new();
+    pagination.insert("page".to_owned(), Value::U64(n as u64));
+    pagination.insert("pages".to_owned(), Value::U64(count as u64));
+    if n > 1 {
+        pagination.insert("prev".to_owned(), Value::String(file(n - 1)));
+    }
+    if n < count {
+        pagination.insert("next".to_owned(), Value::String(file(n + 1)));
+    }
+    let urls = (1..=count).map(|n| Value::String(file(n))).collect();
+    pagination.insert("urls".to_owned(), Value::Array(urls));
+    Value::Object(pagination)
+}
+
+// the label `[venue_ranks]` gives the entry's venue, if any. Venues are
+// matched by name, whatever their case.
+fn venue_rank(config: &Config, entry: &Entry) -> Option<String> {
+    let venue = entry.venue()?.to_lowercase();
+    config.venue_ranks.iter()
+        .find(|&(name, _)| name.trim().to_lowercase() == venue)
+        .map(|(_, label)| label.clone())
+}
+
+fn rank_html(rank: &str, venue: &str) -> String {
+    format!(
+        " <span class=\"venue-rank\" title=\"{}\">{}</span>",
+        html::escape(venue),
+        html::escape(rank)
+    )
+}
+
+// the reading status and rating of an entry, as a badge and stars, after
+// badges for drafts and for the venue's rank
+fn badges_html(e: &IndexEntry, text: &Text) -> String {
+    let reading = &e.reading;
+    let mut html = String::new();
+    if e.draft {
+        html.push_str(&format!(
+            " <span class=\"draft\">{}</span>",
+            html::escape(text.get("draft"))
+        ));
+    }
+    if let Some((ref rank, ref venue)) = e.rank {
+        html.push_str(&rank_html(rank, venue));
+    }
+    if let Some(status) = reading.status {
+        html.push_str(&format!(
+            " <span class=\"status {}\">{}</span>",
+            status.name(),
+            html::escape(text.get(status.text_id()))
+        ));
+    }
+    if let Some(rating) = reading.rating {
+        html.push_str(&format!(
+            " <span class=\"rating\" title=\"{}\">{}</span>",
+            html::escape(&text.get("rating").replace("{n}", &rating.to_string())),
+            reading::stars(rating)
+        ));
+    }
+    html
+}
+
+fn make_index(index: &[&IndexEntry], header: &str, config: &Config) -> String {
+    let mut content = String::from(header);
+    content.push_str("<ul class=\"nonetype\">\n");
+
+    for e in index {
+        let links = if e.links.is_empty() {
+            String::new()
+        } else {
+            format!(" · {}", links_html(&e.links))
+        };
+        fmt::write(
+            &mut content,
+            format_args!(
+                "  <li><a href=\"{}\">{}</a>{}<br>\n  {}{}\n",
+                html::escape(&e.link),
+                html::escape(&e.title),
+                badges_html(e, &config.text),
+                html::escape(&bib::author_year(&e.author, &e.year)),
+                links,
+            )
+        ).unwrap();
+        if let Some(abs) = e.abstract_text.as_ref().filter(|_| config.index_abstracts) {
+            fmt::write(
+                &mut content,
+                format_args!(
+                    "  <details class=\"abstract\"><summary>{}</summary>\
+                     <p>{}</p></details>\n",
+                    html::escape(config.text.get("abstract")),
+                    html::escape(abs),
+                )
+            ).unwrap();
+        }
+    }
+    fmt::write(&mut content, format_args!("</ul>")).unwrap();
+    content
+}
+
+impl<'a> Site<'a> {
+    // reads the notes and works out everything about them that any page can
+    // need, reporting the notes that couldn't be read
+    fn new(
+        config: &'a Config,
+        bib: &'a Bibliography,
+        hbs: Handlebars,
+        templates: Vec<Template>,
+        mode: Mode,
+    ) -> (Site<'a>, Vec<(String, Error)>) {
+        let output = Output::new(&config.output_dir, mode);
+
+        // set up code highlighting, and write out its stylesheet
+        let highlighter = config.highlight_theme.as_ref().map(|theme| {
+            let highlighter = Highlighter::new(theme).unwrap_or_else(|e| {
+                Diagnostic::error(e).emit();
+                ::std::process::exit(1);
+            });
+            output.write(Path::new("highlight.css"), &highlighter.css(), &[])
+                .expect("Could not write highlighting stylesheet");
+            highlighter
+        });
+        // read every note first, so that notes can link to each other and each
+        // page can list the notes that cite it
+        let (mut notes, mut failures) = read_notes(config, bib);
+        info!("read {} entries and {} notes", bib.entries.len(), notes.len());
+        let drafts = published(config, bib, &mut notes);
+        let keys = notes.keys().cloned().collect();
+        let mut pages = urls::page_urls(bib, &keys, config.url_style);
+        // rather than one page silently taking another's place
+        for (key, e) in clashes(bib, &pages) {
+            pages.remove(&key);
+            notes.remove(&key);
+            failures.push((key, e));
+        }
+        let mut backlinks: HashMap<String, Vec<String>> = HashMap::new();
+        for entry in &bib.entries {
+            let key = &entry.key;
+            let source = match notes.get(key) {
+                Some(source) => source,
+                None => continue,
+            };
+            for cited in xref::cited(source) {
+                if cited != *key {
+                    backlinks.entry(cited).or_default().push(key.clone());
+                }
+            }
+        }
+        let related = related::compute(bib, &pages, config.related);
+        let pdfs = config.pdf_dir.as_ref().map(|dir| pdfs::find(dir, bib)).unwrap_or_default();
+        let contributors = if config.contributors && mode != Mode::Plan {
+            notes.iter()
+                .map(|(key, note)| {
+                    (key.clone(), contributors::of(&note_path(config, key), note))
+                })
+                .filter(|(_, names)| !names.is_empty())
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let (updated, created) = if mode != Mode::Plan {
+            let paths: HashMap<String, PathBuf> = notes.keys()
+                .map(|key| (key.clone(), source_path(config, key)))
+                .collect();
+            // only commits say the same thing on every checkout
+            let uncommitted = if config.reproducible { Some(now(config)) } else { None };
+            let commits = updated::commits(&config.markdown_dir);
+            (
+                updated::compute(&commits, paths.clone(), uncommitted),
+                updated::created(&commits, paths, uncommitted),
+            )
+        } else {
+            (HashMap::new(), HashMap::new())
+        };
+        let critical_css = config.critical_css.as_ref().map(|path| {
+            fs::read_to_string(path).expect("Could not read the critical stylesheet")
+        });
+        let site = Site {
+            config,
+            bib,
+            hbs,
+            templates,
+            highlighter,
+            output,
+            pages,
+            notes,
+            backlinks,
+            drafts,
+            related,
+            contributors,
+            updated,
+            created,
+            pdfs,
+            critical_css,
+            provenance: provenance::load(config),
+        };
+        (site, failures)
+    }
+
+    // the template pages of a layout go through: their own if there's one,
+    // the one from the command line if not
+    fn template(&self, layout: Layout) -> &Template {
+        let name = layout.name();
+        self.templates.iter().find(|t| t.name == name)
+            .or_else(|| self.templates.iter().find(|t| t.name == DEFAULT))
+            .expect("Could not find the template")
+    }
+
+    // a whole page from the template, or with `--fragments` just its content
+    fn render(&self, layout: Layout, data: &Map<String, Value>) -> Result<String, Failure> {
+        let page = if self.config.fragments {
+            data.get("content").and_then(|c| c.as_str()).unwrap_or("").to_owned()
+        } else {
+            let context = self.context(data);
+            let template = self.template(layout);
+            if self.config.strict_templates {
+                // the base too, for what it has outside the blocks
+                let base = self.templates.iter().filter(|t| t.name == BASE);
+                for t in Some(template).into_iter().chain(base) {
+                    template::check(&t.path.display().to_string(), &t.source, &context)?;
+                }
+            }
+            self.hbs.render(template.name, &context).map_err(|e| {
+                // Handlebars says which template, when it went wrong in the base
+                let failed = e.template_name.as_ref()
+                    .and_then(|name| self.templates.iter().find(|t| t.name == *name))
+                    .unwrap_or(template);
+                Failure::render(e, &failed.path.display().to_string(), &context)
+            })?
+        };
+        Ok(self.post_process(page))
+    }
+
+    // `render` for the pages a build can't go on without
+    fn render_or_exit(&self, layout: Layout, data: &Map<String, Value>) -> String {
+        self.render(layout, data).unwrap_or_else(|e| {
+            let source = self.templates.iter()
+                .find(|t| t.path.display().to_string() == e.template)
+                .map_or("", |t| &t.source);
+            e.diagnostic(source).emit();
+            ::std::process::exit(1);
+        })
+    }
+
+    // what `--minify` and `--critical-css` do to every page, after marking
+    // its links to other sites
+    fn post_process(&self, page: String) -> String {
+        let page = external::decorate(&page, self.config);
+        let page = match self.critical_css {
+            Some(ref sheet) if !self.config.fragments => minify::inline_css(&page, sheet),
+            _ => page,
+        };
+        if self.config.minify {
+            minify::html(&page)
+        } else {
+            page
+        }
+    }
+
+    // everything the template gets for a page with the given data
+    fn context(&self, data: &Map<String, Value>) -> Map<String, Value> {
+        // every page gets the site's words, for the template's own chrome
+        let mut data = data.clone();
+        data.insert("text".to_owned(), self.config.text.data());
+        if let Some(ref lang) = self.config.lang {
+            data.insert("lang".to_owned(), Value::String(lang.clone()));
+        }
+        data
+    }
+
+    // the pages of the given entries, as template data, with links relative
+    // to `root`
+    fn page_links(&self, keys: Option<&Vec<String>>, root: &str) -> Vec<Value> {
+        let keys = match keys {
+            Some(keys) => keys,
+            None => return Vec::new(),
+        };
+        keys.iter()
+            .filter_map(|source| self.bib.get(source))
+            .map(|e| {
+                let mut link = Map::new();
+                link.insert("key".to_owned(), Value::String(e.key.clone()));
+                link.insert("title".to_owned(), Value::String(e.title.clone()));
+                link.insert("year".to_owned(), Value::String(e.year.clone()));
+                let url = format!("{}{}", root, self.pages[&e.key]);
+                link.insert("link".to_owned(), Value::String(url));
+                Value::Object(link)
+            })
+            .collect()
+    }
+
+    // builds the page for a single entry, returning its index line, or `None` if
+    // the entry has no notes
+    fn build_entry(&self, entry: &Entry) -> Result<Option<IndexEntry>, Error> {
+        let config = self.config;
+        let (data, line) = match self.page(entry)? {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+        let rendered = self.render(Layout::Page, &data)?;
+
+        // write output
+        let html_path = urls::file_for(&line.link);
+        let md_path = source_path(config, &entry.key);
+        let sources = [&md_path, &config.template_path, Path::new(&config.bib_path)];
+        self.output.write(&html_path, &format!("{}\n", rendered), &sources)
+            .map_err(|e| Error::Write(config.output_dir.join(&html_path), e))?;
+        Ok(Some(line))
+    }
+
+    // the template data for the page on an entry, along with its index line,
+    // or `None` if the entry has no notes
+    fn page(&self, entry: &Entry) -> Result<Option<Page>, Error> {
+        let config = self.config;
+        let key = &entry.key[..];
+        // skip the entry if it doesn't have notes
+        let md_path = source_path(config, key);
+        let md_contents = match self.notes.get(key) {
+            Some(source) => source,
+            None => return Ok(None),
+        };
+
+        // turn references to other entries into links
+        let html_name = self.pages[key].clone();
+        let root = urls::root_for(&html_name);
+        let (rewritten, unknown) = xref::rewrite(md_contents, self.bib, &self.pages, &root);
+        let level = if config.strict { Level::Error } else { Level::Warning };
+        for u in &unknown {
+            let (line, col) = markdown::line_col(md_contents, u.offset);
+            Diagnostic::new(level, "unknown reference")
+                .in_file(&md_path.display().to_string(), md_contents)
+                .label(line, col, u.len, format!("no entry with key `{}`", u.key))
+                .emit();
+        }
+        if config.strict && !unknown.is_empty() {
+            return Err(Error::References(unknown.into_iter().map(|u| u.key).collect()));
+        }
+
+        // and links to files next to the note into links to their copies
+        let dir = md_path.parent().unwrap_or_else(|| Path::new(""));
+        let attachments = attachments::find(md_contents, key, dir, &config.markdown_dir);
+        let missing: Vec<(&String, &Reference)> = attachments.references.iter()
+            .filter(|&(_, r)| !r.found)
+            .collect();
+        for &(_, r) in &missing {
+            let (line, col) = markdown::line_col(md_contents, r.offset);
+            Diagnostic::new(level, "missing attachment")
+                .in_file(&md_path.display().to_string(), md_contents)
+                .label(line, col, r.len, "no such file next to the note")
+                .emit();
+        }
+        if config.strict && !missing.is_empty() {
+            let files = missing.iter().map(|&(destination, _)| destination.clone()).collect();
+            return Err(Error::Attachments(files));
+        }
+        for (to, from) in &attachments.files {
+            self.output.copy(to, from)
+                .map_err(|e| Error::Write(config.output_dir.join(to), e))?;
+        }
+        let md_contents = attachments::rewrite(&rewritten, &attachments, &root);
+        let pdf_url = match self.pdfs.get(key) {
+            Some(from) => {
+                let to = pdfs::path(key);
+                self.output.copy(&to, from)
+                    .map_err(|e| Error::Write(config.output_dir.join(&to), e))?;
+                Some(to.to_string_lossy().replace(' ', "%20"))
+            },
+            None => None,
+        };
+
+        let (reading, errors) = Reading::from_fields(&frontmatter::fields(&self.notes[key]));
+        for e in errors {
+            Diagnostic::warning(format!("ignoring front matter in the note on `{}`", key))
+                .in_file(&md_path.display().to_string(), &self.notes[key])
+                .note(e)
+                .emit();
+        }
+
+        // convert to HTML
+        let converting = Instant::now();
+        let body = self.body_html(entry, &md_contents)?;
+        let body = images::lazy(&body, &images::sizes(&attachments.files, &root));
+        let excerpt = html::first_paragraph(&body)
+            .map(|text| meta::summary(&text, config.excerpt_length));
+        let toc: Vec<Value> = html::headings(&body).into_iter().map(|h| {
+            let mut heading = Map::new();
+            heading.insert("heading".to_owned(), Value::String(h.text));
+            heading.insert("level".to_owned(), Value::U64(h.level as u64));
+            heading.insert("anchor".to_owned(), Value::String(h.id));
+            Value::Object(heading)
+        }).collect();
+        debug!("converted the note on `{}` in {:.1?}", key, converting.elapsed());
+
+        // add the header
+        let mut links = entry.links();
+        if let Some(ref pdf) = pdf_url {
+            links.push(Link { label: "PDF", url: format!("{}{}", root, pdf) });
+        }
+        let nav = if links.is_empty() {
+            String::new()
+        } else {
+            format!("<nav class=\"links\">{}</nav>", links_html(&links))
+        };
+        let contributors = self.contributors.get(key).map_or(&[][..], |c| &c[..]);
+        let notes_by = config.text.list(contributors);
+        let byline = if notes_by.is_empty() {
+            String::new()
+        } else {
+            // each name linked to the person's page
+            let names: Vec<String> = contributors.iter()
+                .map(|name| format!(
+                    "<a href=\"{}{}\">{}</a>",
+                    root,
+                    html::escape(&contributors::url(name)),
+                    html::escape(name)
+                ))
+                .collect();
+            format!(
+                "<p class=\"contributors\">{} {}</p>",
+                html::escape(config.text.get("notes_by")),
+                config.text.list(&names)
+            )
+        };
+        let rank = venue_rank(config, entry);
+        let rank_badge = match (&rank, entry.venue()) {
+            (Some(rank), Some(venue)) => rank_html(rank, &venue),
+            _ => String::new(),
+        };
+        let rendered = format!(
+            "<header><h1>{}</h1><cite>{} <em>{}</em></cite>{}{}{}</header>\n{}",
+            html::escape(&entry.title),
+            html::escape(&bib::author_year(&entry.author, &entry.year)),
+            html::escape(&entry.title),
+            rank_badge,
+            nav,
+            byline,
+            body
+        );
+
+        // run handlebars
+        let mut data = Map::new();
+        data.insert("title".to_owned(), Value::String(entry.title.clone()));
+        let content = Some(rendered.clone()).filter(|_| config.all || config.all_pdf);
+        data.insert("content".to_owned(), Value::String(rendered));
+        let backlinks = self.page_links(self.backlinks.get(key), &root);
+        data.insert("backlinks".to_owned(), Value::Array(backlinks));
+        let related = self.page_links(self.related.get(key), &root);
+        data.insert("related".to_owned(), Value::Array(related));
+        data.insert("toc".to_owned(), Value::Array(toc));
+        if !contributors.is_empty() {
+            let names = contributors.iter().cloned().map(Value::String).collect();
+            data.insert("contributors".to_owned(), Value::Array(names));
+            data.insert("notes_by".to_owned(), Value::String(notes_by));
+            let authors = contributors.iter().map(|name| {
+                let mut author = Map::new();
+                author.insert("name".to_owned(), Value::String(name.clone()));
+                let link = format!("{}{}", root, contributors::url(name));
+                author.insert("link".to_owned(), Value::String(link));
+                Value::Object(author)
+            });
+            data.insert("note_authors".to_owned(), Value::Array(authors.collect()));
+        }
+        data.insert("root".to_owned(), Value::String(root.clone()));
+        let files = attachments.folder.iter().map(|path| {
+            let mut file = Map::new();
+            let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            file.insert("name".to_owned(), Value::String(name));
+            let link = format!("{}{}", root, path.to_string_lossy().replace(' ', "%20"));
+            file.insert("link".to_owned(), Value::String(link));
+            Value::Object(file)
+        }).collect();
+        data.insert("attachments".to_owned(), Value::Array(files));
+        let fields = [
+            ("abstract", entry.text_field("abstract")),
+            ("venue", entry.venue()),
+            ("pages", entry.text_field("pages")),
+            ("organization", entry.text_field("organization")),
+            ("version", entry.text_field("version")),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                data.insert(name.to_owned(), Value::String(value));
+            }
+        }
+        if config.venue_pages {
+            if let Some(url) = venues::of(entry) {
+                data.insert("venue_url".to_owned(), Value::String(format!("{}{}", root, url)));
+            }
+        }
+        // when a page online was looked at
+        if let Some(urldate) = entry.text_field("urldate") {
+            if let Ok(date) = dates::parse_civil(&urldate) {
+                data.insert("urldate_date".to_owned(), Value::String(config.text.date(date)));
+            }
+            data.insert("urldate".to_owned(), Value::String(urldate));
+        }
+        if let Some(citations) = entry.citations() {
+            data.insert("citations".to_owned(), Value::U64(citations));
+        }
+        if let Some(rank) = rank {
+            data.insert("venue_rank".to_owned(), Value::String(rank));
+        }
+        let urls = [
+            ("doi", entry.doi_url()),
+            ("arxiv", entry.arxiv_url()),
+            ("url", entry.url()),
+        ];
+        for (name, url) in urls {
+            if let Some(url) = url {
+                data.insert(name.to_owned(), Value::String(url));
+            }
+        }
+        data.insert("links".to_owned(), links_data(&links));
+        let extra = entry.extra_fields();
+        data.insert("extra".to_owned(), extra_data(&extra));
+        if let Some(ref pdf) = pdf_url {
+            data.insert("pdf_url".to_owned(), Value::String(format!("{}{}", root, pdf)));
+        }
+        insert_reading(&mut data, &reading, &config.text);
+        let origin = provenance::of(&self.provenance, key);
+        data.insert("provenance".to_owned(), Value::String(origin.source.name().to_owned()));
+        let label = config.text.get(&format!("source_{}", origin.source.name())).to_owned();
+        data.insert("provenance_label".to_owned(), Value::String(label));
+        if let Some(ref added) = origin.added {
+            data.insert("added".to_owned(), Value::String(added.clone()));
+            if let Ok(date) = dates::parse_civil(added) {
+                data.insert("added_date".to_owned(), Value::String(config.text.date(date)));
+            }
+        }
+        let updated = self.updated.get(key).cloned();
+        insert_updated(&mut data, updated, &config.text);
+        let created = self.created.get(key).cloned();
+        insert_created(&mut data, created, &config.text);
+        if self.drafts.contains(key) {
+            data.insert("draft".to_owned(), Value::Bool(true));
+        }
+        let url = config.base_url.as_ref()
+            .map(|base| format!("{}{}", sitemap::normalize_base(base), html_name));
+        let meta = PageMeta::new(entry, &self.notes[key], url, config.description_length);
+        meta.insert_into(&mut data);
+
+        Ok(Some((data, self.index_line(entry, excerpt, content))))
+    }
+
+    // the entry's line in the index, given what only converting its note
+    // tells: the excerpt and, for all.html, the page's content. The rest
+    // comes from the entry and the note's source alone.
+    fn index_line(
+        &self,
+        entry: &Entry,
+        excerpt: Option<String>,
+        content: Option<String>,
+    ) -> IndexEntry {
+        let config = self.config;
+        let key = &entry.key[..];
+        let note = &self.notes[key];
+        let pdf_url = self.pdfs.get(key)
+            .map(|_| pdfs::path(key).to_string_lossy().replace(' ', "%20"));
+        // `page` has already warned about front matter it couldn't read
+        let (reading, _) = Reading::from_fields(&frontmatter::fields(note));
+        let meta = PageMeta::new(entry, note, None, config.description_length);
+        IndexEntry {
+            key: key.to_owned(),
+            link: self.pages[key].clone(),
+            author: entry.author.clone(),
+            author_sort: entry.author_sort_key(),
+            year: entry.year.clone(),
+            title: entry.title.clone(),
+            // the index is at the top of the site
+            links: entry.links().into_iter()
+                .chain(pdf_url.iter().map(|pdf| Link { label: "PDF", url: pdf.clone() }))
+                .collect(),
+            pdf_url,
+            abstract_text: entry.text_field("abstract"),
+            reading,
+            draft: self.drafts.contains(key),
+            updated: self.updated.get(key).cloned(),
+            created: self.created.get(key).cloned(),
+            description: meta.description,
+            excerpt,
+            citations: entry.citations(),
+            rank: venue_rank(config, entry).zip(entry.venue()),
+            extra: entry.extra_fields(),
+            content,
+        }
+    }
+
+    // a note, with its references already rewritten, as HTML
+    fn body_html(&self, entry: &Entry, note: &str) -> Result<String, Error> {
+        let config = self.config;
+        // `clean` only needs to know which pages there are
+        if self.output.planning() {
+            return Ok(String::new());
+        }
+        let note = hooks::before(config, entry, note)?;
+        let body = match config.renderer {
+            Renderer::Pandoc if config.pandoc.cache => cache::to_html(config, &note)?,
+            _ => render::to_html(config, &note)?,
+        };
+        let mut body = hooks::after(config, entry, body)?;
+        if config.math.prerender {
+            body = math::prerender(&body, &config.math.katex_path)?;
+        }
+        if let Some(ref highlighter) = self.highlighter {
+            body = highlighter.highlight_html(&body);
+        }
+        Ok(body)
+    }
+
+    // the schedule page, a page for each session and the calendar
+    fn build_schedule(&self, schedule: &Schedule) {
+        let config = self.config;
+        let sources = [&schedule.path, &config.template_path, Path::new(&config.bib_path)];
+        let base = config.base_url.as_ref().map(|b| sitemap::normalize_base(b));
+
+        let mut data = Map::new();
+        data.insert("title".to_owned(), Value::String(schedule.name.clone()));
+        let content = schedule::page(schedule, self.bib, &self.pages, &config.text);
+        data.insert("content".to_owned(), Value::String(content));
+        let rendered = self.render_or_exit(Layout::Default, &data);
+        self.output.write(Path::new("schedule.html"), &format!("{}\n", rendered), &sources)
+            .expect("Could not write the schedule page");
+
+        for session in &schedule.sessions {
+            let root = urls::root_for(&session.url);
+            let mut notes = HashMap::new();
+            for key in &session.keys {
+                let (entry, note) = match (self.bib.get(key), self.notes.get(key)) {
+                    (Some(entry), Some(note)) => (entry, note),
+                    _ => continue,
+                };
+                // references were already reported when building the note's page
+                let (note, _) = xref::rewrite(note, self.bib, &self.pages, &root);
+                match self.body_html(entry, &note) {
+                    Ok(body) => {
+                        notes.insert(key.clone(), body);
+                    },
+                    Err(e) => {
+                        Diagnostic::warning(format!("leaving out the note on `{}`", key))
+                            .note(e.to_string())
+                            .emit();
+                    },
+                }
+            }
+            let content = schedule::session_page(
+                schedule,
+                session,
+                self.bib,
+                &self.pages,
+                &notes,
+                &config.text
+            );
+            let mut data = Map::new();
+            data.insert("title".to_owned(), Value::String(session.title.clone()));
+            data.insert("content".to_owned(), Value::String(content));
+            data.insert("root".to_owned(), Value::String(root));
+            let rendered = self.render_or_exit(Layout::Default, &data);
+            let path = urls::file_for(&session.url);
+            self.output.write(&path, &format!("{}\n", rendered), &sources)
+                .expect("Could not write session page");
+        }
+
+        let calendar = schedule::calendar(schedule, self.bib, base.as_ref().map(|b| &b[..]));
+        self.output.write(Path::new("schedule.ics"), &calendar, &sources[..1])
+            .expect("Could not write the schedule's calendar");
+    }
+}
+
+// leaves out of `notes` the ones there are no pages of, returning the keys
+// of the drafts
+fn published(
+    config: &Config,
+    bib: &Bibliography,
+    notes: &mut HashMap<String, String>,
+) -> HashSet<String> {
+    // drafts are left out altogether unless asked for; even then they're
+    // kept out of the sitemap
+    let drafts: HashSet<String> = notes.iter()
+        .filter(|&(key, note)| is_draft(config, key, note))
+        .map(|(key, _)| key.clone())
+        .collect();
+    if !config.drafts {
+        notes.retain(|key, _| !drafts.contains(key));
+    }
+    // the public site leaves out internal notes, so nothing links to them
+    if config.profile == Profile::Public {
+        let before = notes.len();
+        notes.retain(|key, note| !bib.get(key).is_some_and(|e| is_internal(e, note)));
+        debug!("left out {} internal notes", before - notes.len());
+    }
+    drafts
+}
+
+// the entries a build makes pages of, in the index's order, with their
+// notes. Notes that can't be read are reported and left out.
+pub fn index_notes<'a>(config: &Config, bib: &'a Bibliography) -> Vec<(&'a Entry, String)> {
+    let (mut notes, _) = read_notes(config, bib);
+    published(config, bib, &mut notes);
+    let mut entries: Vec<(&Entry, String, String)> = bib.entries.iter()
+        .filter_map(|e| Some((e, notes.remove(&e.key)?, e.author_sort_key())))
+        .collect();
+    sort_like_index(&mut entries, config, |e| (&e.2, &e.0.year, &e.0.title, e.0.citations()));
+    entries.into_iter().map(|(entry, note, _)| (entry, note)).collect()
+}
+
+// reads the notes of every entry that has one. Notes that can't be read are
+// reported and returned as failures.
+pub fn read_notes(
+    config: &Config,
+    bib: &Bibliography,
+) -> (HashMap<String, String>, Vec<(String, Error)>) {
+    let mut notes = HashMap::new();
+    let mut failures = Vec::new();
+    for entry in &bib.entries {
+        let key = &entry.key;
+        let md_path = note_path(config, key);
+        let read = if md_path.is_file() {
+            let mut source = String::new();
+            match File::open(&md_path).and_then(|mut f| f.read_to_string(&mut source)) {
+                Err(e) => Err(Error::Read(md_path, e)),
+                // everything else about notes is markdown
+                Ok(_) => match note_format(config, &md_path) {
+                    Some(format) => render::to_markdown(&config.pandoc, format, &source)
+                        .map_err(Error::Pandoc),
+                    None => Ok(source),
+                },
+            }
+        } else {
+            // encrypted notes are only for the internal site
+            let locked = encrypted::path(config, key);
+            if !locked.is_file() || config.profile == Profile::Public {
+                continue;
+            }
+            encrypted::decrypt(config, &locked).map_err(|e| Error::Decrypt(locked, e))
+        };
+        match read {
+            Ok(source) => {
+                notes.insert(key.clone(), source);
+            },
+            Err(e) => {
+                let d = Diagnostic::error(format!("could not read the notes on `{}`", key))
+                    .note(e.to_string());
+                bib.diagnostic_at(d, key, "in this entry").emit();
+                failures.push((key.clone(), e));
+            },
+        }
+    }
+    (notes, failures)
+}
+
+// the reading status and rating, as template data
+fn insert_reading(data: &mut Map<String, Value>, reading: &Reading, text: &Text) {
+    if let Some(status) = reading.status {
+        data.insert("status".to_owned(), Value::String(status.name().to_owned()));
+        let label = text.get(status.text_id()).to_owned();
+        data.insert("status_label".to_owned(), Value::String(label));
+    }
+    if let Some(rating) = reading.rating {
+        data.insert("rating".to_owned(), Value::U64(rating as u64));
+        data.insert("stars".to_owned(), Value::String(reading::stars(rating)));
+    }
+}
+
+// when a note last changed, as `YYYY-MM-DD` and in the site's date format
+fn insert_updated(data: &mut Map<String, Value>, updated: Option<SystemTime>, text: &Text) {
+    if let Some(time) = updated {
+        data.insert("updated".to_owned(), Value::String(dates::ymd(time)));
+        data.insert("updated_date".to_owned(), Value::String(text.date(dates::civil(time))));
+    }
+}
+
+// when a note was written, the same way
+fn insert_created(data: &mut Map<String, Value>, created: Option<SystemTime>, text: &Text) {
+    if let Some(time) = created {
+        data.insert("created".to_owned(), Value::String(dates::ymd(time)));
+        data.insert("created_date".to_owned(), Value::String(text.date(dates::civil(time))));
+    }
+}
+
+// the template data for the entries of the index. Links are relative to the
+// index, and there's also a full `url` when the base URL is known.
+fn index_entries(index: &[&IndexEntry], config: &Config) -> Vec<Value> {
+    let base = config.base_url.as_ref().map(|b| sitemap::normalize_base(b));
+    index.iter().map(|e| {
+        let mut entry = Map::new();
+        let fields = [
+            ("key", &e.key),
+            ("link", &e.link),
+            ("title", &e.title),
+            ("author", &e.author),
+            ("year", &e.year),
+        ];
+        for (name, value) in fields {
+            entry.insert(name.to_owned(), Value::String(value.clone()));
+        }
+        if let Some(ref base) = base {
+            entry.insert("url".to_owned(), Value::String(format!("{}{}", base, e.link)));
+        }
+        if let Some(ref abs) = e.abstract_text {
+            entry.insert("abstract".to_owned(), Value::String(abs.clone()));
+        }
+        if let Some(ref description) = e.description {
+            entry.insert("description".to_owned(), Value::String(description.clone()));
+        }
+        if let Some(ref excerpt) = e.excerpt {
+            entry.insert("excerpt".to_owned(), Value::String(excerpt.clone()));
+        }
+        entry.insert("links".to_owned(), links_data(&e.links));
+        entry.insert("extra".to_owned(), extra_data(&e.extra));
+        if let Some(ref pdf) = e.pdf_url {
+            entry.insert("pdf_url".to_owned(), Value::String(pdf.clone()));
+        }
+        insert_reading(&mut entry, &e.reading, &config.text);
+        insert_updated(&mut entry, e.updated, &config.text);
+        if let Some(citations) = e.citations {
+            entry.insert("citations".to_owned(), Value::U64(citations));
+        }
+        if let Some((ref rank, _)) = e.rank {
+            entry.insert("venue_rank".to_owned(), Value::String(rank.clone()));
+        }
+        insert_created(&mut entry, e.created, &config.text);
+        Value::Object(entry)
+    }).collect()
+}
+
+// the template data of each page of the index, from its lines in order
+fn index_pages(index: &[IndexEntry], config: &Config) -> Vec<Map<String, Value>> {
+    let all: Vec<&IndexEntry> = index.iter().collect();
+    // an empty index still has a page
+    let pages: Vec<&[&IndexEntry]> = match config.index_page_size {
+        Some(size) if !all.is_empty() => all.chunks(size).collect(),
+        _ => vec![&all[..]],
+    };
+    let header = index_header(&config.text);
+    // the notes that changed last, for the top of the index
+    let mut recent = all.clone();
+    recent.retain(|e| e.updated.is_some());
+    recent.sort_by_key(|e| ::std::cmp::Reverse(e.updated));
+    recent.truncate(config.recent);
+    let recent = index_entries(&recent, config);
+    // and the most cited
+    let mut influential = all.clone();
+    influential.retain(|e| e.citations.is_some());
+    influential.sort_by_key(|e| ::std::cmp::Reverse(e.citations));
+    influential.truncate(config.influential);
+    let influential = index_entries(&influential, config);
+    pages.iter().enumerate().map(|(i, page)| {
+        let (n, count) = (i + 1, pages.len());
+        let mut index_contents = make_index(page, &header, config);
+        let mut data = Map::new();
+        let title = config.text.get("index_title").to_owned();
+        data.insert("title".to_owned(), Value::String(title));
+        if count > 1 {
+            index_contents.push_str(&pagination_html(n, count, index_file, &config.text));
+            data.insert("pagination".to_owned(), pagination_data(n, count, index_file));
+        }
+        data.insert("content".to_owned(), Value::String(index_contents));
+        // the entries themselves, for templates that lay out the index their own way
+        data.insert("entries".to_owned(), Value::Array(index_entries(page, config)));
+        data.insert("recent".to_owned(), Value::Array(recent.clone()));
+        data.insert("influential".to_owned(), Value::Array(influential.clone()));
+        data
+    }).collect()
+}
+
+// the latest notes, newest first, as lines of a page
+fn make_latest(latest: &[&IndexEntry], header: &str, config: &Config) -> String {
+    let mut content = String::from(header);
+    content.push_str("<ol class=\"latest\">\n");
+    for e in latest {
+        let created = match e.created {
+            Some(created) => created,
+            None => continue,
+        };
+        fmt::write(
+            &mut content,
+            format_args!(
+                "  <li><time datetime=\"{}\">{}</time> <a href=\"{}\">{}</a>{}<br>\n  {}\n",
+                dates::ymd(created),
+                html::escape(&config.text.date(dates::civil(created))),
+                html::escape(&e.link),
+                html::escape(&e.title),
+                badges_html(e, &config.text),
+                html::escape(&bib::author_year(&e.author, &e.year)),
+            )
+        ).unwrap();
+        if let Some(ref description) = e.description {
+            let description = html::escape(description);
+            fmt::write(&mut content, format_args!("  <p>{}</p>\n", description)).unwrap();
+        }
+    }
+    content.push_str("</ol>");
+    content
+}
+
+// the template data of each page of the latest notes, newest first by when
+// they were written, apart from the index's order
+fn latest_pages(
+    index: &[IndexEntry],
+    size: usize,
+    config: &Config,
+) -> Vec<Map<String, Value>> {
+    let mut latest: Vec<&IndexEntry> = index.iter().filter(|e| e.created.is_some()).collect();
+    latest.sort_by_key(|e| ::std::cmp::Reverse(e.created));
+    let pages: Vec<&[&IndexEntry]> = if latest.is_empty() {
+        vec![&latest[..]]
+    } else {
+        latest.chunks(size).collect()
+    };
+    let title = config.text.get("latest");
+    let header = format!("<header>\n  <h1>{}</h1>\n</header>\n", html::escape(title));
+    pages.iter().enumerate().map(|(i, page)| {
+        let (n, count) = (i + 1, pages.len());
+        let mut content = make_latest(page, &header, config);
+        let mut data = Map::new();
+        data.insert("title".to_owned(), Value::String(title.to_owned()));
+        if count > 1 {
+            content.push_str(&pagination_html(n, count, latest_file, &config.text));
+            data.insert("pagination".to_owned(), pagination_data(n, count, latest_file));
+        }
+        data.insert("content".to_owned(), Value::String(content));
+        data.insert("entries".to_owned(), Value::Array(index_entries(page, config)));
+        data
+    }).collect()
+}
+
+// what a build did, for the caller to summarise
+pub struct Built {
+    // the entries of the bibliography, and how many of them have a page
+    pub entries: usize,
+    pub notes: usize,
+    // the note pages built, and every file written, those pages among them
+    pub pages: usize,
+    pub files: usize,
+    pub failures: Vec<(String, Error)>,
+}
+
+// builds the whole site. Failures in individual entries are reported and the
+// entry is left out of the index, keeping whatever page an earlier build gave
+// it, so that one broken note doesn't take down the rest.
+pub fn build(config: &Config) -> Built {
+    build_to(config, if config.dry_run { Mode::DryRun } else { Mode::Write })
+}
+
+// everything the template gets for the page on `key`, for testing templates
+// against, or `None` if the entry has no page
+pub fn fixture(config: &Config, key: &str) -> Result<Option<Value>, Error> {
+    let mut bib = bib::read_bib(&config.bib_path);
+    enrich::apply(&mut bib, &enrich::load(&enrich::cache_path(config)));
+    venues::apply(&mut bib, &config.venue_aliases);
+    let hbs = Handlebars::new();
+    let (site, failures) = Site::new(config, &bib, hbs, Vec::new(), Mode::Discard);
+    if let Some((_, e)) = failures.into_iter().find(|(failed, _)| failed == key) {
+        return Err(e);
+    }
+    let entry = match bib.get(key) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let page = site.page(entry)?;
+    Ok(page.map(|(data, _)| Value::Object(site.context(&data))))
+}
+
+// the template data of every note page and of every page of the index, as
+// a build would give it to the template
+pub fn template_data(config: &Config) -> (Vec<Value>, Vec<Value>) {
+    let mut bib = bib::read_bib(&config.bib_path);
+    enrich::apply(&mut bib, &enrich::load(&enrich::cache_path(config)));
+    venues::apply(&mut bib, &config.venue_aliases);
+    let (site, _) = Site::new(config, &bib, Handlebars::new(), Vec::new(), Mode::Discard);
+    let mut pages = Vec::new();
+    let mut index = Vec::new();
+    for entry in bib.entries.iter().filter(|e| site.notes.contains_key(&e.key)) {
+        if let Ok(Some((data, line))) = site.page(entry) {
+            pages.push(Value::Object(site.context(&data)));
+            index.push(line);
+        }
+    }
+    sort_index(&mut index, config);
+    let index_pages = index_pages(&index, config).iter()
+        .map(|data| Value::Object(site.context(data)))
+        .collect();
+    (pages, index_pages)
+}
+
+// removes what earlier builds wrote that a build now wouldn't, without
+// building anything
+pub fn clean(config: &Config) -> Vec<(String, Error)> {
+    build_to(config, Mode::Plan).failures
+}
+
+fn build_to(config: &Config, mode: Mode) -> Built {
+    // read .bib file
+    let mut bib = bib::read_bib(&config.bib_path);
+    // fill in missing fields from whatever `enrich` found
+    enrich::apply(&mut bib, &enrich::load(&enrich::cache_path(config)));
+    venues::apply(&mut bib, &config.venue_aliases);
+
+    // register handlebars templates, unless only fragments are written
+    let mut hbs = Handlebars::new();
+    let templates = if config.fragments {
+        Vec::new()
+    } else {
+        register_templates(config, &mut hbs)
+    };
+
+    let (site, mut failures) = Site::new(config, &bib, hbs, templates, mode);
+    // the pages a partial build leaves alone are still the site's
+    let partial = !config.filter.is_empty();
+    if partial {
+        site.output.keep_previous();
+    }
+    // the entries the bibliography had but couldn't be read, for the summary
+    if mode != Mode::Plan {
+        let skipped = bib.skipped.iter()
+            .map(|(key, line)| (key.clone(), Error::Malformed(*line)));
+        failures.extend(skipped);
+    }
+    // the index and other pages made from the whole bibliography
+    let sources = [config.template_path.as_path(), Path::new(&config.bib_path)];
+    let output = &site.output;
+
+    // written where it's asked for, like `--report`, rather than as part of
+    // the site; but not by dry runs, which change nothing
+    if let Some(path) = config.emit_json.as_ref().filter(|_| mode == Mode::Write) {
+        let json = model::json(&bib, &site.notes, &site.pages, &site.backlinks, config);
+        fs::write(path, format!("{}\n", json)).expect("Could not write the site model");
+    }
+
+    // this vector will be used to create an index for the notes
+    let mut index = Vec::new();
+    let mut converted = if partial { read_converted(config) } else { HashMap::new() };
+    let needs_content = config.all || config.all_pdf;
+
+    // output all the individual files
+    let started = Instant::now();
+    let mut pages = 0;
+    let mut progress = Progress::new(site.notes.len());
+    for entry in bib.entries.iter().filter(|e| site.notes.contains_key(&e.key)) {
+        let key = &entry.key;
+        progress.start(key);
+        let entry_started = Instant::now();
+        let wanted = config.filter.matches(entry);
+        let built = if wanted {
+            site.build_entry(entry)
+        } else if config.filter_index {
+            Ok(None)
+        } else {
+            // still in the index, as it was when it was last converted; only
+            // a note that never has been is converted now
+            match converted.get(key).filter(|c| c.1.is_some() || !needs_content) {
+                Some((excerpt, content)) => {
+                    Ok(Some(site.index_line(entry, excerpt.clone(), content.clone())))
+                },
+                None => site.page(entry).map(|page| page.map(|(_, line)| line)),
+            }
+        };
+        debug!("built `{}` in {:.1?}", key, entry_started.elapsed());
+        progress.finish_one();
+        match built {
+            Ok(Some(line)) => {
+                pages += wanted as usize;
+                index.push(line);
+            },
+            Ok(None) => {},
+            Err(e) => {
+                let d = Diagnostic::error(format!("could not build `{}`", key))
+                    .note(e.to_string());
+                bib.diagnostic_at(d, key, "in this entry").emit();
+                failures.push((key.clone(), e));
+            },
+        }
+    }
+
+    progress.finish();
+    info!("built {} pages in {:.1?}", pages, started.elapsed());
+
+    // with what the notes left out of the index last had
+    converted.retain(|key, _| site.notes.contains_key(key));
+    for e in &index {
+        converted.insert(e.key.clone(), (e.excerpt.clone(), e.content.clone()));
+    }
+
+    // now build the index
+    sort_index(&mut index, config);
+    let index_pages = index_pages(&index, config);
+    for (i, data) in index_pages.iter().enumerate() {
+        let rendered_index = site.render_or_exit(Layout::Index, data);
+
+        // write output
+        let path = PathBuf::from(index_file(i + 1));
+        output.write(&path, &format!("{}\n", rendered_index), &sources)
+            .expect("Could not write to index file");
+    }
+    // and the same entries again, newest first
+    let latest_notes = config.latest.map(|size| latest_pages(&index, size, config));
+    for (i, data) in latest_notes.iter().flatten().enumerate() {
+        let rendered = site.render_or_exit(Layout::Index, data);
+        let path = PathBuf::from(latest_file(i + 1));
+        output.write(&path, &format!("{}\n", rendered), &sources)
+            .expect("Could not write the latest notes");
+    }
+    // and every note on one page
+    if config.all || config.all_pdf {
+        let chapters: Vec<book::Chapter> = index.iter()
+            .filter_map(|e| Some(book::Chapter {
+                key: &e.key,
+                link: &e.link,
+                title: &e.title,
+                author: &e.author,
+                year: &e.year,
+                content: e.content.as_ref()?,
+            }))
+            .collect();
+        let content = book::page(&chapters, &config.text);
+        let title = config.text.get("index_title");
+        let mut data = Map::new();
+        data.insert("title".to_owned(), Value::String(title.to_owned()));
+        data.insert("content".to_owned(), Value::String(content.clone()));
+        let rendered = site.render_or_exit(Layout::Default, &data);
+        output.write(Path::new("all.html"), &format!("{}\n", rendered), &sources)
+            .expect("Could not write all.html");
+        if config.all_pdf {
+            // pandoc needs the images, so they have to be written already
+            let pdf = if output.planning() {
+                Ok(Vec::new())
+            } else {
+                render::to_pdf(&config.pandoc, title, &content, &output.dirs())
+            };
+            match pdf {
+                Ok(pdf) => output.write_bytes(Path::new("all.pdf"), &pdf, &sources)
+                    .expect("Could not write all.pdf"),
+                Err(e) => {
+                    Diagnostic::warning("could not make all.pdf").note(e.to_string()).emit()
+                },
+            }
+        }
+    }
+    // and every entry for other sites to embed
+    let all: Vec<&IndexEntry> = index.iter().collect();
+    let mut json = Map::new();
+    json.insert("entries".to_owned(), Value::Array(index_entries(&all, config)));
+    let json = serde_json::to_string_pretty(&json).expect("Could not serialise index");
+    output.write(Path::new("index.json"), &format!("{}\n", json), &sources)
+        .expect("Could not write index.json");
+
+    if config.coverage != CoverageOutput::None {
+        let coverage = coverage::compute(&bib, &site.pages);
+        let json = format!("{}\n", coverage::json(&coverage));
+        output.write(Path::new("coverage.json"), &json, &sources)
+            .expect("Could not write coverage data");
+        if config.coverage == CoverageOutput::Page {
+            let mut data = Map::new();
+            let title = config.text.get("coverage").to_owned();
+            data.insert("title".to_owned(), Value::String(title));
+            let content = coverage::page(&coverage, &config.text);
+            data.insert("content".to_owned(), Value::String(content));
+            let rendered = site.render_or_exit(Layout::Default, &data);
+            output.write(Path::new("coverage.html"), &format!("{}\n", rendered), &sources)
+                .expect("Could not write coverage page");
+        }
+    }
+
+    if config.stats {
+        let stats = statistics::compute(&bib, &site.notes, &site.pages);
+        let json = format!("{}\n", statistics::json(&stats));
+        output.write(Path::new("stats.json"), &json, &sources)
+            .expect("Could not write statistics");
+        let mut data = Map::new();
+        let title = config.text.get("statistics").to_owned();
+        data.insert("title".to_owned(), Value::String(title));
+        let content = statistics::page(&stats, &config.text);
+        data.insert("content".to_owned(), Value::String(content));
+        let rendered = site.render_or_exit(Layout::Default, &data);
+        output.write(Path::new("stats.html"), &format!("{}\n", rendered), &sources)
+            .expect("Could not write statistics page");
+    }
+
+    if config.graph {
+        let graph = graph::compute(&bib, &site.notes, &site.pages);
+        let json = graph::json(&graph, &bib, &site.pages);
+        let pretty = serde_json::to_string_pretty(&json)
+            .expect("Could not serialise the graph");
+        output.write(Path::new("graph.json"), &format!("{}\n", pretty), &sources)
+            .expect("Could not write graph data");
+        let mut data = Map::new();
+        data.insert("title".to_owned(), Value::String(config.text.get("graph").to_owned()));
+        data.insert("content".to_owned(), Value::String(graph::page(&json, &config.text)));
+        let rendered = site.render_or_exit(Layout::Default, &data);
+        output.write(Path::new("graph.html"), &format!("{}\n", rendered), &sources)
+            .expect("Could not write graph page");
+    }
+
+    if config.contributors {
+        let mut data = Map::new();
+        let title = config.text.get("contributors").to_owned();
+        data.insert("title".to_owned(), Value::String(title));
+        let content = contributors::page(&bib, &site.contributors, &site.pages, &config.text);
+        data.insert("content".to_owned(), Value::String(content));
+        let rendered = site.render_or_exit(Layout::Author, &data);
+        output.write(Path::new("contributors.html"), &format!("{}\n", rendered), &sources)
+            .expect("Could not write contributors page");
+        for (name, keys) in contributors::people(&bib, &site.contributors, &site.pages) {
+            let mut data = Map::new();
+            data.insert("title".to_owned(), Value::String(name.to_owned()));
+            let content =
+                contributors::person_page(&bib, name, &keys, &site.pages, &config.text);
+            data.insert("content".to_owned(), Value::String(content));
+            data.insert("root".to_owned(), Value::String("../".to_owned()));
+            let rendered = site.render_or_exit(Layout::Author, &data);
+            let path = PathBuf::from(contributors::url(name));
+            output.write(&path, &format!("{}\n", rendered), &sources)
+                .expect("Could not write a contributor's page");
+        }
+    }
+
+    if config.venue_pages {
+        for (venue, keys) in venues::venues(&bib, &site.pages) {
+            let mut data = Map::new();
+            let content = venues::page(&bib, &venue, &keys, &site.pages, &config.text);
+            data.insert("title".to_owned(), Value::String(venue.clone()));
+            data.insert("content".to_owned(), Value::String(content));
+            data.insert("root".to_owned(), Value::String("../".to_owned()));
+            let rendered = site.render_or_exit(Layout::Default, &data);
+            let path = PathBuf::from(venues::url(&venue));
+            output.write(&path, &format!("{}\n", rendered), &sources)
+                .expect("Could not write a venue's page");
+        }
+    }
+
+    // pages for keys `fmt --fix-keys` renamed, sending visitors on
+    for (old, new) in keys::redirects(config) {
+        let url = match site.pages.get(&new) {
+            Some(url) if !site.pages.contains_key(&old) => url,
+            _ => continue,
+        };
+        let from = match urls::key_url(&old, config.url_style) {
+            Some(from) => from,
+            None => continue,
+        };
+        let target = html::escape(&format!("{}{}", urls::root_for(&from), url));
+        let link = format!("<a href=\"{0}\">{0}</a>", target);
+        let page = format!(
+            "<!DOCTYPE html>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <link rel=\"canonical\" href=\"{2}\">\n\
+             <meta http-equiv=\"refresh\" content=\"0; url={2}\">\n\
+             <p>{}</p>\n",
+            html::escape(config.text.get("moved")),
+            html::escape(config.text.get("moved_to")).replace("{link}", &link),
+            target
+        );
+        output.write(&urls::file_for(&from), &page, &[])
+            .expect("Could not write redirect page");
+    }
+
+    // a page for each reading status that notes have, like to-read.html
+    for &status in reading::STATUSES {
+        let entries: Vec<&IndexEntry> = index.iter()
+            .filter(|e| e.reading.status == Some(status))
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+        let label = config.text.get(status.text_id());
+        let header = format!("<header>\n  <h1>{}</h1>\n</header>\n", html::escape(label));
+        let mut data = Map::new();
+        data.insert("title".to_owned(), Value::String(label.to_owned()));
+        data.insert("content".to_owned(), Value::String(make_index(&entries, &header, config)));
+        data.insert("entries".to_owned(), Value::Array(index_entries(&entries, config)));
+        let rendered = site.render_or_exit(Layout::Default, &data);
+        let path = PathBuf::from(format!("{}.html", status.name()));
+        output.write(&path, &format!("{}\n", rendered), &sources)
+            .expect("Could not write reading status page");
+    }
+
+    let mut schedule = schedule::load(config, &bib);
+    if let Some(ref mut schedule) = schedule {
+        if config.reproducible {
+            schedule.modified = now(config);
+        }
+    }
+    if let Some(ref schedule) = schedule {
+        site.build_schedule(schedule);
+    }
+
+    if config.anniversaries {
+        let base = config.base_url.as_ref().map(|b| sitemap::normalize_base(b));
+        let published: HashMap<String, String> = site.pages.iter()
+            .filter(|&(key, _)| !site.drafts.contains(key))
+            .map(|(key, url)| (key.clone(), url.clone()))
+            .collect();
+        let base = base.as_ref().map(|b| &b[..]);
+        let calendar = anniversaries::calendar(&bib, &published, base, now(config));
+        output.write(Path::new("anniversaries.ics"), &calendar, &sources)
+            .expect("Could not write the anniversaries calendar");
+    }
+
+    // the worklist of TODOs, which isn't linked from anywhere, and is never
+    // published: it's for the author's eyes
+    if config.todo_page && config.profile == Profile::Public {
+        Diagnostic::warning("not writing todo.html, since the site is public").emit();
+    } else if config.todo_page {
+        let mut data = Map::new();
+        data.insert("title".to_owned(), Value::String(config.text.get("todo").to_owned()));
+        let content = todo::page(&bib, &site.notes, &site.pages, &config.text);
+        data.insert("content".to_owned(), Value::String(content));
+        let rendered = site.render_or_exit(Layout::Default, &data);
+        output.write(Path::new("todo.html"), &format!("{}\n", rendered), &sources)
+            .expect("Could not write to TODO page");
+    }
+
+    if let Some(ref base) = config.base_url {
+        let base = sitemap::normalize_base(base);
+        // pages change when their notes do
+        let modified = |key: &str| site.updated.get(key).cloned();
+        let mut pages = Vec::new();
+        let published = |e: &&Entry| {
+            site.pages.contains_key(&e.key) && !site.drafts.contains(&e.key)
+        };
+        for entry in bib.entries.iter().filter(published) {
+            pages.push((site.pages[&entry.key].clone(), modified(&entry.key)));
+        }
+        let latest = pages.iter().filter_map(|p| p.1).max();
+        pages.insert(0, (String::new(), latest));
+        for n in 2..=index_pages.len() {
+            pages.insert(n - 1, (index_file(n), latest));
+        }
+        for n in 1..=latest_notes.as_ref().map_or(0, |p| p.len()) {
+            pages.push((latest_file(n), latest));
+        }
+        if config.all || config.all_pdf {
+            pages.push(("all.html".to_owned(), latest));
+        }
+        if config.coverage == CoverageOutput::Page {
+            pages.push(("coverage.html".to_owned(), latest));
+        }
+        if config.stats {
+            pages.push(("stats.html".to_owned(), latest));
+        }
+        if config.graph {
+            pages.push(("graph.html".to_owned(), latest));
+        }
+        if config.contributors {
+            pages.push(("contributors.html".to_owned(), latest));
+            for (name, _) in contributors::people(&bib, &site.contributors, &site.pages) {
+                pages.push((contributors::url(name), latest));
+            }
+        }
+        if config.venue_pages {
+            for (venue, _) in venues::venues(&bib, &site.pages) {
+                pages.push((venues::url(&venue), latest));
+            }
+        }
+        if let Some(ref schedule) = schedule {
+            let modified = Some(schedule.modified);
+            pages.push(("schedule.html".to_owned(), modified));
+            for session in &schedule.sessions {
+                pages.push((session.url.clone(), modified));
+            }
+        }
+        output.write(Path::new("sitemap.xml"), &sitemap::sitemap(&base, &pages), &sources)
+            .expect("Could not write sitemap");
+        output.write(Path::new("robots.txt"), &sitemap::robots(&base), &sources)
+            .expect("Could not write robots.txt");
+    }
+
+    let built = Built {
+        entries: bib.entries.len(),
+        notes: site.pages.len(),
+        pages,
+        files: output.files_made(),
+        failures,
+    };
+    if mode == Mode::DryRun {
+        output.report();
+        return built;
+    }
+    // entries that failed wrote nothing, so they keep the pages they had
+    output.commit().expect("Could not move the built files into place");
+    if mode == Mode::Write {
+        save_converted(config, &converted).expect("Could not write the index's data");
+    }
+    if config.prune || mode == Mode::Plan {
+        // a page that failed to build this time is still wanted
+        if !built.failures.is_empty() {
+            Diagnostic::warning("not removing stale files, since some entries failed").emit();
+        } else if let Err(e) = output.prune() {
+            // `clean` is for nothing else
+            if mode == Mode::Plan {
+                Diagnostic::error(e).emit();
+                ::std::process::exit(1);
+            }
+            Diagnostic::warning("not removing stale files").note(e).emit();
+        }
+    }
+    // and those pages are still ours, to prune once they're no longer wanted
+    if !built.failures.is_empty() {
+        output.keep_previous();
+    }
+    output.save_manifest().expect("Could not write the output manifest");
+    built
+}
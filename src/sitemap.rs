@@ -0,0 +1,38 @@
+use std::fmt::Write;
+use std::time::SystemTime;
+use dates;
+use html;
+
+// The sitemap.xml and robots.txt search engines look for. Both need full
+// URLs, so they're only written when the site's base URL is known.
+
+// `base` with exactly one trailing slash, so page URLs can be appended
+pub fn normalize_base(base: &str) -> String {
+    format!("{}/", base.trim_end_matches('/'))
+}
+
+// a sitemap listing the given pages, as URLs relative to the root of the
+// site and the time each was last changed, if known
+pub fn sitemap(base: &str, pages: &[(String, Option<SystemTime>)]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n"
+    );
+    for &(ref url, modified) in pages {
+        xml.push_str("  <url>\n");
+        writeln!(xml, "    <loc>{}</loc>", html::escape(&format!("{}{}", base, url))).unwrap();
+        if let Some(modified) = modified {
+            writeln!(xml, "    <lastmod>{}</lastmod>", dates::ymd(modified)).unwrap();
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+// lets everything be crawled, and points at the sitemap
+pub fn robots(base: &str) -> String {
+    let mut robots = String::from("User-agent: *\nDisallow:\n");
+    writeln!(robots, "\nSitemap: {}sitemap.xml", base).unwrap();
+    robots
+}
@@ -0,0 +1,129 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use serde_json::{self, Map, Value};
+use bib::Bibliography;
+use coverage::Counts;
+use html;
+use markdown;
+use text::Text;
+
+// An overview of the bibliography, for stats.html and stats.json: how many
+// entries there are of each year, venue, tag and entry type, how many of
+// them have notes, and how long the notes are altogether.
+
+// entries without one of these are counted under this
+const UNKNOWN: &str = "unknown";
+
+pub struct Statistics {
+    pub entries: Counts,
+    // words in the notes of the entries with a page
+    pub words: usize,
+    pub years: BTreeMap<String, Counts>,
+    pub venues: BTreeMap<String, Counts>,
+    pub tags: BTreeMap<String, Counts>,
+    pub kinds: BTreeMap<String, Counts>,
+}
+
+fn or_unknown(s: &str) -> String {
+    if s.trim().is_empty() { UNKNOWN.to_owned() } else { s.trim().to_owned() }
+}
+
+// `pages` holds the keys of the entries with a page
+pub fn compute(
+    bib: &Bibliography,
+    notes: &HashMap<String, String>,
+    pages: &HashMap<String, String>,
+) -> Statistics {
+    let mut stats = Statistics {
+        entries: Counts::default(),
+        words: 0,
+        years: BTreeMap::new(),
+        venues: BTreeMap::new(),
+        tags: BTreeMap::new(),
+        kinds: BTreeMap::new(),
+    };
+    for entry in &bib.entries {
+        let has_note = pages.contains_key(&entry.key);
+        stats.entries.add(has_note);
+        if has_note {
+            let note = notes.get(&entry.key).map_or("", |n| &n[..]);
+            stats.words += markdown::prose(note).split_whitespace().count();
+        }
+        stats.years.entry(or_unknown(&entry.year)).or_default().add(has_note);
+        let venue = or_unknown(&entry.venue().unwrap_or_default());
+        stats.venues.entry(venue).or_default().add(has_note);
+        for tag in entry.tags() {
+            stats.tags.entry(tag.to_lowercase()).or_default().add(has_note);
+        }
+        stats.kinds.entry(or_unknown(&entry.kind)).or_default().add(has_note);
+    }
+    stats
+}
+
+fn counts_json(counts: &BTreeMap<String, Counts>) -> Value {
+    Value::Object(counts.iter().map(|(k, c)| (k.clone(), c.to_json())).collect())
+}
+
+pub fn json(stats: &Statistics) -> String {
+    let mut root = Map::new();
+    root.insert("entries".to_owned(), stats.entries.to_json());
+    root.insert("words".to_owned(), Value::U64(stats.words as u64));
+    root.insert("years".to_owned(), counts_json(&stats.years));
+    root.insert("venues".to_owned(), counts_json(&stats.venues));
+    root.insert("tags".to_owned(), counts_json(&stats.tags));
+    root.insert("types".to_owned(), counts_json(&stats.kinds));
+    serde_json::to_string_pretty(&root).expect("Could not serialise statistics")
+}
+
+// a table of counts, with a bar for each row as wide as its share of the
+// biggest row. Years stay in order, and everything else goes biggest first.
+fn table(
+    content: &mut String,
+    heading: &str,
+    counts: &BTreeMap<String, Counts>,
+    sorted: bool,
+    text: &Text,
+) {
+    let mut rows: Vec<(&String, &Counts)> = counts.iter().collect();
+    if sorted {
+        rows.sort_by(|a, b| b.1.total.cmp(&a.1.total).then_with(|| a.0.cmp(b.0)));
+    }
+    let most = rows.iter().map(|r| r.1.total).max().unwrap_or(0).max(1);
+    write!(
+        content,
+        "<section class=\"stats\">\n  <h2>{0}</h2>\n  <table>\n    \
+         <tr><th>{0}</th><th>{1}</th><th>{2}</th><th></th></tr>\n",
+        html::escape(heading),
+        html::escape(text.get("entries")),
+        html::escape(text.get("notes"))
+    ).unwrap();
+    for (name, c) in rows {
+        writeln!(
+            content,
+            "    <tr><td>{}</td><td>{}</td><td>{}</td>\
+             <td><span class=\"bar\" style=\"width: {:.0}%\"></span></td></tr>",
+            html::escape(name),
+            c.total,
+            c.annotated,
+            100.0 * c.total as f64 / most as f64
+        ).unwrap();
+    }
+    content.push_str("  </table>\n</section>\n");
+}
+
+pub fn page(stats: &Statistics, text: &Text) -> String {
+    let summary = text.get("stats_summary")
+        .replace("{entries}", &stats.entries.total.to_string())
+        .replace("{notes}", &stats.entries.annotated.to_string())
+        .replace("{words}", &stats.words.to_string());
+    let mut content = format!(
+        "<header>\n  <h1>{}</h1>\n  <p>{}</p>\n</header>\n",
+        html::escape(text.get("statistics")),
+        html::escape(&summary)
+    );
+    table(&mut content, text.get("year"), &stats.years, false, text);
+    table(&mut content, text.get("venue"), &stats.venues, true, text);
+    table(&mut content, text.get("tags"), &stats.tags, true, text);
+    table(&mut content, text.get("entry_type"), &stats.kinds, true, text);
+    content
+}
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use bib::Bibliography;
+use markdown;
+
+// Suggests tags that entries are probably missing, from what their notes
+// have in common with the notes of entries that already have those tags.
+// Each candidate tag is scored on three things: how similar the note is to
+// the other notes with that tag (cosine similarity of TF-IDF vectors), how
+// often the tag goes with the entry's existing tags, and whether the note
+// mentions the tag outright.
+
+// how much each signal counts towards a suggestion's score
+const SIMILARITY_WEIGHT: f64 = 0.5;
+const COOCCURRENCE_WEIGHT: f64 = 0.3;
+const MENTION_WEIGHT: f64 = 0.2;
+
+// suggestions scoring less than this are left out
+const THRESHOLD: f64 = 0.15;
+
+// the most suggestions made for a single entry
+const LIMIT: usize = 3;
+
+// words too common to say anything about a note
+const STOPWORDS: &[&str] = &[
+    "about", "also", "and", "are", "because", "been", "but", "can", "could",
+    "does", "for", "from", "had", "has", "have", "how", "into", "its", "more",
+    "most", "not", "one", "only", "other", "our", "paper", "some", "such",
+    "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "those", "through", "two", "use", "used", "uses", "using", "very",
+    "was", "were", "what", "when", "which", "while", "who", "why", "will",
+    "with", "would", "you",
+];
+
+pub struct Suggestion {
+    pub key: String,
+    pub current: Vec<String>,
+    // suggested tags and their scores, best first
+    pub tags: Vec<(String, f64)>,
+}
+
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3 && !STOPWORDS.contains(w))
+        .filter(|w| !w.chars().all(|c| c.is_numeric()))
+        .collect()
+}
+
+type Vector = HashMap<String, f64>;
+
+fn normalise(v: &mut Vector) {
+    let norm = v.values().map(|x| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        for x in v.values_mut() {
+            *x /= norm;
+        }
+    }
+}
+
+fn dot(a: &Vector, b: &Vector) -> f64 {
+    let (small, large) = if a.len() < b.len() { (a, b) } else { (b, a) };
+    small.iter().map(|(w, x)| x * large.get(w).unwrap_or(&0.0)).sum()
+}
+
+// whether `tag` appears in `text` as a whole word or phrase
+fn mentions(text: &str, tag: &str) -> bool {
+    text.match_indices(tag).any(|(i, m)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + m.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+pub fn suggest(bib: &Bibliography, notes: &HashMap<String, String>) -> Vec<Suggestion> {
+    // TF-IDF vectors of every note, with the title counted as part of it
+    let texts: HashMap<&str, String> = bib.entries.iter()
+        .filter_map(|e| notes.get(&e.key).map(|n| {
+            (&e.key[..], format!("{} {}", e.title, markdown::prose(n)).to_lowercase())
+        }))
+        .collect();
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for text in texts.values() {
+        for w in words(text).into_iter().collect::<HashSet<_>>() {
+            *document_frequency.entry(w).or_insert(0) += 1;
+        }
+    }
+    let documents = texts.len() as f64;
+    let vectors: HashMap<&str, Vector> = texts.iter()
+        .map(|(&key, text)| {
+            let words = words(text);
+            let mut v = Vector::new();
+            for w in &words {
+                *v.entry(w.to_string()).or_insert(0.0) += 1.0;
+            }
+            for (w, x) in v.iter_mut() {
+                let idf = (documents / document_frequency[&w[..]] as f64).ln();
+                *x *= idf / words.len() as f64;
+            }
+            normalise(&mut v);
+            (key, v)
+        })
+        .collect();
+
+    // which entries have each tag, compared case-insensitively
+    let tags: HashMap<&str, Vec<String>> = bib.entries.iter()
+        .map(|e| (&e.key[..], e.tags().iter().map(|t| t.to_lowercase()).collect()))
+        .collect();
+    let mut tagged: HashMap<&str, Vec<&str>> = HashMap::new();
+    for e in &bib.entries {
+        for t in &tags[&e.key[..]] {
+            tagged.entry(t).or_default().push(&e.key);
+        }
+    }
+    // the average note of each tag
+    let centroids: HashMap<&str, Vector> = tagged.iter()
+        .map(|(&t, keys)| {
+            let mut c = Vector::new();
+            for v in keys.iter().filter_map(|k| vectors.get(k)) {
+                for (w, x) in v {
+                    *c.entry(w.clone()).or_insert(0.0) += x;
+                }
+            }
+            normalise(&mut c);
+            (t, c)
+        })
+        .collect();
+
+    let mut suggestions = Vec::new();
+    for e in &bib.entries {
+        let vector = match vectors.get(&e.key[..]) {
+            Some(v) => v,
+            None => continue,
+        };
+        let current = &tags[&e.key[..]];
+        let mut scored: Vec<(String, f64)> = tagged.iter()
+            .filter(|&(t, _)| !current.iter().any(|c| c == t))
+            .map(|(&t, keys)| {
+                let similarity = dot(vector, &centroids[t]);
+                // the share of entries with one of our tags that also have
+                // this one
+                let cooccurrence = current.iter()
+                    .map(|c| {
+                        let with_c = &tagged[&c[..]];
+                        let both = with_c.iter().filter(|k| keys.contains(k)).count();
+                        both as f64 / with_c.len() as f64
+                    })
+                    .fold(0.0, f64::max);
+                let mention = if mentions(&texts[&e.key[..]], t) { 1.0 } else { 0.0 };
+                let score = SIMILARITY_WEIGHT * similarity
+                    + COOCCURRENCE_WEIGHT * cooccurrence
+                    + MENTION_WEIGHT * mention;
+                (t.to_owned(), score)
+            })
+            .filter(|&(_, score)| score >= THRESHOLD)
+            .collect();
+        if scored.is_empty() {
+            continue;
+        }
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(LIMIT);
+        suggestions.push(Suggestion {
+            key: e.key.clone(),
+            current: e.tags(),
+            tags: scored,
+        });
+    }
+    suggestions
+}
+
+fn toml_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn toml_list<I: IntoIterator<Item = String>>(items: I) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(", "))
+}
+
+// the suggestions as a TOML report, one table per entry
+pub fn report(suggestions: &[Suggestion]) -> String {
+    let mut out = String::from(
+        "# Tags suggested by `biblionotes suggest-tags`, best first. To apply a\n\
+         # suggestion, add it to the `keywords` field of the entry.\n"
+    );
+    for s in suggestions {
+        writeln!(out).unwrap();
+        writeln!(out, "[{}]", toml_string(&s.key)).unwrap();
+        writeln!(out, "current = {}", toml_list(s.current.iter().map(|t| toml_string(t))))
+            .unwrap();
+        writeln!(out, "suggested = {}", toml_list(s.tags.iter().map(|t| toml_string(&t.0))))
+            .unwrap();
+        writeln!(out, "scores = {}", toml_list(s.tags.iter().map(|t| format!("{:.2}", t.1))))
+            .unwrap();
+    }
+    out
+}
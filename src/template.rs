@@ -0,0 +1,263 @@
+use std::fmt;
+use handlebars::RenderError;
+use serde_json::{Map, Value};
+use diagnostics::Diagnostic;
+
+// What went wrong filling in the template: where in it, and what it was
+// given to work with. With `--strict-templates` a variable the template uses
+// but a page doesn't have is an error too, where Handlebars would quietly
+// leave it empty. Those are found before rendering by going through the
+// template's own `{{…}}`, following `#if` and `#unless` the way the page's
+// data would, so a variable that's only used when it's there is fine. What's
+// inside `#each`, `#with` and other blocks isn't checked, as it's about other
+// data.
+
+#[derive(Debug)]
+pub struct Failure {
+    pub message: String,
+    // the template's file
+    pub template: String,
+    // 1-based, if it's known where it went wrong
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+    // how much of the line to underline, or 0 for the rest of it
+    pub len: usize,
+    // what the template was given, at the level where it went wrong
+    pub available: Vec<String>,
+}
+
+impl Failure {
+    // a failure Handlebars reported while rendering
+    pub fn render(e: RenderError, template: &str, context: &Map<String, Value>) -> Failure {
+        Failure {
+            message: e.desc,
+            template: template.to_owned(),
+            line: e.line_no,
+            col: e.column_no,
+            len: 0,
+            available: context.keys().cloned().collect(),
+        }
+    }
+
+    fn given(&self) -> String {
+        if self.available.is_empty() {
+            "the template was given nothing there".to_owned()
+        } else {
+            format!("the template was given {}", self.available.join(", "))
+        }
+    }
+
+    // the failure, showing the part of `source`, the template, it's about
+    pub fn diagnostic(&self, source: &str) -> Diagnostic {
+        let d = Diagnostic::error(format!("could not fill in the template: {}", self.message));
+        let d = match (self.line, self.col) {
+            (Some(line), Some(col)) => {
+                d.in_file(&self.template, source).label(line, col, self.len, "here")
+            },
+            _ => d.note(format!("in {}", self.template)),
+        };
+        d.note(self.given())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.line, self.col) {
+            (Some(line), Some(col)) => write!(
+                f,
+                "{} at {}:{}:{}",
+                self.message,
+                self.template,
+                line,
+                col
+            )?,
+            _ => write!(f, "{} in {}", self.message, self.template)?,
+        }
+        write!(f, "; {}", self.given())
+    }
+}
+
+// a `{{…}}` in a template
+struct Tag<'a> {
+    // what's between the braces, without `~` and the third brace of `{{{…}}}`
+    inner: &'a str,
+    start: usize,
+    end: usize,
+}
+
+fn tags(source: &str) -> Vec<Tag<'_>> {
+    let mut tags = Vec::new();
+    let mut at = 0;
+    while let Some(i) = source[at..].find("{{") {
+        let start = at + i;
+        let rest = &source[start..];
+        let close = if rest.starts_with("{{!--") {
+            "--}}"
+        } else if rest.starts_with("{{{") {
+            "}}}"
+        } else {
+            "}}"
+        };
+        let end = match rest[2..].find(close) {
+            Some(j) => start + 2 + j + close.len(),
+            None => break,
+        };
+        let inner = source[start..end]
+            .trim_start_matches('{')
+            .trim_end_matches('}')
+            .trim_matches('~')
+            .trim();
+        tags.push(Tag { inner, start, end });
+        at = end;
+    }
+    tags
+}
+
+// the parts of a path like `@root.links.[0].url`, from the top of the data,
+// or `None` for paths that aren't about it, like `../title` or `@index`
+fn parts(path: &str) -> Option<Vec<&str>> {
+    let mut parts: Vec<&str> = path.split(['.', '/'])
+        .map(|p| p.trim_start_matches('[').trim_end_matches(']'))
+        .collect();
+    if parts[0] == "@root" || parts[0] == "this" {
+        parts.remove(0);
+    }
+    if parts.iter().any(|p| p.is_empty() || p.starts_with('@') || *p == "..") {
+        return None;
+    }
+    Some(parts)
+}
+
+// whether the value at `path` is there in the way `#if` takes it, or `None`
+// if that can't be told from the data
+fn condition(context: &Map<String, Value>, path: &str) -> Option<bool> {
+    let parts = parts(path)?;
+    let (last, init) = parts.split_last()?;
+    let mut object = context;
+    for part in init {
+        match object.get(*part) {
+            Some(Value::Object(inner)) => object = inner,
+            Some(_) => return None,
+            None => return Some(false),
+        }
+    }
+    Some(match object.get(*last) {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(Value::U64(n)) => *n != 0,
+        Some(Value::I64(n)) => *n != 0,
+        Some(Value::F64(n)) => *n != 0.0,
+        Some(_) => true,
+    })
+}
+
+// the first part of `parts` that isn't in `context`, as a path, with what
+// there was instead
+fn missing(context: &Map<String, Value>, parts: &[&str]) -> Option<(String, Vec<String>)> {
+    let mut object = context;
+    for (i, part) in parts.iter().enumerate() {
+        match object.get(*part) {
+            Some(Value::Object(inner)) => object = inner,
+            // the insides of strings and lists aren't checked
+            Some(_) => return None,
+            None => return Some((parts[..=i].join("."), object.keys().cloned().collect())),
+        }
+    }
+    None
+}
+
+fn is_literal(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_digit() || c == '"' || c == '\'' || c == '-')
+        || ["true", "false", "null", "undefined"].contains(&word)
+}
+
+// whether the tags being read are checked, and whether they will be after
+// the block's `{{else}}`
+struct Block {
+    checked: bool,
+    otherwise: bool,
+}
+
+// `{{#if test}}` or `{{#unless test}}`, from a block that's `checked` or not
+fn conditional(
+    context: &Map<String, Value>,
+    checked: bool,
+    helper: &str,
+    test: &str,
+) -> Block {
+    let shown = condition(context, test).map(|t| t != (helper == "unless"));
+    Block {
+        checked: checked && shown == Some(true),
+        otherwise: checked && shown == Some(false),
+    }
+}
+
+// goes through `source`, the template in the file `template`, for the first
+// variable it uses that `context` doesn't have
+pub fn check(
+    template: &str,
+    source: &str,
+    context: &Map<String, Value>,
+) -> Result<(), Failure> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for tag in tags(source) {
+        let checked = blocks.last().is_none_or(|b| b.checked);
+        let words: Vec<&str> = tag.inner.split_whitespace().collect();
+        let first = match words.first() {
+            Some(first) => *first,
+            None => continue,
+        };
+        if let Some(helper) = first.strip_prefix('#') {
+            let block = match (helper, &words[1..]) {
+                ("if", [test]) | ("unless", [test]) => {
+                    conditional(context, checked, helper, test)
+                },
+                // a base layout's blocks and what a page fills them with
+                ("partial", [_]) | ("block", [_]) => Block { checked, otherwise: false },
+                ("each", [test]) | ("with", [test]) => Block {
+                    checked: false,
+                    otherwise: checked && condition(context, test) == Some(false),
+                },
+                _ => Block { checked: false, otherwise: false },
+            };
+            blocks.push(block);
+        } else if first.starts_with('/') {
+            blocks.pop();
+        } else if first == "else" || first == "^" {
+            if let Some(block) = blocks.last_mut() {
+                let checked = block.otherwise;
+                *block = match &words[1..] {
+                    [] => Block { checked, otherwise: false },
+                    [helper, test] if *helper == "if" || *helper == "unless" => {
+                        conditional(context, checked, helper, test)
+                    },
+                    _ => Block { checked: false, otherwise: false },
+                };
+            }
+        } else if let Some(test) = first.strip_prefix('^') {
+            blocks.push(conditional(context, checked, "unless", test));
+        } else if checked && words.len() == 1 && !first.starts_with(['!', '>']) {
+            let path = first.trim_start_matches('&');
+            if is_literal(path) {
+                continue;
+            }
+            let (variable, available) = match parts(path).and_then(|p| missing(context, &p)) {
+                Some(missing) => missing,
+                None => continue,
+            };
+            let before = &source[..tag.start];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            let len = source[tag.start..tag.end].lines().next().unwrap_or("").chars().count();
+            return Err(Failure {
+                message: format!("`{}` is not defined", variable),
+                template: template.to_owned(),
+                line: Some(before.matches('\n').count() + 1),
+                col: Some(before[line_start..].chars().count() + 1),
+                len,
+                available,
+            });
+        }
+    }
+    Ok(())
+}
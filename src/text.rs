@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use serde_json::{Map, Value};
+
+// The words biblionotes puts on pages itself, like the index's title or the
+// "Draft" badge, so sites in other languages can have their own. Each can be
+// changed under `[text]` in the config file, and templates get them all as
+// `text`, to use in their own chrome.
+
+const DEFAULTS: &[(&str, &str)] = &[
+    ("index_title", "Annotated bibliography"),
+    (
+        "index_intro",
+        "This is an annotated bibliography of various papers I find interesting. It is \
+         automatically generated by <a href=\"https://github.com/jeanm/biblionotes\">\
+         BiblioNotes</a> from a BibTeX file and an archive of Markdown files.",
+    ),
+    ("draft", "Draft"),
+    ("abstract", "Abstract"),
+    ("rating", "{n} out of 5"),
+    ("to_read", "To read"),
+    ("reading", "Reading"),
+    ("read", "Read"),
+    ("notes_by", "Notes by"),
+    ("and", "and"),
+    ("no_notes", "No notes yet."),
+    ("contributors", "Contributors"),
+    ("one_note", "1 note"),
+    ("notes_count", "{n} notes"),
+    ("coverage", "Coverage"),
+    ("coverage_intro", "Annotated entries out of all entries, by tag and year."),
+    ("all", "All"),
+    ("previous", "Previous"),
+    ("next", "Next"),
+    ("page_count", "Page {n} of {total}"),
+    ("latest", "Latest notes"),
+    ("contents", "Contents"),
+    ("graph", "Citation graph"),
+    (
+        "graph_intro",
+        "Papers are linked when a note cites one, or its <code>cites</code> field does \
+         (dashed). Blue papers have notes.",
+    ),
+    ("todo", "To do"),
+    ("todo_top", "top"),
+    ("todo_none", "Nothing to do."),
+    ("statistics", "Statistics"),
+    ("stats_summary", "{entries} entries, {notes} with notes, and {words} words of notes."),
+    ("entries", "Entries"),
+    ("notes", "Notes"),
+    ("venue", "Venue"),
+    ("entry_type", "Type"),
+    ("tags", "Tags"),
+    ("year", "Year"),
+    ("source_manual", "Added by hand"),
+    ("source_clipboard", "Pasted from the clipboard"),
+    ("source_doi", "Imported by DOI"),
+    ("source_zotero", "Synced from Zotero"),
+    ("source_merge", "Merged from another file"),
+    ("moved", "Moved"),
+    ("moved_to", "This page has moved to {link}."),
+    ("digest_title", "Notes since {date}"),
+    ("digest_new", "new"),
+    ("digest_updated", "updated"),
+    ("digest_failed", "This note could not be converted."),
+];
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August",
+    "September", "October", "November", "December",
+];
+
+pub struct Text {
+    strings: BTreeMap<String, String>,
+    // how dates are written: `%Y`, `%m` and `%d` are the year, month and day
+    // as numbers, and `%B` and `%e` the month's name and the day without a
+    // leading zero
+    pub date_format: String,
+    pub months: Vec<String>,
+}
+
+impl Default for Text {
+    fn default() -> Text {
+        Text {
+            strings: DEFAULTS.iter().map(|&(id, s)| (id.to_owned(), s.to_owned())).collect(),
+            date_format: "%Y-%m-%d".to_owned(),
+            months: MONTHS.iter().map(|&m| m.to_owned()).collect(),
+        }
+    }
+}
+
+impl Text {
+    pub fn is_known(id: &str) -> bool {
+        DEFAULTS.iter().any(|&(known, _)| known == id)
+    }
+
+    pub fn set(&mut self, id: &str, s: String) {
+        self.strings.insert(id.to_owned(), s);
+    }
+
+    pub fn get(&self, id: &str) -> &str {
+        &self.strings[id]
+    }
+
+    pub fn date(&self, (year, month, day): (i64, u32, u32)) -> String {
+        let month_name = self.months.get(month as usize - 1).map_or("", |m| &m[..]);
+        self.date_format
+            .replace("%Y", &format!("{:04}", year))
+            .replace("%m", &format!("{:02}", month))
+            .replace("%d", &format!("{:02}", day))
+            .replace("%e", &day.to_string())
+            .replace("%B", month_name)
+    }
+
+    // "A", "A and B" or "A, B and C"
+    pub fn list(&self, items: &[String]) -> String {
+        match items.split_last() {
+            None => String::new(),
+            Some((last, [])) => last.clone(),
+            Some((last, rest)) => format!("{} {} {}", rest.join(", "), self.get("and"), last),
+        }
+    }
+
+    pub fn data(&self) -> Value {
+        let strings: Map<String, Value> = self.strings.iter()
+            .map(|(id, s)| (id.clone(), Value::String(s.clone())))
+            .collect();
+        Value::Object(strings)
+    }
+}
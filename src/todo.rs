@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use bib::Bibliography;
+use html;
+use text::Text;
+use markdown;
+
+// A private page gathering the `TODO:` and `FIXME:` markers left in notes,
+// each linked to the section of the note it's in.
+
+const MARKERS: &[&str] = &["TODO:", "FIXME:"];
+
+pub struct Item {
+    pub marker: &'static str,
+    pub text: String,
+    // the text and id of the heading the marker is under, if any
+    pub section: Option<(String, String)>,
+}
+
+pub fn items(note: &str) -> Vec<Item> {
+    let headings = markdown::headings(note);
+    let mut items = Vec::new();
+    for (n, line) in markdown::prose_lines(note) {
+        let found = MARKERS.iter()
+            .filter_map(|&m| line.find(m).map(|i| (i, m)))
+            .min_by_key(|&(i, _)| i);
+        let (i, marker) = match found {
+            Some(found) => found,
+            None => continue,
+        };
+        // markers are often hidden in HTML comments
+        let text = line[i + marker.len()..].trim().trim_end_matches("-->").trim();
+        let section = headings.iter().rev()
+            .find(|h| h.line <= n)
+            .map(|h| (h.text.clone(), h.id.clone()));
+        items.push(Item {
+            marker: marker.trim_end_matches(':'),
+            text: text.to_owned(),
+            section,
+        });
+    }
+    items
+}
+
+// the content of the page, with the notes in bibliography order
+pub fn page(
+    bib: &Bibliography,
+    notes: &HashMap<String, String>,
+    pages: &HashMap<String, String>,
+    text: &Text,
+) -> String {
+    let mut content = format!(
+        "<header>\n  <h1>{}</h1>\n</header>\n",
+        html::escape(text.get("todo"))
+    );
+    let mut total = 0;
+    for entry in &bib.entries {
+        let (note, url) = match (notes.get(&entry.key), pages.get(&entry.key)) {
+            (Some(note), Some(url)) => (note, url),
+            _ => continue,
+        };
+        let items = items(note);
+        if items.is_empty() {
+            continue;
+        }
+        total += items.len();
+        let url = html::escape(url);
+        writeln!(content, "<h2><a href=\"{}\">{}</a></h2>", url, html::escape(&entry.title))
+            .unwrap();
+        writeln!(content, "<ul class=\"todo\">").unwrap();
+        for item in items {
+            let place = match item.section {
+                Some((ref text, ref id)) => format!(
+                    "<a href=\"{}#{}\">{}</a>",
+                    url,
+                    html::escape(id),
+                    html::escape(text)
+                ),
+                None => {
+                    format!("<a href=\"{}\">{}</a>", url, html::escape(text.get("todo_top")))
+                },
+            };
+            writeln!(
+                content,
+                "  <li><strong>{}</strong> ({}): {}</li>",
+                item.marker,
+                place,
+                html::escape(&item.text)
+            ).unwrap();
+        }
+        writeln!(content, "</ul>").unwrap();
+    }
+    if total == 0 {
+        writeln!(content, "<p>{}</p>", html::escape(text.get("todo_none"))).unwrap();
+    }
+    content
+}
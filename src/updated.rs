@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// When each note last changed: the time of the last commit that touched it,
+// from a single `git log` over the notes, or else when the file was last
+// written, for notes outside a git repository or not committed yet. When
+// each note was written is taken the same way, from its first commit in the
+// same `git log`.
+
+// the times of the first and last commits to each file, by its path
+pub type Commits = HashMap<PathBuf, (SystemTime, SystemTime)>;
+
+// the commits to the files in `dir`, by their paths under `dir`
+pub fn commits(dir: &Path) -> Commits {
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(["-c", "core.quotepath=off", "log", "--format=%x00%ct", "--name-only"])
+        .args(["--relative", "--", "."])
+        .output();
+    let output = match output {
+        Ok(ref output) if output.status.success() => String::from_utf8_lossy(&output.stdout),
+        _ => return HashMap::new(),
+    };
+    let mut times = HashMap::new();
+    // newest first, so the first commit a file shows up in is its last, and
+    // the last its first
+    for commit in output.split('\0') {
+        let mut lines = commit.lines();
+        let secs = match lines.next().and_then(|l| l.trim().parse::<u64>().ok()) {
+            Some(secs) => secs,
+            None => continue,
+        };
+        let time = UNIX_EPOCH + Duration::from_secs(secs);
+        for name in lines.filter(|l| !l.is_empty()) {
+            times.entry(dir.join(name)).or_insert((time, time)).0 = time;
+        }
+    }
+    times
+}
+
+// when each note, given by its key and where it was read from, last changed.
+// Notes that aren't committed changed at `uncommitted`, if it's given.
+pub fn compute(
+    commits: &Commits,
+    notes: HashMap<String, PathBuf>,
+    uncommitted: Option<SystemTime>,
+) -> HashMap<String, SystemTime> {
+    notes.into_iter()
+        .filter_map(|(key, path)| {
+            let time = commits.get(&path).map(|c| c.1).or(uncommitted)
+                .or_else(|| fs::metadata(&path).and_then(|m| m.modified()).ok())?;
+            Some((key, time))
+        })
+        .collect()
+}
+
+// when each note was first committed, the same way. Notes that aren't
+// committed were written at `uncommitted`, if it's given, or else when their
+// files were made.
+pub fn created(
+    commits: &Commits,
+    notes: HashMap<String, PathBuf>,
+    uncommitted: Option<SystemTime>,
+) -> HashMap<String, SystemTime> {
+    notes.into_iter()
+        .filter_map(|(key, path)| {
+            let file = || fs::metadata(&path).ok();
+            let time = commits.get(&path).map(|c| c.0).or(uncommitted)
+                .or_else(|| file()?.created().ok())
+                .or_else(|| file()?.modified().ok())?;
+            Some((key, time))
+        })
+        .collect()
+}
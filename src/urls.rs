@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use bib::{Bibliography, Entry};
+use collate;
+use config::UrlStyle;
+
+// Where each page goes. URLs are relative to the root of the site, and end
+// in a slash for pages written as `<name>/index.html`.
+
+// the most title words that go into a slug
+const SLUG_WORDS: usize = 6;
+
+// lowercase ASCII words joined by hyphens, with accents dropped
+pub fn slugify(s: &str) -> String {
+    collate::to_ascii(s).to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// the first author's last name, the year and the start of the title, as in
+// `vaswani-2017-attention-is-all-you-need`
+fn slug(entry: &Entry) -> String {
+    let who = entry.authors().first().map(|n| n.last.clone()).unwrap_or_default();
+    let title: Vec<&str> = entry.title.split_whitespace().take(SLUG_WORDS).collect();
+    let slug = slugify(&format!("{} {} {}", who, entry.year, title.join(" ")));
+    if slug.is_empty() {
+        slugify(&entry.key)
+    } else {
+        slug
+    }
+}
+
+// the URL of a page named after a key, or `None` if page names don't
+// depend on keys
+pub fn key_url(key: &str, style: UrlStyle) -> Option<String> {
+    match style {
+        UrlStyle::Key => Some(format!("{}.html", key)),
+        UrlStyle::Pretty => Some(format!("{}/", key)),
+        UrlStyle::Slug => None,
+    }
+}
+
+// the URLs of the pages of the given entries, in bibliography order so that
+// clashing slugs are numbered predictably
+pub fn page_urls(
+    bib: &Bibliography,
+    keys: &HashSet<String>,
+    style: UrlStyle,
+) -> HashMap<String, String> {
+    let mut urls = HashMap::new();
+    let mut taken = HashSet::new();
+    for entry in bib.entries.iter().filter(|e| keys.contains(&e.key)) {
+        let url = match key_url(&entry.key, style) {
+            Some(url) => url,
+            None => {
+                let base = slug(entry);
+                let mut name = base.clone();
+                let mut n = 1;
+                while !taken.insert(name.clone()) {
+                    n += 1;
+                    name = format!("{}-{}", base, n);
+                }
+                format!("{}.html", name)
+            },
+        };
+        urls.insert(entry.key.clone(), url);
+    }
+    urls
+}
+
+// the file a URL is written to, relative to the output directory
+pub fn file_for(url: &str) -> PathBuf {
+    if url.ends_with('/') {
+        PathBuf::from(url).join("index.html")
+    } else {
+        PathBuf::from(url)
+    }
+}
+
+// the relative path from the page at `url` back to the root of the site,
+// like `../`, or nothing for pages at the top
+pub fn root_for(url: &str) -> String {
+    "../".repeat(url.matches('/').count())
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeMap;
+use serde_json::Value;
+
+// What templates get to work with, for `template vars`. The variables are
+// found by building every note page and the index the way a build would, and
+// looking at what they were given, so the list can't fall behind the code.
+// Variables that only some pages have say on how many.
+
+// the helpers Handlebars has built in; biblionotes adds none of its own
+pub const HELPERS: &[&str] = &["if", "unless", "each", "with", "lookup", "log"];
+
+// the longest example shown, in characters
+const EXAMPLE_LEN: usize = 50;
+
+struct Var {
+    example: String,
+    // how many pages have it
+    pages: usize,
+}
+
+fn example(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => {
+            let short: String = s.chars().take(EXAMPLE_LEN).collect();
+            let more = if short.len() < s.len() { "…" } else { "" };
+            format!("{:?}{}", short, more)
+        },
+        Value::Array(ref items) if items.is_empty() => "[]".to_owned(),
+        Value::Array(_) | Value::Object(_) => String::new(),
+        ref other => ::serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+// every path in `value`, like `links[].url`, with an example of each
+fn paths(value: &Value, path: &str, found: &mut BTreeMap<String, String>) {
+    found.entry(path.to_owned()).or_insert_with(|| example(value));
+    match *value {
+        Value::Object(ref object) => {
+            for (key, value) in object {
+                let path = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                paths(value, &path, found);
+            }
+        },
+        Value::Array(ref items) => {
+            let path = format!("{}[]", path);
+            for item in items {
+                paths(item, &path, found);
+            }
+        },
+        _ => {},
+    }
+}
+
+// the variables of a kind of page, from the data of each page of that kind
+fn vars(pages: &[Value]) -> BTreeMap<String, Var> {
+    let mut vars: BTreeMap<String, Var> = BTreeMap::new();
+    for page in pages {
+        let mut found = BTreeMap::new();
+        paths(page, "", &mut found);
+        for (path, example) in found.into_iter().filter(|(path, _)| !path.is_empty()) {
+            let var = vars.entry(path).or_insert(Var { example: String::new(), pages: 0 });
+            var.pages += 1;
+            // the first page where it says something
+            if var.example.is_empty() || var.example == "[]" {
+                var.example = example;
+            }
+        }
+    }
+    vars
+}
+
+fn section(out: &mut String, heading: &str, pages: &[Value]) {
+    let vars = vars(pages);
+    out.push_str(&format!("{} ({} built)\n", heading, pages.len()));
+    if vars.is_empty() {
+        out.push_str("  (none built, so nothing to show)\n");
+    }
+    let width = vars.keys().map(|p| p.chars().count()).max().unwrap_or(0);
+    for (path, var) in &vars {
+        let mut line = format!("  {:<width$}  {}", path, var.example, width = width);
+        if var.pages < pages.len() {
+            line.push_str(&format!("  ({} of {})", var.pages, pages.len()));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out.push('\n');
+}
+
+// the whole list, for note pages and for the index
+pub fn list(note_pages: &[Value], index_pages: &[Value], partials: &[&str]) -> String {
+    let mut out = String::new();
+    section(&mut out, "Note pages", note_pages);
+    section(&mut out, "Index pages", index_pages);
+    out.push_str("Helpers\n");
+    out.push_str(&format!("  {}\n\n", HELPERS.join(", ")));
+    out.push_str("Partials\n");
+    if partials.is_empty() {
+        out.push_str("  (none)\n");
+    } else {
+        out.push_str(&format!("  {}\n", partials.join(", ")));
+    }
+    out
+}
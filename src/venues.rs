@@ -0,0 +1,91 @@
+use std::collections::{BTreeMap, HashMap};
+use bib::{Bibliography, Entry};
+use contributors;
+use html;
+use text::Text;
+use urls;
+
+// One name for each venue, however the bibliography spells it: the
+// `[venue_aliases]` table maps spellings (like "Proc. of NeurIPS") to the name
+// to use ("NeurIPS"), and entries get that name as their `journal`,
+// `journaltitle` or `booktitle` before anything else looks at them, so ranks,
+// related papers, statistics and templates all see the one name. Spellings
+// match whatever their case and punctuation. With `--venues`, every venue
+// also gets a page, under `venue/`, listing the papers in it that have notes.
+
+// the fields a venue can be in
+const FIELDS: &[&str] = &["journal", "journaltitle", "booktitle"];
+
+// a venue's name with only what tells venues apart: its letters and digits
+fn comparable(venue: &str) -> String {
+    urls::slugify(venue)
+}
+
+// gives each entry's venue the name an alias has for it, if any
+pub fn apply(bib: &mut Bibliography, aliases: &BTreeMap<String, String>) {
+    if aliases.is_empty() {
+        return;
+    }
+    let aliases: HashMap<String, &String> = aliases.iter()
+        .map(|(spelling, name)| (comparable(spelling), name))
+        .collect();
+    for entry in &mut bib.entries {
+        for &field in FIELDS {
+            let name = entry.text_field(field)
+                .and_then(|venue| aliases.get(&comparable(&venue)).cloned());
+            if let Some(name) = name {
+                entry.fields.insert(field.to_owned(), name.clone());
+            }
+        }
+    }
+}
+
+pub fn url(venue: &str) -> String {
+    match comparable(venue) {
+        ref slug if slug.is_empty() => "venue/unnamed.html".to_owned(),
+        slug => format!("venue/{}.html", slug),
+    }
+}
+
+// every venue with the keys of the entries in it that have notes, in
+// bibliography order, with the most notes first. Venues whose names only
+// differ in case or punctuation are one, under the name used first.
+pub fn venues<'a>(
+    bib: &'a Bibliography,
+    pages: &HashMap<String, String>,
+) -> Vec<(String, Vec<&'a str>)> {
+    let mut venues: BTreeMap<String, (String, Vec<&str>)> = BTreeMap::new();
+    for entry in bib.entries.iter().filter(|e| pages.contains_key(&e.key)) {
+        if let Some(venue) = entry.venue() {
+            venues.entry(comparable(&venue))
+                .or_insert_with(|| (venue, Vec::new()))
+                .1
+                .push(&entry.key);
+        }
+    }
+    let mut venues: Vec<(String, Vec<&str>)> = venues.into_values().collect();
+    venues.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+    venues
+}
+
+// the URL of the entry's venue's page, relative to the top of the site
+pub fn of(entry: &Entry) -> Option<String> {
+    entry.venue().map(|venue| url(&venue))
+}
+
+// the content of a venue's page, which is a level down
+pub fn page(
+    bib: &Bibliography,
+    venue: &str,
+    keys: &[&str],
+    pages: &HashMap<String, String>,
+    text: &Text,
+) -> String {
+    let mut content = format!(
+        "<header>\n  <h1>{}</h1>\n  <p>{}</p>\n</header>\n",
+        html::escape(venue),
+        html::escape(&contributors::count(keys, text))
+    );
+    contributors::notes_list(&mut content, bib, keys, pages, "../");
+    content
+}
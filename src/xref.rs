@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use bib::Bibliography;
+use html;
+use markdown;
+
+// a `[@key]` reference to an entry that isn't in the bibliography
+pub struct Unknown {
+    pub key: String,
+    // byte offset and length of the whole `[...]` group in the note
+    pub offset: usize,
+    pub len: usize,
+}
+
+pub fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-:./+".contains(c)
+}
+
+// parses `@key1; @key2]` (the part after the opening bracket), returning the
+// keys and the length consumed, including the closing bracket
+fn parse_group(s: &str) -> Option<(Vec<&str>, usize)> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    loop {
+        i += s[i..].len() - s[i..].trim_start().len();
+        if !s[i..].starts_with('@') {
+            return None;
+        }
+        i += 1;
+        let len = s[i..].find(|c| !is_key_char(c)).unwrap_or(s.len() - i);
+        if len == 0 {
+            return None;
+        }
+        keys.push(&s[i..i + len]);
+        i += len;
+        i += s[i..].len() - s[i..].trim_start().len();
+        match s[i..].chars().next() {
+            Some(';') => i += 1,
+            Some(']') => return Some((keys, i + 1)),
+            _ => return None,
+        }
+    }
+}
+
+// finds the `[@...]` groups in a piece of text, as their start and end
+// offsets and the keys they contain
+fn groups(text: &str) -> Vec<(usize, usize, Vec<&str>)> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while let Some(found) = text[i..].find("[@") {
+        let start = i + found;
+        let escaped = text[..start].ends_with('\\');
+        match parse_group(&text[start + 1..]) {
+            Some((keys, len)) if !escaped => {
+                let end = start + 1 + len;
+                // `[@foo](...)` is an ordinary link
+                if !text[end..].starts_with('(') {
+                    out.push((start, end, keys));
+                }
+                i = end;
+            },
+            _ => i = start + 2,
+        }
+    }
+    out
+}
+
+// the keys referenced in a note, in order of first appearance
+pub fn cited(markdown: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    markdown::map_text(markdown, |_, text| {
+        for (_, _, group) in groups(text) {
+            for key in group {
+                if !keys.iter().any(|k| k == key) {
+                    keys.push(key.to_owned());
+                }
+            }
+        }
+        String::new()
+    });
+    keys
+}
+
+// replaces the keys in the references of a note with their new names
+pub fn rename(markdown: &str, renames: &HashMap<String, String>) -> String {
+    markdown::map_text(markdown, |_, text| {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, end, keys) in groups(text) {
+            if !keys.iter().any(|k| renames.contains_key(*k)) {
+                continue;
+            }
+            out.push_str(&text[last..start]);
+            last = end;
+            let keys: Vec<String> = keys.iter()
+                .map(|k| format!("@{}", renames.get(*k).map_or(*k, |k| &k[..])))
+                .collect();
+            out.push_str(&format!("[{}]", keys.join("; ")));
+        }
+        out.push_str(&text[last..]);
+        out
+    })
+}
+
+// rewrites `[@key]` and `[@key1; @key2]` into the short citations of the
+// referenced entries, linked to their pages when they have one. `pages` maps
+// the keys of entries with notes to their URLs, which are relative to `root`.
+// The citations are emitted as inline HTML so both renderers pass them
+// through as they are.
+pub fn rewrite(
+    markdown: &str,
+    bib: &Bibliography,
+    pages: &HashMap<String, String>,
+    root: &str,
+) -> (String, Vec<Unknown>) {
+    let mut unknown = Vec::new();
+    let rewritten = markdown::map_text(markdown, |offset, text| {
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for (start, end, keys) in groups(text) {
+            out.push_str(&text[last..start]);
+            last = end;
+            let mut citations = Vec::new();
+            let mut all_known = true;
+            for key in &keys {
+                let entry = match bib.get(key) {
+                    Some(entry) => entry,
+                    None => {
+                        all_known = false;
+                        unknown.push(Unknown {
+                            key: key.to_string(),
+                            offset: offset + start,
+                            len: end - start,
+                        });
+                        continue;
+                    },
+                };
+                let citation = html::escape(&entry.short_citation());
+                citations.push(match pages.get(*key) {
+                    Some(url) => format!(
+                        "<a class=\"xref\" href=\"{}{}\">{}</a>",
+                        html::escape(root),
+                        html::escape(url),
+                        citation
+                    ),
+                    None => format!("<span class=\"xref\">{}</span>", citation),
+                });
+            }
+            if all_known {
+                out.push_str(&citations.join("; "));
+            } else {
+                // leave the text alone so the typo is visible on the page
+                out.push_str(&text[start..end]);
+            }
+        }
+        out.push_str(&text[last..]);
+        out
+    });
+    (rewritten, unknown)
+}
@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use serde_json::{self, Map, Value};
+use bib::{self, Bibliography};
+use config::{Config, Prefer};
+use diagnostics::Diagnostic;
+use provenance::{self, Source};
+
+// Keeps the bibliography in step with a Zotero group library, such as a
+// lab's shared one. Syncing pulls what changed in Zotero since the last
+// sync: new items are added to the bibliography, and changed ones replace
+// their entries, unless the entry was edited here too, which is a conflict.
+// Nothing is ever sent to Zotero; entries that are only in the bibliography,
+// and items deleted from Zotero, are reported for someone to sort out.
+//
+// What was synced is kept in `.biblionotes/zotero.json` next to the
+// bibliography: the library version last seen, and for each item the key of
+// its entry and the entry's text as of the last sync, which is how local
+// edits are told apart.
+
+const API: &str = "https://api.zotero.org";
+
+// the most items the API returns at a time
+const PAGE: usize = 100;
+
+struct Synced {
+    key: String,
+    text: String,
+}
+
+struct State {
+    group: String,
+    version: u64,
+    // by Zotero item key
+    items: BTreeMap<String, Synced>,
+}
+
+// an item that changed in Zotero, as BibTeX
+struct Item {
+    zotero: String,
+    bibtex: String,
+}
+
+fn state_path(config: &Config) -> PathBuf {
+    let dir = Path::new(&config.bib_path).parent().unwrap_or_else(|| Path::new(""));
+    dir.join(".biblionotes").join("zotero.json")
+}
+
+fn load_state(path: &Path, group: &str) -> State {
+    let fresh = State { group: group.to_owned(), version: 0, items: BTreeMap::new() };
+    let mut contents = String::new();
+    if File::open(path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return fresh;
+    }
+    let value: Value = match serde_json::from_str(&contents) {
+        Ok(value) => value,
+        Err(e) => {
+            Diagnostic::warning(format!("ignoring invalid sync state {}", path.display()))
+                .note(e.to_string())
+                .note("everything in Zotero will be pulled again")
+                .emit();
+            return fresh;
+        },
+    };
+    if value.find("group").and_then(|g| g.as_str()) != Some(group) {
+        if path.exists() {
+            Diagnostic::warning("syncing with a different group than last time")
+                .note("everything in Zotero will be pulled again")
+                .emit();
+        }
+        return fresh;
+    }
+    let items = value.find("items").and_then(|i| i.as_object()).into_iter()
+        .flat_map(|items| items.iter())
+        .filter_map(|(zotero, synced)| {
+            let field = |name: &str| synced.find(name).and_then(|v| v.as_str());
+            Some((zotero.clone(), Synced {
+                key: field("key")?.to_owned(),
+                text: field("text")?.to_owned(),
+            }))
+        })
+        .collect();
+    State {
+        group: group.to_owned(),
+        version: value.find("version").and_then(|v| v.as_u64()).unwrap_or(0),
+        items,
+    }
+}
+
+fn save_state(path: &Path, state: &State) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let items: Map<String, Value> = state.items.iter()
+        .map(|(zotero, synced)| {
+            let mut item = Map::new();
+            item.insert("key".to_owned(), Value::String(synced.key.clone()));
+            item.insert("text".to_owned(), Value::String(synced.text.clone()));
+            (zotero.clone(), Value::Object(item))
+        })
+        .collect();
+    let mut value = Map::new();
+    value.insert("group".to_owned(), Value::String(state.group.clone()));
+    value.insert("version".to_owned(), Value::U64(state.version));
+    value.insert("items".to_owned(), Value::Object(items));
+    let json = serde_json::to_string_pretty(&value).map_err(io::Error::other)?;
+    File::create(path).and_then(|mut f| writeln!(f, "{}", json))
+}
+
+// fetches a response with curl, along with the library version it's from
+fn get(config: &Config, url: &str) -> Result<(Option<u64>, String), String> {
+    let mut command = Command::new(&config.enrich.curl);
+    command.args(["--silent", "--show-error", "--fail", "--include"])
+        .args(["--header", "Zotero-API-Version: 3"]);
+    let key = config.zotero.api_key.clone().or_else(|| env::var("ZOTERO_API_KEY").ok());
+    if let Some(key) = key {
+        command.arg("--header").arg(format!("Zotero-API-Key: {}", key));
+    }
+    let output = command.arg(url)
+        .output()
+        .map_err(|e| format!("could not run {}: {}", config.enrich.curl, e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    let response = String::from_utf8_lossy(&output.stdout);
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let version = head.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("last-modified-version"))
+        .and_then(|(_, version)| version.trim().parse().ok());
+    Ok((version, body.to_owned()))
+}
+
+// the items changed since a library version, and the version they're at
+fn pull(config: &Config, group: &str, since: u64) -> Result<(u64, Vec<Item>), String> {
+    let mut items = Vec::new();
+    let mut version = since;
+    for start in (0..).step_by(PAGE) {
+        let url = format!(
+            "{}/groups/{}/items/top?format=json&include=bibtex&since={}&limit={}&start={}",
+            API, group, since, PAGE, start
+        );
+        let (at, body) = get(config, &url)?;
+        version = version.max(at.unwrap_or(0));
+        let page: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+        let page = page.as_array().ok_or("unexpected response from Zotero")?;
+        for item in page {
+            let zotero = item.find("key").and_then(|k| k.as_str());
+            let bibtex = item.find("bibtex").and_then(|b| b.as_str());
+            // standalone notes have no BibTeX to speak of
+            if let (Some(zotero), Some(bibtex)) = (zotero, bibtex) {
+                if bibtex.trim_start().starts_with('@') {
+                    let bibtex = bibtex.trim().to_owned();
+                    items.push(Item { zotero: zotero.to_owned(), bibtex });
+                }
+            }
+        }
+        if page.len() < PAGE {
+            break;
+        }
+    }
+    Ok((version, items))
+}
+
+// the items deleted since a library version
+fn deleted(config: &Config, group: &str, since: u64) -> Result<Vec<String>, String> {
+    let url = format!("{}/groups/{}/deleted?since={}", API, group, since);
+    let (_, body) = get(config, &url)?;
+    let value: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    Ok(value.find("items").and_then(|i| i.as_array()).into_iter()
+        .flat_map(|items| items.iter())
+        .filter_map(|i| i.as_str().map(|s| s.to_owned()))
+        .collect())
+}
+
+fn fail(message: String, e: String) -> ! {
+    Diagnostic::error(message).note(e).emit();
+    ::std::process::exit(1);
+}
+
+pub fn sync(config: &Config, bib: &Bibliography, group: &str) {
+    let path = state_path(config);
+    let mut state = load_state(&path, group);
+    let (version, items) = pull(config, group, state.version)
+        .unwrap_or_else(|e| fail(format!("could not get the items of group {}", group), e));
+    let gone = if state.version > 0 {
+        deleted(config, group, state.version)
+            .unwrap_or_else(|e| {
+                fail("could not get the items deleted from Zotero".to_owned(), e)
+            })
+    } else {
+        Vec::new()
+    };
+
+    let mut replaced = Vec::new();
+    let mut added = Vec::new();
+    let mut conflicts = 0;
+    for item in &items {
+        let name = format!("Zotero item {}", item.zotero);
+        let remote = bib::parse(&name, item.bibtex.as_bytes());
+        let entry = match remote.entries.first() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if let Some(synced) = state.items.get_mut(&item.zotero) {
+            let local = match bib.text(&synced.key) {
+                Some(local) => local,
+                None => {
+                    println!("`{}` was removed here but changed in Zotero; not adding it back",
+                             synced.key);
+                    state.items.remove(&item.zotero);
+                    continue;
+                },
+            };
+            let text = bib::with_key(&item.bibtex, &synced.key);
+            if local == text {
+                synced.text = text;
+                continue;
+            }
+            if local != synced.text {
+                match config.zotero.prefer {
+                    // it's as good as synced now
+                    Some(Prefer::Local) => {
+                        synced.text = local.to_owned();
+                        println!("kept `{}` as it is here", synced.key);
+                        continue;
+                    },
+                    Some(Prefer::Zotero) => {},
+                    None => {
+                        Diagnostic::warning(
+                            format!("`{}` changed both here and in Zotero", synced.key)
+                        ).note("left as it is here; settle it with `--prefer local` or \
+                                `--prefer zotero`").emit();
+                        conflicts += 1;
+                        continue;
+                    },
+                }
+            }
+            println!("updated `{}`", synced.key);
+            replaced.push((bib.span(&synced.key).unwrap(), text.clone()));
+            synced.text = text;
+            continue;
+        }
+
+        // an item that's new to us, but maybe not to the bibliography
+        let doi = entry.doi();
+        let existing = bib.get(&entry.key)
+            .or_else(|| bib.entries.iter().find(|e| doi.is_some() && e.doi() == doi));
+        if let Some(existing) = existing {
+            let text = bib.text(&existing.key).unwrap_or_default().to_owned();
+            println!("linked `{}` to its Zotero item", existing.key);
+            let key = existing.key.clone();
+            state.items.insert(item.zotero.clone(), Synced { key, text });
+            continue;
+        }
+        if added.iter().any(|(key, _)| *key == entry.key) {
+            Diagnostic::warning(format!("two Zotero items have the key `{}`", entry.key))
+                .note(format!("leaving out item {}", item.zotero))
+                .emit();
+            continue;
+        }
+        println!("added `{}`", entry.key);
+        added.push((entry.key.clone(), item.bibtex.clone()));
+        state.items.insert(item.zotero.clone(), Synced {
+            key: entry.key.clone(),
+            text: item.bibtex.clone(),
+        });
+    }
+    for zotero in &gone {
+        if let Some(synced) = state.items.remove(zotero) {
+            Diagnostic::warning(format!("`{}` was deleted from Zotero", synced.key))
+                .note("it's still in the bibliography")
+                .emit();
+        }
+    }
+
+    if !replaced.is_empty() || !added.is_empty() {
+        let mut source = bib.source.clone();
+        replaced.sort_by_key(|(span, _)| ::std::cmp::Reverse(span.start));
+        for (span, text) in replaced {
+            source.replace_range(span, &text);
+        }
+        for (_, text) in &added {
+            source.push_str(&format!("\n{}\n", text));
+        }
+        // in one go, so it's never left half-synced
+        let bib_path = Path::new(&config.bib_path);
+        let temp = bib_path.with_extension("bib.tmp");
+        File::create(&temp)
+            .and_then(|mut f| f.write_all(source.as_bytes()))
+            .and_then(|_| fs::rename(&temp, bib_path))
+            .unwrap_or_else(|e| {
+                fail(format!("could not write {}", bib_path.display()), e.to_string())
+            });
+        let keys: Vec<String> = added.into_iter().map(|(key, _)| key).collect();
+        let detail = format!("group {}", group);
+        if let Err(e) = provenance::record(config, &keys, Source::Zotero, Some(&detail)) {
+            Diagnostic::warning("could not note where the new entries came from")
+                .note(e.to_string())
+                .emit();
+        }
+    }
+
+    // with conflicts left, the same changes are pulled again next time
+    if conflicts == 0 {
+        state.version = version;
+    }
+    save_state(&path, &state)
+        .unwrap_or_else(|e| {
+            fail(format!("could not write {}", path.display()), e.to_string())
+        });
+
+    let synced: HashSet<&str> = state.items.values().map(|s| &s.key[..]).collect();
+    let local: Vec<&str> = bib.entries.iter()
+        .map(|e| &e.key[..])
+        .filter(|k| !synced.contains(k))
+        .collect();
+    if !local.is_empty() {
+        println!("{} entries are only in the bibliography: {}", local.len(), local.join(", "));
+    }
+    if conflicts > 0 {
+        println!("{} conflicts were left as they are here", conflicts);
+    }
+}
+
+// follows entries renamed by `fmt --fix-keys`, so they aren't taken for
+// entries edited here
+pub fn rename(config: &Config, renames: &[(String, String)]) -> io::Result<()> {
+    let path = state_path(config);
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut contents = String::new();
+    File::open(&path)?.read_to_string(&mut contents)?;
+    let group = serde_json::from_str::<Value>(&contents).ok()
+        .and_then(|v| v.find("group").and_then(|g| g.as_str()).map(|g| g.to_owned()));
+    let group = match group {
+        Some(group) => group,
+        None => return Ok(()),
+    };
+    let mut state = load_state(&path, &group);
+    for synced in state.items.values_mut() {
+        if let Some((_, new)) = renames.iter().find(|(old, _)| *old == synced.key) {
+            synced.key = new.clone();
+            synced.text = bib::with_key(&synced.text, new);
+        }
+    }
+    save_state(&path, &state)
+}